@@ -0,0 +1,51 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+const HEADER: &str = r#""remotehost","rfc931","authuser","date","request","status","bytes""#;
+const RECORDS: usize = 200_000;
+
+fn write_logs(path: &Path) {
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path).unwrap());
+    writeln!(file, "{}", HEADER).unwrap();
+    for i in 0..RECORDS {
+        writeln!(
+            file,
+            r#""10.0.0.{}","-","apache",{},"GET /api/user/{} HTTP/1.0",200,{}"#,
+            i % 5,
+            1549573860 + i / 100,
+            i % 10,
+            1000 + i % 500
+        )
+        .unwrap();
+    }
+}
+
+fn run(path: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_log-ingestor"))
+        .arg(path)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+fn count_only(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("access.csv");
+    write_logs(&path);
+
+    let mut group = c.benchmark_group("count");
+    group.sample_size(10);
+    group.bench_function("count_only", |b| b.iter(|| run(&path, &["--count-only"])));
+    group.bench_function("full_parse", |b| b.iter(|| run(&path, &[])));
+    group.finish();
+}
+
+criterion_group!(benches, count_only);
+criterion_main!(benches);
@@ -0,0 +1,121 @@
+use crate::reader::HttpLog;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+
+/// Value of the identity fields without a user, kept as it is.
+const NO_USER: &str = "-";
+
+/// Fields of the logs replaced with a salted hash (e.g. `host,user`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AnonymizedFields {
+    /// The remote host.
+    host: bool,
+    /// The `rfc931` and `authuser` identities.
+    user: bool,
+}
+
+impl FromStr for AnonymizedFields {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = Self::default();
+        for field in s.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+            match field {
+                "host" => fields.host = true,
+                "user" => fields.user = true,
+                _ => anyhow::bail!("Invalid anonymized field: {}. Use host or user", field),
+            }
+        }
+        if fields == Self::default() {
+            anyhow::bail!("Invalid anonymized fields, none found: {}", s);
+        }
+        Ok(fields)
+    }
+}
+
+/// Replaces the personal data of the logs with salted hashes. The same value and salt
+/// always give the same hash, so the logs can still be grouped by those fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anonymizer {
+    fields: AnonymizedFields,
+    salt: String,
+}
+
+impl Anonymizer {
+    pub fn new(fields: AnonymizedFields, salt: impl Into<String>) -> Self {
+        Self {
+            fields,
+            salt: salt.into(),
+        }
+    }
+
+    pub fn anonymize(&self, log: &mut HttpLog) {
+        if self.fields.host {
+            log.remote_host = self.hash(&log.remote_host);
+        }
+        if self.fields.user {
+            for identity in [&mut log.rfc931, &mut log.auth_user] {
+                if identity != NO_USER {
+                    *identity = self.hash(identity);
+                }
+            }
+        }
+    }
+
+    /// First 8 bytes of the SHA-256 of the salt and the value, in hex.
+    fn hash(&self, value: &str) -> String {
+        let digest = Sha256::new()
+            .chain_update(self.salt.as_bytes())
+            // so moving bytes between the salt and the value changes the hash
+            .chain_update([0])
+            .chain_update(value.as_bytes())
+            .finalize();
+        digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_test_http_log;
+
+    fn anonymize(fields: &str, salt: &str, host: &str, user: &str) -> HttpLog {
+        let mut log = build_test_http_log(1, None);
+        log.remote_host = host.to_string();
+        log.auth_user = user.to_string();
+        Anonymizer::new(fields.parse().unwrap(), salt).anonymize(&mut log);
+        log
+    }
+
+    #[tokio::test]
+    async fn same_values_and_salt_give_the_same_hash() {
+        let log = anonymize("host,user", "salt", "10.0.0.1", "apache");
+        let same = anonymize("host,user", "salt", "10.0.0.1", "apache");
+        let other_host = anonymize("host,user", "salt", "10.0.0.2", "apache");
+        let other_salt = anonymize("host,user", "pepper", "10.0.0.1", "apache");
+
+        assert_eq!(log.remote_host.len(), 16);
+        assert_ne!(log.remote_host, "10.0.0.1");
+        assert_ne!(log.auth_user, "apache");
+        assert_eq!(log, same);
+        assert_ne!(log.remote_host, other_host.remote_host);
+        assert_eq!(log.auth_user, other_host.auth_user);
+        assert_ne!(log.remote_host, other_salt.remote_host);
+        assert_ne!(log.auth_user, other_salt.auth_user);
+        // no user is kept as it is
+        assert_eq!(log.rfc931, NO_USER);
+    }
+
+    #[tokio::test]
+    async fn only_the_given_fields_are_anonymized() {
+        let host_only = anonymize("host", "salt", "10.0.0.1", "apache");
+        let user_only = anonymize(" user ", "salt", "10.0.0.1", "apache");
+
+        assert_ne!(host_only.remote_host, "10.0.0.1");
+        assert_eq!(host_only.auth_user, "apache");
+        assert_eq!(user_only.remote_host, "10.0.0.1");
+        assert_ne!(user_only.auth_user, "apache");
+        assert!("host,ip".parse::<AnonymizedFields>().is_err());
+        assert!(",".parse::<AnonymizedFields>().is_err());
+    }
+}
@@ -0,0 +1,72 @@
+/// Tells the requests of bots from the human ones by substrings of their user agent,
+/// ignoring the case.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BotPatterns(Vec<String>);
+
+impl BotPatterns {
+    /// User agent substrings of the most common crawlers.
+    pub const DEFAULT: &'static [&'static str] = &[
+        "Googlebot",
+        "bingbot",
+        "Slurp",
+        "DuckDuckBot",
+        "Baiduspider",
+        "YandexBot",
+        "facebookexternalhit",
+        "crawler",
+        "spider",
+    ];
+
+    pub fn new<S: AsRef<str>>(patterns: impl IntoIterator<Item = S>) -> Self {
+        Self(
+            patterns
+                .into_iter()
+                .map(|pattern| pattern.as_ref().trim().to_lowercase())
+                .filter(|pattern| !pattern.is_empty())
+                .collect(),
+        )
+    }
+
+    pub fn is_bot(&self, user_agent: &str) -> bool {
+        let user_agent = user_agent.to_lowercase();
+        self.0
+            .iter()
+            .any(|pattern| user_agent.contains(pattern.as_str()))
+    }
+}
+
+impl Default for BotPatterns {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn classifies_the_known_bots() {
+        let patterns = BotPatterns::default();
+
+        assert!(patterns
+            .is_bot("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)"));
+        assert!(patterns
+            .is_bot("Mozilla/5.0 (compatible; bingbot/2.0; +http://www.bing.com/bingbot.htm)"));
+        assert!(patterns.is_bot("Some-Custom-SPIDER/1.0"));
+        assert!(!patterns.is_bot(
+            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0 Safari/537.36"
+        ));
+        assert!(!patterns.is_bot("curl/8.4.0"));
+    }
+
+    #[tokio::test]
+    async fn custom_patterns_replace_the_default_ones() {
+        let patterns = BotPatterns::new(["curl", " python-requests ", ""]);
+
+        assert!(patterns.is_bot("curl/8.4.0"));
+        assert!(patterns.is_bot("Python-Requests/2.31"));
+        assert!(!patterns.is_bot("Googlebot/2.1"));
+        assert!(!patterns.is_bot("Mozilla/5.0"));
+    }
+}
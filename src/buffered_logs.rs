@@ -3,12 +3,63 @@ use futures::{Stream, StreamExt};
 use pin_project::pin_project;
 use std::{
     collections::HashMap,
+    num::NonZeroUsize,
     pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
 };
 
 pub type LogResult = Result<HttpLog, anyhow::Error>;
 
+/// Stops the grouping of the logs once too many records in a row can't be parsed, as that
+/// means the input isn't in the expected format. Isolated errors only reset it.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorBreaker {
+    max_consecutive_errors: Option<NonZeroUsize>,
+    consecutive_errors: usize,
+    // shared with the clones, so whoever runs the stream can tell why it ended
+    is_tripped: Arc<AtomicBool>,
+}
+
+impl ErrorBreaker {
+    /// Without `max_consecutive_errors`, it never trips.
+    pub fn new(max_consecutive_errors: Option<NonZeroUsize>) -> Self {
+        Self {
+            max_consecutive_errors,
+            ..Self::default()
+        }
+    }
+
+    /// Counts a record, telling whether the breaker tripped.
+    pub fn record(&mut self, is_error: bool) -> bool {
+        self.consecutive_errors = match is_error {
+            true => self.consecutive_errors + 1,
+            false => 0,
+        };
+        let is_tripped = self
+            .max_consecutive_errors
+            .is_some_and(|max| self.consecutive_errors >= max.get());
+        if is_tripped {
+            self.is_tripped.store(true, Ordering::SeqCst);
+        }
+        is_tripped
+    }
+
+    /// Fails once the breaker tripped.
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.is_tripped.load(Ordering::SeqCst) {
+            anyhow::bail!(
+                "Aborting after {} records in a row that couldn't be parsed. Is the input in the expected format? (see --format and --header)",
+                self.max_consecutive_errors.map_or(0, NonZeroUsize::get)
+            );
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct GroupedHttpLogs {
     pub time: usize,
@@ -29,6 +80,10 @@ where
     ordered_time_buffer: Vec<usize>,
     minor_time_in_buffer: usize,
     major_time_in_buffer: usize,
+    // the times above are meaningless until the first log arrives
+    is_initialized: bool,
+    max_group_len: Option<usize>,
+    error_breaker: ErrorBreaker,
 }
 
 impl<St> BufferedLogs<St>
@@ -43,8 +98,24 @@ where
             ordered_time_buffer: Vec::new(),
             minor_time_in_buffer: 0,
             major_time_in_buffer: 0,
+            is_initialized: false,
+            max_group_len: None,
+            error_breaker: ErrorBreaker::default(),
         }
     }
+
+    /// Splits the groups with more logs than `max_group_len` into several groups with the same time.
+    pub fn with_max_group_len(mut self, max_group_len: Option<usize>) -> Self {
+        // empty groups would never drain the buffer
+        self.max_group_len = max_group_len.map(|max_group_len| max_group_len.max(1));
+        self
+    }
+
+    /// Ends the stream, dropping the buffered logs, once the breaker trips.
+    pub fn with_error_breaker(mut self, error_breaker: ErrorBreaker) -> Self {
+        self.error_breaker = error_breaker;
+        self
+    }
 }
 
 impl<St> Stream for BufferedLogs<St>
@@ -60,12 +131,16 @@ where
         {
             match this.stream.as_mut().poll_next(cx) {
                 Poll::Ready(Some(x)) => {
+                    if this.error_breaker.record(x.is_err()) {
+                        return Poll::Ready(None);
+                    }
                     match x {
                         Ok(log) => {
                             let current_date = log.time;
-                            if *this.minor_time_in_buffer == 0 && *this.major_time_in_buffer == 0 {
+                            if !*this.is_initialized {
                                 *this.minor_time_in_buffer = current_date;
                                 *this.major_time_in_buffer = current_date;
+                                *this.is_initialized = true;
                             }
                             if current_date < *this.minor_time_in_buffer {
                                 *this.minor_time_in_buffer = current_date;
@@ -90,11 +165,23 @@ where
                     }
                 }
                 Poll::Ready(None) => break,
-                Poll::Pending => (), // keep waiting
+                // the buffer isn't full yet, so there's nothing to emit
+                Poll::Pending => return Poll::Pending,
             }
         }
 
         if let Some(log_time) = this.ordered_time_buffer.pop() {
+            let logs = this.time_buffer.remove(&log_time).map(|mut logs| {
+                // keeping the logs over the max for the next group
+                if let Some(max_group_len) = *this.max_group_len {
+                    if logs.len() > max_group_len {
+                        this.time_buffer
+                            .insert(log_time, logs.split_off(max_group_len));
+                        this.ordered_time_buffer.push(log_time);
+                    }
+                }
+                logs
+            });
             // modify the minor date and return
             *this.minor_time_in_buffer = this
                 .ordered_time_buffer
@@ -102,14 +189,10 @@ where
                 .copied()
                 .unwrap_or(*this.major_time_in_buffer);
             // return the entry
-            if let Some(group) = this
-                .time_buffer
-                .remove(&log_time)
-                .map(|logs| GroupedHttpLogs {
-                    time: log_time,
-                    logs,
-                })
-            {
+            if let Some(group) = logs.map(|logs| GroupedHttpLogs {
+                time: log_time,
+                logs,
+            }) {
                 return Poll::Ready(Some(group));
             }
         }
@@ -123,11 +206,142 @@ where
     }
 }
 
+/// Checks at runtime that the groups leave the buffer in order, telling whether
+/// the buffer is wide enough for a real input.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrderingCheck {
+    latest_time: Option<usize>,
+    groups: usize,
+    violations: usize,
+}
+
+impl OrderingCheck {
+    pub fn check(&mut self, group: &GroupedHttpLogs) {
+        self.groups += 1;
+        match self.latest_time {
+            Some(latest_time) if group.time < latest_time => {
+                self.violations += 1;
+                tracing::error!(
+                    "ORDERING VIOLATION: group {} emitted after group {} ({} of {} groups out of order so far). Increase --buffer-seconds",
+                    group.time,
+                    latest_time,
+                    self.violations,
+                    self.groups
+                );
+            }
+            _ => self.latest_time = Some(group.time),
+        }
+    }
+
+    pub fn report(&self) {
+        if self.violations > 0 {
+            tracing::error!(
+                "ORDERING VIOLATIONS: {} of {} groups were out of order. Increase --buffer-seconds",
+                self.violations,
+                self.groups
+            );
+        } else {
+            tracing::info!(
+                "Ordering verified: all the {} groups were in order",
+                self.groups
+            );
+        }
+    }
+}
+
+/// Groups consecutive logs sharing the same time without buffering them.
+/// Meant for pre-sorted input: logs older than the last emitted group are dropped
+/// so the processors still receive monotonic times.
+#[pin_project]
+#[must_use = "streams do nothing unless polled"]
+#[derive(Debug)]
+pub struct ConsecutiveLogs<St>
+where
+    St: Stream<Item = LogResult>,
+{
+    #[pin]
+    stream: futures::stream::Fuse<St>,
+    current: Option<GroupedHttpLogs>,
+    error_breaker: ErrorBreaker,
+}
+
+impl<St> ConsecutiveLogs<St>
+where
+    St: Stream<Item = LogResult>,
+{
+    pub fn new(stream: St) -> Self {
+        Self {
+            stream: stream.fuse(),
+            current: None,
+            error_breaker: ErrorBreaker::default(),
+        }
+    }
+
+    /// Ends the stream, dropping the current group, once the breaker trips.
+    pub fn with_error_breaker(mut self, error_breaker: ErrorBreaker) -> Self {
+        self.error_breaker = error_breaker;
+        self
+    }
+}
+
+impl<St> Stream for ConsecutiveLogs<St>
+where
+    St: Stream<Item = LogResult>,
+{
+    type Item = GroupedHttpLogs;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let log = this.stream.as_mut().poll_next(cx);
+            if let Poll::Ready(Some(log)) = &log {
+                if this.error_breaker.record(log.is_err()) {
+                    return Poll::Ready(None);
+                }
+            }
+            match log {
+                Poll::Ready(Some(Ok(log))) => match this.current {
+                    Some(group) if log.time == group.time => group.logs.push(log),
+                    Some(group) if log.time < group.time => {
+                        tracing::warn!(
+                            "Dropping out of order log (time {} < {}), remove --no-buffer for unsorted input",
+                            log.time,
+                            group.time
+                        );
+                    }
+                    _ => {
+                        let group = GroupedHttpLogs {
+                            time: log.time,
+                            logs: vec![log],
+                        };
+                        if let Some(previous) = this.current.replace(group) {
+                            return Poll::Ready(Some(previous));
+                        }
+                    }
+                },
+                Poll::Ready(Some(Err(e))) => {
+                    // swallowing log parsing errors and log it
+                    tracing::error!("Error grouping logs: {}", e);
+                }
+                Poll::Ready(None) => return Poll::Ready(this.current.take()),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{reader::read_csv_async, test_utils};
-    use futures::StreamExt;
+    use crate::{
+        reader::{read_csv_async, read_many_async, ReaderOptions},
+        test_utils,
+    };
+    use futures::{stream, StreamExt};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
 
     fn assert_buffered_is_ordered(logs: &Vec<GroupedHttpLogs>) {
         let is_sorted = test_utils::is_sorted_by(logs.iter(), |a, b| a.time.partial_cmp(&b.time));
@@ -166,7 +380,7 @@ mod tests {
 "10.0.0.2","-","apache",1549573862,"GET /report HTTP/1.0",200,1307
 "10.0.0.2","-","apache",1549573863,"GET /report HTTP/1.0",200,1194"#
             .as_bytes();
-        let log_stream = read_csv_async(&mut input).await;
+        let log_stream = read_csv_async(&mut input, ReaderOptions::default()).await;
         let log_stream = BufferedLogs::new(log_stream, 2);
         let logs = log_stream.collect::<Vec<_>>().await;
         let log_dates = logs.iter().map(|x| x.time).collect::<Vec<_>>();
@@ -181,9 +395,198 @@ mod tests {
     async fn it_buffers_logs_and_returns_them_in_order_from_file() {
         let file_path = std::env::current_dir().unwrap().join("sample.csv");
         let mut input = tokio::fs::File::open(file_path).await.unwrap();
-        let log_stream = read_csv_async(&mut input).await;
+        let log_stream = read_csv_async(&mut input, ReaderOptions::default()).await;
         let log_stream = BufferedLogs::new(log_stream, 2);
         let logs = log_stream.collect::<Vec<_>>().await;
         assert_buffered_is_ordered(&logs);
     }
+
+    #[tokio::test]
+    async fn consecutive_logs_match_buffered_logs_on_sorted_input() {
+        let input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573859,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573860,"GET /api/help HTTP/1.0",200,1234
+"10.0.0.2","-","apache",1549573861,"GET /report HTTP/1.0",200,1136
+"10.0.0.5","-","apache",1549573863,"POST /report HTTP/1.0",500,1307
+"10.0.0.3","-","apache",1549573863,"GET /report HTTP/1.0",200,1194"#;
+
+        let mut buffered_input = input.as_bytes();
+        let log_stream = read_csv_async(&mut buffered_input, ReaderOptions::default()).await;
+        let buffered = BufferedLogs::new(log_stream, 2).collect::<Vec<_>>().await;
+
+        let mut consecutive_input = input.as_bytes();
+        let log_stream = read_csv_async(&mut consecutive_input, ReaderOptions::default()).await;
+        let consecutive = ConsecutiveLogs::new(log_stream).collect::<Vec<_>>().await;
+
+        assert_eq!(consecutive, buffered);
+    }
+
+    #[tokio::test]
+    async fn consecutive_logs_drop_out_of_order_logs() {
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573861,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573859,"GET /api/help HTTP/1.0",200,1234
+"10.0.0.2","-","apache",1549573862,"GET /report HTTP/1.0",200,1136"#
+            .as_bytes();
+        let log_stream = read_csv_async(&mut input, ReaderOptions::default()).await;
+        let logs = ConsecutiveLogs::new(log_stream).collect::<Vec<_>>().await;
+        let log_dates = logs.iter().map(|x| x.time).collect::<Vec<_>>();
+        assert_buffered_is_ordered(&logs);
+        assert_eq!(log_dates, vec![1549573860, 1549573861, 1549573862]);
+    }
+
+    #[tokio::test]
+    async fn it_buffers_logs_merged_from_many_sources_in_order() {
+        let mut first_input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573862,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573863,"GET /api/user HTTP/1.0",200,1234"#
+            .as_bytes();
+        let mut second_input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573861,"GET /api/help HTTP/1.0",200,1234
+"10.0.0.2","-","apache",1549573862,"GET /api/help HTTP/1.0",200,1234
+"10.0.0.2","-","apache",1549573864,"GET /api/help HTTP/1.0",200,1234"#
+            .as_bytes();
+
+        let log_stream = read_many_async(
+            vec![&mut first_input, &mut second_input],
+            ReaderOptions::default(),
+        )
+        .await;
+        let logs = BufferedLogs::new(log_stream, 2).collect::<Vec<_>>().await;
+        let log_dates = logs
+            .iter()
+            .map(|x| (x.time, x.logs.len()))
+            .collect::<Vec<_>>();
+
+        assert_buffered_is_ordered(&logs);
+        assert_eq!(
+            log_dates,
+            vec![
+                (1549573860, 1),
+                (1549573861, 1),
+                (1549573862, 2),
+                (1549573863, 1),
+                (1549573864, 1)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_splits_the_groups_over_the_max_len() {
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573861,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573861,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573861,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573861,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573861,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573862,"GET /api/user HTTP/1.0",200,1234"#
+            .as_bytes();
+        let log_stream = read_csv_async(&mut input, ReaderOptions::default()).await;
+        let logs = BufferedLogs::new(log_stream, 2)
+            .with_max_group_len(Some(2))
+            .collect::<Vec<_>>()
+            .await;
+        let log_dates = logs
+            .iter()
+            .map(|x| (x.time, x.logs.len()))
+            .collect::<Vec<_>>();
+
+        assert_buffered_is_ordered(&logs);
+        assert_eq!(
+            log_dates,
+            vec![
+                (1549573860, 1),
+                (1549573861, 2),
+                (1549573861, 2),
+                (1549573861, 1),
+                (1549573862, 1)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_buffers_logs_with_time_zero() {
+        let read = Arc::new(AtomicUsize::new(0));
+        let log_stream = stream::iter([0, 0, 3, 4, 5])
+            .map(|time| Ok(test_utils::build_test_http_log(time, None)))
+            .inspect({
+                let read = read.clone();
+                move |_| {
+                    read.fetch_add(1, Ordering::SeqCst);
+                }
+            });
+        let mut logs = BufferedLogs::new(log_stream, 2);
+
+        let first = logs.next().await.unwrap();
+
+        // the group is emitted as soon as the buffer is full, not at the end of the stream
+        assert_eq!((first.time, first.logs.len()), (0, 2));
+        assert_eq!(read.load(Ordering::SeqCst), 3);
+        let log_dates = logs.map(|x| x.time).collect::<Vec<_>>().await;
+        assert_eq!(log_dates, vec![3, 4, 5]);
+    }
+
+    fn logs_with_errors(errors: &[bool]) -> impl Stream<Item = LogResult> {
+        let logs = errors
+            .iter()
+            .enumerate()
+            .map(|(i, is_error)| match is_error {
+                true => Err(anyhow::anyhow!("Invalid record")),
+                false => Ok(test_utils::build_test_http_log(i, None)),
+            })
+            .collect::<Vec<_>>();
+        stream::iter(logs)
+    }
+
+    #[tokio::test]
+    async fn a_run_of_errors_trips_the_breaker() {
+        // a good log followed by garbage
+        let errors = [[false].as_slice(), &[true; 10]].concat();
+
+        let buffered_breaker = ErrorBreaker::new(NonZeroUsize::new(5));
+        let buffered = BufferedLogs::new(logs_with_errors(&errors), 2)
+            .with_error_breaker(buffered_breaker.clone())
+            .collect::<Vec<_>>()
+            .await;
+        let consecutive_breaker = ErrorBreaker::new(NonZeroUsize::new(5));
+        let consecutive = ConsecutiveLogs::new(logs_with_errors(&errors))
+            .with_error_breaker(consecutive_breaker.clone())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(buffered.is_empty());
+        assert!(consecutive.is_empty());
+        assert!(buffered_breaker.check().is_err());
+        assert!(consecutive_breaker.check().is_err());
+    }
+
+    #[tokio::test]
+    async fn scattered_errors_do_not_trip_the_breaker() {
+        let errors = (0..100).map(|i| i % 5 != 0).collect::<Vec<_>>();
+
+        let buffered_breaker = ErrorBreaker::new(NonZeroUsize::new(5));
+        let buffered = BufferedLogs::new(logs_with_errors(&errors), 2)
+            .with_error_breaker(buffered_breaker.clone())
+            .collect::<Vec<_>>()
+            .await;
+        let consecutive_breaker = ErrorBreaker::new(NonZeroUsize::new(5));
+        let consecutive = ConsecutiveLogs::new(logs_with_errors(&errors))
+            .with_error_breaker(consecutive_breaker.clone())
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(buffered.len(), 20);
+        assert_eq!(consecutive.len(), 20);
+        assert!(buffered_breaker.check().is_ok());
+        assert!(consecutive_breaker.check().is_ok());
+    }
 }
@@ -0,0 +1,521 @@
+use crate::{
+    buffered_logs::LogResult,
+    processors::Processor,
+    reader::{read_csv_with_meta_async, AsyncReader, ReaderOptions, UTF8_BOM},
+};
+use futures::{stream, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    ffi::OsString,
+    fmt,
+    io::{Read, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader};
+
+/// Real time between two saves of the checkpoint while processing.
+pub const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Bytes at the beginning of the input hashed to tell whether it's still the same input.
+const HASHED_BYTES: u64 = 64 * 1024;
+
+/// State of a processor in a checkpoint.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ProcessorState {
+    name: String,
+    // `None` for the processors which can't be resumed
+    state: Option<serde_json::Value>,
+}
+
+/// What's persisted to resume a batch run over a file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Checkpoint {
+    /// Bytes of the input whose logs were processed.
+    offset: u64,
+    /// SHA-256 of the first `hashed_len` bytes of the input.
+    input_hash: String,
+    hashed_len: u64,
+    /// State of every processor, in the order they run.
+    processors: Vec<ProcessorState>,
+}
+
+impl Checkpoint {
+    fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        match std::fs::read(path) {
+            Ok(checkpoint) => {
+                Ok(Some(serde_json::from_slice(&checkpoint).map_err(|e| {
+                    anyhow::anyhow!("Invalid checkpoint {:?}: {}", path, e)
+                })?))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(anyhow::anyhow!(
+                "Can't read the checkpoint {:?}: {}",
+                path,
+                e
+            )),
+        }
+    }
+
+    /// Writes the checkpoint to a temporary file first, so a run killed while saving
+    /// doesn't leave a broken checkpoint behind.
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut tmp_path = OsString::from(path);
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(self)?)
+            .and_then(|_| std::fs::rename(&tmp_path, path))
+            .map_err(|e| anyhow::anyhow!("Can't write the checkpoint {:?}: {}", path, e))
+    }
+}
+
+/// SHA-256 of the first `len` bytes of a file, in hex.
+fn hash_head(path: &Path, len: u64) -> anyhow::Result<String> {
+    let mut head = Vec::new();
+    std::fs::File::open(path)?
+        .take(len)
+        .read_to_end(&mut head)?;
+    if (head.len() as u64) < len {
+        anyhow::bail!("The input is shorter than its checkpoint");
+    }
+    let digest = Sha256::digest(&head);
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Where the reading of the input is, in bytes of the input.
+#[derive(Debug, Default)]
+struct Progress {
+    // bytes of the last record read
+    last_record: Option<Range<u64>>,
+    is_input_done: bool,
+    // offset of the logs grouped and waiting to be processed
+    grouped: u64,
+    // offset of the logs already processed
+    processed: u64,
+    last_saved: Option<Instant>,
+}
+
+struct Inner {
+    path: PathBuf,
+    input: PathBuf,
+    interval: Duration,
+    // offset in the input of the first byte read, the header aside
+    base: u64,
+    restored: Option<Vec<ProcessorState>>,
+    progress: Mutex<Progress>,
+}
+
+/// Saves the progress of a batch run over a CSV file every interval, so a later run over
+/// the same file resumes from it instead of starting over.
+///
+/// The offsets only make sense for sorted input processed as it comes (`--no-buffer`):
+/// then the processed logs are always the beginning of the file.
+#[derive(Clone)]
+pub struct Checkpointer(Arc<Inner>);
+
+impl fmt::Debug for Checkpointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Checkpointer").field(&self.0.path).finish()
+    }
+}
+
+/// Checkpointers are only equal to their clones.
+impl PartialEq for Checkpointer {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Checkpointer {}
+
+impl Checkpointer {
+    /// Opens the input, resuming after the logs already processed if there's a checkpoint
+    /// at `path`. The header row, if any, is read again so the records can be parsed.
+    pub async fn open(
+        path: &Path,
+        input: &Path,
+        has_header_row: bool,
+        interval: Duration,
+    ) -> anyhow::Result<(Self, Box<AsyncReader>)> {
+        let checkpoint = Checkpoint::load(path)?;
+        let mut file = tokio::fs::File::open(input).await?;
+        let mut header = Vec::new();
+        if has_header_row {
+            BufReader::new(&mut file)
+                .read_until(b'\n', &mut header)
+                .await?;
+        }
+        // the BOM isn't counted in the offsets of the records
+        if let Some(stripped) = header.strip_prefix(UTF8_BOM) {
+            header = stripped.to_vec();
+        }
+
+        let (base, reader): (u64, Box<AsyncReader>) = match &checkpoint {
+            Some(checkpoint) => {
+                let len = file.metadata().await?.len();
+                let is_same_input = checkpoint.offset <= len
+                    && hash_head(input, checkpoint.hashed_len)
+                        .is_ok_and(|hash| hash == checkpoint.input_hash);
+                if !is_same_input {
+                    anyhow::bail!(
+                        "The checkpoint {:?} was saved for another input, remove it to start over",
+                        path
+                    );
+                }
+                file.seek(SeekFrom::Start(checkpoint.offset)).await?;
+                let base = checkpoint.offset - header.len() as u64;
+                (
+                    base,
+                    Box::new(AsyncReadExt::chain(std::io::Cursor::new(header), file)),
+                )
+            }
+            None => {
+                file.seek(SeekFrom::Start(0)).await?;
+                let mut bom = [0; 3];
+                let bom_len = match file.read_exact(&mut bom).await {
+                    Ok(_) if bom[..] == *UTF8_BOM => UTF8_BOM.len() as u64,
+                    _ => 0,
+                };
+                file.seek(SeekFrom::Start(0)).await?;
+                (bom_len, Box::new(file))
+            }
+        };
+        let offset = checkpoint
+            .as_ref()
+            .map_or(0, |checkpoint| checkpoint.offset);
+        let checkpointer = Self(Arc::new(Inner {
+            path: path.to_path_buf(),
+            input: input.to_path_buf(),
+            interval,
+            base,
+            restored: checkpoint.map(|checkpoint| checkpoint.processors),
+            progress: Mutex::new(Progress {
+                grouped: offset,
+                processed: offset,
+                ..Progress::default()
+            }),
+        }));
+        Ok((checkpointer, reader))
+    }
+
+    /// Restores the state of the processors saved in the checkpoint, if resuming.
+    /// The processors which can't save their state are refused, as they'd silently start
+    /// over halfway through the input when resuming.
+    pub fn restore(&self, processors: &mut [Box<dyn Processor>]) -> anyhow::Result<()> {
+        let mut non_resumable = Vec::new();
+        for processor in processors.iter() {
+            if processor.save_state()?.is_none() {
+                non_resumable.push(processor.name());
+            }
+        }
+        if !non_resumable.is_empty() {
+            anyhow::bail!(
+                "--checkpoint can't resume {}, run without them or without --checkpoint",
+                non_resumable.join(", ")
+            );
+        }
+        let saved = match &self.0.restored {
+            Some(saved) => saved,
+            None => return Ok(()),
+        };
+        let is_same_pipeline = saved.len() == processors.len()
+            && saved
+                .iter()
+                .zip(processors.iter())
+                // the processors disabled after panicking have no name
+                .all(|(saved, processor)| saved.name.is_empty() || saved.name == processor.name());
+        if !is_same_pipeline {
+            anyhow::bail!(
+                "The processors changed since the checkpoint {:?} was saved, remove it to start over",
+                self.0.path
+            );
+        }
+        for (saved, processor) in saved.iter().zip(processors.iter_mut()) {
+            if let Some(state) = &saved.state {
+                processor.restore_state(state.clone()).map_err(|e| {
+                    anyhow::anyhow!("Can't restore {} from the checkpoint: {}", saved.name, e)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the logs of the input opened by [`Checkpointer::open`], keeping track of the
+    /// bytes read.
+    pub async fn read_logs<'a>(
+        &self,
+        reader: &'a mut AsyncReader,
+        options: ReaderOptions,
+    ) -> impl Stream<Item = LogResult> + 'a {
+        let records = read_csv_with_meta_async(reader, options).await;
+        let on_record = self.clone();
+        let on_end = self.clone();
+        records
+            .map(move |record| {
+                record.map(|(log, meta)| {
+                    let base = on_record.0.base;
+                    on_record.progress().last_record =
+                        Some(base + meta.bytes.start..base + meta.bytes.end);
+                    log
+                })
+            })
+            .chain(stream::poll_fn(move |_| {
+                on_end.progress().is_input_done = true;
+                std::task::Poll::Ready(None)
+            }))
+    }
+
+    fn progress(&self) -> std::sync::MutexGuard<'_, Progress> {
+        self.0.progress.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Called as a group is taken from the input. The last record read is the first one
+    /// of the next group, so the input is grouped up to its start.
+    pub fn group_taken(&self) {
+        let mut progress = self.progress();
+        let grouped = match (&progress.last_record, progress.is_input_done) {
+            (Some(record), true) => record.end,
+            (Some(record), false) => record.start,
+            (None, _) => progress.grouped,
+        };
+        progress.grouped = grouped;
+    }
+
+    /// Called once the group is processed, saving the checkpoint if it's due.
+    pub fn group_processed(&self, processors: &[Option<Box<dyn Processor>>]) {
+        let is_due = {
+            let mut progress = self.progress();
+            progress.processed = progress.grouped;
+            progress
+                .last_saved
+                .is_none_or(|last_saved| last_saved.elapsed() >= self.0.interval)
+        };
+        if is_due {
+            self.save(processors);
+        }
+    }
+
+    /// Saves the state of the processors and the offset of the logs they processed.
+    pub fn save(&self, processors: &[Option<Box<dyn Processor>>]) {
+        let offset = self.progress().processed;
+        let checkpoint = || -> anyhow::Result<Checkpoint> {
+            let hashed_len = offset.min(HASHED_BYTES);
+            Ok(Checkpoint {
+                offset,
+                input_hash: hash_head(&self.0.input, hashed_len)?,
+                hashed_len,
+                processors: processors
+                    .iter()
+                    .map(|slot| match slot {
+                        Some(processor) => Ok(ProcessorState {
+                            name: processor.name().to_string(),
+                            state: processor.save_state()?,
+                        }),
+                        // the processors disabled after panicking start over
+                        None => Ok(ProcessorState {
+                            name: String::new(),
+                            state: None,
+                        }),
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            })
+        };
+        match checkpoint().and_then(|checkpoint| checkpoint.save(&self.0.path)) {
+            Ok(()) => self.progress().last_saved = Some(Instant::now()),
+            Err(e) => tracing::error!("Can't save the checkpoint: {:?}", e),
+        }
+    }
+
+    /// Removes the checkpoint once the whole input is processed.
+    pub fn remove(&self) {
+        match std::fs::remove_file(&self.0.path) {
+            Ok(()) => (),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => tracing::error!("Can't remove the checkpoint {:?}: {}", self.0.path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffered_logs::GroupedHttpLogs,
+        process::{process_log_stream, Completion, ProcessOptions},
+    };
+    use std::io::Write;
+
+    /// Counts the logs and bytes, closing the output once it has seen `stop_after` logs.
+    struct Totals {
+        totals: Arc<Mutex<(usize, usize)>>,
+        stop_after: Option<usize>,
+    }
+
+    impl Processor for Totals {
+        fn process(
+            &mut self,
+            log_group: &GroupedHttpLogs,
+            _writer: &mut dyn Write,
+        ) -> anyhow::Result<()> {
+            let mut totals = self.totals.lock().unwrap();
+            totals.0 += log_group.logs.len();
+            totals.1 += log_group.logs.iter().map(|log| log.bytes).sum::<usize>();
+            match self.stop_after {
+                Some(stop_after) if totals.0 >= stop_after => {
+                    Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe).into())
+                }
+                _ => Ok(()),
+            }
+        }
+
+        fn save_state(&self) -> anyhow::Result<Option<serde_json::Value>> {
+            Ok(Some(serde_json::to_value(*self.totals.lock().unwrap())?))
+        }
+
+        fn restore_state(&mut self, state: serde_json::Value) -> anyhow::Result<()> {
+            *self.totals.lock().unwrap() = serde_json::from_value(state)?;
+            Ok(())
+        }
+    }
+
+    async fn run(
+        checkpoint: &Path,
+        input: &Path,
+        stop_after: Option<usize>,
+    ) -> (Completion, (usize, usize)) {
+        let (checkpointer, mut reader) =
+            Checkpointer::open(checkpoint, input, true, Duration::ZERO)
+                .await
+                .unwrap();
+        let totals = Arc::new(Mutex::new((0, 0)));
+        let mut processors: Vec<Box<dyn Processor>> = vec![Box::new(Totals {
+            totals: totals.clone(),
+            stop_after,
+        })];
+        checkpointer.restore(&mut processors).unwrap();
+        let options = ProcessOptions {
+            no_buffer: true,
+            checkpoint: Some(checkpointer.clone()),
+            ..ProcessOptions::default()
+        };
+        let log_stream = checkpointer
+            .read_logs(reader.as_mut(), options.reader.clone())
+            .await;
+        let completion = process_log_stream(log_stream, processors, Vec::new(), &options)
+            .await
+            .unwrap();
+        let totals = *totals.lock().unwrap();
+        (completion, totals)
+    }
+
+    #[tokio::test]
+    async fn resuming_gives_the_same_totals_as_a_single_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("access.csv");
+        let mut csv = String::from(
+            "\u{feff}\"remotehost\",\"rfc931\",\"authuser\",\"date\",\"request\",\"status\",\"bytes\"\n",
+        );
+        for i in 0..300 {
+            csv.push_str(&format!(
+                "\"10.0.0.{}\",\"-\",\"apache\",{},\"GET /api/user HTTP/1.0\",200,{}\n",
+                i % 7,
+                1549573860 + i / 3,
+                100 + i
+            ));
+        }
+        std::fs::write(&input, csv).unwrap();
+
+        let single_checkpoint = dir.path().join("single.json");
+        let (completion, single_run) = run(&single_checkpoint, &input, None).await;
+        assert_eq!(completion, Completion::Done);
+        assert_eq!(single_run.0, 300);
+        // the checkpoint of a finished run is removed
+        assert!(!single_checkpoint.exists());
+
+        // interrupted mid-file, as a killed job would be
+        let checkpoint = dir.path().join("checkpoint.json");
+        let (completion, interrupted) = run(&checkpoint, &input, Some(100)).await;
+        assert_eq!(completion, Completion::OutputClosed);
+        assert!(interrupted.0 < 300);
+        assert!(checkpoint.exists());
+
+        let (completion, resumed) = run(&checkpoint, &input, None).await;
+        assert_eq!(completion, Completion::Done);
+        assert_eq!(resumed, single_run);
+        assert!(!checkpoint.exists());
+    }
+
+    #[tokio::test]
+    async fn processors_which_cannot_be_resumed_are_refused() {
+        struct Stateless;
+
+        impl Processor for Stateless {
+            fn process(
+                &mut self,
+                _log_group: &GroupedHttpLogs,
+                _writer: &mut dyn Write,
+            ) -> anyhow::Result<()> {
+                Ok(())
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("access.csv");
+        std::fs::write(&input, "\"remotehost\",\"rfc931\"\n\"10.0.0.2\",\"-\"\n").unwrap();
+        let (checkpointer, _) = Checkpointer::open(
+            &dir.path().join("checkpoint.json"),
+            &input,
+            true,
+            Duration::ZERO,
+        )
+        .await
+        .unwrap();
+        let mut processors: Vec<Box<dyn Processor>> = vec![
+            Box::new(Totals {
+                totals: Arc::new(Mutex::new((0, 0))),
+                stop_after: None,
+            }),
+            Box::new(Stateless),
+        ];
+
+        let error = checkpointer.restore(&mut processors).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "--checkpoint can't resume Stateless, run without them or without --checkpoint"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_checkpoints_of_other_inputs_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("access.csv");
+        let checkpoint = dir.path().join("checkpoint.json");
+        let header = "\"remotehost\",\"rfc931\"\n";
+        std::fs::write(&input, format!("{}\"10.0.0.2\",\"-\"\n", header)).unwrap();
+        let offset = header.len() as u64;
+        Checkpoint {
+            offset,
+            input_hash: hash_head(&input, offset).unwrap(),
+            hashed_len: offset,
+            processors: Vec::new(),
+        }
+        .save(&checkpoint)
+        .unwrap();
+        assert!(
+            Checkpointer::open(&checkpoint, &input, true, Duration::ZERO)
+                .await
+                .is_ok()
+        );
+
+        std::fs::write(&input, "\"remotehost\",\"authuser\"\n\"10.0.0.2\",\"-\"\n").unwrap();
+
+        assert!(
+            Checkpointer::open(&checkpoint, &input, true, Duration::ZERO)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,209 @@
+use crate::reader::{parse_byte_count, skip_bom, AsyncReader, HttpLog, LogRequest, ReaderOptions};
+use futures::{Stream, StreamExt};
+use time::{Date, Month, PrimitiveDateTime, Time, UtcOffset};
+use tokio::io::AsyncBufReadExt;
+use tokio_stream::wrappers::LinesStream;
+use tracing::instrument;
+
+/// Value of the fields which weren't logged.
+const MISSING_FIELD: &str = "-";
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Splits a line into its fields: words, `"quoted strings"` (with `\"` escapes) and
+/// `[bracketed dates]`.
+fn split_fields(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = line.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        let mut field = String::new();
+        match c {
+            ' ' | '\t' => continue,
+            '"' => loop {
+                match chars.next() {
+                    Some('\\') => field.extend(chars.next()),
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => anyhow::bail!("Unclosed quotes"),
+                }
+            },
+            '[' => loop {
+                match chars.next() {
+                    Some(']') => break,
+                    Some(c) => field.push(c),
+                    None => anyhow::bail!("Unclosed brackets"),
+                }
+            },
+            c => {
+                field.push(c);
+                while let Some(c) = chars.next_if(|c| *c != ' ' && *c != '\t') {
+                    field.push(c);
+                }
+            }
+        }
+        fields.push(field);
+    }
+    Ok(fields)
+}
+
+/// Epoch time of a date like `10/Oct/2000:13:55:36 -0700`.
+fn parse_date(date: &str) -> anyhow::Result<usize> {
+    let invalid = || anyhow::anyhow!("Invalid date: {}", date);
+    let (date_time, offset) = date.split_once(' ').ok_or_else(invalid)?;
+    let parts = date_time.split(['/', ':']).collect::<Vec<_>>();
+    let [day, month, year, hour, minute, second] = parts[..] else {
+        return Err(invalid());
+    };
+    let month = MONTHS
+        .iter()
+        .position(|m| *m == month)
+        .ok_or_else(invalid)?;
+    let number = |n: &str| n.parse::<u8>().map_err(|_| invalid());
+    let date = Date::from_calendar_date(
+        year.parse().map_err(|_| invalid())?,
+        Month::try_from(month as u8 + 1)?,
+        number(day)?,
+    )?;
+    let time = Time::from_hms(number(hour)?, number(minute)?, number(second)?)?;
+    let offset = match (offset.get(..1), offset.get(1..3), offset.get(3..)) {
+        (Some(sign), Some(hours), Some(minutes)) => {
+            let (hours, minutes) = (hours.parse::<i8>()?, minutes.parse::<i8>()?);
+            match sign {
+                "+" => UtcOffset::from_hms(hours, minutes, 0)?,
+                "-" => UtcOffset::from_hms(-hours, -minutes, 0)?,
+                _ => return Err(invalid()),
+            }
+        }
+        _ => return Err(invalid()),
+    };
+    let timestamp = PrimitiveDateTime::new(date, time)
+        .assume_offset(offset)
+        .unix_timestamp();
+    usize::try_from(timestamp).map_err(|_| invalid())
+}
+
+/// An optional field of the combined format, `None` when missing or not logged.
+fn optional_field(field: Option<&String>) -> Option<String> {
+    field.filter(|f| *f != MISSING_FIELD).cloned()
+}
+
+/// Parses a Common Log Format line, taking the referrer and the user agent of the
+/// Combined Log Format when they follow the byte count.
+fn parse_line(line: &str, options: &ReaderOptions) -> anyhow::Result<HttpLog> {
+    let fields = split_fields(line)?;
+    let [remote_host, rfc931, auth_user, date, request, status, bytes] = match fields.get(..7) {
+        Some([a, b, c, d, e, f, g]) => [a, b, c, d, e, f, g],
+        _ => anyhow::bail!("Expected at least 7 fields, found {}", fields.len()),
+    };
+    let mut log = HttpLog {
+        remote_host: remote_host.clone(),
+        rfc931: rfc931.clone(),
+        auth_user: auth_user.clone(),
+        time: options.log_time(Some(parse_date(date)?))?,
+        request: LogRequest::from_str_with_separator(request, &options.request_separator)?,
+        status: status.parse()?,
+        bytes: parse_byte_count(bytes)?,
+        response_time_ms: None,
+        referrer: optional_field(fields.get(7)),
+        user_agent: optional_field(fields.get(8)),
+    };
+    options.rewrite(&mut log);
+    Ok(log)
+}
+
+/// Reads Common (or Combined) Log Format lines asynchronously. Lines which aren't valid
+/// logs are errors, the rest of them are still read.
+#[instrument(skip(reader))]
+pub async fn read_clf_async(
+    reader: &mut AsyncReader,
+    options: ReaderOptions,
+) -> impl Stream<Item = anyhow::Result<HttpLog>> + '_ {
+    LinesStream::new(skip_bom(reader).await.lines()).filter_map(move |line| {
+        let log = match line {
+            Ok(line) if line.trim().is_empty() => None,
+            Ok(line) => Some(
+                parse_line(&line, &options)
+                    .map_err(|e| anyhow::anyhow!("Invalid log line {:?}: {}", line, e)),
+            ),
+            Err(e) => Some(Err(e.into())),
+        };
+        futures::future::ready(log)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(input: &'static str) -> Vec<anyhow::Result<HttpLog>> {
+        let mut input = input.as_bytes();
+        read_clf_async(&mut input, ReaderOptions::default())
+            .await
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn reads_combined_lines() {
+        let logs = read(
+            r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326 "http://www.example.com/start.html" "Mozilla/4.08 [en] (Win98; I ;Nav)""#,
+        )
+        .await;
+
+        let log = logs[0].as_ref().unwrap();
+        assert_eq!(log.remote_host, "127.0.0.1");
+        assert_eq!(log.auth_user, "frank");
+        assert_eq!(log.time, 971211336);
+        assert_eq!(log.request.path, "/apache_pb.gif");
+        assert_eq!(log.request.section, "/apache_pb.gif");
+        assert_eq!((log.status, log.bytes), (200, 2326));
+        assert_eq!(
+            log.referrer.as_deref(),
+            Some("http://www.example.com/start.html")
+        );
+        assert_eq!(
+            log.user_agent.as_deref(),
+            Some("Mozilla/4.08 [en] (Win98; I ;Nav)")
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_common_lines_without_referrer_nor_user_agent() {
+        let logs = read(
+            r#"10.0.0.2 - apache [07/Feb/2019:21:11:00 +0000] "POST /api/user HTTP/1.1" 304 -
+
+10.0.0.4 - - [07/Feb/2019:21:11:01 +0000] "GET /report HTTP/1.1" 200 1136 "-" "-""#,
+        )
+        .await;
+
+        let logs = logs
+            .into_iter()
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs[0].time, 1549573860);
+        assert_eq!(logs[0].request.verb, "POST");
+        assert_eq!(logs[0].bytes, 0);
+        assert_eq!(logs[0].referrer, None);
+        assert_eq!(logs[0].user_agent, None);
+        assert_eq!(logs[1].referrer, None);
+        assert_eq!(logs[1].user_agent, None);
+    }
+
+    #[tokio::test]
+    async fn malformed_lines_are_errors() {
+        let logs = read(
+            r#"10.0.0.2 - apache [07/Feb/2019:21:11:00 +0000] "GET /api/user HTTP/1.1
+10.0.0.2 - apache [07/Foo/2019:21:11:00 +0000] "GET /api/user HTTP/1.1" 200 100
+10.0.0.2 - apache [07/Feb/2019:21:11:00 +0000] "GET /api/user HTTP/1.1" 200
+10.0.0.2 - apache [07/Feb/2019:21:11:00 +0000] "GET /api/user HTTP/1.1" 200 100"#,
+        )
+        .await;
+
+        assert_eq!(logs.len(), 4);
+        assert!(logs[..3].iter().all(Result::is_err));
+        assert!(logs[3].is_ok());
+    }
+}
@@ -0,0 +1,242 @@
+use crate::{buffered_logs::LogResult, processors::GroupBy, reader::HttpLog};
+use futures::{Stream, StreamExt};
+use std::{collections::BTreeMap, fmt};
+use tracing::instrument;
+
+/// Totals of the logs sharing the same key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyTotals {
+    pub hits: u64,
+    pub bytes: u128,
+    /// Requests with a 4xx or 5xx status.
+    pub errors: u64,
+}
+
+impl KeyTotals {
+    fn add(&mut self, log: &HttpLog) {
+        self.hits += 1;
+        self.bytes += log.bytes as u128;
+        if (400..600).contains(&log.status) {
+            self.errors += 1;
+        }
+    }
+
+    /// Percentage of the requests which are errors.
+    pub fn error_rate(&self) -> f64 {
+        match self.hits {
+            0 => 0.0,
+            hits => self.errors as f64 * 100.0 / hits as f64,
+        }
+    }
+}
+
+/// Whether a key is in both inputs or only in one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyChange {
+    Kept,
+    /// Only in the second input.
+    New,
+    /// Only in the first input.
+    Removed,
+}
+
+/// Totals of a key in both inputs, zero where it's missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDelta {
+    pub key: String,
+    pub change: KeyChange,
+    pub before: KeyTotals,
+    pub after: KeyTotals,
+}
+
+impl KeyDelta {
+    pub fn hits(&self) -> i128 {
+        self.after.hits as i128 - self.before.hits as i128
+    }
+
+    pub fn bytes(&self) -> i128 {
+        self.after.bytes as i128 - self.before.bytes as i128
+    }
+
+    /// Difference of the error rates, in percentage points.
+    pub fn error_rate(&self) -> f64 {
+        self.after.error_rate() - self.before.error_rate()
+    }
+}
+
+impl fmt::Display for KeyDelta {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.key)?;
+        match self.change {
+            KeyChange::Kept => (),
+            KeyChange::New => write!(f, " {}", console::style("(new)").bold().green())?,
+            KeyChange::Removed => write!(f, " {}", console::style("(removed)").bold().red())?,
+        }
+        write!(
+            f,
+            ": Hits: {} -> {} ({:+}), Bytes: {} -> {} ({:+}), Error Rate: {:.2}% -> {:.2}% ({:+.2})",
+            self.before.hits,
+            self.after.hits,
+            self.hits(),
+            self.before.bytes,
+            self.after.bytes,
+            self.bytes(),
+            self.before.error_rate(),
+            self.after.error_rate(),
+            self.error_rate()
+        )
+    }
+}
+
+/// Per key deltas between the stats of two inputs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompareReport {
+    pub group_by: GroupBy,
+    /// Ordered by key.
+    pub deltas: Vec<KeyDelta>,
+}
+
+impl CompareReport {
+    /// Joins the totals of both inputs on their keys.
+    pub fn new(
+        group_by: GroupBy,
+        before: BTreeMap<String, KeyTotals>,
+        mut after: BTreeMap<String, KeyTotals>,
+    ) -> Self {
+        let mut deltas = before
+            .into_iter()
+            .map(|(key, before)| {
+                let (change, after) = match after.remove(&key) {
+                    Some(after) => (KeyChange::Kept, after),
+                    None => (KeyChange::Removed, KeyTotals::default()),
+                };
+                KeyDelta {
+                    key,
+                    change,
+                    before,
+                    after,
+                }
+            })
+            .collect::<Vec<_>>();
+        deltas.extend(after.into_iter().map(|(key, after)| KeyDelta {
+            key,
+            change: KeyChange::New,
+            before: KeyTotals::default(),
+            after,
+        }));
+        deltas.sort_by(|a, b| a.key.cmp(&b.key));
+        Self { group_by, deltas }
+    }
+}
+
+impl fmt::Display for CompareReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}",
+            console::style(format!(
+                "\nCOMPARE ({}, first -> second):\n********",
+                self.group_by.label()
+            ))
+            .bold()
+            .cyan()
+        )?;
+        for delta in &self.deltas {
+            writeln!(f, "{}", delta)?;
+        }
+        Ok(())
+    }
+}
+
+/// Totals of every key of a log stream.
+#[instrument(skip(log_stream))]
+pub async fn aggregate(
+    log_stream: impl Stream<Item = LogResult>,
+    group_by: GroupBy,
+) -> BTreeMap<String, KeyTotals> {
+    log_stream
+        .fold(BTreeMap::new(), |mut totals, log| async move {
+            match log {
+                Ok(log) => totals
+                    .entry(group_by.key(&log))
+                    .or_insert_with(KeyTotals::default)
+                    .add(&log),
+                Err(e) => tracing::error!("Error comparing the logs: {}", e),
+            }
+            totals
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{read_csv_async, ReaderOptions};
+
+    async fn aggregate_csv(rows: &str) -> BTreeMap<String, KeyTotals> {
+        let input = format!(
+            "\"remotehost\",\"rfc931\",\"authuser\",\"date\",\"request\",\"status\",\"bytes\"\n{}",
+            rows
+        );
+        let mut input = std::io::Cursor::new(input);
+        aggregate(
+            read_csv_async(&mut input, ReaderOptions::default()).await,
+            GroupBy::Section,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn computes_the_deltas_of_every_section() {
+        let before = aggregate_csv(
+            r#""10.0.0.2","-","apache",1,"GET /api/user HTTP/1.0",200,100
+"10.0.0.2","-","apache",1,"GET /api/help HTTP/1.0",500,100
+"10.0.0.2","-","apache",2,"GET /old/page HTTP/1.0",200,300"#,
+        )
+        .await;
+        let after = aggregate_csv(
+            r#""10.0.0.2","-","apache",1,"GET /api/user HTTP/1.0",200,100
+"10.0.0.2","-","apache",1,"GET /api/user HTTP/1.0",200,100
+"10.0.0.2","-","apache",1,"GET /api/user HTTP/1.0",404,100
+"10.0.0.2","-","apache",2,"GET /api/help HTTP/1.0",200,200
+"10.0.0.2","-","apache",2,"POST /new HTTP/1.0",201,50"#,
+        )
+        .await;
+
+        let report = CompareReport::new(GroupBy::Section, before, after);
+
+        let sections = report
+            .deltas
+            .iter()
+            .map(|delta| (delta.key.as_str(), delta.change))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            vec![
+                ("/api", KeyChange::Kept),
+                ("/new", KeyChange::New),
+                ("/old", KeyChange::Removed)
+            ]
+        );
+        let api = &report.deltas[0];
+        assert_eq!((api.hits(), api.bytes()), (2, 300));
+        assert_eq!(
+            (api.before.error_rate(), api.after.error_rate()),
+            (50.0, 25.0)
+        );
+        assert_eq!(api.error_rate(), -25.0);
+        let new = &report.deltas[1];
+        assert_eq!((new.hits(), new.bytes()), (1, 50));
+        assert_eq!(new.before, KeyTotals::default());
+        let removed = &report.deltas[2];
+        assert_eq!((removed.hits(), removed.bytes()), (-1, -300));
+        assert_eq!(removed.after, KeyTotals::default());
+
+        let output = console::strip_ansi_codes(&report.to_string()).to_string();
+        assert!(output.contains(
+            "/api: Hits: 2 -> 4 (+2), Bytes: 200 -> 500 (+300), Error Rate: 50.00% -> 25.00% (-25.00)"
+        ));
+        assert!(output.contains("/new (new): Hits: 0 -> 1 (+1)"));
+        assert!(output.contains("/old (removed): Hits: 1 -> 0 (-1)"));
+    }
+}
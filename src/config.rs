@@ -0,0 +1,300 @@
+use crate::Cli;
+use serde::Deserialize;
+use std::{num::NonZeroUsize, path::Path, str::FromStr};
+
+/// Defaults for the CLI options loaded from a TOML file.
+/// Keys are named after the CLI flags (e.g. `buffer-seconds = 5`). The inputs and the flags
+/// choosing what the run does instead of processing them (`--watch-dir`, `--journald`,
+/// `--count-only`, `--compare`...) have no key. The boolean keys can only turn an option on,
+/// there's no flag to turn it off from the CLI.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub buffer_seconds: Option<usize>,
+    pub max_group_len: Option<usize>,
+    pub no_buffer: Option<bool>,
+    pub verify_ordering: Option<bool>,
+    pub profile: Option<bool>,
+    pub max_runtime: Option<String>,
+    pub max_consecutive_errors: Option<NonZeroUsize>,
+    pub checkpoint: Option<std::path::PathBuf>,
+    pub tee: Option<std::path::PathBuf>,
+    pub tail_lines: Option<usize>,
+    pub journald_unit: Option<String>,
+    pub journald_since: Option<String>,
+    pub resolve_dns: Option<bool>,
+    pub brotli: Option<bool>,
+    pub format: Option<String>,
+    pub header: Option<String>,
+    pub epoch_offset: Option<i64>,
+    pub time_fallback: Option<String>,
+    pub time_column: Option<String>,
+    pub normalize_paths: Option<bool>,
+    pub section: Option<String>,
+    pub unknown_section: Option<String>,
+    pub request_sep: Option<String>,
+    pub input_encoding: Option<String>,
+    pub only_status: Option<String>,
+    pub anonymize: Option<String>,
+    pub anonymize_salt: Option<String>,
+    pub max_record_bytes: Option<usize>,
+    pub alert_threshold: Option<usize>,
+    pub alert_tier: Option<Vec<String>>,
+    pub alert_window: Option<usize>,
+    pub alert_cooldown: Option<usize>,
+    pub align_alert_windows: Option<bool>,
+    pub explain_alert: Option<bool>,
+    pub suppress_from: Option<Vec<usize>>,
+    pub suppress_to: Option<Vec<usize>>,
+    pub alert_stream: Option<String>,
+    pub alert_format: Option<String>,
+    pub rate_unit: Option<String>,
+    pub state_file: Option<std::path::PathBuf>,
+    pub output_socket: Option<std::path::PathBuf>,
+    pub syslog: Option<bool>,
+    pub syslog_addr: Option<String>,
+    pub pagerduty_key: Option<String>,
+    pub otlp_endpoint: Option<String>,
+    pub group_window_secs: Option<usize>,
+    pub precision: Option<usize>,
+    pub stats_period: Option<usize>,
+    pub wall_interval: Option<String>,
+    pub stats_cumulative: Option<bool>,
+    pub stats_protocols: Option<bool>,
+    pub status_detail: Option<bool>,
+    pub gini: Option<bool>,
+    pub bot_stats: Option<bool>,
+    pub bot_patterns: Option<Vec<String>>,
+    pub stats_examples: Option<usize>,
+    pub compact: Option<bool>,
+    pub pretty: Option<bool>,
+    pub max_sections: Option<usize>,
+    pub stats_flush_lines: Option<usize>,
+    pub sparklines: Option<bool>,
+    pub group_by: Option<String>,
+    pub error_spike_threshold: Option<usize>,
+    pub alert_sigma: Option<f64>,
+    pub abuse_threshold: Option<String>,
+    pub bad_path_threshold: Option<usize>,
+    pub latency_threshold_ms: Option<u64>,
+    pub latency_stat: Option<String>,
+    pub replay: Option<bool>,
+    pub replay_speed: Option<usize>,
+    pub dump_groups: Option<bool>,
+    pub forbidden_protocols: Option<Vec<String>>,
+    pub rps_histogram: Option<bool>,
+    pub leaderboard: Option<bool>,
+    pub leaderboard_size: Option<usize>,
+    pub rps_histogram_edges: Option<Vec<usize>>,
+    pub csv_out: Option<std::path::PathBuf>,
+    pub output_compression: Option<String>,
+    pub expand_request: Option<bool>,
+    pub rate_out: Option<std::path::PathBuf>,
+    pub prom_out: Option<std::path::PathBuf>,
+    pub html_out: Option<std::path::PathBuf>,
+    pub sqlite_out: Option<std::path::PathBuf>,
+    pub parquet_out: Option<std::path::PathBuf>,
+    pub log_file: Option<std::path::PathBuf>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Can't read the config file {:?}: {}", path, e))?;
+        content.parse()
+    }
+
+    /// Fills the options that were not explicitly set in the CLI with the ones in the config.
+    pub fn apply_to(self, cli: &mut Cli) -> anyhow::Result<()> {
+        cli.buffer_seconds = cli.buffer_seconds.or(self.buffer_seconds);
+        cli.epoch_offset = cli.epoch_offset.or(self.epoch_offset);
+        cli.max_group_len = cli.max_group_len.or(self.max_group_len);
+        cli.max_consecutive_errors = cli.max_consecutive_errors.or(self.max_consecutive_errors);
+        cli.no_buffer |= self.no_buffer.unwrap_or_default();
+        cli.verify_ordering |= self.verify_ordering.unwrap_or_default();
+        cli.profile |= self.profile.unwrap_or_default();
+        cli.resolve_dns |= self.resolve_dns.unwrap_or_default();
+        cli.max_record_bytes = cli.max_record_bytes.or(self.max_record_bytes);
+        cli.alert_threshold = cli.alert_threshold.or(self.alert_threshold);
+        if cli.alert_tier.is_empty() {
+            cli.alert_tier = self
+                .alert_tier
+                .unwrap_or_default()
+                .iter()
+                .map(|tier| tier.parse())
+                .collect::<anyhow::Result<_>>()?;
+        }
+        cli.alert_window = cli.alert_window.or(self.alert_window);
+        cli.alert_cooldown = cli.alert_cooldown.or(self.alert_cooldown);
+        cli.align_alert_windows |= self.align_alert_windows.unwrap_or_default();
+        cli.explain_alert |= self.explain_alert.unwrap_or_default();
+        if cli.suppress_from.is_empty() && cli.suppress_to.is_empty() {
+            cli.suppress_from = self.suppress_from.unwrap_or_default();
+            cli.suppress_to = self.suppress_to.unwrap_or_default();
+        }
+        cli.state_file = cli.state_file.take().or(self.state_file);
+        cli.output_socket = cli.output_socket.take().or(self.output_socket);
+        cli.syslog |= self.syslog.unwrap_or_default();
+        cli.syslog_addr = cli.syslog_addr.take().or(self.syslog_addr);
+        cli.pagerduty_key = cli.pagerduty_key.take().or(self.pagerduty_key);
+        cli.otlp_endpoint = cli.otlp_endpoint.take().or(self.otlp_endpoint);
+        cli.group_window_secs = cli.group_window_secs.or(self.group_window_secs);
+        cli.precision = cli.precision.or(self.precision);
+        cli.stats_period = cli.stats_period.or(self.stats_period);
+        cli.error_spike_threshold = cli.error_spike_threshold.or(self.error_spike_threshold);
+        cli.alert_sigma = cli.alert_sigma.or(self.alert_sigma);
+        cli.bad_path_threshold = cli.bad_path_threshold.or(self.bad_path_threshold);
+        cli.latency_threshold_ms = cli.latency_threshold_ms.or(self.latency_threshold_ms);
+        cli.replay |= self.replay.unwrap_or_default();
+        cli.replay_speed = cli.replay_speed.or(self.replay_speed);
+        cli.dump_groups |= self.dump_groups.unwrap_or_default();
+        if cli.forbidden_protocols.is_empty() {
+            cli.forbidden_protocols = self.forbidden_protocols.unwrap_or_default();
+        }
+        cli.rps_histogram |= self.rps_histogram.unwrap_or_default();
+        cli.leaderboard |= self.leaderboard.unwrap_or_default();
+        cli.leaderboard_size = cli.leaderboard_size.or(self.leaderboard_size);
+        if cli.rps_histogram_edges.is_empty() {
+            cli.rps_histogram_edges = self.rps_histogram_edges.unwrap_or_default();
+        }
+        cli.normalize_paths |= self.normalize_paths.unwrap_or_default();
+        cli.brotli |= self.brotli.unwrap_or_default();
+        cli.stats_cumulative |= self.stats_cumulative.unwrap_or_default();
+        cli.stats_protocols |= self.stats_protocols.unwrap_or_default();
+        cli.status_detail |= self.status_detail.unwrap_or_default();
+        cli.gini |= self.gini.unwrap_or_default();
+        cli.bot_stats |= self.bot_stats.unwrap_or_default();
+        if cli.bot_patterns.is_empty() {
+            cli.bot_patterns = self.bot_patterns.unwrap_or_default();
+        }
+        cli.stats_examples = cli.stats_examples.or(self.stats_examples);
+        cli.compact |= self.compact.unwrap_or_default();
+        cli.pretty |= self.pretty.unwrap_or_default();
+        cli.max_sections = cli.max_sections.or(self.max_sections);
+        cli.unknown_section = cli.unknown_section.take().or(self.unknown_section);
+        cli.stats_flush_lines = cli.stats_flush_lines.or(self.stats_flush_lines);
+        cli.sparklines |= self.sparklines.unwrap_or_default();
+        cli.checkpoint = cli.checkpoint.take().or(self.checkpoint);
+        cli.tee = cli.tee.take().or(self.tee);
+        cli.tail_lines = cli.tail_lines.or(self.tail_lines);
+        cli.journald_unit = cli.journald_unit.take().or(self.journald_unit);
+        cli.journald_since = cli.journald_since.take().or(self.journald_since);
+        cli.csv_out = cli.csv_out.take().or(self.csv_out);
+        cli.expand_request |= self.expand_request.unwrap_or_default();
+        cli.rate_out = cli.rate_out.take().or(self.rate_out);
+        cli.prom_out = cli.prom_out.take().or(self.prom_out);
+        cli.html_out = cli.html_out.take().or(self.html_out);
+        cli.sqlite_out = cli.sqlite_out.take().or(self.sqlite_out);
+        cli.parquet_out = cli.parquet_out.take().or(self.parquet_out);
+        cli.log_file = cli.log_file.take().or(self.log_file);
+        cli.time_column = cli.time_column.take().or(self.time_column);
+        apply_parsed(&mut cli.format, self.format)?;
+        apply_parsed(&mut cli.header, self.header)?;
+        apply_parsed(&mut cli.time_fallback, self.time_fallback)?;
+        apply_parsed(&mut cli.only_status, self.only_status)?;
+        apply_parsed(&mut cli.wall_interval, self.wall_interval)?;
+        apply_parsed(&mut cli.max_runtime, self.max_runtime)?;
+        apply_parsed(&mut cli.latency_stat, self.latency_stat)?;
+        apply_parsed(&mut cli.section, self.section)?;
+        apply_parsed(&mut cli.request_sep, self.request_sep)?;
+        apply_parsed(&mut cli.input_encoding, self.input_encoding)?;
+        apply_parsed(&mut cli.anonymize, self.anonymize)?;
+        cli.anonymize_salt = cli.anonymize_salt.take().or(self.anonymize_salt);
+        apply_parsed(&mut cli.alert_stream, self.alert_stream)?;
+        apply_parsed(&mut cli.alert_format, self.alert_format)?;
+        apply_parsed(&mut cli.rate_unit, self.rate_unit)?;
+        apply_parsed(&mut cli.group_by, self.group_by)?;
+        apply_parsed(&mut cli.abuse_threshold, self.abuse_threshold)?;
+        apply_parsed(&mut cli.output_compression, self.output_compression)?;
+        Ok(())
+    }
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(|e| anyhow::anyhow!("Invalid config file: {}", e))
+    }
+}
+
+/// Parses the config value only if the CLI one is missing.
+fn apply_parsed<T>(cli_value: &mut Option<T>, config_value: Option<String>) -> anyhow::Result<()>
+where
+    T: FromStr<Err = anyhow::Error>,
+{
+    if cli_value.is_none() {
+        *cli_value = config_value.map(|v| v.parse()).transpose()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processors::GroupBy;
+    use structopt::StructOpt;
+
+    const CONFIG: &str = r#"
+buffer-seconds = 5
+alert-threshold = 20
+group-by = "verb"
+"#;
+
+    #[tokio::test]
+    async fn cli_overrides_config() {
+        let mut cli = Cli::from_iter(["log-ingestor", "--buffer-seconds", "3"]);
+        let config = CONFIG.parse::<Config>().unwrap();
+
+        config.apply_to(&mut cli).unwrap();
+
+        assert_eq!(cli.buffer_seconds, Some(3));
+        assert_eq!(cli.alert_threshold, Some(20));
+        assert_eq!(cli.group_by, Some(GroupBy::Verb));
+        assert_eq!(cli.stats_period, None);
+    }
+
+    #[tokio::test]
+    async fn the_keys_are_named_after_the_flags() {
+        let mut cli = Cli::from_iter(["log-ingestor"]);
+        let config = r#"
+alert-tier = ["100:warn"]
+tail-lines = 50
+log-file = "ingestor.log"
+dump-groups = true
+"#
+        .parse::<Config>()
+        .unwrap();
+
+        config.apply_to(&mut cli).unwrap();
+
+        assert_eq!(cli.alert_tier, vec!["100:warn".parse().unwrap()]);
+        assert_eq!(cli.tail_lines, Some(50));
+        assert_eq!(cli.log_file, Some("ingestor.log".into()));
+        assert!(cli.dump_groups);
+    }
+
+    #[tokio::test]
+    async fn boolean_keys_only_turn_options_on() {
+        let mut cli = Cli::from_iter(["log-ingestor", "--profile"]);
+        let config = "profile = false\ngini = true".parse::<Config>().unwrap();
+
+        config.apply_to(&mut cli).unwrap();
+
+        assert!(cli.profile);
+        assert!(cli.gini);
+    }
+
+    #[tokio::test]
+    async fn rejects_unknown_keys() {
+        let result = "buffer-secs = 5".parse::<Config>();
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_values() {
+        let mut cli = Cli::from_iter(["log-ingestor"]);
+        let config = r#"group-by = "nope""#.parse::<Config>().unwrap();
+        assert!(config.apply_to(&mut cli).is_err());
+    }
+}
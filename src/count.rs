@@ -0,0 +1,154 @@
+use crate::buffered_logs::ErrorBreaker;
+use futures::{future, Stream, StreamExt};
+use std::fmt;
+use tracing::instrument;
+
+/// Totals of a log stream, computed without fully parsing the logs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CountReport {
+    /// Amount of correctly read records.
+    pub records: usize,
+    /// Amount of records which couldn't be read.
+    pub errors: usize,
+    /// Sum of the bytes of the records.
+    pub bytes: u128,
+    /// Oldest time of the records.
+    pub min_time: Option<usize>,
+    /// Newest time of the records.
+    pub max_time: Option<usize>,
+}
+
+impl CountReport {
+    fn add(&mut self, time: usize, bytes: usize) {
+        self.records += 1;
+        self.bytes += bytes as u128;
+        self.min_time = Some(self.min_time.map_or(time, |min| min.min(time)));
+        self.max_time = Some(self.max_time.map_or(time, |max| max.max(time)));
+    }
+
+    /// Average records per second between the oldest and the newest time.
+    pub fn avg_rate(&self) -> f64 {
+        match (self.min_time, self.max_time) {
+            (Some(min), Some(max)) => self.records as f64 / (max - min + 1) as f64,
+            _ => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for CountReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Count - records: {}, errors: {}, bytes: {}, avg rate: {:.2} req/s",
+            self.records,
+            self.errors,
+            self.bytes,
+            self.avg_rate()
+        )
+    }
+}
+
+/// Counts the records of a stream of times and bytes, see
+/// [`read_time_and_bytes_async`](crate::reader::read_time_and_bytes_async).
+/// It stops once the breaker trips, so the report is partial then.
+#[instrument(skip(records, error_breaker))]
+pub async fn count_records(
+    records: impl Stream<Item = anyhow::Result<(usize, usize)>>,
+    mut error_breaker: ErrorBreaker,
+) -> CountReport {
+    records
+        .take_while(move |record| future::ready(!error_breaker.record(record.is_err())))
+        .fold(CountReport::default(), |mut report, record| async move {
+            match record {
+                Ok((time, bytes)) => report.add(time, bytes),
+                Err(e) => {
+                    tracing::error!("Error counting the records: {}", e);
+                    report.errors += 1;
+                }
+            }
+            report
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{read_csv_async, read_time_and_bytes_async, ReaderOptions};
+
+    #[tokio::test]
+    async fn counts_the_same_as_the_full_parse() {
+        let sample = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/sample.csv")).unwrap();
+
+        let mut input = std::io::Cursor::new(sample.clone());
+        let report = count_records(
+            read_time_and_bytes_async(&mut input, ReaderOptions::default()).await,
+            ErrorBreaker::default(),
+        )
+        .await;
+
+        let mut input = std::io::Cursor::new(sample);
+        let logs = read_csv_async(&mut input, ReaderOptions::default())
+            .await
+            .filter_map(|log| async move { log.ok() })
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(report.records > 0);
+        assert_eq!(report.records, logs.len());
+        assert_eq!(
+            report.bytes,
+            logs.iter().map(|log| log.bytes as u128).sum::<u128>()
+        );
+        assert_eq!(report.min_time, logs.iter().map(|log| log.time).min());
+        assert_eq!(report.max_time, logs.iter().map(|log| log.time).max());
+    }
+
+    #[tokio::test]
+    async fn counts_the_invalid_records_as_errors() {
+        let mut input = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",10,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.5","-","apache","a","GET /api/help HTTP/1.0",200,1234
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.5","-","apache",13,"POST /report HTTP/1.0",500,1307"#
+            .as_bytes();
+
+        let report = count_records(
+            read_time_and_bytes_async(&mut input, ReaderOptions::default()).await,
+            ErrorBreaker::default(),
+        )
+        .await;
+
+        assert_eq!(
+            report,
+            CountReport {
+                records: 2,
+                errors: 1,
+                bytes: 2541,
+                min_time: Some(10),
+                max_time: Some(13),
+            }
+        );
+        assert_eq!(report.avg_rate(), 0.5);
+    }
+
+    #[tokio::test]
+    async fn stops_once_the_breaker_trips() {
+        let mut input = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",10,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.5","-","apache","a","GET /api/help HTTP/1.0",200,1234
+"10.0.0.5","-","apache","b","GET /api/help HTTP/1.0",200,1234
+"10.0.0.5","-","apache",13,"POST /report HTTP/1.0",500,1307"#
+            .as_bytes();
+        let error_breaker = ErrorBreaker::new(std::num::NonZeroUsize::new(2));
+
+        let report = count_records(
+            read_time_and_bytes_async(&mut input, ReaderOptions::default()).await,
+            error_breaker.clone(),
+        )
+        .await;
+
+        assert_eq!(report.records, 1);
+        assert!(error_breaker.check().is_err());
+    }
+}
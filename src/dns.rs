@@ -0,0 +1,138 @@
+use crate::buffered_logs::GroupedHttpLogs;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Semaphore;
+
+/// Maximum amount of reverse DNS lookups running at the same time.
+const MAX_CONCURRENT_LOOKUPS: usize = 8;
+
+/// Reverse DNS lookup. It's blocking, so it's run in a blocking thread.
+#[cfg_attr(test, mockall::automock)]
+pub trait Resolve: Send + Sync {
+    fn lookup(&self, ip: IpAddr) -> Option<String>;
+}
+
+/// Resolves the hosts using the system resolver.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemResolver;
+
+impl Resolve for SystemResolver {
+    fn lookup(&self, ip: IpAddr) -> Option<String> {
+        dns_lookup::lookup_addr(&ip)
+            .map_err(|e| tracing::debug!("Can't resolve {}: {}", ip, e))
+            .ok()
+    }
+}
+
+/// Replaces the remote hosts of the logs with their names.
+/// Every host is only looked up once per run, even if it can't be resolved. The lookups run
+/// on background tasks filling the cache, so the logs are never held waiting for them: the
+/// hosts are kept as they are until they're resolved.
+pub struct HostResolver {
+    resolver: Arc<dyn Resolve>,
+    // `None` while the host is being looked up or when it can't be resolved
+    cache: Arc<Mutex<HashMap<String, Option<String>>>>,
+    semaphore: Arc<Semaphore>,
+}
+
+impl HostResolver {
+    pub fn new(resolver: impl Resolve + 'static) -> Self {
+        Self {
+            resolver: Arc::new(resolver),
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            semaphore: Arc::new(Semaphore::new(MAX_CONCURRENT_LOOKUPS)),
+        }
+    }
+
+    /// Looks the host up in the background, caching its name once it's resolved.
+    fn spawn_lookup(&self, host: String) {
+        let ip = match host.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => return,
+        };
+        let resolver = self.resolver.clone();
+        let cache = self.cache.clone();
+        let semaphore = self.semaphore.clone();
+        tokio::spawn(async move {
+            let _permit = semaphore.acquire().await;
+            let name = tokio::task::spawn_blocking(move || resolver.lookup(ip))
+                .await
+                .ok()
+                .flatten();
+            if name.is_some() {
+                cache.lock().unwrap().insert(host, name);
+            }
+        });
+    }
+
+    /// Replaces the hosts already resolved, looking the new ones up for the next groups.
+    pub fn resolve(&self, mut log_group: GroupedHttpLogs) -> GroupedHttpLogs {
+        let mut cache = self.cache.lock().unwrap();
+        for log in &mut log_group.logs {
+            match cache.get(&log.remote_host) {
+                Some(Some(name)) => log.remote_host = name.clone(),
+                Some(None) => (),
+                None => {
+                    cache.insert(log.remote_host.clone(), None);
+                    self.spawn_lookup(log.remote_host.clone());
+                }
+            }
+        }
+        log_group
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_test_http_grouped_log;
+    use std::time::Duration;
+
+    /// Resolves groups until the hosts are resolved or some time goes by, returning the last.
+    async fn resolve_until_settled(host_resolver: &HostResolver, time: usize) -> GroupedHttpLogs {
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let group = host_resolver.resolve(build_test_http_grouped_log(time, 1, None));
+            if group.logs[0].remote_host != "10.1.1.1" {
+                return group;
+            }
+        }
+        host_resolver.resolve(build_test_http_grouped_log(time, 1, None))
+    }
+
+    #[tokio::test]
+    async fn resolves_hosts_in_the_background_and_caches_them() {
+        let mut resolver = MockResolve::new();
+        resolver
+            .expect_lookup()
+            .withf(|ip| *ip == "10.1.1.1".parse::<IpAddr>().unwrap())
+            .times(1)
+            .returning(|_| Some("web-1.local".to_string()));
+        let host_resolver = HostResolver::new(resolver);
+
+        // the lookup hasn't run yet, so the group isn't held waiting for it
+        let first = host_resolver.resolve(build_test_http_grouped_log(1, 2, None));
+        let second = resolve_until_settled(&host_resolver, 2).await;
+
+        assert!(first.logs.iter().all(|log| log.remote_host == "10.1.1.1"));
+        assert!(second
+            .logs
+            .iter()
+            .all(|log| log.remote_host == "web-1.local"));
+    }
+
+    #[tokio::test]
+    async fn keeps_the_hosts_that_cannot_be_resolved() {
+        let mut resolver = MockResolve::new();
+        resolver.expect_lookup().times(1).returning(|_| None);
+        let host_resolver = HostResolver::new(resolver);
+
+        host_resolver.resolve(build_test_http_grouped_log(1, 2, None));
+        let group = resolve_until_settled(&host_resolver, 2).await;
+
+        assert!(group.logs.iter().all(|log| log.remote_host == "10.1.1.1"));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::reader::AsyncReader;
+use std::{
+    io,
+    pin::Pin,
+    process::Stdio,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    process::{Child, ChildStdout, Command},
+};
+
+/// `journalctl` printing the message of every entry of the unit (of all of them without
+/// one) as a line, following the new ones. It starts with the entries written from then
+/// on, or from `since` (any time `journalctl --since` takes, e.g. `1 hour ago`).
+///
+/// The journal is read through `journalctl` rather than `libsystemd`, so building the
+/// tool needs no native libraries and it runs wherever `journalctl` is installed.
+pub fn journalctl(unit: Option<&str>, since: Option<&str>) -> Command {
+    let mut command = Command::new("journalctl");
+    command.args(["--output", "cat", "--follow", "--no-pager"]);
+    match since {
+        Some(since) => command.args(["--since", since]),
+        // the whole history of the journal could take hours to replay
+        None => command.args(["--lines", "0"]),
+    };
+    if let Some(unit) = unit {
+        command.args(["--unit", unit]);
+    }
+    command
+}
+
+/// Reads the lines written by a journal source, e.g. [`journalctl`]. The source is killed
+/// once the reader is dropped.
+pub fn open(mut source: Command) -> anyhow::Result<Box<AsyncReader>> {
+    let mut child = source
+        .stdout(Stdio::piped())
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Can't read the journal with {:?}: {}", source, e))?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("The journal source has no output"))?;
+    Ok(Box::new(JournalReader {
+        _child: child,
+        stdout,
+    }))
+}
+
+/// Output of the journal source, keeping it alive while it's read.
+struct JournalReader {
+    _child: Child,
+    stdout: ChildStdout,
+}
+
+impl AsyncRead for JournalReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stdout).poll_read(cx, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{read_many_async, InputFormat, ReaderOptions};
+    use futures::TryStreamExt;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn follows_the_unit_of_the_journal_from_its_tail() {
+        let command = journalctl(Some("nginx.service"), None);
+        let args = args(&command);
+
+        assert_eq!(command.as_std().get_program(), "journalctl");
+        assert!(args.windows(2).any(|args| args == ["--output", "cat"]));
+        assert!(args
+            .windows(2)
+            .any(|args| args == ["--unit", "nginx.service"]));
+        assert!(args.windows(2).any(|args| args == ["--lines", "0"]));
+        assert!(args.contains(&"--follow".into()));
+    }
+
+    #[tokio::test]
+    async fn starts_at_the_given_time() {
+        let args = args(&journalctl(None, Some("1 hour ago")));
+
+        assert!(args
+            .windows(2)
+            .any(|args| args == ["--since", "1 hour ago"]));
+        assert!(!args.contains(&"--lines".into()));
+        assert!(!args.contains(&"--unit".into()));
+    }
+
+    #[tokio::test]
+    async fn reads_the_messages_of_the_journal_source() {
+        // a journal source writing known messages, as `journalctl --output cat` does
+        let mut source = Command::new("printf");
+        source.arg(
+            "10.0.0.2 - apache [07/Feb/2019:21:11:00 +0000] \"GET /api/user HTTP/1.0\" 200 1234\n\
+             10.0.0.4 - apache [07/Feb/2019:21:11:01 +0000] \"POST /report HTTP/1.0\" 500 12\n",
+        );
+        let mut reader = open(source).unwrap();
+        let options = ReaderOptions {
+            format: InputFormat::Clf,
+            ..ReaderOptions::default()
+        };
+
+        let logs = read_many_async(vec![reader.as_mut()], options)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let logs = logs
+            .iter()
+            .map(|log| (log.time, log.request.path.as_str(), log.status))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            logs,
+            [(1549573860, "/api/user", 200), (1549573861, "/report", 500)]
+        );
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_source_cannot_run() {
+        let error = open(Command::new("/nonexistent/journalctl")).err().unwrap();
+
+        assert!(error.to_string().starts_with("Can't read the journal"));
+    }
+}
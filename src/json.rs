@@ -0,0 +1,249 @@
+use crate::reader::{
+    deserialize_byte_count, skip_bom, AsyncReader, HttpLog, LogRequest, ReaderOptions,
+};
+use futures::{stream, Stream};
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing::instrument;
+
+/// Log object of a JSON input, with the same fields as the CSV columns.
+#[derive(Debug, Deserialize)]
+struct JsonLog {
+    remotehost: String,
+    rfc931: String,
+    authuser: String,
+    date: Option<usize>,
+    request: String,
+    status: u16,
+    #[serde(deserialize_with = "deserialize_byte_count")]
+    bytes: usize,
+    #[serde(default)]
+    responsetime: Option<u64>,
+}
+
+impl JsonLog {
+    fn into_http_log(self, options: &ReaderOptions) -> anyhow::Result<HttpLog> {
+        let mut log = HttpLog {
+            remote_host: self.remotehost,
+            rfc931: self.rfc931,
+            auth_user: self.authuser,
+            time: options.log_time(self.date)?,
+            request: LogRequest::from_str_with_separator(
+                &self.request,
+                &options.request_separator,
+            )?,
+            status: self.status,
+            bytes: self.bytes,
+            response_time_ms: self.responsetime,
+            referrer: None,
+            user_agent: None,
+        };
+        options.rewrite(&mut log);
+        Ok(log)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArrayState {
+    /// The opening bracket hasn't been read yet.
+    Start,
+    Elements,
+    Done,
+}
+
+/// Splits the elements of a top-level JSON array as they're read, so only one of them is
+/// in memory at a time.
+struct JsonArrayReader<'a> {
+    reader: BufReader<&'a mut AsyncReader>,
+    state: ArrayState,
+}
+
+impl<'a> JsonArrayReader<'a> {
+    async fn next_byte(&mut self) -> anyhow::Result<Option<u8>> {
+        let byte = self.reader.fill_buf().await?.first().copied();
+        if byte.is_some() {
+            self.reader.consume(1);
+        }
+        Ok(byte)
+    }
+
+    async fn next_non_whitespace(&mut self) -> anyhow::Result<Option<u8>> {
+        loop {
+            match self.next_byte().await? {
+                Some(byte) if byte.is_ascii_whitespace() => continue,
+                byte => return Ok(byte),
+            }
+        }
+    }
+
+    /// Bytes of the next element of the array, `None` once the array is closed.
+    async fn next_element(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.state == ArrayState::Start {
+            match self.next_non_whitespace().await? {
+                Some(b'[') => self.state = ArrayState::Elements,
+                _ => anyhow::bail!("The input is not a JSON array"),
+            }
+        }
+        let mut element = Vec::new();
+        let (mut depth, mut in_string, mut escaped) = (0_usize, false, false);
+        loop {
+            let byte = match self.next_byte().await? {
+                Some(byte) => byte,
+                None => anyhow::bail!("Unexpected end of the JSON array"),
+            };
+            if in_string {
+                match byte {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => (),
+                }
+            } else {
+                match byte {
+                    b'"' => in_string = true,
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' if depth > 0 => depth -= 1,
+                    b',' | b']' if depth == 0 => {
+                        let is_empty = element.iter().all(u8::is_ascii_whitespace);
+                        if byte == b']' {
+                            self.state = ArrayState::Done;
+                            return Ok((!is_empty).then_some(element));
+                        }
+                        if is_empty {
+                            anyhow::bail!("Empty element in the JSON array");
+                        }
+                        return Ok(Some(element));
+                    }
+                    _ => (),
+                }
+            }
+            element.push(byte);
+        }
+    }
+}
+
+/// Reads a JSON array of logs asynchronously, one element at a time.
+/// Elements which aren't valid logs are errors, the rest of them are still read.
+#[instrument(skip(reader))]
+pub async fn read_json_array_async(
+    reader: &mut AsyncReader,
+    options: ReaderOptions,
+) -> impl Stream<Item = anyhow::Result<HttpLog>> + '_ {
+    let array_reader = JsonArrayReader {
+        reader: skip_bom(reader).await,
+        state: ArrayState::Start,
+    };
+    stream::unfold(array_reader, move |mut array_reader| {
+        let options = options.clone();
+        async move {
+            if array_reader.state == ArrayState::Done {
+                return None;
+            }
+            let log = match array_reader.next_element().await {
+                Ok(None) => return None,
+                Ok(Some(element)) => serde_json::from_slice::<JsonLog>(&element)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|log| log.into_http_log(&options))
+                    .map_err(|e| anyhow::anyhow!("Invalid JSON log: {}", e)),
+                Err(e) => {
+                    // the rest of the input can't be trusted
+                    array_reader.state = ArrayState::Done;
+                    Err(e)
+                }
+            };
+            Some((log, array_reader))
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::read_csv_async;
+    use futures::{StreamExt, TryStreamExt};
+
+    #[tokio::test]
+    async fn reads_a_json_array_of_logs() {
+        let mut input = r#"[
+  {"remotehost": "10.0.0.2", "rfc931": "-", "authuser": "apache", "date": 1549573860, "request": "GET /api/user HTTP/1.0", "status": 200, "bytes": 1234},
+  {"remotehost": "10.0.0.4", "rfc931": "-", "authuser": "apache", "date": 1549573861, "request": "POST /report/{id} HTTP/1.0", "status": 500, "bytes": 1307}
+]"#
+        .as_bytes();
+        let mut csv = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573861,"POST /report/{id} HTTP/1.0",500,1307"#
+            .as_bytes();
+
+        let logs = read_json_array_async(&mut input, ReaderOptions::default())
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let expected = read_csv_async(&mut csv, ReaderOptions::default())
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(logs.len(), 2);
+        assert_eq!(logs, expected);
+    }
+
+    #[tokio::test]
+    async fn malformed_elements_are_errors() {
+        let mut input = r#"[
+  {"remotehost": "10.0.0.2", "rfc931": "-", "authuser": "apache", "date": "now", "request": "GET /api/user HTTP/1.0", "status": 200, "bytes": 1234},
+  {"remotehost": "10.0.0.4", "rfc931": "-", "authuser": "apache", "date": 1549573861, "request": "GET \"/a,]\" HTTP/1.0", "status": 200, "bytes": 1307},
+  {"remotehost": "10.0.0.4"
+"#
+        .as_bytes();
+
+        let logs = read_json_array_async(&mut input, ReaderOptions::default())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(logs.len(), 3);
+        assert!(logs[0].is_err());
+        assert_eq!(logs[1].as_ref().unwrap().request.path, "\"/a,]\"");
+        assert!(logs[2].is_err());
+    }
+
+    #[tokio::test]
+    async fn missing_byte_counts_are_read_as_zero() {
+        let mut input = r#"[
+  {"remotehost": "10.0.0.2", "rfc931": "-", "authuser": "apache", "date": 1549573860, "request": "GET /api/user HTTP/1.0", "status": 304, "bytes": "-"},
+  {"remotehost": "10.0.0.2", "rfc931": "-", "authuser": "apache", "date": 1549573860, "request": "GET /api/user HTTP/1.0", "status": 204, "bytes": ""},
+  {"remotehost": "10.0.0.2", "rfc931": "-", "authuser": "apache", "date": 1549573860, "request": "GET /api/user HTTP/1.0", "status": 200, "bytes": 1234}
+]"#
+        .as_bytes();
+
+        let bytes = read_json_array_async(&mut input, ReaderOptions::default())
+            .await
+            .map_ok(|log| log.bytes)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(bytes, vec![0, 0, 1234]);
+    }
+
+    #[tokio::test]
+    async fn empty_arrays_have_no_logs() {
+        let mut input = " [ ] ".as_bytes();
+        let mut not_an_array = "{}".as_bytes();
+
+        let logs = read_json_array_async(&mut input, ReaderOptions::default())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        let errors = read_json_array_async(&mut not_an_array, ReaderOptions::default())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(logs.is_empty());
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].is_err());
+    }
+}
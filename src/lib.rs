@@ -0,0 +1,32 @@
+//! The components of the log ingestor, for applications running the pipeline themselves.
+
+pub mod anonymize;
+pub mod bots;
+pub mod buffered_logs;
+pub mod checkpoint;
+mod clf;
+pub mod compare;
+pub mod count;
+mod dns;
+#[cfg(feature = "journald")]
+pub mod journald;
+mod json;
+pub mod output;
+pub mod process;
+pub mod processors;
+mod profile;
+pub mod reader;
+mod record_limit;
+mod replay;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod sample;
+pub mod section;
+pub mod skew;
+pub mod status;
+pub mod tail;
+pub mod tee;
+#[cfg(test)]
+mod test_utils;
+pub mod transcode;
+pub mod watch;
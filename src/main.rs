@@ -1,16 +1,45 @@
-mod buffered_logs;
-mod process;
-mod processors;
-mod reader;
-#[cfg(test)]
-mod test_utils;
+mod config;
 
-use crate::{
-    processors::{Alerts, Processor, Stats},
-    reader::AsyncReader,
+use crate::config::Config;
+#[cfg(feature = "journald")]
+use log_ingestor::journald;
+#[cfg(feature = "s3")]
+use log_ingestor::s3;
+use log_ingestor::{
+    anonymize::{AnonymizedFields, Anonymizer},
+    bots::BotPatterns,
+    buffered_logs::ErrorBreaker,
+    checkpoint::{Checkpointer, CHECKPOINT_INTERVAL},
+    compare, count,
+    output::Output,
+    process::{self, Completion, ProcessOptions, WallInterval},
+    processors::{
+        self, AbuseDetector, AbuseThreshold, AdaptiveAlerts, AlertFormat, AlertNotifier,
+        AlertProcessor, AlertStream, AlertTier, AlertTimeline, Alerts, BadPaths, CsvSink,
+        DumpGroups, ErrorSpike, ForbiddenProtocols, GroupBy, HtmlReport, LatencyAlerts,
+        LatencyStat, Leaderboard, OutputCompression, Peak, Processor, PromExposition,
+        RateHistogram, RateSeries, RateUnit, Stats, StatsFormat, SuppressionWindow,
+    },
+    reader::{
+        self, AsyncReader, Header, InputFormat, ReaderOptions, RequestSeparator, TimeFallback,
+    },
+    sample,
+    section::SectionPolicy,
+    skew,
+    status::StatusMatcher,
+    tail, tee,
+    transcode::InputEncoding,
+    watch,
+};
+use std::{
+    env::current_dir,
+    io::Write,
+    num::NonZeroUsize,
+    path::Path,
+    sync::{Arc, Mutex},
 };
-use std::env::current_dir;
 use structopt::StructOpt;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
 #[derive(Debug, StructOpt)]
 #[structopt(
@@ -19,41 +48,911 @@ use structopt::StructOpt;
     long_about = "🧰  Small utility to process http access logs"
 )]
 pub struct Cli {
-    /// The path to the csv file containing the logs
+    /// The paths to the csv files containing the logs, or `s3://bucket/key` URLs of S3 objects
+    /// (requires the `s3` feature). Several files are read at the same time and their logs merged by time
     #[structopt(parse(from_os_str))]
-    pub path: Option<std::path::PathBuf>,
+    pub paths: Vec<std::path::PathBuf>,
+    /// Prints the available processors and group-by keys and exits
+    #[structopt(long)]
+    pub list_processors: bool,
+    /// Directory whose `*.csv` files are read as they're created or appended to, until Ctrl-C
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["paths", "analyze-skew", "count-only"])]
+    pub watch_dir: Option<std::path::PathBuf>,
+    /// Reads the messages of the systemd journal written from now on as the input lines,
+    /// following the new entries until Ctrl-C. The format defaults to clf with it (requires
+    /// the `journald` feature and `journalctl`)
+    #[structopt(long, conflicts_with_all = &["paths", "watch-dir", "tail-lines", "count-only"])]
+    pub journald: bool,
+    /// Only reads the journal entries of this unit (e.g. `nginx.service`)
+    #[structopt(long, requires = "journald")]
+    pub journald_unit: Option<String>,
+    /// Reads the journal entries from this time on instead, in any format `journalctl --since`
+    /// takes (e.g. `1 hour ago` or `2024-01-31 10:00`)
+    #[structopt(long, requires = "journald")]
+    pub journald_since: Option<String>,
+    /// Decompresses the input with Brotli. Files with a `.br` extension always are
+    #[structopt(long, conflicts_with = "watch-dir")]
+    pub brotli: bool,
+    /// Encoding of the input (e.g. `latin1` or `windows-1252`), transcoded to UTF-8 as it's
+    /// read [default: utf-8]
+    #[structopt(long, conflicts_with = "watch-dir")]
+    pub input_encoding: Option<InputEncoding>,
+    /// TOML file with defaults for the options below (e.g. `buffer-seconds = 5`).
+    /// Explicit CLI options take precedence, but a flag turned on in the file can't be turned off
+    #[structopt(long, parse(from_os_str))]
+    pub config: Option<std::path::PathBuf>,
+    /// Replaces the remote hosts with their names using reverse DNS (cached per run)
+    #[structopt(long)]
+    pub resolve_dns: bool,
+    /// Amount of seconds used to buffer and order the logs [default: 2].
+    /// It only affects the ordering, not the windows of the processors
+    #[structopt(long)]
+    pub buffer_seconds: Option<usize>,
+    /// Maximum amount of logs in a group, the rest of the logs of that second go in other groups
+    #[structopt(long)]
+    pub max_group_len: Option<usize>,
+    /// Skips the buffer and processes the logs as they come. Only for pre-sorted input,
+    /// out of order logs are dropped
+    #[structopt(long)]
+    pub no_buffer: bool,
+    /// Reports every group leaving the buffer out of order, with the counts, to tell whether
+    /// `--buffer-seconds` is wide enough for the input
+    #[structopt(long)]
+    pub verify_ordering: bool,
+    /// Prints the time spent reading, buffering and processing the logs once they end,
+    /// to know which stage to optimize
+    #[structopt(long)]
+    pub profile: bool,
+    /// Stops reading the logs after this amount of real time (e.g. `30s`, `5m`), still reporting
+    /// the results of the logs read until then
+    #[structopt(long)]
+    pub max_runtime: Option<WallInterval>,
+    /// Aborts the run after this amount of records in a row which can't be parsed, as the
+    /// input is likely in another format. Scattered bad records are still skipped. It also
+    /// applies to `--count-only`
+    #[structopt(long)]
+    pub max_consecutive_errors: Option<NonZeroUsize>,
+    /// File where the progress of the batch run is saved every few seconds, so a run over the
+    /// same file interrupted before the end resumes from it. Needs `--no-buffer`, and refuses
+    /// the processors which can't save their state (e.g. `--leaderboard`)
+    #[structopt(long, parse(from_os_str))]
+    pub checkpoint: Option<std::path::PathBuf>,
+    /// Format of the input: csv, json-array (a single JSON array of log objects) or clf
+    /// (Common or Combined Log Format lines) [default: csv]
+    #[structopt(long)]
+    pub format: Option<InputFormat>,
+    /// Column names of a file without a header row, in any order
+    /// (e.g. `remotehost,rfc931,authuser,date,request,status,bytes`)
+    #[structopt(long)]
+    pub header: Option<Header>,
+    /// Seconds added to the time of every log to fix producers with a shifted epoch (it can be negative)
+    #[structopt(long, allow_hyphen_values = true)]
+    pub epoch_offset: Option<i64>,
+    /// Replaces the numeric and UUID segments of the paths with `{id}` so the requests
+    /// to the same resource are aggregated (e.g. `/api/user/{id}`)
+    #[structopt(long)]
+    pub normalize_paths: bool,
+    /// How the sections are taken from the paths: `first` (first segment), `depth:N`
+    /// (first N segments) or `regex:PATTERN` (first capture group) [default: first]
+    #[structopt(long)]
+    pub section: Option<SectionPolicy>,
+    /// Section of the requests with an empty path or a path without a leading slash, so
+    /// they're aggregated together [default: <unknown>]
+    #[structopt(long)]
+    pub unknown_section: Option<String>,
+    /// What splits the verb, the path and the protocol of the request column
+    /// (e.g. `|` for `GET|/api/user|HTTP/1.0`) [default: whitespace]
+    #[structopt(long)]
+    pub request_sep: Option<RequestSeparator>,
+    /// Only processes the last N lines of every file (or of the standard input). They're
+    /// physical lines, so a quoted field spanning several lines counts as several. The files
+    /// are read backwards, so their beginning is never read
+    #[structopt(long, conflicts_with = "watch-dir")]
+    pub tail_lines: Option<usize>,
+    /// Copies the input, as read and decompressed, to this file while processing it, e.g. to
+    /// archive exactly what was processed. Only a single input can be teed
+    #[structopt(long, parse(from_os_str), conflicts_with = "watch-dir")]
+    pub tee: Option<std::path::PathBuf>,
+    /// Replaces these fields of the logs with a salted hash when they're read: `host`
+    /// (remote host) and/or `user` (rfc931 and auth user), e.g. `host,user`
+    #[structopt(long)]
+    pub anonymize: Option<AnonymizedFields>,
+    /// Salt of the hashes of the anonymized fields
+    #[structopt(long, requires = "anonymize")]
+    pub anonymize_salt: Option<String>,
+    /// Time used for the logs without a time: `now` (ingestion time)
+    #[structopt(long)]
+    pub time_fallback: Option<TimeFallback>,
+    /// Name of the CSV column holding the epoch time of the logs, for files whose only
+    /// difference with the expected columns is its name (e.g. `ts`) [default: date]
+    #[structopt(long)]
+    pub time_column: Option<String>,
+    /// Only processes the logs with these status classes or codes (e.g. `4xx,5xx`)
+    #[structopt(long)]
+    pub only_status: Option<StatusMatcher>,
+    /// Lines longer than this amount of bytes are skipped instead of being parsed
+    #[structopt(long)]
+    pub max_record_bytes: Option<usize>,
+    /// Average amount of requests per second that triggers an alert [default: 10]
+    #[structopt(long)]
+    pub alert_threshold: Option<usize>,
+    /// Escalation tier of the high traffic alert as `threshold:label` (e.g. `100:warn`).
+    /// Repeatable, the alert is at the highest tier crossed. Replaces `--alert-threshold`
+    #[structopt(long, number_of_values = 1, conflicts_with = "alert-threshold")]
+    pub alert_tier: Vec<AlertTier>,
+    /// Amount of seconds used to calculate the average requests per second of the alerts [default: 120]
+    #[structopt(long)]
+    pub alert_window: Option<usize>,
+    /// Minimum amount of seconds (in log time) between two alert messages [default: 0]
+    #[structopt(long)]
+    pub alert_cooldown: Option<usize>,
+    /// Starts the alert windows at multiples of `--alert-window` in epoch seconds instead of
+    /// at the first log, so several instances processing the same logs alert alike
+    #[structopt(long)]
+    pub align_alert_windows: bool,
+    /// Appends a line to every high traffic alert with the window, the threshold, the average
+    /// observed and the second of the window with more requests
+    #[structopt(long)]
+    pub explain_alert: bool,
+    /// Start (epoch seconds, in log time) of a window during which the alert messages are
+    /// withheld, e.g. a planned maintenance. Repeatable, paired with `--suppress-to`
+    #[structopt(long, number_of_values = 1)]
+    pub suppress_from: Vec<usize>,
+    /// End (epoch seconds, in log time, included) of a window started by `--suppress-from`
+    #[structopt(long, number_of_values = 1)]
+    pub suppress_to: Vec<usize>,
+    /// Unix domain socket whose consumer gets the output of the processors instead of stdout.
+    /// Once the consumer disconnects, the output is discarded
+    #[structopt(long, parse(from_os_str))]
+    pub output_socket: Option<std::path::PathBuf>,
+    /// Where the alerts are written: stdout or stderr [default: stdout]
+    #[structopt(long)]
+    pub alert_stream: Option<AlertStream>,
+    /// How the alerts are laid out: `banner` (multi-line, colored) or `line` (a single line per
+    /// alert, for log aggregators) [default: banner in a terminal, line otherwise]
+    #[structopt(long)]
+    pub alert_format: Option<AlertFormat>,
+    /// Time unit of the average rates of the stats: `sec`, `min` or `hour`, e.g. to read the
+    /// rates of low traffic [default: sec]
+    #[structopt(long)]
+    pub rate_unit: Option<RateUnit>,
+    /// File where the state of the high traffic alert is kept between runs, so an alert
+    /// that was already active isn't announced again after a restart
+    #[structopt(long, parse(from_os_str))]
+    pub state_file: Option<std::path::PathBuf>,
+    /// Also sends the high traffic alerts to syslog (requires the `syslog` feature)
+    #[structopt(long)]
+    pub syslog: bool,
+    /// Address (`host:port`) of the syslog server the alerts are sent to over UDP,
+    /// instead of the local syslog socket
+    #[structopt(long, requires = "syslog")]
+    pub syslog_addr: Option<String>,
+    /// Also sends the high traffic alerts to PagerDuty as `trigger`/`resolve` events with this
+    /// routing key (requires the `pagerduty` feature)
+    #[structopt(long)]
+    pub pagerduty_key: Option<String>,
+    /// Endpoint of an OTLP/HTTP collector (e.g. `http://localhost:4318`) where the request,
+    /// bytes, error and alert metrics are pushed (requires the `otlp` feature)
+    #[structopt(long)]
+    pub otlp_endpoint: Option<String>,
+    /// Reporting window in seconds of both the stats and the alerts, computed from the time
+    /// of the logs. `--stats-period` and `--alert-window` take precedence
+    #[structopt(long)]
+    pub group_window_secs: Option<usize>,
+    /// Amount of decimals of the averages printed by the stats and the alerts [default: 2]
+    #[structopt(long)]
+    pub precision: Option<usize>,
+    /// Amount of seconds between two stats reports [default: 10]
+    #[structopt(long)]
+    pub stats_period: Option<usize>,
+    /// Reports the stats every interval of real time (e.g. `60s`, `5m`) instead of every
+    /// period of log time, so they're regular even with sparse traffic. Only with --watch-dir
+    #[structopt(long, requires = "watch-dir")]
+    pub wall_interval: Option<WallInterval>,
+    /// Reports running totals every period instead of resetting the stats
+    #[structopt(long)]
+    pub stats_cumulative: bool,
+    /// Also reports the hits per request protocol every stats period
+    #[structopt(long)]
+    pub stats_protocols: bool,
+    /// Also reports the hits per exact status code (e.g. 401 apart from 403) of every line of
+    /// the stats
+    #[structopt(long)]
+    pub status_detail: bool,
+    /// Also reports the Gini coefficient of the hits of the sections (or --group-by keys) every
+    /// stats period, from 0 for evenly spread traffic to almost 1 when a single key gets it all
+    #[structopt(long)]
+    pub gini: bool,
+    /// Also reports the hits of bots and humans, told apart by their user agent (only
+    /// known with --format clf)
+    #[structopt(long)]
+    pub bot_stats: bool,
+    /// Comma separated substrings of the user agents of bots, replacing the built-in ones
+    /// (Googlebot, bingbot...). Implies --bot-stats
+    #[structopt(long, use_delimiter = true)]
+    pub bot_patterns: Vec<String>,
+    /// Maximum amount of stats lines written per group of logs, spreading the stats of a period
+    /// over the following groups instead of writing them at once at the end of the period
+    #[structopt(long)]
+    pub stats_flush_lines: Option<usize>,
+    /// Shows a sparkline with the hits of the last periods of every stats line.
+    /// Only on terminals, and never with NO_COLOR set
+    #[structopt(long)]
+    pub sparklines: bool,
+    /// Maximum amount of sections (or other keys) per stats period, the rest are aggregated into `<other>`
+    #[structopt(long)]
+    pub max_sections: Option<usize>,
+    /// Prints a single line per stats key, without banner
+    #[structopt(long, conflicts_with = "pretty")]
+    pub compact: bool,
+    /// Prints the stats banner followed by a line per key (default)
+    #[structopt(long)]
+    pub pretty: bool,
+    /// Amount of example request paths shown for every stats line [default: 0]
+    #[structopt(long)]
+    pub stats_examples: Option<usize>,
+    /// Field used to group the stats: section, path, verb, status or host [default: section]
+    #[structopt(long)]
+    pub group_by: Option<GroupBy>,
+    /// Alerts when there are more 5xx responses than this within the alerts window, no matter the traffic
+    #[structopt(long)]
+    pub error_spike_threshold: Option<usize>,
+    /// Alerts when the requests of a second go over the mean plus this many standard
+    /// deviations of the alerts window, once the window is full (e.g. `3`)
+    #[structopt(long)]
+    pub alert_sigma: Option<f64>,
+    /// Alerts when a single host does more requests than this within the alerts window.
+    /// Either an absolute amount (e.g. `500`) or a fraction of the total (e.g. `0.5` or `50%`)
+    #[structopt(long)]
+    pub abuse_threshold: Option<AbuseThreshold>,
+    /// Alerts when a single path returns more 4xx than this within the alerts window
+    #[structopt(long)]
+    pub bad_path_threshold: Option<usize>,
+    /// Alerts when the response time of a section within the alerts window goes over this amount
+    /// of milliseconds. Needs a `responsetime` column
+    #[structopt(long)]
+    pub latency_threshold_ms: Option<u64>,
+    /// How the response times of a section are summarized for --latency-threshold-ms: avg or p95 [default: avg]
+    #[structopt(long)]
+    pub latency_stat: Option<LatencyStat>,
+    /// Alerts when a request uses one of these protocols (e.g. `HTTP/1.0,unknown`).
+    /// `unknown` matches any protocol out of the known HTTP versions
+    #[structopt(long, use_delimiter = true)]
+    pub forbidden_protocols: Vec<String>,
+    /// Replays the logs paced by their time, printing a timeline of the alerts at the end.
+    /// Useful to tune the alert thresholds with historical logs
+    #[structopt(long)]
+    pub replay: bool,
+    /// How many times faster than real time the logs are replayed [default: 1]
+    #[structopt(long, requires = "replay")]
+    pub replay_speed: Option<usize>,
+    /// Prints every group of logs emitted by the buffer (time, amount of logs and first path)
+    #[structopt(long)]
+    pub dump_groups: bool,
+    /// Prints the top hosts and sections by hits every stats period
+    #[structopt(long)]
+    pub leaderboard: bool,
+    /// Amount of hosts and sections of the leaderboard [default: 5]
+    #[structopt(long, requires = "leaderboard")]
+    pub leaderboard_size: Option<usize>,
+    /// Prints how many seconds had every amount of requests (by buckets) once the logs end
+    #[structopt(long)]
+    pub rps_histogram: bool,
+    /// Lower bounds of the buckets of --rps-histogram but the first one, which starts at 0
+    /// [default: 10,100,1000]
+    #[structopt(long, use_delimiter = true, requires = "rps-histogram")]
+    pub rps_histogram_edges: Vec<usize>,
+    /// Reports how out of order the timestamps are instead of processing the logs
+    #[structopt(long)]
+    pub analyze_skew: bool,
+    /// Only counts the records, their bytes and the average rate, skipping the full parsing of the logs.
+    /// It can't be combined with --only-status
+    #[structopt(long, conflicts_with = "analyze-skew")]
+    pub count_only: bool,
+    /// Prints the first N logs as they were parsed and exits, to check how the columns are
+    /// mapped into the fields of the logs
+    #[structopt(long, conflicts_with_all = &["watch-dir", "analyze-skew", "count-only"])]
+    pub dry_parse_sample: Option<usize>,
+    /// Compares the stats of the two given files (e.g. before and after a deploy), printing
+    /// the hits, bytes and error rate deltas of every section (or --group-by key)
+    #[structopt(long, conflicts_with_all = &["watch-dir", "analyze-skew", "count-only"])]
+    pub compare: bool,
+    /// Path to a CSV file where the processed logs will be written
+    #[structopt(long, parse(from_os_str))]
+    pub csv_out: Option<std::path::PathBuf>,
+    /// Compression of the written files: gzip, zstd or none [default: none]
+    #[structopt(long)]
+    pub output_compression: Option<OutputCompression>,
+    /// Writes the verb, path, section and protocol of the requests as separate columns
+    /// of the CSV output instead of the request column
+    #[structopt(long, requires = "csv-out")]
+    pub expand_request: bool,
+    /// Path to a CSV file where the requests and bytes of every second will be written
+    /// (`time,requests_per_sec,bytes_per_sec`), e.g. to plot them
+    #[structopt(long, parse(from_os_str))]
+    pub rate_out: Option<std::path::PathBuf>,
+    /// Path to a file where the requests per section and status, and a histogram of the
+    /// response bytes, are written in the Prometheus text format once the run completes
+    #[structopt(long, parse(from_os_str))]
+    pub prom_out: Option<std::path::PathBuf>,
+    /// Path to a self-contained HTML file where a summary of the run, the top sections, the
+    /// hits per status and a chart of the requests per second are written once it completes
+    #[structopt(long, parse(from_os_str))]
+    pub html_out: Option<std::path::PathBuf>,
+    /// File where the diagnostic logs are written instead of stderr
+    #[structopt(long, parse(from_os_str))]
+    pub log_file: Option<std::path::PathBuf>,
+    /// Path to a SQLite database where the stats of every period will be stored (requires the `sqlite` feature)
+    #[structopt(long, parse(from_os_str))]
+    pub sqlite_out: Option<std::path::PathBuf>,
+    /// Path to a Parquet file where the processed logs will be written (requires the `parquet` feature)
+    #[structopt(long, parse(from_os_str))]
+    pub parquet_out: Option<std::path::PathBuf>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let cli = Cli::from_args();
+    let mut cli = Cli::from_args();
+    if let Some(config_path) = &cli.config {
+        Config::load(config_path)?.apply_to(&mut cli)?;
+    }
     dotenv::dotenv().ok();
-    set_up_tracing();
+    set_up_tracing(cli.log_file.as_deref())?;
     tracing::info!("Starting the Log Ingestor CLI");
 
-    // supporting both a path or stdin as input
-    let mut reader: Box<AsyncReader> = if let Some(path) = cli.path {
+    if cli.list_processors {
+        print!("{}", processors::list_processors());
+        return Ok(());
+    }
+
+    // the journal messages are usually access log lines, never with a header row
+    if cli.journald {
+        cli.format.get_or_insert(InputFormat::Clf);
+    }
+
+    if cli.tail_lines.is_some() && cli.format.unwrap_or_default() != InputFormat::Csv {
+        anyhow::bail!("--tail-lines only supports CSV input");
+    }
+    let has_header = cli.header.is_none();
+    if cli.checkpoint.is_some() {
+        if !cli.no_buffer {
+            anyhow::bail!(
+                "--checkpoint needs --no-buffer, buffered logs can't be resumed from an offset"
+            );
+        }
+        let is_plain_csv = cli.format.unwrap_or_default() == InputFormat::Csv
+            && !cli.brotli
+            && cli.input_encoding.is_none()
+            && cli.max_record_bytes.is_none()
+            && cli.tail_lines.is_none()
+            && cli.watch_dir.is_none();
+        let is_single_file = match &cli.paths[..] {
+            [path] => !path.to_string_lossy().starts_with("s3://") && !reader::is_compressed(path),
+            _ => false,
+        };
+        if !is_plain_csv || !is_single_file {
+            anyhow::bail!(
+                "--checkpoint only supports a single uncompressed CSV file, read as it is"
+            );
+        }
+    }
+
+    // supporting both paths or stdin as input
+    let mut readers: Vec<Box<AsyncReader>> = Vec::new();
+    let mut checkpointer = None;
+    for path in &cli.paths {
+        let decompress = |input| match cli.brotli {
+            true => reader::brotli_decoder(input),
+            false => reader::decompress(path, input),
+        };
+        if let Some(url) = path.to_str().filter(|url| url.starts_with("s3://")) {
+            let mut object = decompress(s3_reader(url).await?);
+            readers.push(match cli.tail_lines {
+                Some(lines) => {
+                    tail::tail_stream(&mut object, lines, has_header, cli.max_record_bytes).await?
+                }
+                None => object,
+            });
+            continue;
+        }
         let file_path = current_dir()?.join(path);
-        Box::new(tokio::fs::File::open(file_path).await?)
-    } else {
-        Box::new(tokio::io::stdin())
+        if let Some(checkpoint_path) = &cli.checkpoint {
+            let (opened, reader) =
+                Checkpointer::open(checkpoint_path, &file_path, has_header, CHECKPOINT_INTERVAL)
+                    .await?;
+            checkpointer = Some(opened);
+            readers.push(reader);
+            continue;
+        }
+        let file = tokio::fs::File::open(file_path).await?;
+        readers.push(match cli.tail_lines {
+            // compressed files can't be read backwards
+            Some(lines) if cli.brotli || reader::is_compressed(path) => {
+                let mut file = decompress(Box::new(file));
+                tail::tail_stream(&mut file, lines, has_header, cli.max_record_bytes).await?
+            }
+            Some(lines) => tail::tail_file(file, lines, has_header).await?,
+            None => decompress(Box::new(file)),
+        });
+    }
+    if cli.journald {
+        readers.push(journald_reader(
+            cli.journald_unit.as_deref(),
+            cli.journald_since.as_deref(),
+        )?);
+    }
+    if readers.is_empty() {
+        let mut stdin: Box<AsyncReader> = match cli.brotli {
+            true => reader::brotli_decoder(Box::new(tokio::io::stdin())),
+            false => Box::new(tokio::io::stdin()),
+        };
+        if let Some(lines) = cli.tail_lines {
+            stdin = tail::tail_stream(&mut stdin, lines, has_header, cli.max_record_bytes).await?;
+        }
+        readers.push(stdin);
+    }
+    if let Some(archive) = &cli.tee {
+        readers = match <[_; 1]>::try_from(readers) {
+            Ok([reader]) => vec![tee::tee(reader, archive)?],
+            Err(_) => anyhow::bail!("--tee only supports a single input"),
+        };
+    }
+    if let Some(encoding) = cli.input_encoding {
+        readers = readers
+            .into_iter()
+            .map(|reader| encoding.transcode(reader))
+            .collect();
+    }
+    let readers = readers.iter_mut().map(|reader| reader.as_mut()).collect();
+
+    let options = ProcessOptions {
+        buffer_seconds: cli.buffer_seconds.unwrap_or(2),
+        max_group_len: cli.max_group_len,
+        no_buffer: cli.no_buffer,
+        resolve_dns: cli.resolve_dns,
+        reader: ReaderOptions {
+            time_fallback: cli.time_fallback,
+            time_column: cli.time_column.clone(),
+            max_record_bytes: cli.max_record_bytes,
+            header: cli.header,
+            epoch_offset: cli.epoch_offset.unwrap_or(0),
+            format: cli.format.unwrap_or_default(),
+            normalize_paths: cli.normalize_paths,
+            anonymizer: cli.anonymize.map(|fields| {
+                if cli.anonymize_salt.is_none() {
+                    tracing::warn!("Anonymizing without a salt, the hashes can be easily reversed");
+                }
+                Anonymizer::new(fields, cli.anonymize_salt.clone().unwrap_or_default())
+            }),
+            sections: cli.section,
+            unknown_section: cli.unknown_section.clone(),
+            request_separator: cli.request_sep.unwrap_or_default(),
+        },
+        only_status: cli.only_status,
+        replay_speed: cli.replay.then(|| cli.replay_speed.unwrap_or(1)),
+        wall_interval: cli.wall_interval,
+        verify_ordering: cli.verify_ordering,
+        profile: cli.profile,
+        max_runtime: cli.max_runtime.map(|WallInterval(max_runtime)| max_runtime),
+        checkpoint: checkpointer,
+        max_consecutive_errors: cli.max_consecutive_errors,
+        output: match &cli.output_socket {
+            Some(path) => Output::socket(path)?,
+            None => Output::Stdout,
+        },
     };
 
-    let processors: Vec<Box<dyn Processor>> =
-        vec![Box::new(Alerts::new(10, 120)), Box::new(Stats::new(10))];
+    if let Some(len) = cli.dry_parse_sample {
+        let log_stream = reader::read_many_async(readers, options.reader.clone()).await;
+        sample::dry_parse_sample(log_stream, len, &mut std::io::stdout()).await?;
+        return Ok(());
+    }
 
-    process::process_logs(&mut reader, processors).await?;
-    Ok(())
+    if cli.analyze_skew {
+        let log_stream = reader::read_many_async(readers, options.reader.clone()).await;
+        let report = skew::analyze_skew(log_stream).await;
+        println!("{}", report);
+        return Ok(());
+    }
+
+    if options.reader.format != InputFormat::Csv && (cli.count_only || cli.watch_dir.is_some()) {
+        anyhow::bail!("--count-only and --watch-dir only support CSV input");
+    }
+
+    if cli.count_only && options.only_status.is_some() {
+        // the status isn't read to count the records
+        anyhow::bail!(
+            "--count-only can't filter the logs with --only-status, run without one of them"
+        );
+    }
+
+    if cli.count_only {
+        let records = reader::read_many_time_and_bytes_async(readers, options.reader.clone()).await;
+        let error_breaker = ErrorBreaker::new(cli.max_consecutive_errors);
+        let report = count::count_records(records, error_breaker.clone()).await;
+        error_breaker.check()?;
+        println!("{}", report);
+        return Ok(());
+    }
+
+    if cli.compare {
+        let [before, after]: [&mut AsyncReader; 2] = readers
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("--compare needs exactly two files"))?;
+        let group_by = cli.group_by.unwrap_or(GroupBy::Section);
+        let before = reader::read_many_async(vec![before], options.reader.clone()).await;
+        let before = compare::aggregate(before, group_by).await;
+        let after = reader::read_many_async(vec![after], options.reader.clone()).await;
+        let after = compare::aggregate(after, group_by).await;
+        print!("{}", compare::CompareReport::new(group_by, before, after));
+        return Ok(());
+    }
+
+    let alert_window = cli.alert_window.or(cli.group_window_secs).unwrap_or(120);
+    let stats_period = cli.stats_period.or(cli.group_window_secs).unwrap_or(10);
+
+    let otlp_metrics = match &cli.otlp_endpoint {
+        Some(endpoint) => Some(otlp_metrics(endpoint)?),
+        None => None,
+    };
+
+    if cli.suppress_from.len() != cli.suppress_to.len() {
+        anyhow::bail!("Every --suppress-from needs its --suppress-to");
+    }
+    let suppression_windows = cli
+        .suppress_from
+        .iter()
+        .zip(&cli.suppress_to)
+        .map(|(&from, &to)| SuppressionWindow::new(from, to))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut thresholds = cli
+        .alert_tier
+        .iter()
+        .map(AlertTier::threshold)
+        .collect::<Vec<_>>();
+    thresholds.sort_unstable();
+    if let Some(w) = thresholds.windows(2).find(|w| w[0] == w[1]) {
+        anyhow::bail!("Several alert tiers with the same threshold: {}", w[0]);
+    }
+
+    let alert_stream = match options.replay_speed {
+        Some(_) => AlertStream::Stdout,
+        None => cli.alert_stream.unwrap_or(AlertStream::Stdout),
+    };
+    let is_output_terminal = console::user_attended() && cli.output_socket.is_none();
+    let alert_format = |is_terminal| {
+        cli.alert_format
+            .unwrap_or_else(|| AlertFormat::for_terminal(is_terminal))
+    };
+    let mut alert_processors: Vec<Box<dyn Processor>> = vec![Box::new(
+        Alerts::builder()
+            .avg_req_sec_threshold(cli.alert_threshold.unwrap_or(10))
+            .tiers(cli.alert_tier)
+            .window_size_in_secs(alert_window)
+            .align_windows(cli.align_alert_windows)
+            .cooldown_in_secs(cli.alert_cooldown.unwrap_or(0))
+            .explain(cli.explain_alert)
+            .precision(Some(cli.precision.unwrap_or(2)))
+            .state_file(cli.state_file)
+            .suppression_windows(suppression_windows)
+            .notifier(match cli.syslog {
+                true => Some(syslog_notifier(cli.syslog_addr.as_deref())?),
+                false => None,
+            })
+            .notifier(otlp_metrics.as_ref().map(|(_, notifier)| notifier.clone()))
+            .notifier(match &cli.pagerduty_key {
+                Some(routing_key) => Some(pagerduty_notifier(routing_key)?),
+                None => None,
+            })
+            // the timeline only sees the alerts written along with the rest of the output
+            .stream(alert_stream)
+            .format(alert_format(match alert_stream {
+                AlertStream::Stdout => is_output_terminal,
+                AlertStream::Stderr => console::user_attended_stderr(),
+            }))
+            .build(),
+    )];
+    let mut other_alerts: Vec<Box<dyn AlertProcessor>> = Vec::new();
+
+    if let Some(error_spike_threshold) = cli.error_spike_threshold {
+        other_alerts.push(Box::new(ErrorSpike::new(
+            error_spike_threshold,
+            alert_window,
+        )));
+    }
+
+    if let Some(alert_sigma) = cli.alert_sigma {
+        if alert_sigma <= 0.0 {
+            anyhow::bail!("Invalid alert sigma: {}. Use a number above 0", alert_sigma);
+        }
+        other_alerts.push(Box::new(AdaptiveAlerts::new(alert_sigma, alert_window)));
+    }
+
+    if let Some(abuse_threshold) = cli.abuse_threshold {
+        other_alerts.push(Box::new(AbuseDetector::new(abuse_threshold, alert_window)));
+    }
+
+    if let Some(bad_path_threshold) = cli.bad_path_threshold {
+        other_alerts.push(Box::new(BadPaths::new(bad_path_threshold, alert_window)));
+    }
+
+    if let Some(latency_threshold_ms) = cli.latency_threshold_ms {
+        other_alerts.push(Box::new(LatencyAlerts::new(
+            latency_threshold_ms,
+            alert_window,
+            cli.latency_stat.unwrap_or(LatencyStat::Avg),
+        )));
+    }
+
+    if !cli.forbidden_protocols.is_empty() {
+        other_alerts.push(Box::new(ForbiddenProtocols::new(
+            cli.forbidden_protocols,
+            alert_window,
+        )));
+    }
+
+    // only the high traffic alert can be written to stderr
+    for alerts in &mut other_alerts {
+        alerts.set_format(alert_format(is_output_terminal));
+    }
+    alert_processors.extend(
+        other_alerts
+            .into_iter()
+            .map(|alerts| alerts as Box<dyn Processor>),
+    );
+
+    let mut processors: Vec<Box<dyn Processor>> = vec![
+        Box::new(
+            Stats::builder()
+                .period_in_secs(stats_period)
+                .group_by(cli.group_by.unwrap_or(GroupBy::Section))
+                .cumulative(cli.stats_cumulative)
+                .max_examples(cli.stats_examples.unwrap_or(0))
+                .max_keys(cli.max_sections)
+                .flush_lines(cli.stats_flush_lines)
+                .sparklines(
+                    cli.sparklines
+                        && console::user_attended()
+                        && cli.output_socket.is_none()
+                        && std::env::var_os("NO_COLOR").is_none(),
+                )
+                .protocol_breakdown(cli.stats_protocols)
+                .status_detail(cli.status_detail)
+                .rate_unit(cli.rate_unit.unwrap_or_default())
+                .gini(cli.gini)
+                .bot_patterns(match cli.bot_patterns.is_empty() {
+                    true => cli.bot_stats.then(BotPatterns::default),
+                    false => Some(BotPatterns::new(&cli.bot_patterns)),
+                })
+                .precision(Some(cli.precision.unwrap_or(2)))
+                .wall_clock(cli.wall_interval.is_some())
+                .format(if cli.compact && !cli.pretty {
+                    StatsFormat::Compact
+                } else {
+                    StatsFormat::Pretty
+                })
+                .build(),
+        ),
+        Box::new(Peak::new()),
+    ];
+
+    match options.replay_speed {
+        Some(speed) => processors.push(Box::new(AlertTimeline::new(alert_processors, speed))),
+        None => processors.extend(alert_processors),
+    }
+
+    if cli.dump_groups {
+        processors.push(Box::new(DumpGroups::new()));
+    }
+
+    if cli.leaderboard {
+        processors.push(Box::new(Leaderboard::new(
+            cli.leaderboard_size.unwrap_or(5),
+            stats_period,
+        )));
+    }
+
+    if cli.rps_histogram {
+        let edges = match cli.rps_histogram_edges.is_empty() {
+            true => RateHistogram::DEFAULT_EDGES.to_vec(),
+            false => cli.rps_histogram_edges,
+        };
+        processors.push(Box::new(RateHistogram::new(edges)?));
+    }
+
+    if let Some(csv_path) = cli.csv_out {
+        let compression = cli.output_compression.unwrap_or(OutputCompression::None);
+        processors.push(Box::new(
+            CsvSink::create(&csv_path, compression)?.expand_request(cli.expand_request),
+        ));
+    }
+
+    if let Some(rate_path) = cli.rate_out {
+        processors.push(Box::new(RateSeries::create(&rate_path)?));
+    }
+
+    if let Some(prom_path) = cli.prom_out {
+        processors.push(Box::new(PromExposition::new(&prom_path)));
+    }
+
+    if let Some(html_path) = cli.html_out {
+        processors.push(Box::new(HtmlReport::new(&html_path)));
+    }
+
+    if let Some((otlp_processor, _)) = otlp_metrics {
+        processors.push(otlp_processor);
+    }
+
+    if let Some(sqlite_path) = cli.sqlite_out {
+        processors.push(sqlite_stats(&sqlite_path, stats_period)?);
+    }
+
+    if let Some(parquet_path) = cli.parquet_out {
+        processors.push(parquet_sink(&parquet_path)?);
+    }
+
+    let completion = match (cli.watch_dir, &options.checkpoint) {
+        (Some(dir), _) => {
+            let log_stream = watch::watch_dir(&dir, options.reader.clone())?;
+            process::process_log_stream(log_stream, processors, Vec::new(), &options).await?
+        }
+        (None, Some(checkpointer)) => {
+            checkpointer.restore(&mut processors)?;
+            let [reader]: [&mut AsyncReader; 1] = readers
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("--checkpoint needs exactly one file"))?;
+            let log_stream = checkpointer.read_logs(reader, options.reader.clone()).await;
+            process::process_log_stream(log_stream, processors, Vec::new(), &options).await?
+        }
+        (None, None) => process::process_logs(readers, processors, Vec::new(), &options).await?,
+    };
+    // a read of stdin pending when stopping would keep the tool from exiting
+    match completion {
+        Completion::Done => Ok(()),
+        Completion::TimedOut => {
+            std::io::stdout().flush()?;
+            std::process::exit(0);
+        }
+        // like other CLIs, a closed output isn't an error
+        Completion::OutputClosed => std::process::exit(0),
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn sqlite_stats(path: &Path, period_in_secs: usize) -> anyhow::Result<Box<dyn Processor>> {
+    Ok(Box::new(processors::SqliteStats::open(
+        path,
+        period_in_secs,
+    )?))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn sqlite_stats(path: &Path, _period_in_secs: usize) -> anyhow::Result<Box<dyn Processor>> {
+    anyhow::bail!(
+        "Can't write stats to {:?}: the tool was built without the `sqlite` feature",
+        path
+    )
+}
+
+#[cfg(feature = "parquet")]
+fn parquet_sink(path: &Path) -> anyhow::Result<Box<dyn Processor>> {
+    Ok(Box::new(processors::ParquetSink::create(path)?))
+}
+
+#[cfg(not(feature = "parquet"))]
+fn parquet_sink(path: &Path) -> anyhow::Result<Box<dyn Processor>> {
+    anyhow::bail!(
+        "Can't write the logs to {:?}: the tool was built without the `parquet` feature",
+        path
+    )
+}
+
+#[cfg(feature = "s3")]
+async fn s3_reader(url: &str) -> anyhow::Result<Box<AsyncReader>> {
+    s3::open(url).await
 }
 
-fn set_up_tracing() {
+#[cfg(not(feature = "s3"))]
+async fn s3_reader(url: &str) -> anyhow::Result<Box<AsyncReader>> {
+    anyhow::bail!(
+        "Can't read {}: the tool was built without the `s3` feature",
+        url
+    )
+}
+
+#[cfg(feature = "journald")]
+fn journald_reader(unit: Option<&str>, since: Option<&str>) -> anyhow::Result<Box<AsyncReader>> {
+    journald::open(journald::journalctl(unit, since))
+}
+
+#[cfg(not(feature = "journald"))]
+fn journald_reader(_unit: Option<&str>, _since: Option<&str>) -> anyhow::Result<Box<AsyncReader>> {
+    anyhow::bail!("Can't read the journal: the tool was built without the `journald` feature")
+}
+
+#[cfg(feature = "syslog")]
+fn syslog_notifier(addr: Option<&str>) -> anyhow::Result<Arc<dyn AlertNotifier>> {
+    Ok(Arc::new(processors::SyslogSink::connect(addr)?))
+}
+
+#[cfg(not(feature = "syslog"))]
+fn syslog_notifier(_addr: Option<&str>) -> anyhow::Result<Arc<dyn AlertNotifier>> {
+    anyhow::bail!(
+        "Can't send the alerts to syslog: the tool was built without the `syslog` feature"
+    )
+}
+
+#[cfg(feature = "pagerduty")]
+fn pagerduty_notifier(routing_key: &str) -> anyhow::Result<Arc<dyn AlertNotifier>> {
+    Ok(Arc::new(processors::PagerDuty::new(routing_key)))
+}
+
+#[cfg(not(feature = "pagerduty"))]
+fn pagerduty_notifier(_routing_key: &str) -> anyhow::Result<Arc<dyn AlertNotifier>> {
+    anyhow::bail!(
+        "Can't send the alerts to PagerDuty: the tool was built without the `pagerduty` feature"
+    )
+}
+
+/// Processor pushing the metrics to the collector and notifier of its alert state.
+#[cfg(feature = "otlp")]
+fn otlp_metrics(endpoint: &str) -> anyhow::Result<(Box<dyn Processor>, Arc<dyn AlertNotifier>)> {
+    let metrics = processors::OtlpMetrics::connect(endpoint)?;
+    let notifier = metrics.alert_notifier();
+    Ok((Box::new(metrics), notifier))
+}
+
+#[cfg(not(feature = "otlp"))]
+fn otlp_metrics(_endpoint: &str) -> anyhow::Result<(Box<dyn Processor>, Arc<dyn AlertNotifier>)> {
+    anyhow::bail!("Can't export OTLP metrics: the tool was built without the `otlp` feature")
+}
+
+/// Diagnostic logs never go to stdout so they don't get mixed with the processors output.
+fn log_writer(log_file: Option<&Path>) -> anyhow::Result<BoxMakeWriter> {
+    Ok(match log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| anyhow::anyhow!("Can't open the log file {:?}: {}", path, e))?;
+            BoxMakeWriter::new(Mutex::new(file))
+        }
+        None => BoxMakeWriter::new(std::io::stderr),
+    })
+}
+
+fn set_up_tracing(log_file: Option<&Path>) -> anyhow::Result<()> {
     let tracing = tracing_subscriber::fmt()
         .with_timer(tracing_subscriber::fmt::time::UtcTime::rfc_3339())
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env());
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(log_writer(log_file)?);
 
     if cfg!(debug_assertions) {
         tracing.pretty().init();
     } else {
         tracing.json().init();
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn diagnostic_logs_go_to_the_log_file() {
+        let path = std::env::temp_dir().join(format!("log-ingestor-{}.log", std::process::id()));
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(log_writer(Some(&path)).unwrap())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("Starting the Log Ingestor CLI");
+        });
+
+        let logs = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(logs.contains("Starting the Log Ingestor CLI"));
+    }
 }
@@ -0,0 +1,183 @@
+use std::{io::Write, path::Path};
+
+/// Where the output of the processors is written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Output {
+    #[default]
+    Stdout,
+    /// A consumer listening on a Unix domain socket.
+    #[cfg(unix)]
+    Socket(SocketOutput),
+}
+
+impl Output {
+    /// Connects to the consumer listening on the socket.
+    #[cfg(unix)]
+    pub fn socket(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self::Socket(SocketOutput::connect(path)?))
+    }
+
+    #[cfg(not(unix))]
+    pub fn socket(path: &Path) -> anyhow::Result<Self> {
+        anyhow::bail!(
+            "Can't write the output to {:?}: Unix domain sockets aren't supported",
+            path
+        )
+    }
+}
+
+/// Shared by all the processors, which write to it from several threads.
+impl Write for &Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Stdout => std::io::stdout().write(buf),
+            #[cfg(unix)]
+            Output::Socket(socket) => socket.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Stdout => std::io::stdout().flush(),
+            #[cfg(unix)]
+            Output::Socket(socket) => socket.flush(),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use socket::SocketOutput;
+
+#[cfg(unix)]
+mod socket {
+    use std::{
+        fmt,
+        io::Write,
+        os::unix::net::UnixStream,
+        path::Path,
+        sync::{Arc, Mutex},
+    };
+
+    /// Unix domain socket shared by the processors. Once the consumer disconnects,
+    /// the output is discarded instead of failing the run.
+    #[derive(Clone)]
+    pub struct SocketOutput(Arc<Mutex<Option<UnixStream>>>);
+
+    impl SocketOutput {
+        pub fn connect(path: &Path) -> anyhow::Result<Self> {
+            let stream = UnixStream::connect(path)
+                .map_err(|e| anyhow::anyhow!("Can't connect to the socket {:?}: {}", path, e))?;
+            Ok(Self(Arc::new(Mutex::new(Some(stream)))))
+        }
+
+        /// Runs a call on the stream, dropping it if it fails.
+        fn with_stream<T>(
+            &self,
+            default: T,
+            f: impl FnOnce(&mut UnixStream) -> std::io::Result<T>,
+        ) -> std::io::Result<T> {
+            let mut stream = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            let result = match stream.as_mut() {
+                Some(stream) => f(stream),
+                None => return Ok(default),
+            };
+            result.or_else(|e| {
+                tracing::warn!(
+                    "The output socket consumer is gone, discarding the output: {}",
+                    e
+                );
+                *stream = None;
+                Ok(default)
+            })
+        }
+
+        pub(super) fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
+            self.with_stream(buf.len(), |stream| stream.write_all(buf).map(|_| buf.len()))
+        }
+
+        pub(super) fn flush(&self) -> std::io::Result<()> {
+            self.with_stream((), |stream| stream.flush())
+        }
+    }
+
+    impl fmt::Debug for SocketOutput {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("SocketOutput")
+        }
+    }
+
+    /// Sockets are only equal to their clones.
+    impl PartialEq for SocketOutput {
+        fn eq(&self, other: &Self) -> bool {
+            Arc::ptr_eq(&self.0, &other.0)
+        }
+    }
+
+    impl Eq for SocketOutput {}
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::{
+        process::{process_logs, ProcessOptions},
+        processors::Stats,
+    };
+    use std::{io::Read, os::unix::net::UnixListener};
+
+    #[tokio::test]
+    async fn the_consumer_of_the_socket_receives_the_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ingest.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let input = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573861,"GET /report HTTP/1.0",200,1234"#;
+        let options = ProcessOptions {
+            output: Output::socket(&path).unwrap(),
+            ..ProcessOptions::default()
+        };
+
+        process_logs(
+            vec![&mut std::io::Cursor::new(input)],
+            vec![Box::new(Stats::builder().period_in_secs(10).build())],
+            Vec::new(),
+            &options,
+        )
+        .await
+        .unwrap();
+        // closing the socket so the consumer reaches its end
+        drop(options);
+
+        let mut received = String::new();
+        let (mut consumer, _) = listener.accept().unwrap();
+        consumer.read_to_string(&mut received).unwrap();
+        let received = console::strip_ansi_codes(&received).to_string();
+        assert!(
+            received.contains("Section: /api, Total Hits: 1,"),
+            "{}",
+            received
+        );
+        assert!(
+            received.contains("Section: /report, Total Hits: 1,"),
+            "{}",
+            received
+        );
+    }
+
+    #[tokio::test]
+    async fn the_output_is_discarded_once_the_consumer_disconnects() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ingest.sock");
+        let listener = UnixListener::bind(&path).unwrap();
+        let output = Output::socket(&path).unwrap();
+        let (consumer, _) = listener.accept().unwrap();
+
+        drop(consumer);
+
+        for _ in 0..3 {
+            assert!((&output).write_all(b"stats\n").is_ok());
+        }
+        assert!((&output).flush().is_ok());
+    }
+}
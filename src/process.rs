@@ -1,41 +1,523 @@
-use std::sync::Arc;
+use std::{
+    io::{self, Write},
+    num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use crate::{
-    buffered_logs::BufferedLogs,
-    processors::Processor,
-    reader::{read_csv_async, AsyncReader},
+    buffered_logs::{
+        BufferedLogs, ConsecutiveLogs, ErrorBreaker, GroupedHttpLogs, LogResult, OrderingCheck,
+    },
+    checkpoint::Checkpointer,
+    dns::{HostResolver, SystemResolver},
+    output::Output,
+    processors::{AsyncProcessor, Processor},
+    profile::{Profile, StageTimer, Timed},
+    reader::{read_many_async, AsyncReader, ReaderOptions},
+    replay::replay,
+    status::StatusMatcher,
 };
-use futures::StreamExt;
+use futures::{future, stream, Stream, StreamExt};
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
+use tokio::time::{interval_at, Instant, Interval, MissedTickBehavior};
 use tracing::instrument;
 
-/// Processes all the logs coming from an async reader
-#[instrument(skip(reader, processors))]
+/// Real time between two ticks of the processors, e.g. `60s`, `5m` or `1h` (seconds by default).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WallInterval(pub Duration);
+
+impl FromStr for WallInterval {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (amount, unit_secs) = match s.trim() {
+            s if s.ends_with('h') => (&s[..s.len() - 1], 3600),
+            s if s.ends_with('m') => (&s[..s.len() - 1], 60),
+            s => (s.strip_suffix('s').unwrap_or(s), 1),
+        };
+        match amount.parse::<u64>() {
+            Ok(amount) if amount > 0 => Ok(Self(Duration::from_secs(amount * unit_secs))),
+            _ => Err(anyhow::anyhow!("Invalid wall interval: {}", s)),
+        }
+    }
+}
+
+/// Options of the processing pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessOptions {
+    /// Amount of seconds used to buffer and order the logs.
+    pub buffer_seconds: usize,
+    /// Groups with more logs than this are split into several groups with the same time.
+    pub max_group_len: Option<usize>,
+    /// Skips the buffer and processes the logs as they come. Only for pre-sorted input.
+    pub no_buffer: bool,
+    /// Replaces the remote hosts with their names using reverse DNS.
+    pub resolve_dns: bool,
+    /// How the logs are read.
+    pub reader: ReaderOptions,
+    /// Only the logs with a matching status will be processed.
+    pub only_status: Option<StatusMatcher>,
+    /// Paces the groups by the time between them, this amount of times faster.
+    pub replay_speed: Option<usize>,
+    /// Ticks the processors every interval of real time, whatever the time of the logs.
+    pub wall_interval: Option<WallInterval>,
+    /// Where the processors write their output.
+    pub output: Output,
+    /// Reports the groups leaving the buffer out of order.
+    pub verify_ordering: bool,
+    /// Writes the time spent in every stage of the pipeline once the logs end.
+    pub profile: bool,
+    /// Stops reading the logs after this amount of real time, finalizing the processors.
+    pub max_runtime: Option<Duration>,
+    /// Saves the progress so a later run over the same input resumes from it.
+    pub checkpoint: Option<Checkpointer>,
+    /// Aborts the run after this amount of records in a row which can't be parsed.
+    pub max_consecutive_errors: Option<NonZeroUsize>,
+}
+
+impl Default for ProcessOptions {
+    fn default() -> Self {
+        Self {
+            buffer_seconds: 2,
+            max_group_len: None,
+            no_buffer: false,
+            resolve_dns: false,
+            reader: ReaderOptions::default(),
+            only_status: None,
+            replay_speed: None,
+            wall_interval: None,
+            output: Output::Stdout,
+            verify_ordering: false,
+            profile: false,
+            max_runtime: None,
+            checkpoint: None,
+            max_consecutive_errors: None,
+        }
+    }
+}
+
+/// How the processing ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+    /// All the logs were processed.
+    Done,
+    /// The maximum runtime elapsed before the logs ended.
+    TimedOut,
+    /// The output was closed (e.g. piped into `head`), so nothing else could be written.
+    OutputClosed,
+}
+
+/// Processes all the logs coming from several async readers, merged by time
+#[instrument(skip(readers, processors, async_processors))]
 pub async fn process_logs<'a>(
-    reader: &'a mut AsyncReader,
-    mut processors: Vec<Box<dyn Processor>>,
-) -> anyhow::Result<()> {
-    // reading and buffering in order to order the logs
-    // we'll use a 2 secs buffer
-    let log_stream = read_csv_async(reader).await;
-    let mut grouped_log_stream = BufferedLogs::new(log_stream, 2);
+    readers: Vec<&'a mut AsyncReader>,
+    processors: Vec<Box<dyn Processor>>,
+    async_processors: Vec<Box<dyn AsyncProcessor>>,
+    options: &ProcessOptions,
+) -> anyhow::Result<Completion> {
+    // the headers are read within the pipeline, so an input without them can't block it
+    let log_stream = stream::once(read_many_async(readers, options.reader.clone())).flatten();
+    process_log_stream(log_stream, processors, async_processors, options).await
+}
+
+/// Processes all the logs coming from a stream, whatever their source.
+#[instrument(skip(log_stream, processors, async_processors))]
+pub async fn process_log_stream(
+    log_stream: impl Stream<Item = LogResult>,
+    processors: Vec<Box<dyn Processor>>,
+    async_processors: Vec<Box<dyn AsyncProcessor>>,
+    options: &ProcessOptions,
+) -> anyhow::Result<Completion> {
+    let (completion, profile) =
+        run_pipeline(log_stream, processors, async_processors, options).await?;
+    if let Some(profile) = profile.filter(|_| completion != Completion::OutputClosed) {
+        write!(&options.output, "{}", profile)?;
+    }
+    Ok(completion)
+}
+
+/// Runs the logs through the pipeline, returning how it ended and the time spent in every
+/// stage if profiling.
+async fn run_pipeline(
+    log_stream: impl Stream<Item = LogResult>,
+    processors: Vec<Box<dyn Processor>>,
+    mut async_processors: Vec<Box<dyn AsyncProcessor>>,
+    options: &ProcessOptions,
+) -> anyhow::Result<(Completion, Option<Profile>)> {
+    if options.checkpoint.is_some() && !async_processors.is_empty() {
+        anyhow::bail!("--checkpoint can't resume the async processors");
+    }
+    let reading = options.profile.then(|| Arc::new(StageTimer::default()));
+    let grouping = options.profile.then(|| Arc::new(StageTimer::default()));
+    let mut processing = Duration::ZERO;
+
+    // buffering in order to order the logs
+    let log_stream = Timed::new(Box::pin(log_stream), reading.clone()).filter(|log| {
+        // parsing errors are kept so they're reported when buffering
+        let keep = match (log, &options.only_status) {
+            (Ok(log), Some(only_status)) => only_status.matches(log.status),
+            _ => true,
+        };
+        future::ready(keep)
+    });
+    let error_breaker = ErrorBreaker::new(options.max_consecutive_errors);
+    let grouped_log_stream = if options.no_buffer {
+        ConsecutiveLogs::new(log_stream)
+            .with_error_breaker(error_breaker.clone())
+            .left_stream()
+    } else {
+        BufferedLogs::new(log_stream, options.buffer_seconds)
+            .with_max_group_len(options.max_group_len)
+            .with_error_breaker(error_breaker.clone())
+            .right_stream()
+    };
+    // the pacing of the replay isn't part of any stage
+    let grouped_log_stream = Timed::new(grouped_log_stream, grouping.clone());
+    let mut grouped_log_stream = match options.replay_speed {
+        Some(speed) => Box::pin(replay(grouped_log_stream, speed)).left_stream(),
+        None => grouped_log_stream.right_stream(),
+    };
+
+    // the processors which panic are disabled, dropping them
+    let mut processors = processors.into_iter().map(Some).collect::<Vec<_>>();
+    let host_resolver = options
+        .resolve_dns
+        .then(|| HostResolver::new(SystemResolver));
+
+    let mut ticks = options.wall_interval.map(|WallInterval(period)| {
+        let mut ticks = interval_at(Instant::now() + period, period);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticks
+    });
+
+    let mut ordering_check = options.verify_ordering.then(OrderingCheck::default);
+
+    let deadline = options
+        .max_runtime
+        .map(|max_runtime| Instant::now() + max_runtime);
+    let mut completion = Completion::Done;
+    // set by the processors failing to write to a closed output
+    let is_output_closed = AtomicBool::new(false);
 
     // sending logs to all processors in a parallel way
-    while let Some(log_group) = grouped_log_stream.next().await {
+    loop {
+        let log_group = tokio::select! {
+            log_group = grouped_log_stream.next() => match log_group {
+                Some(log_group) => log_group,
+                None => break,
+            },
+            elapsed = next_tick(&mut ticks) => {
+                let start = std::time::Instant::now();
+                tick_processors(&mut processors, elapsed, &mut &options.output, &is_output_closed);
+                processing += start.elapsed();
+                if is_output_closed.load(Ordering::Relaxed) {
+                    tracing::info!("The output was closed, stopping");
+                    completion = Completion::OutputClosed;
+                    break;
+                }
+                continue;
+            }
+            _ = sleep_until(deadline) => {
+                tracing::warn!("The maximum runtime elapsed, stopping");
+                completion = Completion::TimedOut;
+                break;
+            }
+        };
+        if let Some(checkpointer) = &options.checkpoint {
+            checkpointer.group_taken();
+        }
+        let start = std::time::Instant::now();
+        if let Some(ordering_check) = &mut ordering_check {
+            ordering_check.check(&log_group);
+        }
+        let log_group = match &host_resolver {
+            Some(host_resolver) => host_resolver.resolve(log_group),
+            None => log_group,
+        };
         let log_group = Arc::new(log_group);
-        processors.par_iter_mut().for_each(|processor| {
-            if let Err(e) = processor.process(&log_group.clone(), &mut std::io::stdout()) {
-                tracing::error!("Error processing log group: {:?} - {:?}", log_group, e);
+        process_group(
+            &mut processors,
+            &log_group,
+            &mut &options.output,
+            &is_output_closed,
+        );
+        // the async processors are awaited concurrently
+        let results = future::join_all(
+            async_processors
+                .iter_mut()
+                .map(|processor| processor.process(&log_group)),
+        )
+        .await;
+        for (processor, result) in async_processors.iter().zip(results) {
+            if let Err(e) = result {
+                tracing::error!(
+                    "Error processing log group in {}: {:?} - {:?}",
+                    processor.name(),
+                    log_group,
+                    e
+                );
             }
-        });
+        }
+        processing += start.elapsed();
+        if let Some(checkpointer) = &options.checkpoint {
+            checkpointer.group_processed(&processors);
+        }
+        if is_output_closed.load(Ordering::Relaxed) {
+            tracing::info!("The output was closed, stopping");
+            completion = Completion::OutputClosed;
+            break;
+        }
+    }
+    // the output of a run over the wrong input is meaningless, so nothing is finalized
+    error_breaker.check()?;
+
+    // saved before finalizing, as the processors may change their state then
+    if let Some(checkpointer) = &options.checkpoint {
+        match completion {
+            Completion::Done => checkpointer.remove(),
+            _ => checkpointer.save(&processors),
+        }
+    }
+
+    if let Some(ordering_check) = &ordering_check {
+        ordering_check.report();
     }
+
+    // letting processors flush whatever they still have buffered
+    let start = std::time::Instant::now();
+    finalize_processors(&mut processors, &mut &options.output, &is_output_closed);
+    let results = future::join_all(
+        async_processors
+            .iter_mut()
+            .map(|processor| processor.finalize()),
+    )
+    .await;
+    for (processor, result) in async_processors.iter().zip(results) {
+        if let Err(e) = result {
+            tracing::error!("Error finalizing {}: {:?}", processor.name(), e);
+        }
+    }
+    processing += start.elapsed();
     tracing::info!("Processing done!");
-    Ok(())
+    if let Some(max_runtime) = options
+        .max_runtime
+        .filter(|_| completion != Completion::OutputClosed)
+    {
+        write!(
+            &options.output,
+            "{}",
+            runtime_message(max_runtime, completion == Completion::TimedOut)
+        )?;
+    }
+    let profile = reading.zip(grouping).map(|(reading, grouping)| Profile {
+        reading: reading.elapsed(),
+        // the reading happens while polling the buffer
+        buffering: grouping.elapsed().saturating_sub(reading.elapsed()),
+        processing,
+        logs: reading.items(),
+        groups: grouping.items(),
+    });
+    Ok((completion, profile))
+}
+
+/// Whether the error comes from writing to a closed pipe.
+fn is_broken_pipe(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
+/// Waits for the deadline, forever without it.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => future::pending().await,
+    }
+}
+
+/// Tells whether the logs ended or the processing was stopped by the maximum runtime.
+fn runtime_message(max_runtime: Duration, is_timed_out: bool) -> String {
+    match is_timed_out {
+        true => console::style(format!(
+            "\nSTOPPED: the maximum runtime ({:?}) elapsed, the results only cover the logs read until then\n",
+            max_runtime
+        ))
+        .bold()
+        .yellow()
+        .to_string(),
+        false => console::style(format!(
+            "\nCOMPLETED: all the logs were processed within the maximum runtime ({:?})\n",
+            max_runtime
+        ))
+        .bold()
+        .green()
+        .to_string(),
+    }
+}
+
+/// Real time since the previous tick, once the next one comes. Never without ticks.
+async fn next_tick(ticks: &mut Option<Interval>) -> Duration {
+    match ticks {
+        Some(ticks) => {
+            ticks.tick().await;
+            ticks.period()
+        }
+        None => future::pending().await,
+    }
+}
+
+/// Sends a group to the processors in parallel. Every processor writes to its own buffer,
+/// and the buffers are written to the output in the order of the processors once they're
+/// all done, so the lines of different processors never interleave.
+fn process_group(
+    processors: &mut [Option<Box<dyn Processor>>],
+    log_group: &Arc<GroupedHttpLogs>,
+    output: &mut dyn Write,
+    is_output_closed: &AtomicBool,
+) {
+    let outputs = processors
+        .par_iter_mut()
+        .map(|slot| {
+            let mut buffer = Vec::new();
+            let processor = match slot {
+                Some(processor) => processor,
+                None => return buffer,
+            };
+            // skipping the processors waiting for a later group
+            if processor
+                .next_interesting_time()
+                .is_some_and(|time| log_group.time < time)
+            {
+                return buffer;
+            }
+            match catch_panic(|| processor.process(log_group, &mut buffer)) {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) if is_broken_pipe(&e) => is_output_closed.store(true, Ordering::Relaxed),
+                Ok(Err(e)) => tracing::error!(
+                    "Error processing log group in {}: {:?} - {:?}",
+                    processor.name(),
+                    log_group,
+                    e
+                ),
+                Err(panic) => {
+                    tracing::error!(
+                        "{} panicked processing the group of {}, disabling it: {}",
+                        processor.name(),
+                        log_group.time,
+                        panic
+                    );
+                    *slot = None;
+                }
+            }
+            buffer
+        })
+        .collect::<Vec<_>>();
+    write_buffers(&outputs, output, is_output_closed);
+}
+
+/// Ticks the processors, disabling the ones which panic. Like with the groups, every
+/// processor writes to its own buffer.
+fn tick_processors(
+    processors: &mut [Option<Box<dyn Processor>>],
+    elapsed: Duration,
+    output: &mut dyn Write,
+    is_output_closed: &AtomicBool,
+) {
+    let outputs = processors
+        .iter_mut()
+        .map(|slot| {
+            let mut buffer = Vec::new();
+            let processor = match slot {
+                Some(processor) => processor,
+                None => return buffer,
+            };
+            match catch_panic(|| processor.tick(elapsed, &mut buffer)) {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) if is_broken_pipe(&e) => is_output_closed.store(true, Ordering::Relaxed),
+                Ok(Err(e)) => tracing::error!("Error ticking {}: {:?}", processor.name(), e),
+                Err(panic) => {
+                    tracing::error!(
+                        "{} panicked ticking, disabling it: {}",
+                        processor.name(),
+                        panic
+                    );
+                    *slot = None;
+                }
+            }
+            buffer
+        })
+        .collect::<Vec<_>>();
+    write_buffers(&outputs, output, is_output_closed);
+}
+
+/// Finalizes the processors, every one writing to its own buffer. The processors still
+/// get to finalize, e.g. saving their state, once the output is closed.
+fn finalize_processors(
+    processors: &mut [Option<Box<dyn Processor>>],
+    output: &mut dyn Write,
+    is_output_closed: &AtomicBool,
+) {
+    let outputs = processors
+        .iter_mut()
+        .flatten()
+        .map(|processor| {
+            let mut buffer = Vec::new();
+            match catch_panic(|| processor.finalize(&mut buffer)) {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) if is_broken_pipe(&e) => {
+                    tracing::debug!("{} finalized with the output closed", processor.name())
+                }
+                Ok(Err(e)) => tracing::error!("Error finalizing {}: {:?}", processor.name(), e),
+                Err(panic) => {
+                    tracing::error!("{} panicked finalizing: {}", processor.name(), panic)
+                }
+            }
+            buffer
+        })
+        .collect::<Vec<_>>();
+    if !is_output_closed.load(Ordering::Relaxed) {
+        write_buffers(&outputs, output, is_output_closed);
+    }
+}
+
+/// Writes the buffers of the processors to the output in their order, stopping once the
+/// output is closed.
+fn write_buffers(outputs: &[Vec<u8>], output: &mut dyn Write, is_output_closed: &AtomicBool) {
+    for buffer in outputs.iter().filter(|buffer| !buffer.is_empty()) {
+        match output.write_all(buffer) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                is_output_closed.store(true, Ordering::Relaxed);
+                return;
+            }
+            Err(e) => tracing::error!("Error writing the output of the processors: {}", e),
+        }
+    }
+}
+
+/// Runs a call to a processor, catching its panic so the rest of them can go on.
+fn catch_panic<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|panic| {
+        panic
+            .downcast_ref::<&str>()
+            .map(|msg| msg.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string())
+    })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::processors::MockProcessor;
+    use crate::processors::{MockAsyncProcessor, MockProcessor};
+    use std::sync::Mutex;
 
     use super::*;
 
@@ -73,22 +555,654 @@ mod tests {
             .as_bytes();
 
         let mut mock_processor = MockProcessor::new();
+
+        mock_processor
+            .expect_next_interesting_time()
+            .return_const(None);
         mock_processor
             .expect_process()
             .times(5)
             .returning(|_, _| Ok(()));
+        mock_processor
+            .expect_finalize()
+            .times(1)
+            .returning(|_| Ok(()));
 
         let mut mock_processor2 = MockProcessor::new();
+
+        mock_processor2
+            .expect_next_interesting_time()
+            .return_const(None);
         mock_processor2
             .expect_process()
             .times(5)
             .returning(|_, _| Ok(()));
+        mock_processor2
+            .expect_finalize()
+            .times(1)
+            .returning(|_| Ok(()));
 
         let processors: Vec<Box<dyn Processor>> =
             vec![Box::new(mock_processor), Box::new(mock_processor2)];
 
-        let result = process_logs(&mut input, processors).await;
+        let result = process_logs(
+            vec![&mut input],
+            processors,
+            Vec::new(),
+            &ProcessOptions::default(),
+        )
+        .await;
+
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn only_logs_with_matching_status_are_processed() {
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.5","-","apache",1549573860,"POST /report HTTP/1.0",500,1307
+"10.0.0.3","-","apache",1549573861,"POST /report HTTP/1.0",301,1234
+"10.0.0.2","-","apache",1549573861,"POST /api/user HTTP/1.0",404,1307
+"10.0.0.1","-","apache",1549573862,"GET /api/help HTTP/1.0",200,1136"#
+            .as_bytes();
+
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let processed_clone = processed.clone();
+
+        let mut mock_processor = MockProcessor::new();
+
+        mock_processor
+            .expect_next_interesting_time()
+            .return_const(None);
+        mock_processor
+            .expect_process()
+            .returning(move |log_group, _| {
+                let mut processed = processed_clone.lock().unwrap();
+                processed.extend(log_group.logs.iter().map(|log| log.status));
+                Ok(())
+            });
+        mock_processor.expect_finalize().returning(|_| Ok(()));
+
+        let options = ProcessOptions {
+            only_status: Some("4xx,5xx".parse().unwrap()),
+            ..ProcessOptions::default()
+        };
+
+        let result = process_logs(
+            vec![&mut input],
+            vec![Box::new(mock_processor)],
+            Vec::new(),
+            &options,
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(*processed.lock().unwrap(), vec![500, 404]);
+    }
+
+    #[tokio::test]
+    async fn each_async_processor_is_awaited_n_times() {
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.5","-","apache",1549573859,"POST /report HTTP/1.0",500,1307
+"10.0.0.3","-","apache",1549573861,"POST /report HTTP/1.0",301,1234
+"10.0.0.2","-","apache",1549573861,"POST /api/user HTTP/1.0",404,1307
+"10.0.0.1","-","apache",1549573862,"GET /api/help HTTP/1.0",200,1136"#
+            .as_bytes();
+
+        let mut mock_processor = MockProcessor::new();
+
+        mock_processor
+            .expect_next_interesting_time()
+            .return_const(None);
+        mock_processor
+            .expect_process()
+            .times(4)
+            .returning(|_, _| Ok(()));
+        mock_processor
+            .expect_finalize()
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let mut mock_async_processor = MockAsyncProcessor::new();
+        mock_async_processor
+            .expect_process()
+            .times(4)
+            .returning(|_| Ok(()));
+        mock_async_processor
+            .expect_finalize()
+            .times(1)
+            .returning(|| Ok(()));
+
+        let result = process_logs(
+            vec![&mut input],
+            vec![Box::new(mock_processor)],
+            vec![Box::new(mock_async_processor)],
+            &ProcessOptions::default(),
+        )
+        .await;
 
         assert!(result.is_ok());
     }
+
+    /// Writes the time of a group every `period` seconds, ignoring the groups in between.
+    struct Ticker {
+        period: usize,
+        next_time: usize,
+        throttled: bool,
+        calls: Arc<Mutex<usize>>,
+        output: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Ticker {
+        fn new(period: usize, throttled: bool) -> Self {
+            Self {
+                period,
+                next_time: 0,
+                throttled,
+                calls: Arc::default(),
+                output: Arc::default(),
+            }
+        }
+    }
+
+    impl Processor for Ticker {
+        fn process(
+            &mut self,
+            log_group: &crate::buffered_logs::GroupedHttpLogs,
+            _writer: &mut dyn std::io::Write,
+        ) -> anyhow::Result<()> {
+            *self.calls.lock().unwrap() += 1;
+            if log_group.time >= self.next_time {
+                self.output.lock().unwrap().push(log_group.time);
+                self.next_time = log_group.time + self.period;
+            }
+            Ok(())
+        }
+
+        fn next_interesting_time(&self) -> Option<usize> {
+            self.throttled.then_some(self.next_time)
+        }
+    }
+
+    #[tokio::test]
+    async fn throttled_processors_are_called_less_with_the_same_output() {
+        let input = (0..20)
+            .map(|i| {
+                format!(
+                    r#""10.0.0.2","-","apache",{},"GET /api/user HTTP/1.0",200,1234"#,
+                    1549573860 + i
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let input = format!(
+            "\"remotehost\",\"rfc931\",\"authuser\",\"date\",\"request\",\"status\",\"bytes\"\n{}",
+            input
+        );
+
+        let always = Ticker::new(5, false);
+        let throttled = Ticker::new(5, true);
+        let (always_calls, always_output) = (always.calls.clone(), always.output.clone());
+        let (throttled_calls, throttled_output) =
+            (throttled.calls.clone(), throttled.output.clone());
+
+        let result = process_logs(
+            vec![&mut std::io::Cursor::new(input.into_bytes())],
+            vec![Box::new(always), Box::new(throttled)],
+            Vec::new(),
+            &ProcessOptions::default(),
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(*always_calls.lock().unwrap(), 20);
+        assert_eq!(*throttled_calls.lock().unwrap(), 4);
+        assert_eq!(
+            *always_output.lock().unwrap(),
+            vec![1549573860, 1549573865, 1549573870, 1549573875]
+        );
+        assert_eq!(
+            *throttled_output.lock().unwrap(),
+            *always_output.lock().unwrap()
+        );
+    }
+
+    /// Panics when it gets the group of a given time.
+    struct PanicsAt {
+        time: usize,
+        finalized: Arc<Mutex<bool>>,
+    }
+
+    impl Processor for PanicsAt {
+        fn process(
+            &mut self,
+            log_group: &crate::buffered_logs::GroupedHttpLogs,
+            _writer: &mut dyn std::io::Write,
+        ) -> anyhow::Result<()> {
+            assert_ne!(log_group.time, self.time, "panicking on purpose");
+            Ok(())
+        }
+
+        fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+            *self.finalized.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn a_panicking_processor_does_not_stop_the_rest() {
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.5","-","apache",1549573861,"POST /report HTTP/1.0",500,1307
+"10.0.0.3","-","apache",1549573862,"POST /report HTTP/1.0",301,1234
+"10.0.0.1","-","apache",1549573863,"GET /api/help HTTP/1.0",200,1136"#
+            .as_bytes();
+
+        let ticker = Ticker::new(1, false);
+        let panics_at = PanicsAt {
+            time: 1549573861,
+            finalized: Arc::default(),
+        };
+        let finalized = panics_at.finalized.clone();
+        let (calls, output) = (ticker.calls.clone(), ticker.output.clone());
+        let mut mock_processor = MockProcessor::new();
+        mock_processor
+            .expect_next_interesting_time()
+            .return_const(None);
+        mock_processor
+            .expect_process()
+            .times(4)
+            .returning(|_, _| Ok(()));
+        mock_processor
+            .expect_finalize()
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let result = process_logs(
+            vec![&mut input],
+            vec![
+                Box::new(ticker),
+                Box::new(panics_at),
+                Box::new(mock_processor),
+            ],
+            Vec::new(),
+            &ProcessOptions::default(),
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(*calls.lock().unwrap(), 4);
+        assert_eq!(
+            *output.lock().unwrap(),
+            vec![1549573860, 1549573861, 1549573862, 1549573863]
+        );
+        // the panicking processor was disabled
+        assert!(!*finalized.lock().unwrap());
+    }
+
+    /// Fails processing every group.
+    struct AlwaysFails;
+
+    impl Processor for AlwaysFails {
+        fn process(
+            &mut self,
+            log_group: &crate::buffered_logs::GroupedHttpLogs,
+            _writer: &mut dyn std::io::Write,
+        ) -> anyhow::Result<()> {
+            anyhow::bail!("failing on purpose at {}", log_group.time)
+        }
+    }
+
+    #[tokio::test]
+    async fn processor_errors_are_logged_with_the_processor_name() {
+        let logs = crate::test_utils::captured_logs();
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234"#
+            .as_bytes();
+
+        let result = process_logs(
+            vec![&mut input],
+            vec![Box::new(AlwaysFails)],
+            Vec::new(),
+            &ProcessOptions::default(),
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(AlwaysFails.name(), "AlwaysFails");
+        assert!(logs.contents().lines().any(|line| {
+            line.contains("Error processing log group in AlwaysFails")
+                && line.contains("failing on purpose at 1549573860")
+        }));
+    }
+
+    #[tokio::test]
+    async fn a_too_small_buffer_is_reported_when_verifying_the_ordering() {
+        let logs = crate::test_utils::captured_logs();
+        // 1549570082 arrives once 1549570084 has already left the buffer
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549570080,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.2","-","apache",1549570084,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.2","-","apache",1549570088,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.2","-","apache",1549570082,"GET /api/user HTTP/1.0",200,1234"#
+            .as_bytes();
+        let options = ProcessOptions {
+            verify_ordering: true,
+            ..ProcessOptions::default()
+        };
+
+        process_logs(vec![&mut input], Vec::new(), Vec::new(), &options)
+            .await
+            .unwrap();
+
+        let logs = logs.contents();
+        assert!(logs.lines().any(|line| line.contains(
+            "ORDERING VIOLATION: group 1549570082 emitted after group 1549570084 (1 of 3 groups out of order so far)"
+        )), "{}", logs);
+        assert!(logs
+            .lines()
+            .any(|line| line.contains("ORDERING VIOLATIONS: 1 of 4 groups were out of order")));
+    }
+
+    #[tokio::test]
+    async fn the_profile_has_the_time_of_every_stage() {
+        let sample = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/sample.csv")).unwrap();
+        let records = sample
+            .split(|b| *b == b'\n')
+            .skip(1)
+            .filter(|l| !l.is_empty())
+            .count();
+        let mut input = std::io::Cursor::new(sample);
+        let options = ProcessOptions {
+            profile: true,
+            ..ProcessOptions::default()
+        };
+        let log_stream = read_many_async(vec![&mut input], options.reader.clone()).await;
+
+        let (_, profile) = run_pipeline(
+            log_stream,
+            vec![Box::new(crate::processors::Stats::builder().build())],
+            Vec::new(),
+            &options,
+        )
+        .await
+        .unwrap();
+        let profile = profile.unwrap();
+
+        assert!(profile.reading > Duration::ZERO);
+        assert!(profile.buffering > Duration::ZERO);
+        assert!(profile.processing > Duration::ZERO);
+        assert_eq!(profile.logs, records);
+        assert!(profile.groups > 0 && profile.groups <= records);
+        let report = console::strip_ansi_codes(&profile.to_string()).to_string();
+        for stage in ["Reading: ", "Buffering: ", "Processing: "] {
+            assert!(report.contains(stage), "{}", report);
+        }
+        assert!(report.contains(&format!("{} logs", records)), "{}", report);
+    }
+
+    #[tokio::test]
+    async fn parses_the_wall_intervals() {
+        let parse = |s: &str| s.parse::<WallInterval>().map(|WallInterval(d)| d.as_secs());
+
+        assert_eq!(parse("60s").unwrap(), 60);
+        assert_eq!(parse("45").unwrap(), 45);
+        assert_eq!(parse("5m").unwrap(), 300);
+        assert_eq!(parse("1h").unwrap(), 3600);
+        assert!(parse("0s").is_err());
+        assert!(parse("1d").is_err());
+    }
+
+    /// Counts the groups and the ticks it gets.
+    #[derive(Default)]
+    struct TickCounter {
+        groups: Arc<Mutex<usize>>,
+        ticks: Arc<Mutex<Vec<Duration>>>,
+        is_finalized: Arc<Mutex<bool>>,
+    }
+
+    impl Processor for TickCounter {
+        fn process(
+            &mut self,
+            _log_group: &crate::buffered_logs::GroupedHttpLogs,
+            _writer: &mut dyn std::io::Write,
+        ) -> anyhow::Result<()> {
+            *self.groups.lock().unwrap() += 1;
+            Ok(())
+        }
+
+        fn tick(
+            &mut self,
+            elapsed: Duration,
+            _writer: &mut dyn std::io::Write,
+        ) -> anyhow::Result<()> {
+            self.ticks.lock().unwrap().push(elapsed);
+            Ok(())
+        }
+
+        fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+            *self.is_finalized.lock().unwrap() = true;
+            Ok(())
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn processors_are_ticked_on_the_wall_interval_without_new_logs() {
+        let counter = TickCounter::default();
+        let (groups, ticks) = (counter.groups.clone(), counter.ticks.clone());
+        // a couple of logs and then nothing, as a followed file without traffic.
+        // Only the group of the first one is complete
+        let log_stream = futures::stream::iter(
+            [1549573860, 1549573861]
+                .map(|time| Ok(crate::test_utils::build_test_http_log(time, None))),
+        )
+        .chain(futures::stream::pending());
+        let options = ProcessOptions {
+            no_buffer: true,
+            wall_interval: Some(WallInterval(Duration::from_secs(60))),
+            ..ProcessOptions::default()
+        };
+
+        // the paused clock jumps to the next timer as soon as the processing is idle
+        let result = tokio::time::timeout(
+            Duration::from_secs(150),
+            process_log_stream(log_stream, vec![Box::new(counter)], Vec::new(), &options),
+        )
+        .await;
+
+        assert!(result.is_err(), "the processing never ends");
+        assert_eq!(*groups.lock().unwrap(), 1);
+        assert_eq!(
+            *ticks.lock().unwrap(),
+            vec![Duration::from_secs(60), Duration::from_secs(60)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn the_processing_stops_after_the_max_runtime() {
+        let counter = TickCounter::default();
+        let (groups, is_finalized) = (counter.groups.clone(), counter.is_finalized.clone());
+        // an input which never ends, as a followed file
+        let log_stream = futures::stream::iter(
+            [1549573860, 1549573861]
+                .map(|time| Ok(crate::test_utils::build_test_http_log(time, None))),
+        )
+        .chain(futures::stream::pending());
+        let options = ProcessOptions {
+            no_buffer: true,
+            max_runtime: Some(Duration::from_secs(30)),
+            ..ProcessOptions::default()
+        };
+
+        let start = Instant::now();
+        let result = tokio::time::timeout(
+            Duration::from_secs(150),
+            process_log_stream(log_stream, vec![Box::new(counter)], Vec::new(), &options),
+        )
+        .await;
+
+        assert_eq!(result.unwrap().unwrap(), Completion::TimedOut);
+        assert_eq!(start.elapsed(), Duration::from_secs(30));
+        assert_eq!(*groups.lock().unwrap(), 1);
+        assert!(*is_finalized.lock().unwrap());
+    }
+
+    /// Fails every write as if the output was piped into `head` and it already exited.
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn the_processing_stops_cleanly_once_the_output_is_closed() {
+        let groups = Arc::new(Mutex::new(0));
+        let groups_clone = groups.clone();
+        let mut mock_processor = MockProcessor::new();
+        mock_processor
+            .expect_next_interesting_time()
+            .return_const(None);
+        mock_processor.expect_process().returning(move |_, _| {
+            *groups_clone.lock().unwrap() += 1;
+            writeln!(ClosedPipe, "STATS")?;
+            Ok(())
+        });
+        mock_processor
+            .expect_finalize()
+            .times(1)
+            .returning(|_| Ok(writeln!(ClosedPipe, "FINAL STATS")?));
+        let log_stream = futures::stream::iter(
+            [1549573860, 1549573861, 1549573862]
+                .map(|time| Ok(crate::test_utils::build_test_http_log(time, None))),
+        );
+        let options = ProcessOptions {
+            no_buffer: true,
+            ..ProcessOptions::default()
+        };
+
+        let completion = process_log_stream(
+            log_stream,
+            vec![Box::new(mock_processor)],
+            Vec::new(),
+            &options,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(completion, Completion::OutputClosed);
+        assert_eq!(*groups.lock().unwrap(), 1);
+        assert!(is_broken_pipe(
+            &anyhow::Error::from(io::Error::from(io::ErrorKind::BrokenPipe))
+                .context("Can't write the stats")
+        ));
+    }
+
+    /// Writes a long line of its letter per log, a byte at a time.
+    struct LongLines(u8);
+
+    impl Processor for LongLines {
+        fn process(
+            &mut self,
+            log_group: &GroupedHttpLogs,
+            writer: &mut dyn std::io::Write,
+        ) -> anyhow::Result<()> {
+            for _ in &log_group.logs {
+                for _ in 0..2_000 {
+                    writer.write_all(&[self.0])?;
+                    std::thread::yield_now();
+                }
+                writer.write_all(b"\n")?;
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn the_output_of_parallel_processors_never_interleaves() {
+        let mut processors: Vec<Option<Box<dyn Processor>>> = vec![
+            Some(Box::new(LongLines(b'a'))),
+            Some(Box::new(LongLines(b'b'))),
+        ];
+        let mut output = Vec::new();
+        let is_output_closed = AtomicBool::new(false);
+
+        for time in 0..10 {
+            let log_group = Arc::new(crate::test_utils::build_test_http_grouped_log(
+                time, 2, None,
+            ));
+            process_group(&mut processors, &log_group, &mut output, &is_output_closed);
+        }
+
+        let output = String::from_utf8(output).unwrap();
+        let a = "a".repeat(2_000);
+        let b = "b".repeat(2_000);
+        // the lines of every group in the order of the processors
+        let expected = [&a, &a, &b, &b].map(|line| format!("{}\n", line)).concat();
+        assert_eq!(output, expected.repeat(10));
+        assert!(!is_output_closed.load(Ordering::Relaxed));
+    }
+
+    /// Writes its letter once finalized.
+    struct Summary(u8, Arc<AtomicBool>);
+
+    impl Processor for Summary {
+        fn process(
+            &mut self,
+            _log_group: &GroupedHttpLogs,
+            _writer: &mut dyn std::io::Write,
+        ) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn finalize(&mut self, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+            self.1.store(true, Ordering::Relaxed);
+            writer.write_all(&[self.0, b'\n'])?;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn the_processors_are_finalized_through_their_buffers() {
+        let is_finalized = Arc::new(AtomicBool::new(false));
+        let mut processors: Vec<Option<Box<dyn Processor>>> = vec![
+            Some(Box::new(Summary(b'a', is_finalized.clone()))),
+            None,
+            Some(Box::new(Summary(b'b', is_finalized.clone()))),
+        ];
+        let mut output = Vec::new();
+        let is_output_closed = AtomicBool::new(false);
+
+        finalize_processors(&mut processors, &mut output, &is_output_closed);
+        assert_eq!(output, b"a\nb\n");
+
+        // once the output is closed, they still finalize but nothing is written
+        let mut output = Vec::new();
+        is_finalized.store(false, Ordering::Relaxed);
+        is_output_closed.store(true, Ordering::Relaxed);
+        finalize_processors(&mut processors, &mut output, &is_output_closed);
+        assert!(output.is_empty());
+        assert!(is_finalized.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn the_runtime_message_tells_how_the_processing_ended() {
+        let stopped = runtime_message(Duration::from_secs(30), true);
+        let completed = runtime_message(Duration::from_secs(30), false);
+
+        assert!(console::strip_ansi_codes(&stopped)
+            .starts_with("\nSTOPPED: the maximum runtime (30s) elapsed"));
+        assert!(console::strip_ansi_codes(&completed).starts_with("\nCOMPLETED: all the logs"));
+    }
 }
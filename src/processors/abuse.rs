@@ -0,0 +1,275 @@
+use super::alerts::{AlertFormat, AlertProcessor};
+use super::keyed_alert::KeyedAlert;
+use super::GroupedHttpLogs;
+use super::Processor;
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    str::FromStr,
+};
+use tracing::instrument;
+
+/// Amount of requests a single host can do within the window before being flagged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AbuseThreshold {
+    /// Absolute amount of requests, e.g. `500`.
+    Absolute(usize),
+    /// Fraction of the total requests, e.g. `0.5` or `50%`.
+    Fraction(f64),
+}
+
+impl AbuseThreshold {
+    fn is_exceeded(self, host_reqs: usize, total_reqs: usize) -> bool {
+        match self {
+            Self::Absolute(max) => host_reqs > max,
+            Self::Fraction(max) => host_reqs as f64 > max * total_reqs as f64,
+        }
+    }
+}
+
+impl FromStr for AbuseThreshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow::anyhow!("Invalid abuse threshold: {}", s);
+        let fraction = if let Some(percentage) = s.strip_suffix('%') {
+            percentage.parse::<f64>().map_err(|_| invalid())? / 100.0
+        } else if s.contains('.') {
+            s.parse::<f64>().map_err(|_| invalid())?
+        } else {
+            return s.parse().map(Self::Absolute).map_err(|_| invalid());
+        };
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(invalid());
+        }
+        Ok(Self::Fraction(fraction))
+    }
+}
+
+const ABUSE_ALERT: KeyedAlert = KeyedAlert {
+    name: "abuse",
+    key_kind: "host",
+    triggered: "Possible abuse from {key}",
+    recovered: "Traffic from {key} recovered",
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HostCounter {
+    time: usize,
+    req_count_by_host: HashMap<String, usize>,
+}
+
+impl From<&GroupedHttpLogs> for HostCounter {
+    fn from(g: &GroupedHttpLogs) -> Self {
+        let mut req_count_by_host = HashMap::new();
+        for log in &g.logs {
+            *req_count_by_host
+                .entry(log.remote_host.clone())
+                .or_insert(0) += 1;
+        }
+        HostCounter {
+            time: g.time,
+            req_count_by_host,
+        }
+    }
+}
+
+/// Alerts when a single host does a disproportionate amount of requests within a sliding window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbuseDetector {
+    threshold: AbuseThreshold,
+    window_size_in_secs: usize,
+    format: AlertFormat,
+    buffer: VecDeque<HostCounter>,
+    req_count_by_host: HashMap<String, usize>,
+    total_reqs: usize,
+    flagged_hosts: BTreeSet<String>,
+}
+
+impl AbuseDetector {
+    pub fn new(threshold: AbuseThreshold, window_size_in_secs: usize) -> Self {
+        Self {
+            threshold,
+            window_size_in_secs,
+            format: AlertFormat::Banner,
+            buffer: VecDeque::new(),
+            req_count_by_host: HashMap::new(),
+            total_reqs: 0,
+            flagged_hosts: BTreeSet::new(),
+        }
+    }
+
+    fn add(&mut self, host_counter: HostCounter) {
+        for (host, req_count) in &host_counter.req_count_by_host {
+            *self.req_count_by_host.entry(host.clone()).or_insert(0) += req_count;
+            self.total_reqs += req_count;
+        }
+        self.buffer.push_back(host_counter);
+    }
+
+    /// Drains the host counters which are out of the window.
+    fn evict_older_than(&mut self, minor_time: usize) {
+        while let Some(host_counter) = self.buffer.front() {
+            if host_counter.time >= minor_time {
+                break;
+            }
+            for (host, req_count) in &host_counter.req_count_by_host {
+                if let Some(count) = self.req_count_by_host.get_mut(host) {
+                    *count -= req_count;
+                    if *count == 0 {
+                        self.req_count_by_host.remove(host);
+                    }
+                }
+                self.total_reqs -= req_count;
+            }
+            self.buffer.pop_front();
+        }
+    }
+
+    fn host_reqs(&self, host: &str) -> usize {
+        self.req_count_by_host
+            .get(host)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl AlertProcessor for AbuseDetector {
+    fn set_format(&mut self, format: AlertFormat) {
+        self.format = format;
+    }
+}
+
+impl Processor for AbuseDetector {
+    #[instrument(skip(self, writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        self.add(HostCounter::from(log_group));
+        self.evict_older_than(log_group.time.saturating_sub(self.window_size_in_secs));
+
+        let abusive_hosts = self
+            .req_count_by_host
+            .iter()
+            .filter(|(_, req_count)| self.threshold.is_exceeded(**req_count, self.total_reqs))
+            .map(|(host, _)| host.clone())
+            .collect::<BTreeSet<_>>();
+
+        for host in abusive_hosts.difference(&self.flagged_hosts) {
+            let details = format!("hits = {} of {}", self.host_reqs(host), self.total_reqs);
+            ABUSE_ALERT.trace_triggered(host, &details, log_group.time);
+            let msg = ABUSE_ALERT.triggered(host, &details, log_group.time);
+            writer.write_all(self.format.layout(&abuse_prefix(), &msg).as_bytes())?;
+        }
+
+        for host in self.flagged_hosts.difference(&abusive_hosts) {
+            let details = format!("hits = {} of {}", self.host_reqs(host), self.total_reqs);
+            ABUSE_ALERT.trace_recovered(host, &details, log_group.time);
+            let msg = ABUSE_ALERT.recovered(host, &details, log_group.time);
+            writer.write_all(self.format.layout(&abuse_prefix(), &msg).as_bytes())?;
+        }
+
+        self.flagged_hosts = abusive_hosts;
+        Ok(())
+    }
+}
+
+fn abuse_prefix() -> String {
+    console::style("\n>>> ABUSE\n").bold().red().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_test_http_grouped_log_with, run_processor as run};
+
+    fn build_group(time: usize, hosts: &[(&str, usize)]) -> GroupedHttpLogs {
+        let logs = hosts
+            .iter()
+            .flat_map(|(host, len)| {
+                build_test_http_grouped_log_with(time, *len, |log| {
+                    log.remote_host = host.to_string()
+                })
+                .logs
+            })
+            .collect();
+        GroupedHttpLogs { time, logs }
+    }
+
+    #[tokio::test]
+    async fn noisy_host_trips_the_detector() {
+        let mut detector = AbuseDetector::new(AbuseThreshold::Absolute(5), 10);
+
+        let msg = run(
+            &mut detector,
+            vec![
+                build_group(1, &[("10.0.0.1", 3), ("10.0.0.2", 1)]),
+                build_group(2, &[("10.0.0.1", 3)]),
+                build_group(20, &[("10.0.0.1", 1)]),
+            ],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{0}Possible abuse from 10.0.0.1 - hits = 6 of 7, triggered at 2\n{0}Traffic from 10.0.0.1 recovered - hits = 1 of 1, recovered at 20\n",
+                abuse_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn distributed_traffic_does_not_trip_the_detector() {
+        let mut detector = AbuseDetector::new(AbuseThreshold::Fraction(0.5), 10);
+
+        let msg = run(
+            &mut detector,
+            vec![
+                build_group(1, &[("10.0.0.1", 3), ("10.0.0.2", 3), ("10.0.0.3", 3)]),
+                build_group(2, &[("10.0.0.1", 2), ("10.0.0.2", 2), ("10.0.0.3", 2)]),
+            ],
+        );
+
+        assert!(msg.is_empty());
+    }
+
+    #[tokio::test]
+    async fn host_over_the_fraction_trips_the_detector() {
+        let mut detector = AbuseDetector::new("50%".parse().unwrap(), 10);
+
+        let msg = run(
+            &mut detector,
+            vec![build_group(
+                1,
+                &[("10.0.0.1", 6), ("10.0.0.2", 2), ("10.0.0.3", 2)],
+            )],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{}Possible abuse from 10.0.0.1 - hits = 6 of 10, triggered at 1\n",
+                abuse_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_thresholds() {
+        assert_eq!(
+            "500".parse::<AbuseThreshold>().unwrap(),
+            AbuseThreshold::Absolute(500)
+        );
+        assert_eq!(
+            "0.25".parse::<AbuseThreshold>().unwrap(),
+            AbuseThreshold::Fraction(0.25)
+        );
+        assert_eq!(
+            "25%".parse::<AbuseThreshold>().unwrap(),
+            AbuseThreshold::Fraction(0.25)
+        );
+        assert!("1.5".parse::<AbuseThreshold>().is_err());
+        assert!("abc".parse::<AbuseThreshold>().is_err());
+    }
+}
@@ -0,0 +1,221 @@
+use super::alerts::{AlertFormat, AlertProcessor, LogCounter};
+use super::GroupedHttpLogs;
+use super::Processor;
+use std::collections::VecDeque;
+use tracing::instrument;
+
+/// Lowest standard deviation of the baseline, so a flat traffic doesn't alert on
+/// a single extra request.
+const MIN_STDDEV: f64 = 1.0;
+
+/// Alerts when the requests of a second go over the mean of the previous seconds plus
+/// `sigma` times their standard deviation, so the threshold follows the traffic cycles.
+/// It doesn't alert until the baseline window is full.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveAlerts {
+    sigma: f64,
+    window_size_in_secs: usize,
+    format: AlertFormat,
+    // completed seconds of the window, the ones without logs included
+    baseline: VecDeque<LogCounter>,
+    sum: u128,
+    sum_of_squares: u128,
+    current: Option<LogCounter>,
+    is_alert_set: bool,
+}
+
+impl AdaptiveAlerts {
+    pub fn new(sigma: f64, window_size_in_secs: usize) -> Self {
+        Self {
+            sigma,
+            window_size_in_secs,
+            format: AlertFormat::Banner,
+            baseline: VecDeque::new(),
+            sum: 0,
+            sum_of_squares: 0,
+            current: None,
+            is_alert_set: false,
+        }
+    }
+
+    fn push(&mut self, log_counter: LogCounter) {
+        let req_count = log_counter.req_count as u128;
+        self.sum += req_count;
+        self.sum_of_squares += req_count * req_count;
+        self.baseline.push_back(log_counter);
+        while self.baseline.len() > self.window_size_in_secs {
+            if let Some(oldest) = self.baseline.pop_front() {
+                let req_count = oldest.req_count as u128;
+                self.sum -= req_count;
+                self.sum_of_squares -= req_count * req_count;
+            }
+        }
+    }
+
+    /// Mean and standard deviation of the requests per second of the baseline,
+    /// `None` while it's warming up.
+    fn mean_and_stddev(&self) -> Option<(f64, f64)> {
+        if self.window_size_in_secs == 0 || self.baseline.len() < self.window_size_in_secs {
+            return None;
+        }
+        let len = self.baseline.len() as f64;
+        let mean = self.sum as f64 / len;
+        let variance = (self.sum_of_squares as f64 / len - mean * mean).max(0.0);
+        Some((mean, variance.sqrt().max(MIN_STDDEV)))
+    }
+
+    /// Compares a completed second with the baseline before it.
+    fn check(
+        &mut self,
+        log_counter: &LogCounter,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let (mean, stddev) = match self.mean_and_stddev() {
+            Some(baseline) => baseline,
+            None => return Ok(()),
+        };
+        let is_anomaly = log_counter.req_count as f64 > mean + self.sigma * stddev;
+
+        if is_anomaly && !self.is_alert_set {
+            self.is_alert_set = true;
+            let msg = format!(
+                "Traffic anomaly generated an alert - hits = {}/s, baseline = {:.2} ± {:.2}/s, triggered at {}",
+                log_counter.req_count,
+                mean,
+                stddev,
+                log_counter.time
+            );
+            writer.write_all(self.format.layout(&anomaly_prefix(), &msg).as_bytes())?;
+        } else if self.is_alert_set && !is_anomaly {
+            self.is_alert_set = false;
+            let msg = format!(
+                "Traffic anomaly recovered - hits = {}/s, baseline = {:.2} ± {:.2}/s, recovered at {}",
+                log_counter.req_count,
+                mean,
+                stddev,
+                log_counter.time
+            );
+            writer.write_all(self.format.layout(&anomaly_prefix(), &msg).as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl AlertProcessor for AdaptiveAlerts {
+    fn set_format(&mut self, format: AlertFormat) {
+        self.format = format;
+    }
+}
+
+impl Processor for AdaptiveAlerts {
+    #[instrument(skip(self, writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        match &mut self.current {
+            // late groups are counted in the current second
+            Some(current) if log_group.time <= current.time => {
+                current.req_count += log_group.logs.len();
+                return Ok(());
+            }
+            _ => (),
+        }
+        if let Some(completed) = self.current.take() {
+            self.check(&completed, writer)?;
+            let gap = (log_group.time - completed.time - 1).min(self.window_size_in_secs);
+            self.push(completed);
+            // the seconds without logs are part of the baseline too
+            for time in log_group.time - gap..log_group.time {
+                self.push(LogCounter { time, req_count: 0 });
+            }
+        }
+        self.current = Some(LogCounter::from(log_group));
+        Ok(())
+    }
+
+    fn finalize(&mut self, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        match self.current.take() {
+            Some(last) => self.check(&last, writer),
+            None => Ok(()),
+        }
+    }
+}
+
+fn anomaly_prefix() -> String {
+    console::style("\n>>> ANOMALY\n").bold().red().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_test_http_grouped_log, run_processor};
+
+    /// Processes a group per second from time 1 with these amounts of requests.
+    fn run(adaptive_alerts: &mut AdaptiveAlerts, req_counts: &[usize]) -> String {
+        let groups = req_counts
+            .iter()
+            .enumerate()
+            .map(|(i, req_count)| build_test_http_grouped_log(i + 1, *req_count, None))
+            .collect();
+        run_processor(adaptive_alerts, groups)
+    }
+
+    #[tokio::test]
+    async fn a_spike_over_a_stable_baseline_trips_the_sigma_alert() {
+        let mut adaptive_alerts = AdaptiveAlerts::new(3.0, 10);
+        let mut req_counts = [9, 11].repeat(5);
+        // 10 ± 1 req/s, so the threshold is 13 req/s
+        req_counts.extend([13, 30, 10]);
+
+        let msg = run(&mut adaptive_alerts, &req_counts);
+
+        assert_eq!(
+            msg,
+            format!(
+                "{0}Traffic anomaly generated an alert - hits = 30/s, baseline = 10.40 ± 1.28/s, triggered at 12\n{0}Traffic anomaly recovered - hits = 10/s, baseline = 12.30 ± 6.03/s, recovered at 13\n",
+                anomaly_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn the_alerts_can_be_single_lines() {
+        let mut adaptive_alerts = AdaptiveAlerts::new(3.0, 10);
+        adaptive_alerts.set_format(AlertFormat::Line);
+        let mut req_counts = [9, 11].repeat(5);
+        req_counts.push(30);
+
+        let msg = run(&mut adaptive_alerts, &req_counts);
+
+        assert_eq!(
+            msg,
+            ">>> ANOMALY: Traffic anomaly generated an alert - hits = 30/s, baseline = 10.00 ± 1.00/s, triggered at 11\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn does_not_alert_while_warming_up() {
+        let mut adaptive_alerts = AdaptiveAlerts::new(3.0, 10);
+
+        let msg = run(&mut adaptive_alerts, &[10, 10, 10, 100]);
+
+        assert!(msg.is_empty());
+    }
+
+    #[tokio::test]
+    async fn seconds_without_logs_lower_the_baseline() {
+        let mut adaptive_alerts = AdaptiveAlerts::new(3.0, 10);
+
+        let msg = run_processor(
+            &mut adaptive_alerts,
+            [(1, 10), (20, 10), (21, 10)]
+                .map(|(time, req_count)| build_test_http_grouped_log(time, req_count, None))
+                .to_vec(),
+        );
+
+        // the second 20 is compared with 10 empty seconds
+        assert!(msg.contains("hits = 10/s, baseline = 0.00 ± 1.00/s, triggered at 20"));
+    }
+}
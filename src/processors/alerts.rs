@@ -1,12 +1,186 @@
+use super::format_float;
 use super::GroupedHttpLogs;
 use super::Processor;
-use std::collections::VecDeque;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ffi::OsString,
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 use tracing::instrument;
 
-#[derive(Debug, Clone, Eq, PartialEq)]
-struct LogCounter {
-    time: usize,
-    req_count: usize,
+/// Where the alert messages are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertStream {
+    /// Along with the rest of the processors output.
+    Stdout,
+    Stderr,
+}
+
+impl FromStr for AlertStream {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stdout" => Ok(Self::Stdout),
+            "stderr" => Ok(Self::Stderr),
+            _ => Err(anyhow::anyhow!("Invalid alert stream: {}", s)),
+        }
+    }
+}
+
+/// How the alert messages are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertFormat {
+    /// A colored banner above the message, for terminals.
+    Banner,
+    /// A single plain line per message, for log aggregators reading line by line.
+    Line,
+}
+
+impl AlertFormat {
+    /// The banner for terminals, a line otherwise.
+    pub fn for_terminal(is_terminal: bool) -> Self {
+        match is_terminal {
+            true => Self::Banner,
+            false => Self::Line,
+        }
+    }
+
+    /// The message under the banner, or in a single line after the title of the banner.
+    pub(super) fn layout(self, banner: &str, msg: &str) -> String {
+        let msg = msg.trim_end_matches('\n');
+        match self {
+            Self::Banner => format!("{}{}\n", banner, msg),
+            // the explanation goes in the same line too
+            Self::Line => format!(
+                "{}: {}\n",
+                console::strip_ansi_codes(banner).trim(),
+                msg.replace('\n', " | ")
+            ),
+        }
+    }
+}
+
+impl FromStr for AlertFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "banner" => Ok(Self::Banner),
+            "line" => Ok(Self::Line),
+            _ => Err(anyhow::anyhow!("Invalid alert format: {}", s)),
+        }
+    }
+}
+
+/// Processor writing its own alert messages next to the high traffic ones of [`Alerts`],
+/// laid out as a banner unless told otherwise.
+pub trait AlertProcessor: Processor {
+    /// Lays out the alert messages with this format.
+    fn set_format(&mut self, format: AlertFormat);
+}
+
+/// Severity of an alert message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertSeverity {
+    /// An alert is triggered.
+    Warning,
+    /// An alert is recovered.
+    Notice,
+}
+
+/// Somewhere else the alert messages are sent to, besides the alert stream (e.g. syslog).
+#[cfg_attr(test, mockall::automock)]
+pub trait AlertNotifier: Send + Sync {
+    fn notify(&self, severity: AlertSeverity, msg: &str) -> anyhow::Result<()>;
+}
+
+/// Shared [`AlertNotifier`], only equal to itself.
+#[derive(Clone)]
+struct Notifier(Arc<dyn AlertNotifier>);
+
+impl fmt::Debug for Notifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Notifier")
+    }
+}
+
+impl PartialEq for Notifier {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Notifier {}
+
+/// Range of log times (both ends included) during which the alert messages are withheld,
+/// e.g. a planned load test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuppressionWindow {
+    from: usize,
+    to: usize,
+}
+
+impl SuppressionWindow {
+    pub fn new(from: usize, to: usize) -> anyhow::Result<Self> {
+        if from > to {
+            anyhow::bail!(
+                "Invalid suppression window, it ends before it starts: {}-{}",
+                from,
+                to
+            );
+        }
+        Ok(Self { from, to })
+    }
+
+    fn contains(&self, time: usize) -> bool {
+        (self.from..=self.to).contains(&time)
+    }
+}
+
+/// Average requests per second over which the alert reaches a tier, e.g. `500:page`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlertTier {
+    threshold: usize,
+    label: String,
+}
+
+impl AlertTier {
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+}
+
+impl FromStr for AlertTier {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((threshold, label)) if !label.trim().is_empty() => Ok(Self {
+                threshold: threshold
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("Invalid alert tier threshold: {}", threshold))?,
+                label: label.trim().to_string(),
+            }),
+            _ => Err(anyhow::anyhow!(
+                "Invalid alert tier: {}. Use threshold:label, e.g. 500:page",
+                s
+            )),
+        }
+    }
+}
+
+/// Requests of a second of the logs.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub(super) struct LogCounter {
+    pub(super) time: usize,
+    pub(super) req_count: usize,
 }
 
 impl From<&GroupedHttpLogs> for LogCounter {
@@ -20,24 +194,491 @@ impl From<&GroupedHttpLogs> for LogCounter {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Alerts {
-    avg_req_sec_threshold: usize,
+    // sorted by threshold, the alert is at the highest one crossed
+    tiers: Vec<AlertTier>,
     minor_time: usize,
     major_time: usize,
     buffer: VecDeque<LogCounter>,
-    is_alert_set: bool,
+    current_tier: Option<usize>,
     window_size_in_secs: usize,
+    cooldown_in_secs: usize,
+    last_emitted_time: Option<usize>,
+    stream: AlertStream,
+    format: AlertFormat,
+    notifiers: Vec<Notifier>,
+    precision: Option<usize>,
+    state_file: Option<PathBuf>,
+    // the window restored from the state file is dropped if the logs are older
+    is_restored: bool,
+    suppression_windows: Vec<SuppressionWindow>,
+    // the windows whose suppression was already noted
+    noted_suppressions: Vec<SuppressionWindow>,
+    // the alert was triggered within a suppression window, so it wasn't announced
+    is_trigger_suppressed: bool,
+    explain: bool,
+    // windows start at multiples of their size and are evaluated once they're complete,
+    // instead of sliding with the logs
+    align_windows: bool,
+}
+
+/// What's persisted in the state file so an active alert isn't announced again on restart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct AlertsState {
+    current_tier: Option<usize>,
+    // the state files written before the tiers only tell whether the alert was set
+    #[serde(default, skip_serializing)]
+    is_alert_set: bool,
+    minor_time: usize,
+    major_time: usize,
+    last_emitted_time: Option<usize>,
+    #[serde(default)]
+    is_trigger_suppressed: bool,
+    // tables must go after the plain values in TOML
+    buffer: Vec<LogCounter>,
+}
+
+impl AlertsState {
+    /// The saved state, if any. A missing file is a fresh start.
+    fn load(path: &Path) -> anyhow::Result<Option<Self>> {
+        match std::fs::read_to_string(path) {
+            Ok(state) => Ok(Some(toml::from_str(&state)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the state to a temporary file first, so a run killed while saving doesn't
+    /// leave a broken state file behind.
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let mut tmp_path = OsString::from(path);
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, toml::to_string(self)?)
+            .and_then(|_| std::fs::rename(&tmp_path, path))
+            .map_err(|e| anyhow::anyhow!("Can't write the state file {:?}: {}", path, e))
+    }
 }
 
 impl Alerts {
     pub fn new(avg_req_sec_threshold: usize, window_size_in_secs: usize) -> Self {
         Self {
-            avg_req_sec_threshold,
+            tiers: vec![AlertTier {
+                threshold: avg_req_sec_threshold,
+                label: String::new(),
+            }],
             minor_time: 0,
             major_time: 0,
             buffer: VecDeque::new(),
-            is_alert_set: false,
+            current_tier: None,
             window_size_in_secs,
+            cooldown_in_secs: 0,
+            last_emitted_time: None,
+            stream: AlertStream::Stdout,
+            format: AlertFormat::Banner,
+            notifiers: Vec::new(),
+            precision: None,
+            state_file: None,
+            is_restored: false,
+            suppression_windows: Vec::new(),
+            noted_suppressions: Vec::new(),
+            is_trigger_suppressed: false,
+            explain: false,
+            align_windows: false,
+        }
+    }
+
+    fn state(&self) -> AlertsState {
+        AlertsState {
+            current_tier: self.current_tier,
+            is_alert_set: false,
+            minor_time: self.minor_time,
+            major_time: self.major_time,
+            last_emitted_time: self.last_emitted_time,
+            is_trigger_suppressed: self.is_trigger_suppressed,
+            buffer: self.buffer.iter().cloned().collect(),
+        }
+    }
+
+    fn restore(&mut self, state: AlertsState) {
+        // the tiers may have changed since the state was saved
+        self.current_tier = state
+            .current_tier
+            .or(state.is_alert_set.then_some(0))
+            .map(|tier| tier.min(self.tiers.len() - 1));
+        self.minor_time = state.minor_time;
+        self.major_time = state.major_time;
+        self.last_emitted_time = state.last_emitted_time;
+        self.is_trigger_suppressed = state.is_trigger_suppressed;
+        self.buffer = state.buffer.into();
+        self.is_restored = true;
+    }
+
+    pub fn builder() -> AlertsBuilder {
+        AlertsBuilder::default()
+    }
+
+    /// Highest tier whose threshold the average is over, if any.
+    fn crossed_tier(&self, avg_req_per_sec: f64) -> Option<usize> {
+        self.tiers
+            .iter()
+            .rposition(|tier| avg_req_per_sec > tier.threshold as f64)
+    }
+
+    /// Second of the window with more requests, the earliest one on ties.
+    fn top_second(&self) -> Option<(usize, usize)> {
+        let mut seconds = BTreeMap::new();
+        // late groups may share the second with another one
+        for log_counter in &self.buffer {
+            *seconds.entry(log_counter.time).or_insert(0) += log_counter.req_count;
         }
+        seconds
+            .into_iter()
+            .fold(None, |top, (time, req_count)| match top {
+                Some((_, top_count)) if top_count >= req_count => top,
+                _ => Some((time, req_count)),
+            })
+    }
+
+    /// Line with the inputs of the alert, so it can be understood without the logs.
+    fn explanation(&self, threshold: usize, avg_req_per_sec: f64) -> String {
+        let top_second = match self.top_second() {
+            Some((time, req_count)) => format!("({},{})", time, req_count),
+            None => "none".to_string(),
+        };
+        format!(
+            "window={}s threshold={} observed={} top_second={}",
+            self.window_size_in_secs,
+            threshold,
+            format_float(avg_req_per_sec, self.precision),
+            top_second
+        )
+    }
+
+    /// Oldest time of the window ending at the latest time seen.
+    fn window_start(&self) -> usize {
+        self.major_time.saturating_sub(self.window_size_in_secs)
+    }
+
+    /// Start of the aligned window the time is in, a multiple of the window size.
+    fn aligned_window_start(&self, time: usize) -> usize {
+        time - time % self.window_size_in_secs.max(1)
+    }
+
+    /// Amount of requests within the window, wide enough to never overflow.
+    fn total_reqs(&self) -> u128 {
+        self.buffer
+            .iter()
+            .fold(0, |acc, log_counter| acc + log_counter.req_count as u128)
+    }
+
+    /// Suppression window the time is within, if any.
+    fn suppression_window(&self, time: usize) -> Option<SuppressionWindow> {
+        self.suppression_windows
+            .iter()
+            .find(|w| w.contains(time))
+            .copied()
+    }
+
+    /// Writes the message unless another one was written less than `cooldown_in_secs` ago
+    /// or the time is within a suppression window, noting the first message it withholds.
+    /// The notifiers get the message without the prefix.
+    fn emit(
+        &mut self,
+        time: usize,
+        severity: AlertSeverity,
+        msg: &str,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        if let Some(window) = self.suppression_window(time) {
+            tracing::debug!("Alert message suppressed by a suppression window: {}", msg);
+            if !self.noted_suppressions.contains(&window) {
+                self.noted_suppressions.push(window);
+                let note = format!(
+                    "Alerts suppressed from {} to {}, the first one at {}",
+                    window.from, window.to, time
+                );
+                self.write(&note, writer)?;
+            }
+            return Ok(());
+        }
+        if let Some(last_emitted_time) = self.last_emitted_time {
+            if time.saturating_sub(last_emitted_time) < self.cooldown_in_secs {
+                tracing::debug!("Alert message suppressed by the cooldown: {}", msg);
+                return Ok(());
+            }
+        }
+        self.last_emitted_time = Some(time);
+        self.write(msg, writer)?;
+        for notifier in &self.notifiers {
+            notifier.0.notify(severity, msg)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the alert to the tier crossed by the average of the window, writing a message
+    /// at `time` when it changes.
+    /// An alert triggered within a suppression window is announced once the window ends if
+    /// it's still active, and its recovery is never announced otherwise.
+    fn evaluate(&mut self, time: usize, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        // calculate the avg requests per window secs
+        let total_reqs = self.total_reqs();
+
+        let avg_req_per_sec = total_reqs as f64 / self.window_size_in_secs as f64;
+
+        // check the highest threshold the avg requests per window secs is greater than
+        let tier = self.crossed_tier(avg_req_per_sec);
+        let is_suppressed = self.suppression_window(time).is_some();
+        let previous_tier = match self.is_trigger_suppressed && !is_suppressed {
+            true => {
+                self.is_trigger_suppressed = false;
+                self.current_tier = tier;
+                None
+            }
+            false => std::mem::replace(&mut self.current_tier, tier),
+        };
+        if tier == previous_tier {
+            return Ok(());
+        }
+        if is_suppressed {
+            match (previous_tier, tier) {
+                (None, Some(_)) => self.is_trigger_suppressed = true,
+                (_, None) => self.is_trigger_suppressed = false,
+                _ => (),
+            }
+        }
+        let hits = format_float(avg_req_per_sec, self.precision);
+        let (severity, mut msg) = match (previous_tier, tier) {
+            (_, None) => (
+                AlertSeverity::Notice,
+                format!(
+                    "Normal traffic recovered - hits = {}, recovered at {}",
+                    hits, time,
+                ),
+            ),
+            (None, Some(tier)) => {
+                let label = match self.tiers[tier].label.as_str() {
+                    "" => String::new(),
+                    label => format!(" ({})", label),
+                };
+                (
+                    AlertSeverity::Warning,
+                    format!(
+                        "High traffic generated an alert{} - hits = {}, triggered at {}",
+                        label, hits, time
+                    ),
+                )
+            }
+            (Some(previous_tier), Some(tier)) => {
+                let change = match tier > previous_tier {
+                    true => "escalated",
+                    false => "downgraded",
+                };
+                (
+                    AlertSeverity::Warning,
+                    format!(
+                        "High traffic {} the alert to {} - hits = {}, {} at {}",
+                        change, self.tiers[tier].label, hits, change, time
+                    ),
+                )
+            }
+        };
+        if self.explain {
+            // the threshold of the tier reached, or of the lowest one when recovering
+            let threshold = self.tiers[tier.unwrap_or(0)].threshold;
+            msg.push('\n');
+            msg.push_str(&self.explanation(threshold, avg_req_per_sec));
+        }
+        self.emit(time, severity, &msg, writer)
+    }
+
+    /// Evaluates every aligned window once it's complete, that is, when a log of a later one
+    /// comes. The average is always taken over a whole window, so constant traffic gets the
+    /// same average in every window.
+    fn process_aligned(
+        &mut self,
+        log_counter: LogCounter,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let window_start = self.aligned_window_start(log_counter.time);
+        if self.minor_time == 0 && self.major_time == 0 {
+            tracing::debug!("Initial time: {}", log_counter.time);
+            self.minor_time = window_start;
+        }
+        // the window of the late group was already evaluated
+        if window_start < self.minor_time {
+            tracing::debug!(
+                "Ignoring a log group older than the window: {}",
+                log_counter.time
+            );
+            return Ok(());
+        }
+        if window_start > self.minor_time {
+            let window_end = self.minor_time + self.window_size_in_secs;
+            self.evaluate(window_end, writer)?;
+            self.buffer.clear();
+            if window_start > window_end {
+                // the windows in between had no logs
+                self.evaluate(window_end + self.window_size_in_secs, writer)?;
+            }
+            self.minor_time = window_start;
+        }
+        self.major_time = self.major_time.max(log_counter.time);
+        self.buffer.push_back(log_counter);
+        Ok(())
+    }
+
+    /// Writes a message to the alert stream.
+    fn write(&self, msg: &str, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        let prefixed_msg = self.format.layout(&alert_prefix(), msg);
+        match self.stream {
+            AlertStream::Stdout => writer.write_all(prefixed_msg.as_bytes())?,
+            AlertStream::Stderr => std::io::stderr().write_all(prefixed_msg.as_bytes())?,
+        }
+        Ok(())
+    }
+}
+
+/// Builds [`Alerts`] with sensible defaults.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AlertsBuilder {
+    avg_req_sec_threshold: usize,
+    window_size_in_secs: usize,
+    cooldown_in_secs: usize,
+    stream: AlertStream,
+    format: AlertFormat,
+    notifiers: Vec<Notifier>,
+    precision: Option<usize>,
+    state_file: Option<PathBuf>,
+    suppression_windows: Vec<SuppressionWindow>,
+    tiers: Vec<AlertTier>,
+    explain: bool,
+    align_windows: bool,
+}
+
+impl Default for AlertsBuilder {
+    fn default() -> Self {
+        Self {
+            avg_req_sec_threshold: 10,
+            window_size_in_secs: 120,
+            cooldown_in_secs: 0,
+            stream: AlertStream::Stdout,
+            format: AlertFormat::Banner,
+            notifiers: Vec::new(),
+            precision: None,
+            state_file: None,
+            suppression_windows: Vec::new(),
+            tiers: Vec::new(),
+            explain: false,
+            align_windows: false,
+        }
+    }
+}
+
+impl AlertsBuilder {
+    /// Average amount of requests per second that triggers an alert.
+    pub fn avg_req_sec_threshold(mut self, avg_req_sec_threshold: usize) -> Self {
+        self.avg_req_sec_threshold = avg_req_sec_threshold;
+        self
+    }
+
+    /// Amount of seconds used to calculate the average.
+    pub fn window_size_in_secs(mut self, window_size_in_secs: usize) -> Self {
+        self.window_size_in_secs = window_size_in_secs;
+        self
+    }
+
+    /// Minimum amount of seconds (in log time) between two alert messages.
+    /// Transitions happening within the cooldown are still tracked but not written.
+    pub fn cooldown_in_secs(mut self, cooldown_in_secs: usize) -> Self {
+        self.cooldown_in_secs = cooldown_in_secs;
+        self
+    }
+
+    /// Where the alert messages are written.
+    pub fn stream(mut self, stream: AlertStream) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    /// How the alert messages are laid out in the alert stream.
+    pub fn format(mut self, format: AlertFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Also sends the alert messages to this notifier, along with the previously added ones.
+    pub fn notifier(mut self, notifier: Option<Arc<dyn AlertNotifier>>) -> Self {
+        self.notifiers.extend(notifier.map(Notifier));
+        self
+    }
+
+    /// Amount of decimals of the average hits (all of them by default).
+    pub fn precision(mut self, precision: Option<usize>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// File where the state of the alert is saved when finalizing and restored from when
+    /// building, so an alert that was already active isn't announced again.
+    pub fn state_file(mut self, state_file: Option<PathBuf>) -> Self {
+        self.state_file = state_file;
+        self
+    }
+
+    /// Log times during which the alert messages are withheld. The alert is still tracked,
+    /// so it recovers as usual once they're over.
+    pub fn suppression_windows(mut self, suppression_windows: Vec<SuppressionWindow>) -> Self {
+        self.suppression_windows = suppression_windows;
+        self
+    }
+
+    /// Escalation tiers with their own label, replacing the single `avg_req_sec_threshold`.
+    /// The alert is escalated and downgraded as the average crosses them.
+    pub fn tiers(mut self, tiers: Vec<AlertTier>) -> Self {
+        self.tiers = tiers;
+        self
+    }
+
+    /// Appends a line to every alert message with the window, the threshold, the average
+    /// observed and the second of the window with more requests.
+    pub fn explain(mut self, explain: bool) -> Self {
+        self.explain = explain;
+        self
+    }
+
+    /// Starts the windows at multiples of their size in epoch seconds instead of at the
+    /// first log, so every instance processing the same logs gets the same windows.
+    pub fn align_windows(mut self, align_windows: bool) -> Self {
+        self.align_windows = align_windows;
+        self
+    }
+
+    pub fn build(self) -> Alerts {
+        let mut alerts = Alerts::new(self.avg_req_sec_threshold, self.window_size_in_secs);
+        alerts.cooldown_in_secs = self.cooldown_in_secs;
+        alerts.stream = self.stream;
+        alerts.format = self.format;
+        alerts.notifiers = self.notifiers;
+        alerts.precision = self.precision;
+        alerts.suppression_windows = self.suppression_windows;
+        alerts.explain = self.explain;
+        alerts.align_windows = self.align_windows;
+        if !self.tiers.is_empty() {
+            alerts.tiers = self.tiers;
+            alerts.tiers.sort_by_key(|tier| tier.threshold);
+        }
+        if let Some(state_file) = &self.state_file {
+            match AlertsState::load(state_file) {
+                Ok(Some(state)) => alerts.restore(state),
+                Ok(None) => (),
+                Err(e) => tracing::warn!(
+                    "Can't restore the alerts state from {:?}, starting fresh: {}",
+                    state_file,
+                    e
+                ),
+            }
+        }
+        alerts.state_file = self.state_file;
+        alerts
     }
 }
 
@@ -50,67 +691,71 @@ impl Processor for Alerts {
     ) -> anyhow::Result<()> {
         let log_counter = LogCounter::from(log_group);
 
-        self.buffer.push_back(log_counter.clone());
+        if std::mem::take(&mut self.is_restored) && log_counter.time < self.minor_time {
+            tracing::info!("The logs are older than the restored window, starting a new one");
+            self.buffer.clear();
+            self.minor_time = 0;
+            self.major_time = 0;
+        }
+        if self.align_windows {
+            return self.process_aligned(log_counter, writer);
+        }
         if self.minor_time == 0 && self.major_time == 0 {
             tracing::debug!("Initial time: {}", log_counter.time);
             self.minor_time = log_counter.time;
+            self.major_time = log_counter.time;
         }
 
-        if log_counter.time < self.minor_time {
-            return Err(anyhow::Error::msg(
-                    "Log group time is less than the minor time. Try to adjust the BufferedLogs seconds property.",
-                ));
+        // the window only depends on the times seen, late groups are fine while they're in it
+        if log_counter.time < self.window_start() {
+            tracing::debug!(
+                "Ignoring a log group older than the window: {}",
+                log_counter.time
+            );
+            return Ok(());
         }
-
-        self.major_time = log_counter.time;
+        self.buffer.push_back(log_counter.clone());
+        self.minor_time = self.minor_time.min(log_counter.time);
+        self.major_time = self.major_time.max(log_counter.time);
 
         let diff_time = self.major_time - self.minor_time;
 
         if diff_time >= self.window_size_in_secs {
             // set the minor time to major - window secs
             self.minor_time = self.major_time - self.window_size_in_secs;
-            // draing the log groups < minor time
-            while let Some(log_counter) = self.buffer.front() {
-                if log_counter.time >= self.minor_time {
-                    break;
-                }
-                self.buffer.pop_front();
-            }
+            // draining the log groups < minor time, which may not be in order
+            let minor_time = self.minor_time;
+            self.buffer
+                .retain(|log_counter| log_counter.time >= minor_time);
         }
 
-        // calculate the avg requests per window secs
-        let total_reqs = self
-            .buffer
-            .iter()
-            .fold(0, |acc, log_counter| acc + log_counter.req_count);
+        self.evaluate(log_counter.time, writer)
+    }
 
-        let avg_req_per_sec = total_reqs as f64 / self.window_size_in_secs as f64;
+    /// The groups older than the window are ignored, so they aren't dispatched. Until the
+    /// restored window is checked against the logs, every group is.
+    fn next_interesting_time(&self) -> Option<usize> {
+        match (self.is_restored, self.align_windows) {
+            (true, _) => None,
+            (false, true) => Some(self.minor_time),
+            (false, false) => Some(self.window_start()),
+        }
+    }
 
-        // check if the avg requests per window secs is greater than the threshold
-        let is_above_threshold = avg_req_per_sec > self.avg_req_sec_threshold as f64;
+    fn save_state(&self) -> anyhow::Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::to_value(self.state())?))
+    }
 
-        if is_above_threshold && !self.is_alert_set {
-            self.is_alert_set = true;
-            let msg = format!(
-                "{}High traffic generated an alert - hits = {}, triggered at {}\n",
-                alert_prefix(),
-                avg_req_per_sec,
-                log_counter.time
-            );
-            writer.write_all(msg.as_bytes())?;
-        } else if self.is_alert_set && !is_above_threshold {
-            self.is_alert_set = false;
-            let msg = format!(
-                "{}Normal traffic recovered - hits = {}, recovered at {}\n",
-                alert_prefix(),
-                avg_req_per_sec,
-                log_counter.time,
-            );
+    fn restore_state(&mut self, state: serde_json::Value) -> anyhow::Result<()> {
+        self.restore(serde_json::from_value(state)?);
+        Ok(())
+    }
 
-            writer.write_all(msg.as_bytes())?;
+    fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        match &self.state_file {
+            Some(state_file) => self.state().save(state_file),
+            None => Ok(()),
         }
-
-        Ok(())
     }
 }
 
@@ -121,7 +766,7 @@ fn alert_prefix() -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::build_test_http_grouped_log;
+    use crate::test_utils::{build_test_http_grouped_log, captured_logs};
     use std::io::BufWriter;
 
     #[tokio::test]
@@ -208,4 +853,553 @@ mod tests {
             format!("{0}High traffic generated an alert - hits = 1.5, triggered at 1\n{0}Normal traffic recovered - hits = 0.5, recovered at 4\n", alert_prefix())
         );
     }
+
+    #[tokio::test]
+    async fn should_not_emit_alerts_within_the_cooldown() {
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .cooldown_in_secs(10)
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_test_http_grouped_log(1, 3, None),
+            build_test_http_grouped_log(4, 1, None),
+            build_test_http_grouped_log(5, 3, None),
+        ];
+
+        for log in logs {
+            alerts.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}High traffic generated an alert - hits = 1.5, triggered at 1\n",
+                alert_prefix()
+            )
+        );
+        // the state is still tracked while suppressed
+        assert!(alerts.current_tier.is_some());
+    }
+
+    #[tokio::test]
+    async fn should_emit_alerts_after_the_cooldown() {
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .cooldown_in_secs(3)
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_test_http_grouped_log(1, 3, None),
+            build_test_http_grouped_log(4, 1, None),
+        ];
+
+        for log in logs {
+            alerts.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!("{0}High traffic generated an alert - hits = 1.5, triggered at 1\n{0}Normal traffic recovered - hits = 0.5, recovered at 4\n", alert_prefix())
+        );
+    }
+
+    #[tokio::test]
+    async fn should_not_emit_alerts_within_a_suppression_window() {
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .suppression_windows(vec![SuppressionWindow::new(1, 5).unwrap()])
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_test_http_grouped_log(1, 3, None),
+            build_test_http_grouped_log(4, 1, None),
+            build_test_http_grouped_log(6, 3, None),
+        ];
+
+        for log in logs {
+            alerts.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!("{0}Alerts suppressed from 1 to 5, the first one at 1\n{0}High traffic generated an alert - hits = 2, triggered at 6\n", alert_prefix())
+        );
+    }
+
+    #[tokio::test]
+    async fn should_not_emit_the_recovery_of_a_suppressed_alert() {
+        let mut notifier = MockAlertNotifier::new();
+        notifier.expect_notify().never();
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .suppression_windows(vec![SuppressionWindow::new(1, 5).unwrap()])
+            .notifier(Some(Arc::new(notifier)))
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_test_http_grouped_log(1, 3, None),
+            build_test_http_grouped_log(6, 1, None),
+        ];
+
+        for log in logs {
+            alerts.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}Alerts suppressed from 1 to 5, the first one at 1\n",
+                alert_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn should_emit_a_suppressed_alert_still_active_after_the_window() {
+        let mut notifier = MockAlertNotifier::new();
+        notifier
+            .expect_notify()
+            .withf(|severity, msg| {
+                *severity == AlertSeverity::Warning
+                    && msg == "High traffic generated an alert - hits = 1.5, triggered at 6"
+            })
+            .times(1)
+            .returning(|_, _| Ok(()));
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .suppression_windows(vec![SuppressionWindow::new(1, 5).unwrap()])
+            .notifier(Some(Arc::new(notifier)))
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_test_http_grouped_log(1, 3, None),
+            build_test_http_grouped_log(6, 3, None),
+        ];
+
+        for log in logs {
+            alerts.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!("{0}Alerts suppressed from 1 to 5, the first one at 1\n{0}High traffic generated an alert - hits = 1.5, triggered at 6\n", alert_prefix())
+        );
+    }
+
+    #[tokio::test]
+    async fn the_groups_older_than_the_window_are_not_interesting() {
+        let mut alerts = Alerts::new(1, 10);
+        let mut aligned = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(10)
+            .align_windows(true)
+            .build();
+        let mut writer = std::io::sink();
+
+        for time in [1549573861, 1549573875] {
+            let logs = build_test_http_grouped_log(time, 1, None);
+            alerts.process(&logs, &mut writer).unwrap();
+            aligned.process(&logs, &mut writer).unwrap();
+        }
+
+        assert_eq!(alerts.next_interesting_time(), Some(1549573865));
+        assert_eq!(aligned.next_interesting_time(), Some(1549573870));
+    }
+
+    #[tokio::test]
+    async fn suppression_windows_must_not_end_before_they_start() {
+        assert!(SuppressionWindow::new(5, 1).is_err());
+        assert!(SuppressionWindow::new(5, 5).is_ok());
+    }
+
+    #[tokio::test]
+    async fn rounds_the_hits_to_the_precision() {
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(3)
+            .precision(Some(1))
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        alerts
+            .process(&build_test_http_grouped_log(1, 4, None), &mut writer)
+            .unwrap();
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}High traffic generated an alert - hits = 1.3, triggered at 1\n",
+                alert_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn builder_is_equivalent_to_new() {
+        assert_eq!(Alerts::builder().build(), Alerts::new(10, 120));
+        assert_eq!(
+            Alerts::builder()
+                .avg_req_sec_threshold(1)
+                .window_size_in_secs(2)
+                .build(),
+            Alerts::new(1, 2)
+        );
+    }
+
+    #[tokio::test]
+    async fn alerts_can_be_written_to_stderr() {
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .stream(AlertStream::Stderr)
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        alerts
+            .process(&build_test_http_grouped_log(1, 3, None), &mut writer)
+            .unwrap();
+
+        assert!(alerts.current_tier.is_some());
+        assert!(writer.into_inner().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn alerts_are_single_lines_outside_terminals() {
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .explain(true)
+            .format(AlertFormat::for_terminal(false))
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        for log in [
+            build_test_http_grouped_log(1, 3, None),
+            build_test_http_grouped_log(4, 1, None),
+        ] {
+            alerts.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            ">>> ALERT: High traffic generated an alert - hits = 1.5, triggered at 1 | window=2s threshold=1 observed=1.5 top_second=(1,3)\n\
+            >>> ALERT: Normal traffic recovered - hits = 0.5, recovered at 4 | window=2s threshold=1 observed=0.5 top_second=(4,1)\n"
+        );
+        assert_eq!(msg.lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn alerts_have_a_banner_in_terminals() {
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .format(AlertFormat::for_terminal(true))
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        alerts
+            .process(&build_test_http_grouped_log(1, 3, None), &mut writer)
+            .unwrap();
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            console::strip_ansi_codes(&msg),
+            "\n>>> ALERT\nHigh traffic generated an alert - hits = 1.5, triggered at 1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_alert_formats() {
+        assert_eq!(
+            "banner".parse::<AlertFormat>().unwrap(),
+            AlertFormat::Banner
+        );
+        assert_eq!("line".parse::<AlertFormat>().unwrap(), AlertFormat::Line);
+        assert!("json".parse::<AlertFormat>().is_err());
+    }
+
+    #[tokio::test]
+    async fn parses_alert_streams() {
+        assert_eq!(
+            "stdout".parse::<AlertStream>().unwrap(),
+            AlertStream::Stdout
+        );
+        assert_eq!(
+            "stderr".parse::<AlertStream>().unwrap(),
+            AlertStream::Stderr
+        );
+        assert!("file".parse::<AlertStream>().is_err());
+    }
+
+    #[tokio::test]
+    async fn huge_request_counts_do_not_overflow() {
+        let mut alerts = Alerts::new(1, 2);
+        for time in 1..=3 {
+            alerts.buffer.push_back(LogCounter {
+                time,
+                req_count: usize::MAX,
+            });
+        }
+
+        assert_eq!(alerts.total_reqs(), usize::MAX as u128 * 3);
+    }
+
+    #[tokio::test]
+    async fn late_groups_within_the_window_are_counted() {
+        let mut alerts = Alerts::new(1, 10);
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_test_http_grouped_log(1549573860, 4, None),
+            build_test_http_grouped_log(1549573865, 4, None),
+            // late, but within the window
+            build_test_http_grouped_log(1549573862, 4, None),
+            // older than the window
+            build_test_http_grouped_log(1549573850, 4, None),
+        ];
+        for log in logs {
+            alerts.process(&log, &mut writer).unwrap();
+        }
+
+        assert_eq!(alerts.total_reqs(), 12);
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}High traffic generated an alert - hits = 1.2, triggered at 1549573862\n",
+                alert_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn escalates_and_downgrades_through_the_tiers() {
+        let tiers = ["5:page", "1:warn", "10:critical"]
+            .iter()
+            .map(|tier| tier.parse().unwrap())
+            .collect();
+        let mut alerts = Alerts::builder()
+            .window_size_in_secs(1)
+            .precision(Some(0))
+            .tiers(tiers)
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        // rising through warn, page and critical, then falling back to normal
+        for (time, len) in [(1, 3), (3, 8), (5, 9), (7, 30), (9, 7), (11, 2), (13, 1)] {
+            alerts
+                .process(&build_test_http_grouped_log(time, len, None), &mut writer)
+                .unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            [
+                "High traffic generated an alert (warn) - hits = 3, triggered at 1",
+                "High traffic escalated the alert to page - hits = 8, escalated at 3",
+                "High traffic escalated the alert to critical - hits = 30, escalated at 7",
+                "High traffic downgraded the alert to page - hits = 7, downgraded at 9",
+                "High traffic downgraded the alert to warn - hits = 2, downgraded at 11",
+                "Normal traffic recovered - hits = 1, recovered at 13",
+            ]
+            .iter()
+            .map(|msg| format!("{}{}\n", alert_prefix(), msg))
+            .collect::<String>()
+        );
+    }
+
+    #[tokio::test]
+    async fn explains_the_alerts() {
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(2)
+            .window_size_in_secs(10)
+            .explain(true)
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        // the late group adds up with the first one of its second
+        for (time, len) in [(1, 4), (2, 9), (1, 6), (3, 3)] {
+            alerts
+                .process(&build_test_http_grouped_log(time, len, None), &mut writer)
+                .unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}High traffic generated an alert - hits = 2.2, triggered at 3\nwindow=10s threshold=2 observed=2.2 top_second=(1,10)\n",
+                alert_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_alert_tiers() {
+        let tier = " 500 : page ".parse::<AlertTier>().unwrap();
+
+        assert_eq!(tier.threshold(), 500);
+        assert_eq!(tier.label, "page");
+        assert!("500".parse::<AlertTier>().is_err());
+        assert!("500:".parse::<AlertTier>().is_err());
+        assert!("lots:page".parse::<AlertTier>().is_err());
+    }
+
+    #[tokio::test]
+    async fn aligned_windows_do_not_depend_on_the_first_log() {
+        // two instances starting at different logs of the same traffic
+        for first in [1549573860, 1549573863, 1549573868] {
+            let mut alerts = Alerts::builder()
+                .avg_req_sec_threshold(1)
+                .window_size_in_secs(10)
+                .align_windows(true)
+                .build();
+            let mut writer = BufWriter::new(Vec::<u8>::new());
+
+            for time in first..1549573900 {
+                let len = match time {
+                    1549573870..=1549573879 => 2,
+                    _ => 1,
+                };
+                alerts
+                    .process(&build_test_http_grouped_log(time, len, None), &mut writer)
+                    .unwrap();
+                assert_eq!(alerts.minor_time, time - time % 10);
+            }
+
+            let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+            assert_eq!(
+                msg,
+                format!(
+                    "{0}High traffic generated an alert - hits = 2, triggered at 1549573880\n{0}Normal traffic recovered - hits = 1, recovered at 1549573890\n",
+                    alert_prefix()
+                ),
+                "first log at {}",
+                first
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn constant_traffic_over_the_threshold_never_recovers_in_aligned_windows() {
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(10)
+            .align_windows(true)
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        for time in 1549573855..1549573960 {
+            alerts
+                .process(&build_test_http_grouped_log(time, 2, None), &mut writer)
+                .unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}High traffic generated an alert - hits = 2, triggered at 1549573870\n",
+                alert_prefix()
+            )
+        );
+    }
+
+    fn build_with_state_file(state_file: &Path) -> Alerts {
+        Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .state_file(Some(state_file.to_path_buf()))
+            .build()
+    }
+
+    #[tokio::test]
+    async fn active_alerts_are_not_announced_again_after_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_file = dir.path().join("alerts.toml");
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let mut alerts = build_with_state_file(&state_file);
+        alerts
+            .process(&build_test_http_grouped_log(1, 3, None), &mut writer)
+            .unwrap();
+        alerts.finalize(&mut writer).unwrap();
+        assert!(!dir.path().join("alerts.toml.tmp").exists());
+
+        // restarting
+        let mut alerts = build_with_state_file(&state_file);
+        assert!(alerts.current_tier.is_some());
+        alerts
+            .process(&build_test_http_grouped_log(2, 3, None), &mut writer)
+            .unwrap();
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}High traffic generated an alert - hits = 1.5, triggered at 1\n",
+                alert_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn state_files_written_before_the_tiers_are_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_file = dir.path().join("alerts.toml");
+        std::fs::write(
+            &state_file,
+            "is_alert_set = true\nminor_time = 1\nmajor_time = 1\n\n[[buffer]]\ntime = 1\nreq_count = 3\n",
+        )
+        .unwrap();
+
+        let alerts = build_with_state_file(&state_file);
+
+        assert_eq!(alerts.current_tier, Some(0));
+        assert_eq!(alerts.buffer.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn corrupt_state_files_start_fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_file = dir.path().join("alerts.toml");
+        std::fs::write(&state_file, "is-alert-set = maybe").unwrap();
+
+        let alerts = build_with_state_file(&state_file);
+        let missing = build_with_state_file(&dir.path().join("missing.toml"));
+
+        assert!(alerts.current_tier.is_none());
+        assert!(alerts.buffer.is_empty());
+        assert!(missing.current_tier.is_none());
+    }
+
+    #[tokio::test]
+    async fn missing_state_files_start_fresh_silently() {
+        let logs = captured_logs();
+        let dir = tempfile::tempdir().unwrap();
+
+        let alerts = build_with_state_file(&dir.path().join("first-run-alerts.toml"));
+
+        assert!(alerts.current_tier.is_none());
+        assert!(!logs.contents().contains("first-run-alerts.toml"));
+    }
 }
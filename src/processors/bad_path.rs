@@ -0,0 +1,246 @@
+use super::alerts::{AlertFormat, AlertProcessor};
+use super::keyed_alert::KeyedAlert;
+use super::GroupedHttpLogs;
+use super::Processor;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use tracing::instrument;
+
+/// Max amount of paths tracked at the same time. Only the ones with more 4xx are kept.
+const MAX_TRACKED_PATHS: usize = 100;
+
+const BAD_PATH_ALERT: KeyedAlert = KeyedAlert {
+    name: "bad-path",
+    key_kind: "path",
+    triggered: "Path {key} keeps failing",
+    recovered: "Path {key} recovered",
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PathCounter {
+    time: usize,
+    error_count_by_path: HashMap<String, usize>,
+}
+
+impl PathCounter {
+    fn new(g: &GroupedHttpLogs, max_paths: usize) -> Self {
+        let mut error_count_by_path = HashMap::new();
+        for log in g.logs.iter().filter(|log| (400..500).contains(&log.status)) {
+            *error_count_by_path
+                .entry(log.request.path.clone())
+                .or_insert(0) += 1;
+        }
+        retain_top_paths(&mut error_count_by_path, max_paths);
+        PathCounter {
+            time: g.time,
+            error_count_by_path,
+        }
+    }
+}
+
+/// Keeps only the `max_paths` paths with more errors.
+fn retain_top_paths(error_count_by_path: &mut HashMap<String, usize>, max_paths: usize) {
+    if error_count_by_path.len() <= max_paths {
+        return;
+    }
+    let mut counts = error_count_by_path.drain().collect::<Vec<_>>();
+    counts.sort_unstable_by(|(a_path, a), (b_path, b)| b.cmp(a).then_with(|| a_path.cmp(b_path)));
+    counts.truncate(max_paths);
+    error_count_by_path.extend(counts);
+}
+
+/// Alerts when a single path returns more 4xx than a threshold within a sliding window
+/// (e.g. a misconfigured client hammering a bad URL).
+///
+/// The memory is bounded by only tracking the top offending paths, so the counts of the
+/// paths entering and leaving the top are approximate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BadPaths {
+    threshold: usize,
+    window_size_in_secs: usize,
+    format: AlertFormat,
+    max_paths: usize,
+    buffer: VecDeque<PathCounter>,
+    error_count_by_path: HashMap<String, usize>,
+    flagged_paths: BTreeSet<String>,
+}
+
+impl BadPaths {
+    pub fn new(threshold: usize, window_size_in_secs: usize) -> Self {
+        Self {
+            threshold,
+            window_size_in_secs,
+            format: AlertFormat::Banner,
+            max_paths: MAX_TRACKED_PATHS,
+            buffer: VecDeque::new(),
+            error_count_by_path: HashMap::new(),
+            flagged_paths: BTreeSet::new(),
+        }
+    }
+
+    fn add(&mut self, path_counter: PathCounter) {
+        for (path, error_count) in &path_counter.error_count_by_path {
+            *self.error_count_by_path.entry(path.clone()).or_insert(0) += error_count;
+        }
+        retain_top_paths(&mut self.error_count_by_path, self.max_paths);
+        self.buffer.push_back(path_counter);
+    }
+
+    /// Drains the path counters which are out of the window.
+    fn evict_older_than(&mut self, minor_time: usize) {
+        while let Some(path_counter) = self.buffer.front() {
+            if path_counter.time >= minor_time {
+                break;
+            }
+            for (path, error_count) in &path_counter.error_count_by_path {
+                if let Some(count) = self.error_count_by_path.get_mut(path) {
+                    // the path may have left the top and come back with a lower count
+                    *count = count.saturating_sub(*error_count);
+                    if *count == 0 {
+                        self.error_count_by_path.remove(path);
+                    }
+                }
+            }
+            self.buffer.pop_front();
+        }
+    }
+
+    fn path_errors(&self, path: &str) -> usize {
+        self.error_count_by_path
+            .get(path)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+impl AlertProcessor for BadPaths {
+    fn set_format(&mut self, format: AlertFormat) {
+        self.format = format;
+    }
+}
+
+impl Processor for BadPaths {
+    #[instrument(skip(self, writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        self.add(PathCounter::new(log_group, self.max_paths));
+        self.evict_older_than(log_group.time.saturating_sub(self.window_size_in_secs));
+
+        let bad_paths = self
+            .error_count_by_path
+            .iter()
+            .filter(|(_, error_count)| **error_count > self.threshold)
+            .map(|(path, _)| path.clone())
+            .collect::<BTreeSet<_>>();
+
+        for path in bad_paths.difference(&self.flagged_paths) {
+            let details = format!("4xx = {}", self.path_errors(path));
+            BAD_PATH_ALERT.trace_triggered(path, &details, log_group.time);
+            let msg = BAD_PATH_ALERT.triggered(path, &details, log_group.time);
+            writer.write_all(self.format.layout(&bad_path_prefix(), &msg).as_bytes())?;
+        }
+
+        for path in self.flagged_paths.difference(&bad_paths) {
+            let details = format!("4xx = {}", self.path_errors(path));
+            BAD_PATH_ALERT.trace_recovered(path, &details, log_group.time);
+            let msg = BAD_PATH_ALERT.recovered(path, &details, log_group.time);
+            writer.write_all(self.format.layout(&bad_path_prefix(), &msg).as_bytes())?;
+        }
+
+        self.flagged_paths = bad_paths;
+        Ok(())
+    }
+}
+
+fn bad_path_prefix() -> String {
+    console::style("\n>>> BAD PATH\n")
+        .bold()
+        .yellow()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        reader::LogRequest,
+        test_utils::{build_test_http_grouped_log_with, run_processor as run},
+    };
+
+    fn build_group(time: usize, paths: &[(&str, u16, usize)]) -> GroupedHttpLogs {
+        let logs = paths
+            .iter()
+            .flat_map(|(path, status, len)| {
+                build_test_http_grouped_log_with(time, *len, |log| {
+                    log.request = LogRequest::from_str(&format!("GET {} HTTP/1.1", path)).unwrap();
+                    log.status = *status;
+                })
+                .logs
+            })
+            .collect();
+        GroupedHttpLogs { time, logs }
+    }
+
+    #[tokio::test]
+    async fn failing_path_trips_while_overall_4xx_stays_low() {
+        let mut bad_paths = BadPaths::new(4, 10);
+
+        let msg = run(
+            &mut bad_paths,
+            vec![
+                build_group(
+                    1,
+                    &[
+                        ("/api/user", 200, 50),
+                        ("/api/v1/old", 404, 3),
+                        ("/report", 403, 1),
+                    ],
+                ),
+                build_group(
+                    2,
+                    &[
+                        ("/api/user", 200, 50),
+                        ("/api/v1/old", 404, 2),
+                        ("/api/help", 400, 1),
+                    ],
+                ),
+                build_group(20, &[("/api/user", 200, 50)]),
+            ],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{0}Path /api/v1/old keeps failing - 4xx = 5, triggered at 2\n{0}Path /api/v1/old recovered - 4xx = 0, recovered at 20\n",
+                bad_path_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn only_the_top_paths_are_tracked() {
+        let mut bad_paths = BadPaths::new(2, 10);
+        bad_paths.max_paths = 2;
+
+        let msg = run(
+            &mut bad_paths,
+            vec![
+                build_group(1, &[("/a", 404, 3), ("/b", 404, 2), ("/c", 404, 1)]),
+                build_group(2, &[("/d", 404, 1), ("/e", 404, 1)]),
+            ],
+        );
+
+        assert_eq!(bad_paths.error_count_by_path.len(), 2);
+        assert_eq!(bad_paths.path_errors("/a"), 3);
+        assert_eq!(bad_paths.path_errors("/b"), 2);
+        assert_eq!(
+            msg,
+            format!(
+                "{}Path /a keeps failing - 4xx = 3, triggered at 1\n",
+                bad_path_prefix()
+            )
+        );
+    }
+}
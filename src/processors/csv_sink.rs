@@ -0,0 +1,290 @@
+use super::GroupedHttpLogs;
+use super::Processor;
+use crate::reader::HttpLog;
+use flate2::{write::GzEncoder, Compression};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    str::FromStr,
+};
+use tracing::instrument;
+
+const CSV_HEADER: &str =
+    "\"remotehost\",\"rfc931\",\"authuser\",\"date\",\"request\",\"status\",\"bytes\"\n";
+/// Header with the request split in the [`LogRequest`](crate::reader::LogRequest) fields.
+const EXPANDED_CSV_HEADER: &str = "\"remotehost\",\"rfc931\",\"authuser\",\"date\",\"verb\",\"path\",\"section\",\"protocol\",\"status\",\"bytes\"\n";
+
+/// Compression of the written files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl FromStr for OutputCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "gzip" => Ok(Self::Gzip),
+            "zstd" => Ok(Self::Zstd),
+            _ => Err(anyhow::anyhow!(
+                "Invalid output compression: {}. Use gzip, zstd or none",
+                s
+            )),
+        }
+    }
+}
+
+enum Encoder<W: Write> {
+    None(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    fn new(writer: W, compression: OutputCompression) -> anyhow::Result<Self> {
+        Ok(match compression {
+            OutputCompression::None => Self::None(writer),
+            OutputCompression::Gzip => Self::Gzip(GzEncoder::new(writer, Compression::default())),
+            OutputCompression::Zstd => Self::Zstd(zstd::Encoder::new(writer, 0)?),
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Self::None(writer) => writer,
+            Self::Gzip(encoder) => encoder,
+            Self::Zstd(encoder) => encoder,
+        }
+    }
+
+    /// Writes the compression trailers and flushes the underlying writer.
+    fn finish(self) -> anyhow::Result<()> {
+        let mut writer = match self {
+            Self::None(writer) => writer,
+            Self::Gzip(encoder) => encoder.finish()?,
+            Self::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes the logs back to a CSV file, optionally compressed.
+pub struct CsvSink<W: Write> {
+    // `None` once finalized
+    encoder: Option<Encoder<W>>,
+    expand_request: bool,
+    header_written: bool,
+}
+
+impl CsvSink<BufWriter<File>> {
+    pub fn create(path: &Path, compression: OutputCompression) -> anyhow::Result<Self> {
+        let file = File::create(path)
+            .map_err(|e| anyhow::anyhow!("Can't create the CSV output {:?}: {}", path, e))?;
+        Self::new(BufWriter::new(file), compression)
+    }
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W, compression: OutputCompression) -> anyhow::Result<Self> {
+        Ok(Self {
+            encoder: Some(Encoder::new(writer, compression)?),
+            expand_request: false,
+            header_written: false,
+        })
+    }
+
+    /// Writes the verb, path, section and protocol of the requests as separate columns
+    /// instead of the original request column.
+    pub fn expand_request(mut self, expand_request: bool) -> Self {
+        self.expand_request = expand_request;
+        self
+    }
+
+    /// Gets the writer, writing the header the first time.
+    fn writer(&mut self) -> anyhow::Result<&mut dyn Write> {
+        let encoder = self
+            .encoder
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("The CSV output is already finalized"))?;
+        if !self.header_written {
+            let header = if self.expand_request {
+                EXPANDED_CSV_HEADER
+            } else {
+                CSV_HEADER
+            };
+            encoder.writer().write_all(header.as_bytes())?;
+            self.header_written = true;
+        }
+        Ok(encoder.writer())
+    }
+}
+
+fn quote(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+fn to_csv_line(log: &HttpLog, expand_request: bool) -> String {
+    let request = if expand_request {
+        [
+            &log.request.verb,
+            &log.request.path,
+            &log.request.section,
+            &log.request.protocol,
+        ]
+        .map(|field| quote(field))
+        .join(",")
+    } else {
+        quote(&format!(
+            "{} {} {}",
+            log.request.verb, log.request.path, log.request.protocol
+        ))
+    };
+    format!(
+        "{},{},{},{},{},{},{}\n",
+        quote(&log.remote_host),
+        quote(&log.rfc931),
+        quote(&log.auth_user),
+        log.time,
+        request,
+        log.status,
+        log.bytes
+    )
+}
+
+impl<W: Write + Send + Sync> Processor for CsvSink<W> {
+    #[instrument(skip(self, _writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        _writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let expand_request = self.expand_request;
+        let writer = self.writer()?;
+        for log in &log_group.logs {
+            writer.write_all(to_csv_line(log, expand_request).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        if self.encoder.is_none() {
+            return Ok(());
+        }
+        // the header is written even if there were no logs
+        self.writer()?;
+        match self.encoder.take() {
+            Some(encoder) => encoder.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        reader::{read_csv_async, LogRequest, ReaderOptions},
+        test_utils::{build_test_http_grouped_log, build_test_http_log, SharedBuffer},
+    };
+    use futures::TryStreamExt;
+    use std::io::Read;
+
+    fn write_logs(compression: OutputCompression, groups: &[GroupedHttpLogs]) -> Vec<u8> {
+        let buffer = SharedBuffer::default();
+        let sink = CsvSink::new(buffer.clone(), compression).unwrap();
+        write_logs_with(sink, buffer, groups)
+    }
+
+    fn write_logs_with(
+        mut sink: CsvSink<SharedBuffer>,
+        buffer: SharedBuffer,
+        groups: &[GroupedHttpLogs],
+    ) -> Vec<u8> {
+        let mut writer = std::io::sink();
+        for group in groups {
+            sink.process(group, &mut writer).unwrap();
+        }
+        sink.finalize(&mut writer).unwrap();
+        let bytes = buffer.0.lock().unwrap().clone();
+        bytes
+    }
+
+    #[tokio::test]
+    async fn compressed_output_round_trips() {
+        let groups = vec![
+            build_test_http_grouped_log(1, 2, Some("/api/users".to_string())),
+            build_test_http_grouped_log(2, 1, Some("/report".to_string())),
+        ];
+        let plain = write_logs(OutputCompression::None, &groups);
+
+        let mut gzip = String::new();
+        flate2::read::GzDecoder::new(write_logs(OutputCompression::Gzip, &groups).as_slice())
+            .read_to_string(&mut gzip)
+            .unwrap();
+        let zstd =
+            zstd::decode_all(write_logs(OutputCompression::Zstd, &groups).as_slice()).unwrap();
+
+        assert_eq!(gzip.as_bytes(), plain.as_slice());
+        assert_eq!(zstd, plain);
+
+        // the output can be read again
+        let mut input = std::io::Cursor::new(plain);
+        let logs = read_csv_async(&mut input, ReaderOptions::default())
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let expected = groups
+            .into_iter()
+            .flat_map(|group| group.logs)
+            .collect::<Vec<_>>();
+        assert_eq!(logs, expected);
+    }
+
+    #[tokio::test]
+    async fn expanded_output_has_the_request_fields_as_columns() {
+        let mut log = build_test_http_log(1549573860, None);
+        log.request = LogRequest::from_str("POST /report/daily HTTP/1.1").unwrap();
+        let groups = vec![GroupedHttpLogs {
+            time: log.time,
+            logs: vec![log],
+        }];
+        let buffer = SharedBuffer::default();
+        let sink = CsvSink::new(buffer.clone(), OutputCompression::None)
+            .unwrap()
+            .expand_request(true);
+        let output = String::from_utf8(write_logs_with(sink, buffer, &groups)).unwrap();
+
+        assert_eq!(
+            output,
+            format!(
+                "{}{}\n",
+                EXPANDED_CSV_HEADER,
+                r#""10.1.1.1","-","auth_user",1549573860,"POST","/report/daily","/report","HTTP/1.1",200,100"#
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn parses_output_compressions() {
+        assert_eq!(
+            "gzip".parse::<OutputCompression>().unwrap(),
+            OutputCompression::Gzip
+        );
+        assert_eq!(
+            "zstd".parse::<OutputCompression>().unwrap(),
+            OutputCompression::Zstd
+        );
+        assert_eq!(
+            "none".parse::<OutputCompression>().unwrap(),
+            OutputCompression::None
+        );
+        assert!("lz4".parse::<OutputCompression>().is_err());
+    }
+}
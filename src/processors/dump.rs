@@ -0,0 +1,72 @@
+use super::GroupedHttpLogs;
+use super::Processor;
+use tracing::instrument;
+
+/// Prints a line for every log group it receives. Useful to debug the buffering.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DumpGroups;
+
+impl DumpGroups {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Processor for DumpGroups {
+    #[instrument(skip(self, writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let first_path = log_group
+            .logs
+            .first()
+            .map(|log| log.request.path.as_str())
+            .unwrap_or("-");
+        let msg = format!(
+            "time={} count={} first_path={}\n",
+            log_group.time,
+            log_group.logs.len(),
+            first_path
+        );
+        writer.write_all(msg.as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffered_logs::BufferedLogs,
+        reader::{read_csv_async, ReaderOptions},
+    };
+    use futures::StreamExt;
+    use std::io::BufWriter;
+
+    #[tokio::test]
+    async fn dumps_every_emitted_group() {
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573861,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573860,"GET /report HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573861,"GET /api/help HTTP/1.0",200,1234
+"10.0.0.2","-","apache",1549573862,"POST /api/user HTTP/1.0",200,1234"#
+            .as_bytes();
+        let mut dump = DumpGroups::new();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let log_stream = read_csv_async(&mut input, ReaderOptions::default()).await;
+        let groups = BufferedLogs::new(log_stream, 2).collect::<Vec<_>>().await;
+        for group in &groups {
+            dump.process(group, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            "time=1549573860 count=1 first_path=/report\ntime=1549573861 count=2 first_path=/api/user\ntime=1549573862 count=1 first_path=/api/user\n"
+        );
+    }
+}
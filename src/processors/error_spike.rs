@@ -0,0 +1,239 @@
+use super::alerts::{AlertFormat, AlertProcessor};
+use super::keyed_alert::KeyedAlert;
+use super::GroupedHttpLogs;
+use super::Processor;
+use crate::status::StatusMatcher;
+use std::collections::{HashMap, VecDeque};
+use tracing::instrument;
+
+const ERROR_SPIKE_ALERT: KeyedAlert = KeyedAlert {
+    name: "error-spike",
+    key_kind: "section",
+    triggered: "Error spike on section {key} generated an alert",
+    recovered: "Errors on section {key} recovered",
+};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ErrorCounter {
+    time: usize,
+    error_count_by_section: HashMap<String, usize>,
+}
+
+impl ErrorCounter {
+    fn new(g: &GroupedHttpLogs, error_status: &StatusMatcher) -> Self {
+        let mut error_count_by_section = HashMap::new();
+        for log in g.logs.iter().filter(|log| error_status.matches(log.status)) {
+            *error_count_by_section
+                .entry(log.request.section.clone())
+                .or_insert(0) += 1;
+        }
+        ErrorCounter {
+            time: g.time,
+            error_count_by_section,
+        }
+    }
+}
+
+/// Alerts when the amount of 5xx responses within the window goes over a threshold,
+/// no matter how much traffic there is. The alert names the section with more 5xx, with
+/// its own 5xx out of all of them (e.g. `5xx = 3 of 4`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorSpike {
+    threshold: usize,
+    window_size_in_secs: usize,
+    format: AlertFormat,
+    buffer: VecDeque<ErrorCounter>,
+    error_count: usize,
+    error_count_by_section: HashMap<String, usize>,
+    // section named by the alert, if set
+    alerted_section: Option<String>,
+    error_status: StatusMatcher,
+}
+
+impl ErrorSpike {
+    pub fn new(threshold: usize, window_size_in_secs: usize) -> Self {
+        Self {
+            threshold,
+            window_size_in_secs,
+            format: AlertFormat::Banner,
+            buffer: VecDeque::new(),
+            error_count: 0,
+            error_count_by_section: HashMap::new(),
+            alerted_section: None,
+            error_status: StatusMatcher::server_errors(),
+        }
+    }
+
+    /// Section with more 5xx within the window, ties sorted by section.
+    fn top_section(&self) -> Option<&str> {
+        self.error_count_by_section
+            .iter()
+            .max_by(|(a_section, a), (b_section, b)| {
+                a.cmp(b).then_with(|| b_section.cmp(a_section))
+            })
+            .map(|(section, _)| section.as_str())
+    }
+}
+
+impl AlertProcessor for ErrorSpike {
+    fn set_format(&mut self, format: AlertFormat) {
+        self.format = format;
+    }
+}
+
+impl Processor for ErrorSpike {
+    #[instrument(skip(self, writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let error_counter = ErrorCounter::new(log_group, &self.error_status);
+        for (section, error_count) in &error_counter.error_count_by_section {
+            *self
+                .error_count_by_section
+                .entry(section.clone())
+                .or_insert(0) += error_count;
+            self.error_count += error_count;
+        }
+        self.buffer.push_back(error_counter);
+
+        // draining the error counters out of the window
+        let minor_time = log_group.time.saturating_sub(self.window_size_in_secs);
+        while let Some(error_counter) = self.buffer.front() {
+            if error_counter.time >= minor_time {
+                break;
+            }
+            for (section, error_count) in &error_counter.error_count_by_section {
+                if let Some(count) = self.error_count_by_section.get_mut(section) {
+                    *count -= error_count;
+                    if *count == 0 {
+                        self.error_count_by_section.remove(section);
+                    }
+                }
+                self.error_count -= error_count;
+            }
+            self.buffer.pop_front();
+        }
+
+        let is_above_threshold = self.error_count > self.threshold;
+        let details = |section: &str| {
+            let section_count = self.error_count_by_section.get(section).unwrap_or(&0);
+            format!("5xx = {} of {}", section_count, self.error_count)
+        };
+
+        match (&self.alerted_section, self.top_section()) {
+            (None, Some(section)) if is_above_threshold => {
+                let details = details(section);
+                ERROR_SPIKE_ALERT.trace_triggered(section, &details, log_group.time);
+                let msg = ERROR_SPIKE_ALERT.triggered(section, &details, log_group.time);
+                writer.write_all(self.format.layout(&error_spike_prefix(), &msg).as_bytes())?;
+                self.alerted_section = Some(section.to_string());
+            }
+            (Some(section), _) if !is_above_threshold => {
+                let details = details(section);
+                ERROR_SPIKE_ALERT.trace_recovered(section, &details, log_group.time);
+                let msg = ERROR_SPIKE_ALERT.recovered(section, &details, log_group.time);
+                writer.write_all(self.format.layout(&error_spike_prefix(), &msg).as_bytes())?;
+                self.alerted_section = None;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+fn error_spike_prefix() -> String {
+    console::style("\n>>> ERROR SPIKE\n")
+        .bold()
+        .red()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_test_http_grouped_log, run_processor as run};
+
+    fn build_group(time: usize, ok: usize, errors: usize) -> GroupedHttpLogs {
+        build_section_group(time, ok, errors, "/api/user")
+    }
+
+    fn build_section_group(time: usize, ok: usize, errors: usize, path: &str) -> GroupedHttpLogs {
+        let mut group = build_test_http_grouped_log(time, ok + errors, Some(path.to_string()));
+        group
+            .logs
+            .iter_mut()
+            .take(errors)
+            .for_each(|log| log.status = 503);
+        group
+    }
+
+    #[tokio::test]
+    async fn low_traffic_error_burst_trips_the_alert() {
+        let mut error_spike = ErrorSpike::new(3, 10);
+
+        let msg = run(
+            &mut error_spike,
+            vec![
+                build_group(1, 1, 2),
+                build_group(2, 0, 2),
+                build_group(20, 1, 0),
+            ],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{0}Error spike on section /api generated an alert - 5xx = 4 of 4, triggered at 2\n{0}Errors on section /api recovered - 5xx = 0 of 0, recovered at 20\n",
+                error_spike_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn the_alerts_can_be_single_lines() {
+        let mut error_spike = ErrorSpike::new(3, 10);
+        error_spike.set_format(AlertFormat::Line);
+
+        let msg = run(&mut error_spike, vec![build_group(1, 1, 4)]);
+
+        assert_eq!(
+            msg,
+            ">>> ERROR SPIKE: Error spike on section /api generated an alert - 5xx = 4 of 4, triggered at 1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn the_alert_names_the_section_with_more_errors() {
+        let mut error_spike = ErrorSpike::new(3, 10);
+
+        let msg = run(
+            &mut error_spike,
+            vec![
+                build_section_group(1, 5, 1, "/api/user"),
+                build_section_group(2, 0, 3, "/report"),
+            ],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{}Error spike on section /report generated an alert - 5xx = 3 of 4, triggered at 2\n",
+                error_spike_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn high_traffic_without_errors_does_not_trip_the_alert() {
+        let mut error_spike = ErrorSpike::new(3, 10);
+
+        let msg = run(
+            &mut error_spike,
+            vec![build_group(1, 500, 1), build_group(2, 800, 2)],
+        );
+
+        assert!(msg.is_empty());
+    }
+}
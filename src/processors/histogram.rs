@@ -0,0 +1,175 @@
+use super::GroupedHttpLogs;
+use super::Processor;
+use tracing::instrument;
+
+/// Width of the bar of the bucket with more seconds.
+const MAX_BAR_WIDTH: usize = 40;
+
+/// Distribution of the requests per second of the whole run, reported at the end.
+/// The seconds without logs between the first and the last one are counted as 0 requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RateHistogram {
+    // lower bound of every bucket but the first one, which starts at 0
+    edges: Vec<usize>,
+    // seconds per bucket, one more than edges
+    seconds: Vec<usize>,
+    // (time, req_count) of the second being counted, as it can come in several groups
+    current: Option<(usize, usize)>,
+}
+
+impl RateHistogram {
+    /// Default edges of the buckets of requests per second.
+    pub const DEFAULT_EDGES: &'static [usize] = &[10, 100, 1000];
+
+    pub fn new(edges: Vec<usize>) -> anyhow::Result<Self> {
+        if edges.first() == Some(&0) || edges.windows(2).any(|w| w[0] >= w[1]) {
+            anyhow::bail!(
+                "Invalid histogram edges {:?}, they must be positive and increasing",
+                edges
+            );
+        }
+        Ok(Self {
+            seconds: vec![0; edges.len() + 1],
+            edges,
+            current: None,
+        })
+    }
+
+    fn add_seconds(&mut self, req_count: usize, seconds: usize) {
+        let bucket = self.edges.partition_point(|edge| *edge <= req_count);
+        self.seconds[bucket] += seconds;
+    }
+
+    fn bucket_label(&self, bucket: usize) -> String {
+        let from = bucket.checked_sub(1).map_or(0, |i| self.edges[i]);
+        match self.edges.get(bucket) {
+            Some(to) => format!("{}-{}", from, to - 1),
+            None => format!("{}+", from),
+        }
+    }
+}
+
+impl Processor for RateHistogram {
+    #[instrument(skip(self, _writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        _writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let req_count = log_group.logs.len();
+        self.current = match self.current {
+            Some((time, count)) if time < log_group.time => {
+                self.add_seconds(count, 1);
+                self.add_seconds(0, log_group.time - time - 1);
+                Some((log_group.time, req_count))
+            }
+            // late groups are counted in the current second
+            Some((time, count)) => Some((time, count + req_count)),
+            None => Some((log_group.time, req_count)),
+        };
+        Ok(())
+    }
+
+    fn save_state(&self) -> anyhow::Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::to_value((&self.seconds, self.current))?))
+    }
+
+    fn restore_state(&mut self, state: serde_json::Value) -> anyhow::Result<()> {
+        let (seconds, current): (Vec<usize>, _) = serde_json::from_value(state)?;
+        if seconds.len() != self.seconds.len() {
+            anyhow::bail!("The histogram edges changed");
+        }
+        (self.seconds, self.current) = (seconds, current);
+        Ok(())
+    }
+
+    fn finalize(&mut self, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        match self.current.take() {
+            Some((_, count)) => self.add_seconds(count, 1),
+            None => return Ok(()),
+        }
+        let total = self.seconds.iter().sum::<usize>();
+        let max = self.seconds.iter().copied().max().unwrap_or_default();
+        let labels = (0..self.seconds.len())
+            .map(|bucket| self.bucket_label(bucket))
+            .collect::<Vec<_>>();
+        let label_width = labels.iter().map(String::len).max().unwrap_or_default();
+        let mut msg = histogram_prefix();
+        for (label, seconds) in labels.iter().zip(&self.seconds) {
+            msg.push_str(&format!(
+                "{:>width$} reqs/s: {:<bar_width$} {} seconds ({:.1}%)\n",
+                label,
+                "#".repeat(seconds * MAX_BAR_WIDTH / max),
+                seconds,
+                *seconds as f64 * 100.0 / total as f64,
+                width = label_width,
+                bar_width = MAX_BAR_WIDTH
+            ));
+        }
+        writer.write_all(msg.as_bytes())?;
+        Ok(())
+    }
+}
+
+fn histogram_prefix() -> String {
+    console::style("\nREQUESTS PER SECOND:\n********\n")
+        .bold()
+        .green()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_test_http_grouped_log;
+    use std::io::BufWriter;
+
+    #[tokio::test]
+    async fn counts_the_seconds_of_every_bucket() {
+        let mut histogram = RateHistogram::new(vec![2, 5]).unwrap();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        // 1 second with 1 req, 2 without logs, and seconds with 6, 2 (in two groups) and 5
+        for group in [
+            build_test_http_grouped_log(1, 1, None),
+            build_test_http_grouped_log(4, 6, None),
+            build_test_http_grouped_log(5, 1, None),
+            build_test_http_grouped_log(5, 1, None),
+            build_test_http_grouped_log(6, 5, None),
+        ] {
+            histogram.process(&group, &mut writer).unwrap();
+        }
+        histogram.finalize(&mut writer).unwrap();
+
+        assert_eq!(histogram.seconds, vec![3, 1, 2]);
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}0-1 reqs/s: {:<40} 3 seconds (50.0%)\n2-4 reqs/s: {:<40} 1 seconds (16.7%)\n 5+ reqs/s: {:<40} 2 seconds (33.3%)\n",
+                histogram_prefix(),
+                "#".repeat(40),
+                "#".repeat(13),
+                "#".repeat(26)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_nothing_without_logs() {
+        let mut histogram = RateHistogram::new(RateHistogram::DEFAULT_EDGES.to_vec()).unwrap();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        histogram.finalize(&mut writer).unwrap();
+
+        assert!(writer.into_inner().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn edges_must_be_positive_and_increasing() {
+        assert!(RateHistogram::new(vec![0, 10]).is_err());
+        assert!(RateHistogram::new(vec![10, 10]).is_err());
+        assert!(RateHistogram::new(vec![100, 10]).is_err());
+        assert!(RateHistogram::new(Vec::new()).is_ok());
+    }
+}
@@ -0,0 +1,272 @@
+use super::format_float;
+use super::GroupedHttpLogs;
+use super::Processor;
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+use tracing::instrument;
+
+/// Amount of sections in the table of the report, the most requested ones.
+const TOP_SECTIONS: usize = 20;
+/// Maximum amount of points of the rate chart, the seconds are averaged over them.
+const CHART_POINTS: usize = 600;
+const CHART_WIDTH: usize = 800;
+const CHART_HEIGHT: usize = 200;
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:2em}\
+th,td{border:1px solid #ccc;padding:4px 12px;text-align:left}\
+svg{border:1px solid #ccc}polyline{fill:none;stroke:#2a6fdb;stroke-width:1.5}";
+
+/// Text with the HTML special characters escaped.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Writes a self-contained HTML report once the run completes, with a summary of the run,
+/// the top sections, the hits per status and a chart of the requests per second. It needs
+/// no external scripts nor styles, so it can be shared as it is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HtmlReport {
+    path: PathBuf,
+    requests: u64,
+    bytes: u128,
+    // hits and bytes by section
+    sections: HashMap<String, (u64, u128)>,
+    statuses: BTreeMap<u16, u64>,
+    // requests by second, the seconds without logs are missing
+    rate: BTreeMap<usize, u64>,
+}
+
+impl HtmlReport {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            requests: 0,
+            bytes: 0,
+            sections: HashMap::new(),
+            statuses: BTreeMap::new(),
+            rate: BTreeMap::new(),
+        }
+    }
+
+    /// First and last seconds with logs.
+    fn time_range(&self) -> Option<(usize, usize)> {
+        let first = self.rate.keys().next()?;
+        let last = self.rate.keys().next_back()?;
+        Some((*first, *last))
+    }
+
+    /// Polyline of the requests per second, averaged when there are more seconds than points.
+    fn chart(&self) -> String {
+        let (first, last) = match self.time_range() {
+            Some(range) => range,
+            None => return String::new(),
+        };
+        let seconds = last - first + 1;
+        let bucket_secs = seconds.div_ceil(CHART_POINTS);
+        let rates = (first..=last)
+            .step_by(bucket_secs)
+            .map(|start| {
+                let hits = self
+                    .rate
+                    .range(start..start + bucket_secs)
+                    .map(|(_, hits)| hits)
+                    .sum::<u64>();
+                hits as f64 / bucket_secs as f64
+            })
+            .collect::<Vec<_>>();
+        let peak = rates.iter().cloned().fold(0.0, f64::max);
+        // the chart of less than a request per second isn't scaled up
+        let max_rate = peak.max(1.0);
+        let step = CHART_WIDTH as f64 / (rates.len().max(2) - 1) as f64;
+        let points = rates
+            .iter()
+            .enumerate()
+            .map(|(i, rate)| {
+                let x = i as f64 * step;
+                let y = CHART_HEIGHT as f64 * (1.0 - rate / max_rate);
+                format!("{:.1},{:.1}", x, y)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\"><polyline points=\"{points}\"/></svg>\n<p>Peak: {peak} reqs/sec</p>\n",
+            w = CHART_WIDTH,
+            h = CHART_HEIGHT,
+            points = points,
+            peak = format_float(peak, Some(2)),
+        )
+    }
+
+    fn render(&self) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str("<title>Log report</title>\n");
+        let _ = writeln!(html, "<style>{}</style>", STYLE);
+        html.push_str("</head>\n<body>\n<h1>Log report</h1>\n");
+
+        html.push_str("<h2>Summary</h2>\n<table>\n");
+        let mut summary = vec![
+            ("Requests", self.requests.to_string()),
+            ("Bytes", self.bytes.to_string()),
+        ];
+        if let Some((first, last)) = self.time_range() {
+            let seconds = last - first + 1;
+            summary.extend([
+                ("From", first.to_string()),
+                ("To", last.to_string()),
+                ("Seconds", seconds.to_string()),
+                (
+                    "Avg Reqs/Sec",
+                    format_float(self.requests as f64 / seconds as f64, Some(2)),
+                ),
+            ]);
+        }
+        for (name, value) in summary {
+            let _ = writeln!(html, "<tr><th>{}</th><td>{}</td></tr>", name, value);
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Top sections</h2>\n<table>\n");
+        html.push_str("<tr><th>Section</th><th>Hits</th><th>Bytes</th></tr>\n");
+        let mut sections = self.sections.iter().collect::<Vec<_>>();
+        sections.sort_unstable_by(|a, b| b.1 .0.cmp(&a.1 .0).then_with(|| a.0.cmp(b.0)));
+        for (section, (hits, bytes)) in sections.into_iter().take(TOP_SECTIONS) {
+            let _ = writeln!(
+                html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                escape_html(section),
+                hits,
+                bytes
+            );
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Statuses</h2>\n<table>\n");
+        html.push_str("<tr><th>Status</th><th>Hits</th></tr>\n");
+        for (status, hits) in &self.statuses {
+            let _ = writeln!(html, "<tr><td>{}</td><td>{}</td></tr>", status, hits);
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Requests per second</h2>\n");
+        html.push_str(&self.chart());
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+impl Processor for HtmlReport {
+    #[instrument(skip(self, _writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        _writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        for log in &log_group.logs {
+            let section = self
+                .sections
+                .entry(log.request.section.clone())
+                .or_insert((0, 0));
+            section.0 += 1;
+            section.1 += log.bytes as u128;
+            *self.statuses.entry(log.status).or_insert(0) += 1;
+            self.requests += 1;
+            self.bytes += log.bytes as u128;
+        }
+        if !log_group.logs.is_empty() {
+            *self.rate.entry(log_group.time).or_insert(0) += log_group.logs.len() as u64;
+        }
+        Ok(())
+    }
+
+    /// The report is written to a temporary file first, so a half written one never
+    /// replaces the previous report.
+    fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        let mut tmp_path = OsString::from(&self.path);
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, self.render())
+            .and_then(|_| std::fs::rename(&tmp_path, &self.path))
+            .map_err(|e| anyhow::anyhow!("Can't write the HTML report {:?}: {}", self.path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_test_http_grouped_log;
+
+    #[tokio::test]
+    async fn writes_the_report_once_the_run_completes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.html");
+        let mut report = HtmlReport::new(&path);
+        let mut writer = std::io::sink();
+
+        let mut errors = build_test_http_grouped_log(4, 1, Some("/<script>".to_string()));
+        errors.logs[0].status = 503;
+        errors.logs[0].bytes = 5_000;
+        for group in [
+            build_test_http_grouped_log(1, 2, Some("/api/user".to_string())),
+            build_test_http_grouped_log(2, 1, Some("/api/help".to_string())),
+            build_test_http_grouped_log(2, 1, Some("/report".to_string())),
+            errors,
+        ] {
+            report.process(&group, &mut writer).unwrap();
+        }
+        assert!(!path.exists());
+        report.finalize(&mut writer).unwrap();
+
+        let html = std::fs::read_to_string(&path).unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<tr><th>Requests</th><td>5</td></tr>"));
+        assert!(html.contains("<tr><th>Bytes</th><td>5400</td></tr>"));
+        assert!(html.contains("<tr><th>Seconds</th><td>4</td></tr>"));
+        assert!(html.contains("<tr><th>Avg Reqs/Sec</th><td>1.25</td></tr>"));
+        let sections = html
+            .lines()
+            .filter(|line| line.starts_with("<tr><td>/"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            [
+                "<tr><td>/api</td><td>3</td><td>300</td></tr>",
+                "<tr><td>/&lt;script&gt;</td><td>1</td><td>5000</td></tr>",
+                "<tr><td>/report</td><td>1</td><td>100</td></tr>",
+            ]
+        );
+        assert!(html.contains("<tr><td>200</td><td>4</td></tr>"));
+        assert!(html.contains("<tr><td>503</td><td>1</td></tr>"));
+        // a point per second, the one without logs too
+        assert!(html.contains("<polyline points=\"0.0,0.0 266.7,0.0 533.3,200.0 800.0,100.0\"/>"));
+        assert!(!html.contains("<script"));
+        assert!(html.contains("<p>Peak: 2.00 reqs/sec</p>"));
+        assert!(!dir.path().join("report.html.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn the_peak_is_not_clamped_to_a_request_per_second() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.html");
+        let mut report = HtmlReport::new(&path);
+        let mut writer = std::io::sink();
+
+        // 1200 seconds are averaged in points of 2 seconds
+        for time in [1, 1200] {
+            let group = build_test_http_grouped_log(time, 1, None);
+            report.process(&group, &mut writer).unwrap();
+        }
+        report.finalize(&mut writer).unwrap();
+
+        let html = std::fs::read_to_string(&path).unwrap();
+        assert!(html.contains("<p>Peak: 0.50 reqs/sec</p>"));
+        assert!(html.contains("<polyline points=\"0.0,100.0 "));
+    }
+}
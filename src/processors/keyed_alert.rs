@@ -0,0 +1,99 @@
+/// Placeholder of the offending key in the messages.
+const KEY_PLACEHOLDER: &str = "{key}";
+
+/// Messages of an alert tripped by a single key (a section, a host, a path...), with a
+/// `{key}` placeholder so every alert names the offending key the same way. Besides the
+/// message, a structured event with the key is recorded in the diagnostic logs (see
+/// [`KeyedAlert::trace_triggered`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct KeyedAlert {
+    /// Name of the alert in the structured events, e.g. `abuse`.
+    pub(super) name: &'static str,
+    /// What the key is, e.g. `host`.
+    pub(super) key_kind: &'static str,
+    pub(super) triggered: &'static str,
+    pub(super) recovered: &'static str,
+}
+
+impl KeyedAlert {
+    /// Records the event of the alert tripped by `key` in the diagnostic logs.
+    pub(super) fn trace_triggered(&self, key: &str, details: &str, time: usize) {
+        tracing::warn!(
+            alert = self.name,
+            key_kind = self.key_kind,
+            key,
+            details,
+            time,
+            "Alert triggered"
+        );
+    }
+
+    /// Records the event of the alert of `key` recovering in the diagnostic logs.
+    pub(super) fn trace_recovered(&self, key: &str, details: &str, time: usize) {
+        tracing::info!(
+            alert = self.name,
+            key_kind = self.key_kind,
+            key,
+            details,
+            time,
+            "Alert recovered"
+        );
+    }
+
+    /// Message of the alert tripped by `key`, without the prefix.
+    pub(super) fn triggered(&self, key: &str, details: &str, time: usize) -> String {
+        format!(
+            "{} - {}, triggered at {}\n",
+            self.triggered.replace(KEY_PLACEHOLDER, key),
+            details,
+            time
+        )
+    }
+
+    /// Message of the alert of `key` recovering, without the prefix.
+    pub(super) fn recovered(&self, key: &str, details: &str, time: usize) -> String {
+        format!(
+            "{} - {}, recovered at {}\n",
+            self.recovered.replace(KEY_PLACEHOLDER, key),
+            details,
+            time
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::captured_logs;
+
+    const ALERT: KeyedAlert = KeyedAlert {
+        name: "abuse",
+        key_kind: "host",
+        triggered: "Possible abuse from {key}",
+        recovered: "Traffic from {key} recovered",
+    };
+
+    #[tokio::test]
+    async fn the_messages_and_the_events_name_the_key() {
+        let logs = captured_logs();
+
+        let triggered = ALERT.triggered("10.9.9.1", "hits = 8 of 10", 5);
+        let recovered = ALERT.recovered("10.9.9.1", "hits = 1 of 10", 20);
+        // formatting the messages records nothing
+        assert!(!logs.contents().contains(r#"key="10.9.9.1""#));
+        ALERT.trace_triggered("10.9.9.1", "hits = 8 of 10", 5);
+        ALERT.trace_recovered("10.9.9.1", "hits = 1 of 10", 20);
+
+        assert_eq!(
+            triggered,
+            "Possible abuse from 10.9.9.1 - hits = 8 of 10, triggered at 5\n"
+        );
+        assert_eq!(
+            recovered,
+            "Traffic from 10.9.9.1 recovered - hits = 1 of 10, recovered at 20\n"
+        );
+        let logs = logs.contents();
+        assert!(logs.contains(r#"Alert triggered alert="abuse" key_kind="host" key="10.9.9.1""#));
+        assert!(logs.contains(r#"Alert recovered alert="abuse" key_kind="host" key="10.9.9.1""#));
+    }
+}
@@ -0,0 +1,327 @@
+use super::alerts::{AlertFormat, AlertProcessor};
+use super::keyed_alert::KeyedAlert;
+use super::GroupedHttpLogs;
+use super::Processor;
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    str::FromStr,
+};
+use tracing::instrument;
+
+const LATENCY_ALERT: KeyedAlert = KeyedAlert {
+    name: "latency",
+    key_kind: "section",
+    triggered: "Slow section {key} generated an alert",
+    recovered: "Section {key} latency recovered",
+};
+
+/// How the response times of a section within the window are summarized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyStat {
+    Avg,
+    P95,
+}
+
+impl LatencyStat {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Avg => "avg",
+            Self::P95 => "p95",
+        }
+    }
+
+    /// Summary of the response times, which can't be empty.
+    fn compute(self, latencies: &SectionLatencies) -> u64 {
+        let len = latencies.window.len();
+        match self {
+            Self::Avg => (latencies.total_ms / len as u128) as u64,
+            Self::P95 => {
+                // nearest-rank percentile
+                let rank = (len * 95).div_ceil(100).max(1);
+                let mut seen = 0;
+                latencies
+                    .sorted
+                    .iter()
+                    .find(|(_, count)| {
+                        seen += **count;
+                        seen >= rank
+                    })
+                    .map_or(0, |(ms, _)| *ms)
+            }
+        }
+    }
+}
+
+/// Response times of a section within the window, also kept sorted so the percentiles
+/// don't need to sort the whole window on every group.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SectionLatencies {
+    // time and response time of the logs, the oldest first
+    window: VecDeque<(usize, u64)>,
+    // amount of logs by response time
+    sorted: BTreeMap<u64, usize>,
+    total_ms: u128,
+}
+
+impl SectionLatencies {
+    fn push(&mut self, time: usize, ms: u64) {
+        self.window.push_back((time, ms));
+        *self.sorted.entry(ms).or_insert(0) += 1;
+        self.total_ms += ms as u128;
+    }
+
+    /// Drains the response times which are out of the window.
+    fn evict_older_than(&mut self, minor_time: usize) {
+        while let Some((_, ms)) = self.window.front().filter(|(time, _)| *time < minor_time) {
+            let ms = *ms;
+            self.window.pop_front();
+            self.total_ms -= ms as u128;
+            if let Some(count) = self.sorted.get_mut(&ms) {
+                *count -= 1;
+                if *count == 0 {
+                    self.sorted.remove(&ms);
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for LatencyStat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "avg" => Ok(Self::Avg),
+            "p95" => Ok(Self::P95),
+            _ => Err(anyhow::anyhow!(
+                "Invalid latency stat: {}. Use avg or p95",
+                s
+            )),
+        }
+    }
+}
+
+/// Alerts when the average (or p95) response time of a section within a sliding window goes
+/// over a threshold, catching backend slowdowns which don't show in the amount of requests.
+///
+/// Only the logs with a response time are taken into account, so without them it never alerts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyAlerts {
+    threshold_ms: u64,
+    window_size_in_secs: usize,
+    format: AlertFormat,
+    stat: LatencyStat,
+    // response times of the logs within the window, by section
+    latencies: BTreeMap<String, SectionLatencies>,
+    flagged_sections: BTreeSet<String>,
+    has_latencies: bool,
+}
+
+impl LatencyAlerts {
+    pub fn new(threshold_ms: u64, window_size_in_secs: usize, stat: LatencyStat) -> Self {
+        Self {
+            threshold_ms,
+            window_size_in_secs,
+            format: AlertFormat::Banner,
+            stat,
+            latencies: BTreeMap::new(),
+            flagged_sections: BTreeSet::new(),
+            has_latencies: false,
+        }
+    }
+
+    /// Drains the response times which are out of the window.
+    fn evict_older_than(&mut self, minor_time: usize) {
+        self.latencies.retain(|_, latencies| {
+            latencies.evict_older_than(minor_time);
+            !latencies.window.is_empty()
+        });
+    }
+
+    /// Latency of the section within the window, 0 without response times.
+    fn section_latency(&self, section: &str) -> u64 {
+        self.latencies
+            .get(section)
+            .map_or(0, |latencies| self.stat.compute(latencies))
+    }
+}
+
+impl AlertProcessor for LatencyAlerts {
+    fn set_format(&mut self, format: AlertFormat) {
+        self.format = format;
+    }
+}
+
+impl Processor for LatencyAlerts {
+    #[instrument(skip(self, writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        for log in &log_group.logs {
+            if let Some(response_time_ms) = log.response_time_ms {
+                self.latencies
+                    .entry(log.request.section.clone())
+                    .or_default()
+                    .push(log_group.time, response_time_ms);
+                self.has_latencies = true;
+            }
+        }
+        self.evict_older_than(log_group.time.saturating_sub(self.window_size_in_secs));
+
+        let slow_sections = self
+            .latencies
+            .iter()
+            .filter(|(_, latencies)| self.stat.compute(latencies) > self.threshold_ms)
+            .map(|(section, _)| section.clone())
+            .collect::<BTreeSet<_>>();
+
+        for section in slow_sections.difference(&self.flagged_sections) {
+            let details = format!(
+                "{} latency = {}ms",
+                self.stat.label(),
+                self.section_latency(section)
+            );
+            LATENCY_ALERT.trace_triggered(section, &details, log_group.time);
+            let msg = LATENCY_ALERT.triggered(section, &details, log_group.time);
+            writer.write_all(self.format.layout(&latency_prefix(), &msg).as_bytes())?;
+        }
+
+        for section in self.flagged_sections.difference(&slow_sections) {
+            let details = format!(
+                "{} latency = {}ms",
+                self.stat.label(),
+                self.section_latency(section)
+            );
+            LATENCY_ALERT.trace_recovered(section, &details, log_group.time);
+            let msg = LATENCY_ALERT.recovered(section, &details, log_group.time);
+            writer.write_all(self.format.layout(&latency_prefix(), &msg).as_bytes())?;
+        }
+
+        self.flagged_sections = slow_sections;
+        Ok(())
+    }
+
+    fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        if !self.has_latencies {
+            tracing::warn!("None of the logs had a response time, the latency alerts were skipped");
+        }
+        Ok(())
+    }
+}
+
+fn latency_prefix() -> String {
+    console::style("\n>>> SLOW SECTION\n")
+        .bold()
+        .magenta()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        reader::LogRequest,
+        test_utils::{build_test_http_grouped_log_with, run_processor as run},
+    };
+
+    fn build_group(time: usize, paths: &[(&str, Option<u64>, usize)]) -> GroupedHttpLogs {
+        let logs = paths
+            .iter()
+            .flat_map(|(path, response_time_ms, len)| {
+                build_test_http_grouped_log_with(time, *len, |log| {
+                    log.request = LogRequest::from_str(&format!("GET {} HTTP/1.1", path)).unwrap();
+                    log.response_time_ms = *response_time_ms;
+                })
+                .logs
+            })
+            .collect();
+        GroupedHttpLogs { time, logs }
+    }
+
+    #[tokio::test]
+    async fn slow_section_trips_while_the_rest_stay_fast() {
+        let mut latency_alerts = LatencyAlerts::new(500, 10, LatencyStat::Avg);
+
+        let msg = run(
+            &mut latency_alerts,
+            vec![
+                build_group(1, &[("/api/user", Some(20), 50), ("/report", Some(400), 2)]),
+                build_group(
+                    2,
+                    &[("/api/user", Some(30), 50), ("/report", Some(1000), 2)],
+                ),
+                build_group(
+                    20,
+                    &[("/api/user", Some(20), 50), ("/report", Some(100), 1)],
+                ),
+            ],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{0}Slow section /report generated an alert - avg latency = 700ms, triggered at 2\n{0}Section /report latency recovered - avg latency = 100ms, recovered at 20\n",
+                latency_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn p95_catches_the_slow_tail() {
+        let mut avg_alerts = LatencyAlerts::new(500, 10, LatencyStat::Avg);
+        let mut p95_alerts = LatencyAlerts::new(500, 10, LatencyStat::P95);
+        let groups = || {
+            vec![build_group(
+                1,
+                &[("/api", Some(10), 90), ("/api", Some(2000), 10)],
+            )]
+        };
+
+        let avg_msg = run(&mut avg_alerts, groups());
+        let p95_msg = run(&mut p95_alerts, groups());
+
+        assert!(avg_msg.is_empty());
+        assert_eq!(
+            p95_msg,
+            format!(
+                "{}Slow section /api generated an alert - p95 latency = 2000ms, triggered at 1\n",
+                latency_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn p95_recovers_once_the_slow_tail_leaves_the_window() {
+        let mut p95_alerts = LatencyAlerts::new(500, 10, LatencyStat::P95);
+
+        let msg = run(
+            &mut p95_alerts,
+            vec![
+                build_group(1, &[("/api", Some(10), 90), ("/api", Some(2000), 10)]),
+                build_group(12, &[("/api", Some(20), 1)]),
+            ],
+        );
+
+        assert!(
+            msg.ends_with("Section /api latency recovered - p95 latency = 20ms, recovered at 12\n")
+        );
+        let latencies = &p95_alerts.latencies["/api"];
+        assert_eq!(latencies.sorted, BTreeMap::from([(20, 1)]));
+        assert_eq!(latencies.total_ms, 20);
+    }
+
+    #[tokio::test]
+    async fn logs_without_response_times_are_skipped() {
+        let mut latency_alerts = LatencyAlerts::new(500, 10, LatencyStat::Avg);
+
+        let msg = run(
+            &mut latency_alerts,
+            vec![build_group(1, &[("/api", None, 50), ("/report", None, 2)])],
+        );
+
+        assert!(msg.is_empty());
+        assert!(latency_alerts.latencies.is_empty());
+    }
+}
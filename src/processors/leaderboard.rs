@@ -0,0 +1,245 @@
+use super::GroupedHttpLogs;
+use super::Processor;
+use std::collections::{HashMap, VecDeque};
+use tracing::instrument;
+
+/// Width of the host and section columns, longer values are cut.
+const COLUMN_WIDTH: usize = 24;
+
+/// Hits per host and per section of a group of logs.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct TalkerCounter {
+    time: usize,
+    hosts: HashMap<String, usize>,
+    sections: HashMap<String, usize>,
+}
+
+impl From<&GroupedHttpLogs> for TalkerCounter {
+    fn from(g: &GroupedHttpLogs) -> Self {
+        let mut counter = TalkerCounter {
+            time: g.time,
+            ..TalkerCounter::default()
+        };
+        for log in &g.logs {
+            *counter.hosts.entry(log.remote_host.clone()).or_insert(0) += 1;
+            *counter
+                .sections
+                .entry(log.request.section.clone())
+                .or_insert(0) += 1;
+        }
+        counter
+    }
+}
+
+/// Adds (or, when evicting, removes) the hits of a counter to the totals.
+fn merge(totals: &mut HashMap<String, usize>, hits: &HashMap<String, usize>, is_eviction: bool) {
+    for (key, hits) in hits {
+        let total = totals.entry(key.clone()).or_insert(0);
+        match is_eviction {
+            true => *total -= hits,
+            false => *total += hits,
+        }
+        if *total == 0 {
+            totals.remove(key);
+        }
+    }
+}
+
+/// The `k` keys with more hits, ties sorted by key.
+fn top(totals: &HashMap<String, usize>, k: usize) -> Vec<(String, usize)> {
+    let mut top = totals
+        .iter()
+        .map(|(key, hits)| (key.clone(), *hits))
+        .collect::<Vec<_>>();
+    top.sort_unstable_by(|(a_key, a), (b_key, b)| b.cmp(a).then_with(|| a_key.cmp(b_key)));
+    top.truncate(k);
+    top
+}
+
+/// Top hosts and sections by hits within a sliding window, refreshed every window of log time.
+///
+/// A fresh board is printed every time, as the rest of the processors write to the same output
+/// in between and redrawing it in place would overwrite their lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leaderboard {
+    size: usize,
+    window_size_in_secs: usize,
+    buffer: VecDeque<TalkerCounter>,
+    hosts: HashMap<String, usize>,
+    sections: HashMap<String, usize>,
+    last_time: Option<usize>,
+}
+
+impl Leaderboard {
+    pub fn new(size: usize, window_size_in_secs: usize) -> Self {
+        Self {
+            size,
+            window_size_in_secs,
+            buffer: VecDeque::new(),
+            hosts: HashMap::new(),
+            sections: HashMap::new(),
+            last_time: None,
+        }
+    }
+
+    /// Lines of the board, the title included.
+    fn render(&self) -> Vec<String> {
+        let (hosts, sections) = (top(&self.hosts, self.size), top(&self.sections, self.size));
+        let cell = |entry: Option<&(String, usize)>| match entry {
+            Some((key, hits)) => format!(
+                "{:<width$} {:>8}",
+                key.chars().take(COLUMN_WIDTH).collect::<String>(),
+                hits,
+                width = COLUMN_WIDTH
+            ),
+            None => format!("{:<width$} {:>8}", "", "", width = COLUMN_WIDTH),
+        };
+        let mut lines = vec![
+            console::style(format!("LEADERBOARD (last {}s):", self.window_size_in_secs))
+                .bold()
+                .cyan()
+                .to_string(),
+            format!(
+                "{:<3} {:<width$} {:>8}   {:<width$} {:>8}",
+                "#",
+                "Host",
+                "Hits",
+                "Section",
+                "Hits",
+                width = COLUMN_WIDTH
+            ),
+        ];
+        // empty rows keep the size of the board
+        for i in 0..self.size {
+            lines.push(format!(
+                "{:<3} {}   {}",
+                i + 1,
+                cell(hosts.get(i)),
+                cell(sections.get(i))
+            ));
+        }
+        lines
+    }
+
+    fn draw(&self, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        let mut msg = String::from("\n");
+        for line in self.render() {
+            msg.push_str(&line);
+            msg.push('\n');
+        }
+        writer.write_all(msg.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl Processor for Leaderboard {
+    #[instrument(skip(self, writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let counter = TalkerCounter::from(log_group);
+        merge(&mut self.hosts, &counter.hosts, false);
+        merge(&mut self.sections, &counter.sections, false);
+        self.buffer.push_back(counter);
+
+        // draining the counters out of the window
+        let minor_time = log_group
+            .time
+            .saturating_sub(self.window_size_in_secs.saturating_sub(1));
+        while self.buffer.front().is_some_and(|c| c.time < minor_time) {
+            if let Some(counter) = self.buffer.pop_front() {
+                merge(&mut self.hosts, &counter.hosts, true);
+                merge(&mut self.sections, &counter.sections, true);
+            }
+        }
+
+        let last_time = *self.last_time.get_or_insert(log_group.time);
+        if log_group.time.saturating_sub(last_time) >= self.window_size_in_secs {
+            self.last_time = Some(log_group.time);
+            self.draw(writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{reader::LogRequest, test_utils::build_test_http_grouped_log_with};
+
+    fn build_group(time: usize, talkers: &[(&str, &str, usize)]) -> GroupedHttpLogs {
+        let logs = talkers
+            .iter()
+            .flat_map(|(host, path, len)| {
+                build_test_http_grouped_log_with(time, *len, |log| {
+                    log.request = LogRequest::from_str(&format!("GET {} HTTP/1.1", path)).unwrap();
+                    log.remote_host = host.to_string();
+                })
+                .logs
+            })
+            .collect();
+        GroupedHttpLogs { time, logs }
+    }
+
+    fn entries(entries: &[(&str, usize)]) -> Vec<(String, usize)> {
+        entries
+            .iter()
+            .map(|(key, hits)| (key.to_string(), *hits))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn computes_the_top_talkers_of_the_window() {
+        let mut leaderboard = Leaderboard::new(2, 10);
+        let mut writer = std::io::sink();
+
+        for group in [
+            // out of the window once the last group comes
+            build_group(1, &[("10.0.0.9", "/old", 100)]),
+            build_group(
+                12,
+                &[
+                    ("10.0.0.1", "/api/user", 5),
+                    ("10.0.0.2", "/report", 3),
+                    ("10.0.0.3", "/report", 3),
+                ],
+            ),
+            build_group(
+                13,
+                &[("10.0.0.2", "/api/user", 3), ("10.0.0.4", "/help", 1)],
+            ),
+        ] {
+            leaderboard.process(&group, &mut writer).unwrap();
+        }
+
+        assert_eq!(
+            top(&leaderboard.hosts, 2),
+            entries(&[("10.0.0.2", 6), ("10.0.0.1", 5)])
+        );
+        assert_eq!(
+            top(&leaderboard.sections, 2),
+            entries(&[("/api", 8), ("/report", 6)])
+        );
+        assert!(!leaderboard.hosts.contains_key("10.0.0.9"));
+    }
+
+    #[tokio::test]
+    async fn a_fresh_board_is_printed_every_window() {
+        let mut leaderboard = Leaderboard::new(3, 2);
+        let mut writer = Vec::<u8>::new();
+
+        for time in [1, 3, 5] {
+            let group = build_group(time, &[("10.0.0.1", "/api", 1)]);
+            leaderboard.process(&group, &mut writer).unwrap();
+        }
+
+        let printed = String::from_utf8(writer).unwrap();
+        assert_eq!(printed.matches("LEADERBOARD").count(), 2);
+        // nothing written in between is overwritten
+        assert!(!printed.contains("\x1b[J"));
+        // an empty line, the title, the header and a row per position
+        assert_eq!(printed.lines().count(), 2 * (1 + 1 + 1 + 3));
+    }
+}
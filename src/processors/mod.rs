@@ -1,16 +1,140 @@
+mod abuse;
+mod adaptive;
 mod alerts;
+mod bad_path;
+mod csv_sink;
+mod dump;
+mod error_spike;
+mod histogram;
+mod html_report;
+mod keyed_alert;
+mod latency;
+mod leaderboard;
+#[cfg(feature = "otlp")]
+mod otlp_metrics;
+#[cfg(feature = "pagerduty")]
+mod pagerduty;
+#[cfg(feature = "parquet")]
+mod parquet_sink;
+mod peak;
+mod prom;
+mod protocol;
+mod rate;
+mod registry;
+#[cfg(feature = "sqlite")]
+mod sqlite_stats;
 mod stats;
+#[cfg(feature = "syslog")]
+mod syslog_sink;
+mod timeline;
 
-pub use alerts::Alerts;
-pub use stats::Stats;
+pub use abuse::{AbuseDetector, AbuseThreshold};
+pub use adaptive::AdaptiveAlerts;
+pub use alerts::{
+    AlertFormat, AlertNotifier, AlertProcessor, AlertStream, AlertTier, Alerts, SuppressionWindow,
+};
+pub use bad_path::BadPaths;
+pub use csv_sink::{CsvSink, OutputCompression};
+pub use dump::DumpGroups;
+pub use error_spike::ErrorSpike;
+pub use histogram::RateHistogram;
+pub use html_report::HtmlReport;
+pub use latency::{LatencyAlerts, LatencyStat};
+pub use leaderboard::Leaderboard;
+#[cfg(feature = "otlp")]
+pub use otlp_metrics::OtlpMetrics;
+#[cfg(feature = "pagerduty")]
+pub use pagerduty::PagerDuty;
+#[cfg(feature = "parquet")]
+pub use parquet_sink::ParquetSink;
+pub use peak::Peak;
+pub use prom::PromExposition;
+pub use protocol::ForbiddenProtocols;
+pub use rate::RateSeries;
+pub use registry::list_processors;
+#[cfg(feature = "sqlite")]
+pub use sqlite_stats::SqliteStats;
+pub use stats::{GroupBy, RateUnit, SectionStat, Stats, StatsFormat};
+#[cfg(feature = "syslog")]
+pub use syslog_sink::SyslogSink;
+pub use timeline::AlertTimeline;
 
 use crate::buffered_logs::GroupedHttpLogs;
 
+/// Formats a float with the given amount of decimals, or all of them without one.
+fn format_float(value: f64, precision: Option<usize>) -> String {
+    match precision {
+        Some(precision) => format!("{:.*}", precision, value),
+        None => value.to_string(),
+    }
+}
+
+/// Type name without its module path nor its generics (e.g. `RateSeries`).
+fn short_type_name(name: &'static str) -> &'static str {
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name)
+}
+
 #[cfg_attr(test, mockall::automock)]
 pub trait Processor: Sync + Send {
+    /// Name of the processor in the diagnostic logs, its type name by default.
+    fn name(&self) -> &'static str {
+        short_type_name(std::any::type_name::<Self>())
+    }
+
     fn process(
         &mut self,
         log_group: &GroupedHttpLogs,
         writer: &mut dyn std::io::Write,
     ) -> anyhow::Result<()>;
+
+    /// Time of the next log group the processor cares about. The older groups are not
+    /// dispatched to it. By default, it's called for every group.
+    fn next_interesting_time(&self) -> Option<usize> {
+        None
+    }
+
+    /// Called every wall-clock interval, if any, with the real time elapsed since the
+    /// previous tick. It doesn't depend on the time of the logs.
+    fn tick(
+        &mut self,
+        _elapsed: std::time::Duration,
+        _writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// Called once the log stream is exhausted so the processor can flush any pending state.
+    fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// State saved in a checkpoint so a later run can resume the processor. `None` by
+    /// default, those processors start over.
+    fn save_state(&self) -> anyhow::Result<Option<serde_json::Value>> {
+        Ok(None)
+    }
+
+    /// Resumes the processor from the state it saved in a checkpoint.
+    fn restore_state(&mut self, _state: serde_json::Value) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Processor doing IO (network, disk...). Instead of running in the thread pool like
+/// the [`Processor`]s, all of them are awaited concurrently for every log group.
+#[cfg_attr(test, mockall::automock)]
+#[async_trait::async_trait]
+pub trait AsyncProcessor: Sync + Send {
+    /// Name of the processor in the diagnostic logs, its type name by default.
+    fn name(&self) -> &'static str {
+        short_type_name(std::any::type_name::<Self>())
+    }
+
+    async fn process(&mut self, log_group: &GroupedHttpLogs) -> anyhow::Result<()>;
+
+    /// Called once the log stream is exhausted so the processor can flush any pending state.
+    async fn finalize(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
 }
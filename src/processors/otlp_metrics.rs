@@ -0,0 +1,222 @@
+use super::alerts::{AlertNotifier, AlertSeverity};
+use super::GroupedHttpLogs;
+use super::Processor;
+use opentelemetry::metrics::{Counter, Gauge, MeterProvider};
+use opentelemetry_otlp::{MetricExporter, Protocol, WithExportConfig};
+use opentelemetry_sdk::{
+    metrics::{PeriodicReader, SdkMeterProvider},
+    Resource,
+};
+use std::{sync::Arc, time::Duration};
+use tracing::instrument;
+
+/// How often the metrics are pushed to the collector.
+const EXPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Exports the requests, bytes and errors of the logs as OpenTelemetry metrics.
+/// They're pushed to an OTLP/HTTP collector from a background thread, so the processing
+/// isn't blocked by the network.
+pub struct OtlpMetrics {
+    provider: SdkMeterProvider,
+    requests: Counter<u64>,
+    bytes: Counter<u64>,
+    errors: Counter<u64>,
+    request_rate: Gauge<u64>,
+    alert_active: Gauge<u64>,
+    // second being measured by the request rate and its requests
+    current: Option<(usize, u64)>,
+}
+
+impl OtlpMetrics {
+    /// Exports the metrics to the `/v1/metrics` path of the collector endpoint
+    /// (e.g. `http://localhost:4318`).
+    pub fn connect(endpoint: &str) -> anyhow::Result<Self> {
+        let exporter = MetricExporter::builder()
+            .with_http()
+            .with_protocol(Protocol::HttpBinary)
+            .with_endpoint(format!("{}/v1/metrics", endpoint.trim_end_matches('/')))
+            .build()
+            .map_err(|e| anyhow::anyhow!("Can't create the OTLP exporter: {}", e))?;
+        let provider = SdkMeterProvider::builder()
+            .with_reader(
+                PeriodicReader::builder(exporter)
+                    .with_interval(EXPORT_INTERVAL)
+                    .build(),
+            )
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(env!("CARGO_PKG_NAME"))
+                    .build(),
+            )
+            .build();
+        let meter = provider.meter(env!("CARGO_PKG_NAME"));
+        let alert_active = meter
+            .u64_gauge("log_ingestor.alert.active")
+            .with_description("Whether the high traffic alert is active (1) or not (0)")
+            .build();
+        alert_active.record(0, &[]);
+        Ok(Self {
+            requests: meter
+                .u64_counter("log_ingestor.requests")
+                .with_description("Processed requests")
+                .with_unit("{request}")
+                .build(),
+            bytes: meter
+                .u64_counter("log_ingestor.bytes")
+                .with_description("Bytes of the processed requests")
+                .with_unit("By")
+                .build(),
+            errors: meter
+                .u64_counter("log_ingestor.errors")
+                .with_description("Processed requests with a 5xx status")
+                .with_unit("{request}")
+                .build(),
+            request_rate: meter
+                .u64_gauge("log_ingestor.request_rate")
+                .with_description("Requests of the latest second of the logs")
+                .with_unit("{request}/s")
+                .build(),
+            alert_active,
+            provider,
+            current: None,
+        })
+    }
+
+    /// Notifier keeping the state of the high traffic alert up to date.
+    pub fn alert_notifier(&self) -> Arc<dyn AlertNotifier> {
+        Arc::new(OtlpAlertState(self.alert_active.clone()))
+    }
+}
+
+impl Processor for OtlpMetrics {
+    #[instrument(skip(self, _writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        _writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let reqs = log_group.logs.len() as u64;
+        let bytes = log_group.logs.iter().map(|log| log.bytes as u64).sum();
+        let errors = log_group
+            .logs
+            .iter()
+            .filter(|log| (500..600).contains(&log.status))
+            .count() as u64;
+        self.requests.add(reqs, &[]);
+        self.bytes.add(bytes, &[]);
+        self.errors.add(errors, &[]);
+
+        let rate = match self.current {
+            // late groups are counted in the current second
+            Some((time, current_reqs)) if log_group.time <= time => (time, current_reqs + reqs),
+            _ => (log_group.time, reqs),
+        };
+        self.request_rate.record(rate.1, &[]);
+        self.current = Some(rate);
+        Ok(())
+    }
+
+    fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        // exports the pending metrics and stops the background thread
+        self.provider
+            .shutdown()
+            .map_err(|e| anyhow::anyhow!("Can't export the OTLP metrics: {}", e))
+    }
+}
+
+/// Active state of the high traffic alert, as a gauge.
+struct OtlpAlertState(Gauge<u64>);
+
+impl AlertNotifier for OtlpAlertState {
+    fn notify(&self, severity: AlertSeverity, _msg: &str) -> anyhow::Result<()> {
+        self.0
+            .record((severity == AlertSeverity::Warning).into(), &[]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{processors::Alerts, test_utils::build_test_http_grouped_log};
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::mpsc,
+    };
+
+    /// Collector answering every request, sending their request line and body to the channel.
+    fn mock_collector() -> (String, mpsc::Receiver<(String, Vec<u8>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    stream.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).unwrap();
+                stream
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .unwrap();
+                if sender
+                    .send((request_line.trim().to_string(), body))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        (endpoint, receiver)
+    }
+
+    #[tokio::test]
+    async fn exports_the_metrics_to_the_collector() {
+        let (endpoint, receiver) = mock_collector();
+        let mut metrics = OtlpMetrics::connect(&endpoint).unwrap();
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .notifier(Some(metrics.alert_notifier()))
+            .build();
+
+        let mut writer = std::io::sink();
+        for group in [
+            build_test_http_grouped_log(1, 3, None),
+            build_test_http_grouped_log(2, 1, None),
+        ] {
+            alerts.process(&group, &mut writer).unwrap();
+            metrics.process(&group, &mut writer).unwrap();
+        }
+        metrics.finalize(&mut writer).unwrap();
+
+        let (request_line, body) = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        assert_eq!(request_line, "POST /v1/metrics HTTP/1.1");
+        // the names of the metrics are plain strings in the protobuf payload
+        let body = String::from_utf8_lossy(&body);
+        for name in [
+            "log_ingestor.requests",
+            "log_ingestor.bytes",
+            "log_ingestor.errors",
+            "log_ingestor.request_rate",
+            "log_ingestor.alert.active",
+        ] {
+            assert!(body.contains(name), "{} wasn't exported", name);
+        }
+    }
+}
@@ -0,0 +1,239 @@
+use super::alerts::{AlertNotifier, AlertSeverity};
+use serde_json::json;
+use std::{
+    sync::{mpsc, Mutex},
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// Endpoint of the PagerDuty Events API v2.
+const EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+/// Maximum time to send an event.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Sends the alerts to PagerDuty as `trigger` and `resolve` events.
+/// A triggered alert, its escalations and downgrades, and its recovery share the same dedup
+/// key, so they map to one incident.
+/// The events are posted from a background thread, so the processing isn't blocked by the network.
+pub struct PagerDuty {
+    routing_key: String,
+    source: String,
+    // dedup key of the alert being active
+    dedup_key: Mutex<Option<String>>,
+    sender: Option<mpsc::Sender<serde_json::Value>>,
+    poster: Option<JoinHandle<()>>,
+}
+
+impl PagerDuty {
+    pub fn new(routing_key: &str) -> Self {
+        Self::with_endpoint(routing_key, EVENTS_URL)
+    }
+
+    fn with_endpoint(routing_key: &str, endpoint: &str) -> Self {
+        let (sender, receiver) = mpsc::channel::<serde_json::Value>();
+        let agent = ureq::AgentBuilder::new().timeout(TIMEOUT).build();
+        let endpoint = endpoint.to_string();
+        let poster = std::thread::spawn(move || {
+            for event in receiver {
+                if let Err(e) = agent.post(&endpoint).send_json(&event) {
+                    tracing::error!("Can't send the event to PagerDuty: {}", e);
+                }
+            }
+        });
+        Self {
+            routing_key: routing_key.to_string(),
+            source: dns_lookup::get_hostname()
+                .unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string()),
+            dedup_key: Mutex::new(None),
+            sender: Some(sender),
+            poster: Some(poster),
+        }
+    }
+
+    fn event(&self, severity: AlertSeverity, msg: &str) -> Option<serde_json::Value> {
+        let mut dedup_key = self.dedup_key.lock().unwrap_or_else(|e| e.into_inner());
+        match severity {
+            AlertSeverity::Warning => {
+                // the tier changes of an active alert update its incident
+                let key = dedup_key
+                    .get_or_insert_with(|| {
+                        format!(
+                            "{}-high-traffic-{:016x}",
+                            env!("CARGO_PKG_NAME"),
+                            rand::random::<u64>()
+                        )
+                    })
+                    .clone();
+                Some(json!({
+                    "routing_key": self.routing_key,
+                    "event_action": "trigger",
+                    "dedup_key": key,
+                    "payload": {
+                        "summary": msg,
+                        "source": self.source,
+                        "severity": "warning",
+                    },
+                }))
+            }
+            AlertSeverity::Notice => match dedup_key.take() {
+                Some(key) => Some(json!({
+                    "routing_key": self.routing_key,
+                    "event_action": "resolve",
+                    "dedup_key": key,
+                })),
+                None => {
+                    // e.g. an alert restored from the state file
+                    tracing::warn!("No PagerDuty incident to resolve: {}", msg);
+                    None
+                }
+            },
+        }
+    }
+}
+
+impl AlertNotifier for PagerDuty {
+    fn notify(&self, severity: AlertSeverity, msg: &str) -> anyhow::Result<()> {
+        let (event, sender) = match (self.event(severity, msg), &self.sender) {
+            (Some(event), Some(sender)) => (event, sender),
+            _ => return Ok(()),
+        };
+        sender
+            .send(event)
+            .map_err(|_| anyhow::anyhow!("The PagerDuty poster is gone"))
+    }
+}
+
+/// Waits for the pending events to be sent.
+impl Drop for PagerDuty {
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(poster) = self.poster.take() {
+            if poster.join().is_err() {
+                tracing::error!("The PagerDuty poster panicked");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        processors::{Alerts, Processor},
+        test_utils::build_test_http_grouped_log,
+    };
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        sync::Arc,
+    };
+
+    /// Events API answering every request, sending their path and JSON body to the channel.
+    fn mock_events_api() -> (String, mpsc::Receiver<(String, serde_json::Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/v2/enqueue", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = BufReader::new(stream.unwrap());
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    stream.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).unwrap();
+                stream
+                    .get_mut()
+                    .write_all(
+                        b"HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    )
+                    .unwrap();
+                let event = serde_json::from_slice(&body).unwrap();
+                if sender
+                    .send((request_line.trim().to_string(), event))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        (endpoint, receiver)
+    }
+
+    #[tokio::test]
+    async fn alerts_trigger_and_resolve_the_same_incident() {
+        let (endpoint, receiver) = mock_events_api();
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .notifier(Some(Arc::new(PagerDuty::with_endpoint("key", &endpoint))))
+            .build();
+
+        let mut writer = std::io::sink();
+        alerts
+            .process(&build_test_http_grouped_log(1, 3, None), &mut writer)
+            .unwrap();
+        alerts
+            .process(&build_test_http_grouped_log(4, 1, None), &mut writer)
+            .unwrap();
+        // waits for the events to be sent
+        drop(alerts);
+
+        let (request_line, trigger) = receiver.recv_timeout(TIMEOUT).unwrap();
+        let (_, resolve) = receiver.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(request_line, "POST /v2/enqueue HTTP/1.1");
+        assert_eq!(trigger["routing_key"], "key");
+        assert_eq!(trigger["event_action"], "trigger");
+        assert_eq!(trigger["payload"]["severity"], "warning");
+        assert_eq!(
+            trigger["payload"]["summary"],
+            "High traffic generated an alert - hits = 1.5, triggered at 1"
+        );
+        assert_eq!(resolve["event_action"], "resolve");
+        assert!(trigger["dedup_key"].is_string());
+        assert_eq!(resolve["dedup_key"], trigger["dedup_key"]);
+    }
+
+    #[tokio::test]
+    async fn escalations_update_the_same_incident() {
+        let (endpoint, receiver) = mock_events_api();
+        let mut alerts = Alerts::builder()
+            .window_size_in_secs(2)
+            .tiers(vec!["1:warn".parse().unwrap(), "3:page".parse().unwrap()])
+            .notifier(Some(Arc::new(PagerDuty::with_endpoint("key", &endpoint))))
+            .build();
+
+        let mut writer = std::io::sink();
+        // triggered, escalated and recovered
+        for (time, len) in [(1, 3), (2, 6), (5, 1)] {
+            alerts
+                .process(&build_test_http_grouped_log(time, len, None), &mut writer)
+                .unwrap();
+        }
+        drop(alerts);
+
+        let events = (0..3)
+            .map(|_| receiver.recv_timeout(TIMEOUT).unwrap().1)
+            .collect::<Vec<_>>();
+        let actions = events
+            .iter()
+            .map(|event| event["event_action"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(actions, ["trigger", "trigger", "resolve"]);
+        assert!(events
+            .iter()
+            .all(|event| event["dedup_key"] == events[0]["dedup_key"]));
+        assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use super::GroupedHttpLogs;
+use super::Processor;
+use arrow_array::{ArrayRef, RecordBatch, StringArray, UInt16Array, UInt64Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use std::{fs::File, path::Path, sync::Arc, sync::Mutex};
+use tracing::instrument;
+
+/// Columns of the written logs.
+fn schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("host", DataType::Utf8, false),
+        Field::new("time", DataType::UInt64, false),
+        Field::new("verb", DataType::Utf8, false),
+        Field::new("path", DataType::Utf8, false),
+        Field::new("section", DataType::Utf8, false),
+        Field::new("status", DataType::UInt16, false),
+        Field::new("bytes", DataType::UInt64, false),
+    ]))
+}
+
+/// Writes the processed logs to a Parquet file, a record batch per group of logs.
+/// The writer gathers the batches into row groups, the last one is written on finalize.
+pub struct ParquetSink {
+    schema: SchemaRef,
+    // the writer isn't Sync, and it's taken when closing the file
+    writer: Mutex<Option<ArrowWriter<File>>>,
+}
+
+impl ParquetSink {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::create(path)
+            .map_err(|e| anyhow::anyhow!("Can't create the Parquet output {:?}: {}", path, e))?;
+        let schema = schema();
+        let writer = ArrowWriter::try_new(file, schema.clone(), None)?;
+        Ok(Self {
+            schema,
+            writer: Mutex::new(Some(writer)),
+        })
+    }
+
+    fn batch(&self, log_group: &GroupedHttpLogs) -> anyhow::Result<RecordBatch> {
+        let logs = &log_group.logs;
+        let strings = |field: fn(&crate::reader::HttpLog) -> &str| -> ArrayRef {
+            Arc::new(logs.iter().map(field).map(Some).collect::<StringArray>())
+        };
+        let columns: Vec<ArrayRef> = vec![
+            strings(|log| &log.remote_host),
+            Arc::new(UInt64Array::from_iter_values(
+                logs.iter().map(|log| log.time as u64),
+            )),
+            strings(|log| &log.request.verb),
+            strings(|log| &log.request.path),
+            strings(|log| &log.request.section),
+            Arc::new(UInt16Array::from_iter_values(
+                logs.iter().map(|log| log.status),
+            )),
+            Arc::new(UInt64Array::from_iter_values(
+                logs.iter().map(|log| log.bytes as u64),
+            )),
+        ];
+        Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+    }
+}
+
+impl Processor for ParquetSink {
+    #[instrument(skip(self, _writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        _writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let batch = self.batch(log_group)?;
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Parquet writer lock is poisoned"))?;
+        match writer.as_mut() {
+            Some(writer) => writer.write(&batch)?,
+            None => anyhow::bail!("The Parquet output is already closed"),
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        let writer = self
+            .writer
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Parquet writer lock is poisoned"))?
+            .take();
+        if let Some(writer) = writer {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_test_http_grouped_log;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::{UInt16Type, UInt64Type};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[tokio::test]
+    async fn writes_the_groups_as_parquet() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logs.parquet");
+        let mut sink = ParquetSink::create(&path).unwrap();
+        let mut writer = std::io::sink();
+
+        let mut errors = build_test_http_grouped_log(2, 1, Some("/report/daily".to_string()));
+        errors.logs[0].status = 503;
+        errors.logs[0].bytes = 5_000;
+        errors.logs[0].request.verb = "POST".to_string();
+        for group in [
+            build_test_http_grouped_log(1, 2, Some("/api/user".to_string())),
+            errors,
+        ] {
+            sink.process(&group, &mut writer).unwrap();
+        }
+        sink.finalize(&mut writer).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(batches.iter().all(|batch| batch.schema() == schema()));
+        let strings = |column: &str| {
+            batches
+                .iter()
+                .flat_map(|batch| {
+                    batch
+                        .column_by_name(column)
+                        .unwrap()
+                        .as_string::<i32>()
+                        .iter()
+                })
+                .map(|value| value.unwrap().to_string())
+                .collect::<Vec<_>>()
+        };
+        let numbers = |column: &str| {
+            batches
+                .iter()
+                .flat_map(|batch| {
+                    let array = batch.column_by_name(column).unwrap();
+                    match array.data_type() {
+                        DataType::UInt16 => array
+                            .as_primitive::<UInt16Type>()
+                            .values()
+                            .iter()
+                            .map(|value| *value as u64)
+                            .collect::<Vec<_>>(),
+                        _ => array.as_primitive::<UInt64Type>().values().to_vec(),
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(strings("host"), ["10.1.1.1"; 3]);
+        assert_eq!(strings("verb")[2], "POST");
+        assert_eq!(strings("path"), ["/api/user", "/api/user", "/report/daily"]);
+        assert_eq!(strings("section"), ["/api", "/api", "/report"]);
+        assert_eq!(numbers("time"), [1, 1, 2]);
+        assert_eq!(numbers("status"), [200, 200, 503]);
+        assert_eq!(numbers("bytes"), [100, 100, 5_000]);
+    }
+}
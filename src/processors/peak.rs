@@ -0,0 +1,119 @@
+use super::GroupedHttpLogs;
+use super::Processor;
+use tracing::instrument;
+
+/// Tracks the busiest second of the whole run and reports it at the end.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Peak {
+    // (time, req_count)
+    peak: Option<(usize, usize)>,
+    // the same second can come in several groups
+    current: Option<(usize, usize)>,
+}
+
+impl Peak {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Processor for Peak {
+    #[instrument(skip(self, _writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        _writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let req_count = match self.current {
+            Some((time, req_count)) if time == log_group.time => req_count + log_group.logs.len(),
+            _ => log_group.logs.len(),
+        };
+        self.current = Some((log_group.time, req_count));
+        match self.peak {
+            // on ties, the first second wins
+            Some((_, peak_count)) if peak_count >= req_count => {}
+            _ => self.peak = Some((log_group.time, req_count)),
+        }
+        Ok(())
+    }
+
+    fn save_state(&self) -> anyhow::Result<Option<serde_json::Value>> {
+        Ok(Some(serde_json::to_value((self.peak, self.current))?))
+    }
+
+    fn restore_state(&mut self, state: serde_json::Value) -> anyhow::Result<()> {
+        (self.peak, self.current) = serde_json::from_value(state)?;
+        Ok(())
+    }
+
+    fn finalize(&mut self, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        if let Some((time, req_count)) = self.peak {
+            let msg = format!("{}Peak: {} reqs at {}\n", peak_prefix(), req_count, time);
+            writer.write_all(msg.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+fn peak_prefix() -> String {
+    console::style("\nPEAK SECOND:\n********\n")
+        .bold()
+        .green()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_test_http_grouped_log;
+    use std::io::BufWriter;
+
+    #[tokio::test]
+    async fn reports_the_busiest_second() {
+        let mut peak = Peak::new();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_test_http_grouped_log(1, 2, None),
+            build_test_http_grouped_log(2, 5, None),
+            build_test_http_grouped_log(3, 3, None),
+            build_test_http_grouped_log(4, 5, None),
+        ];
+
+        for log in logs {
+            peak.process(&log, &mut writer).unwrap();
+        }
+        peak.finalize(&mut writer).unwrap();
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(msg, format!("{}Peak: 5 reqs at 2\n", peak_prefix()));
+    }
+
+    #[tokio::test]
+    async fn reports_nothing_without_logs() {
+        let mut peak = Peak::new();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        peak.finalize(&mut writer).unwrap();
+
+        assert!(writer.into_inner().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn adds_up_the_groups_of_the_same_second() {
+        let mut peak = Peak::new();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        for group in [
+            build_test_http_grouped_log(1, 3, None),
+            build_test_http_grouped_log(2, 2, None),
+            build_test_http_grouped_log(2, 2, None),
+        ] {
+            peak.process(&group, &mut writer).unwrap();
+        }
+        peak.finalize(&mut writer).unwrap();
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(msg, format!("{}Peak: 4 reqs at 2\n", peak_prefix()));
+    }
+}
@@ -0,0 +1,211 @@
+use super::GroupedHttpLogs;
+use super::Processor;
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+use tracing::instrument;
+
+/// Upper bounds of the buckets of the response bytes histogram.
+const BYTES_BUCKETS: &[usize] = &[100, 1_000, 10_000, 100_000, 1_000_000, 10_000_000];
+
+/// Label value with the backslashes, quotes and line feeds escaped.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Writes the requests per section and status, and a histogram of the response bytes, in
+/// the Prometheus text format once the run completes, e.g. for the textfile collector of
+/// the node exporter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PromExposition {
+    path: PathBuf,
+    requests: BTreeMap<(String, u16), u64>,
+    // requests with up to the bytes of every bucket, the `+Inf` one aside
+    bytes_buckets: Vec<u64>,
+    bytes_sum: u128,
+    bytes_count: u64,
+}
+
+impl PromExposition {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            requests: BTreeMap::new(),
+            bytes_buckets: vec![0; BYTES_BUCKETS.len()],
+            bytes_sum: 0,
+            bytes_count: 0,
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut exposition = String::new();
+        exposition.push_str("# HELP log_ingestor_requests_total Requests by section and status.\n");
+        exposition.push_str("# TYPE log_ingestor_requests_total counter\n");
+        for ((section, status), requests) in &self.requests {
+            let _ = writeln!(
+                exposition,
+                "log_ingestor_requests_total{{section=\"{}\",status=\"{}\"}} {}",
+                escape_label(section),
+                status,
+                requests
+            );
+        }
+        exposition.push_str("# HELP log_ingestor_response_bytes Bytes of the responses.\n");
+        exposition.push_str("# TYPE log_ingestor_response_bytes histogram\n");
+        for (le, requests) in BYTES_BUCKETS.iter().zip(&self.bytes_buckets) {
+            let _ = writeln!(
+                exposition,
+                "log_ingestor_response_bytes_bucket{{le=\"{}\"}} {}",
+                le, requests
+            );
+        }
+        let _ = writeln!(
+            exposition,
+            "log_ingestor_response_bytes_bucket{{le=\"+Inf\"}} {}",
+            self.bytes_count
+        );
+        let _ = writeln!(
+            exposition,
+            "log_ingestor_response_bytes_sum {}",
+            self.bytes_sum
+        );
+        let _ = writeln!(
+            exposition,
+            "log_ingestor_response_bytes_count {}",
+            self.bytes_count
+        );
+        exposition
+    }
+}
+
+impl Processor for PromExposition {
+    #[instrument(skip(self, _writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        _writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        for log in &log_group.logs {
+            *self
+                .requests
+                .entry((log.request.section.clone(), log.status))
+                .or_insert(0) += 1;
+            // the buckets are cumulative
+            let first_bucket = BYTES_BUCKETS.partition_point(|le| *le < log.bytes);
+            for requests in &mut self.bytes_buckets[first_bucket..] {
+                *requests += 1;
+            }
+            self.bytes_sum += log.bytes as u128;
+            self.bytes_count += 1;
+        }
+        Ok(())
+    }
+
+    /// The exposition is written to a temporary file first, so the collector never reads
+    /// half of it.
+    fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        let mut tmp_path = OsString::from(&self.path);
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, self.render())
+            .and_then(|_| std::fs::rename(&tmp_path, &self.path))
+            .map_err(|e| {
+                anyhow::anyhow!("Can't write the Prometheus output {:?}: {}", self.path, e)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_test_http_grouped_log;
+
+    /// Samples of an exposition by their name and labels, failing on invalid lines.
+    fn parse(exposition: &str) -> BTreeMap<String, f64> {
+        let mut samples = BTreeMap::new();
+        for line in exposition.lines() {
+            if line.starts_with("# HELP ") || line.starts_with("# TYPE ") {
+                continue;
+            }
+            let (series, value) = line.rsplit_once(' ').expect("a sample");
+            let name = series.split('{').next().unwrap();
+            assert!(
+                name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+                "invalid metric name in {}",
+                line
+            );
+            if let Some(labels) = series.strip_prefix(name) {
+                assert!(
+                    labels.is_empty() || (labels.starts_with('{') && labels.ends_with('}')),
+                    "invalid labels in {}",
+                    line
+                );
+            }
+            samples.insert(series.to_string(), value.parse().expect("a number"));
+        }
+        samples
+    }
+
+    #[tokio::test]
+    async fn writes_the_exposition_once_the_run_completes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log_ingestor.prom");
+        let mut prom = PromExposition::new(&path);
+        let mut writer = std::io::sink();
+
+        let mut large = build_test_http_grouped_log(2, 1, Some("/report".to_string()));
+        large.logs[0].status = 500;
+        large.logs[0].bytes = 5_000;
+        for group in [
+            build_test_http_grouped_log(1, 2, Some("/api/user".to_string())),
+            build_test_http_grouped_log(2, 1, Some("/api/user".to_string())),
+            large,
+        ] {
+            prom.process(&group, &mut writer).unwrap();
+        }
+        assert!(!path.exists());
+        prom.finalize(&mut writer).unwrap();
+
+        let exposition = std::fs::read_to_string(&path).unwrap();
+        assert!(exposition.contains("# TYPE log_ingestor_requests_total counter\n"));
+        assert!(exposition.contains("# TYPE log_ingestor_response_bytes histogram\n"));
+        let samples = parse(&exposition);
+        assert_eq!(
+            samples[r#"log_ingestor_requests_total{section="/api",status="200"}"#],
+            3.0
+        );
+        assert_eq!(
+            samples[r#"log_ingestor_requests_total{section="/report",status="500"}"#],
+            1.0
+        );
+        assert_eq!(
+            samples[r#"log_ingestor_response_bytes_bucket{le="100"}"#],
+            3.0
+        );
+        assert_eq!(
+            samples[r#"log_ingestor_response_bytes_bucket{le="1000"}"#],
+            3.0
+        );
+        assert_eq!(
+            samples[r#"log_ingestor_response_bytes_bucket{le="10000"}"#],
+            4.0
+        );
+        assert_eq!(
+            samples[r#"log_ingestor_response_bytes_bucket{le="+Inf"}"#],
+            4.0
+        );
+        assert_eq!(samples["log_ingestor_response_bytes_sum"], 5_300.0);
+        assert_eq!(samples["log_ingestor_response_bytes_count"], 4.0);
+    }
+
+    #[tokio::test]
+    async fn escapes_the_label_values() {
+        assert_eq!(escape_label(r#"/a"b\c"#), r#"/a\"b\\c"#);
+        assert_eq!(escape_label("/a\nb"), "/a\\nb");
+    }
+}
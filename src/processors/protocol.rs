@@ -0,0 +1,159 @@
+use super::alerts::{AlertFormat, AlertProcessor};
+use super::GroupedHttpLogs;
+use super::Processor;
+use std::collections::{BTreeMap, HashMap};
+use tracing::instrument;
+
+/// Alerts when a request uses a forbidden protocol (e.g. `HTTP/1.0` where `HTTP/2` is expected).
+/// The same protocol is alerted at most once per window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForbiddenProtocols {
+    // either known protocols or `unknown` for the rest
+    protocols: Vec<String>,
+    window_size_in_secs: usize,
+    format: AlertFormat,
+    last_alerts: HashMap<String, usize>,
+}
+
+impl ForbiddenProtocols {
+    pub fn new(protocols: Vec<String>, window_size_in_secs: usize) -> Self {
+        Self {
+            protocols,
+            window_size_in_secs,
+            format: AlertFormat::Banner,
+            last_alerts: HashMap::new(),
+        }
+    }
+
+    fn is_forbidden(&self, protocol: &str) -> bool {
+        self.protocols
+            .iter()
+            .any(|forbidden| forbidden.eq_ignore_ascii_case(protocol))
+    }
+}
+
+impl AlertProcessor for ForbiddenProtocols {
+    fn set_format(&mut self, format: AlertFormat) {
+        self.format = format;
+    }
+}
+
+impl Processor for ForbiddenProtocols {
+    #[instrument(skip(self, writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let mut hits_by_protocol = BTreeMap::new();
+        for log in &log_group.logs {
+            let protocol = log.request.protocol_label();
+            if self.is_forbidden(protocol) {
+                *hits_by_protocol.entry(protocol).or_insert(0) += 1;
+            }
+        }
+
+        for (protocol, hits) in hits_by_protocol {
+            // late groups are within the window of an alert after them
+            let is_in_window = self.last_alerts.get(protocol).is_some_and(|last_alert| {
+                log_group.time.saturating_sub(*last_alert) < self.window_size_in_secs
+            });
+            if is_in_window {
+                continue;
+            }
+            self.last_alerts
+                .insert(protocol.to_string(), log_group.time);
+            let msg = format!(
+                "Forbidden protocol {} used - hits = {}, triggered at {}",
+                protocol, hits, log_group.time
+            );
+            writer.write_all(
+                self.format
+                    .layout(&forbidden_protocol_prefix(), &msg)
+                    .as_bytes(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn forbidden_protocol_prefix() -> String {
+    console::style("\n>>> FORBIDDEN PROTOCOL\n")
+        .bold()
+        .red()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{build_test_http_grouped_log_with, run_processor as run};
+
+    fn build_group(time: usize, protocols: &[&str]) -> GroupedHttpLogs {
+        let logs = protocols
+            .iter()
+            .flat_map(|protocol| {
+                build_test_http_grouped_log_with(time, 1, |log| {
+                    log.request.protocol = protocol.to_string()
+                })
+                .logs
+            })
+            .collect();
+        GroupedHttpLogs { time, logs }
+    }
+
+    #[tokio::test]
+    async fn forbidden_protocols_trip_the_alert_once_per_window() {
+        let mut forbidden =
+            ForbiddenProtocols::new(vec!["HTTP/1.0".to_string(), "unknown".to_string()], 10);
+
+        let msg = run(
+            &mut forbidden,
+            vec![
+                build_group(1, &["HTTP/2", "HTTP/1.1"]),
+                build_group(2, &["HTTP/2", "HTTP/1.0", "HTTP/1.0"]),
+                build_group(5, &["HTTP/1.0", "garbage"]),
+                build_group(12, &["HTTP/1.0"]),
+            ],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{0}Forbidden protocol HTTP/1.0 used - hits = 2, triggered at 2\n{0}Forbidden protocol unknown used - hits = 1, triggered at 5\n{0}Forbidden protocol HTTP/1.0 used - hits = 1, triggered at 12\n",
+                forbidden_protocol_prefix()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn the_alerts_can_be_single_lines() {
+        let mut forbidden = ForbiddenProtocols::new(vec!["HTTP/1.0".to_string()], 10);
+        forbidden.set_format(AlertFormat::Line);
+
+        let msg = run(&mut forbidden, vec![build_group(1, &["HTTP/1.0"])]);
+
+        assert_eq!(
+            msg,
+            ">>> FORBIDDEN PROTOCOL: Forbidden protocol HTTP/1.0 used - hits = 1, triggered at 1\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn late_groups_are_within_the_window_of_the_last_alert() {
+        let mut forbidden = ForbiddenProtocols::new(vec!["HTTP/1.0".to_string()], 10);
+
+        let msg = run(
+            &mut forbidden,
+            vec![build_group(5, &["HTTP/1.0"]), build_group(3, &["HTTP/1.0"])],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{}Forbidden protocol HTTP/1.0 used - hits = 1, triggered at 5\n",
+                forbidden_protocol_prefix()
+            )
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use super::GroupedHttpLogs;
+use super::Processor;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+use tracing::instrument;
+
+const RATE_HEADER: &str = "time,requests_per_sec,bytes_per_sec\n";
+
+/// Requests and bytes of a single second.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Sample {
+    time: usize,
+    reqs: usize,
+    bytes: u128,
+}
+
+/// Writes the requests and bytes of every second to a CSV file, ready to be plotted.
+/// The seconds without logs are written as zeros so the series is continuous.
+pub struct RateSeries<W: Write> {
+    writer: W,
+    // second being accumulated, it may still get more groups
+    current: Option<Sample>,
+}
+
+impl RateSeries<BufWriter<File>> {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::create(path)
+            .map_err(|e| anyhow::anyhow!("Can't create the rate output {:?}: {}", path, e))?;
+        Self::new(BufWriter::new(file))
+    }
+}
+
+impl<W: Write> RateSeries<W> {
+    pub fn new(mut writer: W) -> anyhow::Result<Self> {
+        writer.write_all(RATE_HEADER.as_bytes())?;
+        Ok(Self {
+            writer,
+            current: None,
+        })
+    }
+
+    fn write_sample(&mut self, sample: Sample) -> anyhow::Result<()> {
+        writeln!(
+            self.writer,
+            "{},{},{}",
+            sample.time, sample.reqs, sample.bytes
+        )?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send + Sync> Processor for RateSeries<W> {
+    #[instrument(skip(self, _writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        _writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let reqs = log_group.logs.len();
+        let bytes = log_group
+            .logs
+            .iter()
+            .map(|log| log.bytes as u128)
+            .sum::<u128>();
+        match &mut self.current {
+            // late groups are counted in the current second, its row isn't written yet
+            Some(current) if log_group.time <= current.time => {
+                current.reqs += reqs;
+                current.bytes += bytes;
+            }
+            current => {
+                if let Some(previous) = current.replace(Sample {
+                    time: log_group.time,
+                    reqs,
+                    bytes,
+                }) {
+                    self.write_sample(previous)?;
+                    for time in previous.time + 1..log_group.time {
+                        self.write_sample(Sample {
+                            time,
+                            ..Sample::default()
+                        })?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        if let Some(current) = self.current.take() {
+            self.write_sample(current)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_test_http_grouped_log;
+
+    #[tokio::test]
+    async fn writes_a_continuous_series_with_zero_filled_gaps() {
+        let mut rate_series = RateSeries::new(Vec::new()).unwrap();
+        let mut writer = std::io::sink();
+
+        for group in [
+            build_test_http_grouped_log(10, 2, None),
+            build_test_http_grouped_log(11, 3, None),
+            // a group split in two
+            build_test_http_grouped_log(11, 1, None),
+            build_test_http_grouped_log(14, 1, None),
+        ] {
+            rate_series.process(&group, &mut writer).unwrap();
+        }
+        rate_series.finalize(&mut writer).unwrap();
+
+        // without the module path nor the writer type
+        assert_eq!(rate_series.name(), "RateSeries");
+        assert_eq!(
+            String::from_utf8(rate_series.writer).unwrap(),
+            "time,requests_per_sec,bytes_per_sec\n10,2,200\n11,4,400\n12,0,0\n13,0,0\n14,1,100\n"
+        );
+    }
+}
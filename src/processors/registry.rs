@@ -0,0 +1,128 @@
+use super::GroupBy;
+
+/// Name and description (with the option enabling it) of every built-in processor.
+const PROCESSORS: &[(&str, &str)] = &[
+    (
+        "stats",
+        "Hits and bytes per section (or --group-by key) every period. Always on",
+    ),
+    (
+        "alerts",
+        "High traffic alert over the average req/s of the window. Always on",
+    ),
+    (
+        "peak",
+        "Second with the highest amount of requests, once the input ends. Always on",
+    ),
+    (
+        "error-spike",
+        "Alert on 5xx bursts within the window (--error-spike-threshold)",
+    ),
+    (
+        "adaptive-alerts",
+        "Alert on seconds over mean + k·stddev of the window (--alert-sigma)",
+    ),
+    (
+        "abuse",
+        "Alert on a single host doing too many requests (--abuse-threshold)",
+    ),
+    (
+        "bad-path",
+        "Alert on a single path returning 4xx steadily (--bad-path-threshold)",
+    ),
+    (
+        "latency",
+        "Alert on a section responding slower than --latency-threshold-ms (responsetime column)",
+    ),
+    (
+        "forbidden-protocol",
+        "Alert on requests using a forbidden protocol (--forbidden-protocols)",
+    ),
+    (
+        "leaderboard",
+        "Top hosts and sections by hits, refreshed every stats period (--leaderboard)",
+    ),
+    (
+        "rps-histogram",
+        "Seconds per bucket of requests per second, once the input ends (--rps-histogram)",
+    ),
+    (
+        "alert-timeline",
+        "Timeline of all the alerts once the logs end (--replay)",
+    ),
+    (
+        "dump-groups",
+        "Prints every group of logs emitted by the buffer (--dump-groups)",
+    ),
+    (
+        "csv-sink",
+        "Writes the processed logs to a CSV file (--csv-out)",
+    ),
+    (
+        "rate-series",
+        "Writes the requests and bytes of every second to a CSV file (--rate-out)",
+    ),
+    (
+        "prom-exposition",
+        "Writes request counters and a bytes histogram in Prometheus format at the end (--prom-out)",
+    ),
+    (
+        "html-report",
+        "Writes a self-contained HTML report with the summary of the run at the end (--html-out)",
+    ),
+    (
+        "otlp-metrics",
+        "Pushes the request, error and alert metrics to OTLP (--otlp-endpoint, `otlp` feature)",
+    ),
+    (
+        "sqlite-stats",
+        "Stores the stats of every period in SQLite (--sqlite-out, `sqlite` feature)",
+    ),
+    (
+        "parquet-sink",
+        "Writes the logs to a Parquet file for analytics (--parquet-out, `parquet` feature)",
+    ),
+];
+
+/// One line per built-in processor with its description, followed by the group-by keys.
+pub fn list_processors() -> String {
+    let processors = PROCESSORS
+        .iter()
+        .map(|(name, description)| format!("{:<20}{}\n", name, description))
+        .collect::<String>();
+    format!(
+        "{}\nGroup-by keys (--group-by): {}\n",
+        processors,
+        GroupBy::VALUES.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lists_the_built_in_processors() {
+        let listing = list_processors();
+
+        for name in [
+            "stats",
+            "alerts",
+            "peak",
+            "error-spike",
+            "abuse",
+            "bad-path",
+            "forbidden-protocol",
+            "dump-groups",
+            "csv-sink",
+            "sqlite-stats",
+        ] {
+            assert!(
+                listing.lines().any(|line| line.starts_with(name)),
+                "{} is not listed",
+                name
+            );
+        }
+        assert!(listing.contains("Group-by keys (--group-by): section, path, verb, status, host"));
+    }
+}
@@ -0,0 +1,181 @@
+use super::GroupedHttpLogs;
+use super::Processor;
+use crate::status::StatusMatcher;
+use rusqlite::{params, Connection};
+use std::{collections::HashMap, path::Path, sync::Mutex};
+use tracing::instrument;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS stats (
+    period_time INTEGER NOT NULL,
+    section TEXT NOT NULL,
+    hits INTEGER NOT NULL,
+    bytes INTEGER NOT NULL,
+    errors INTEGER NOT NULL
+)";
+
+const INSERT: &str =
+    "INSERT INTO stats (period_time, section, hits, bytes, errors) VALUES (?1, ?2, ?3, ?4, ?5)";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct SectionCounter {
+    hits: usize,
+    bytes: usize,
+    errors: usize,
+}
+
+/// Persists the per section stats of every period into a SQLite database.
+#[derive(Debug)]
+pub struct SqliteStats {
+    period_in_secs: usize,
+    // rusqlite connections are not Sync
+    conn: Mutex<Connection>,
+    buffer: HashMap<String, SectionCounter>,
+    period_start: Option<usize>,
+    last_time: usize,
+    error_status: StatusMatcher,
+}
+
+impl SqliteStats {
+    /// Opens (or creates) the database at the given path.
+    pub fn open(path: impl AsRef<Path>, period_in_secs: usize) -> anyhow::Result<Self> {
+        Self::new(Connection::open(path)?, period_in_secs)
+    }
+
+    /// Uses an already open connection, creating the schema if needed.
+    pub fn new(conn: Connection, period_in_secs: usize) -> anyhow::Result<Self> {
+        conn.execute(SCHEMA, [])?;
+        Ok(Self {
+            period_in_secs,
+            conn: Mutex::new(conn),
+            buffer: HashMap::new(),
+            period_start: None,
+            last_time: 0,
+            error_status: StatusMatcher::errors(),
+        })
+    }
+
+    /// Inserts all the buffered sections in a single transaction and clears the buffer.
+    fn flush(&mut self, period_time: usize) -> anyhow::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self
+            .conn
+            .lock()
+            .map_err(|_| anyhow::anyhow!("SQLite connection lock is poisoned"))?;
+        let tx = conn.transaction()?;
+        {
+            let mut stmt = tx.prepare_cached(INSERT)?;
+            for (section, counter) in self.buffer.drain() {
+                stmt.execute(params![
+                    period_time,
+                    section,
+                    counter.hits,
+                    counter.bytes,
+                    counter.errors
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl Processor for SqliteStats {
+    #[instrument(skip(self, _writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        _writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        for log in &log_group.logs {
+            let counter = self.buffer.entry(log.request.section.clone()).or_default();
+            counter.hits += 1;
+            counter.bytes += log.bytes;
+            if self.error_status.matches(log.status) {
+                counter.errors += 1;
+            }
+        }
+        self.last_time = log_group.time;
+
+        let period_start = *self.period_start.get_or_insert(log_group.time);
+        if log_group.time - period_start >= self.period_in_secs {
+            tracing::debug!("Persisting stats for period {}", log_group.time);
+            self.flush(log_group.time)?;
+            self.period_start = Some(log_group.time);
+        }
+        Ok(())
+    }
+
+    fn finalize(&mut self, _writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        self.flush(self.last_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_test_http_grouped_log;
+
+    type Row = (usize, String, usize, usize, usize);
+
+    fn query_rows(stats: &SqliteStats) -> Vec<Row> {
+        let conn = stats.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT period_time, section, hits, bytes, errors FROM stats ORDER BY period_time, section")
+            .unwrap();
+        stmt.query_map([], |r| {
+            Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?))
+        })
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn persists_stats_per_period() {
+        let mut stats = SqliteStats::new(Connection::open_in_memory().unwrap(), 3).unwrap();
+        let mut writer = Vec::<u8>::new();
+
+        let mut web_logs = build_test_http_grouped_log(2, 2, Some("/web/portal".to_string()));
+        web_logs.logs[0].status = 500;
+
+        let logs = vec![
+            build_test_http_grouped_log(1, 3, Some("/api/users".to_string())),
+            web_logs,
+            build_test_http_grouped_log(4, 1, Some("/api/users".to_string())),
+            build_test_http_grouped_log(5, 2, Some("/api/friends".to_string())),
+        ];
+
+        for log in logs {
+            stats.process(&log, &mut writer).unwrap();
+        }
+        stats.finalize(&mut writer).unwrap();
+
+        assert_eq!(
+            query_rows(&stats),
+            vec![
+                (4, "/api".to_string(), 4, 400, 0),
+                (4, "/web".to_string(), 2, 200, 1),
+                (5, "/api".to_string(), 2, 200, 0),
+            ]
+        );
+        // nothing is written to the regular output
+        assert!(writer.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reuses_an_existing_schema() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(SCHEMA, []).unwrap();
+        let mut stats = SqliteStats::new(conn, 1).unwrap();
+        let mut writer = Vec::<u8>::new();
+
+        stats
+            .process(&build_test_http_grouped_log(1, 1, None), &mut writer)
+            .unwrap();
+        stats.finalize(&mut writer).unwrap();
+
+        assert_eq!(query_rows(&stats), vec![(1, "/api".to_string(), 1, 100, 0)]);
+    }
+}
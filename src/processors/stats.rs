@@ -1,23 +1,642 @@
+use super::format_float;
 use super::GroupedHttpLogs;
 use super::Processor;
+use crate::bots::BotPatterns;
 use crate::reader::HttpLog;
-use std::collections::HashMap;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet, VecDeque},
+    hash::{Hash, Hasher},
+    str::FromStr,
+};
 use tracing::instrument;
 
+/// The field of the logs used to group the stats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Section,
+    /// The whole request path, much finer than the section.
+    Path,
+    Verb,
+    Status,
+    Host,
+}
+
+impl GroupBy {
+    /// Accepted values, as shown in the help.
+    pub const VALUES: &'static [&'static str] = &["section", "path", "verb", "status", "host"];
+
+    pub(crate) fn key(self, log: &HttpLog) -> String {
+        match self {
+            Self::Section => log.request.section.clone(),
+            Self::Path => log.request.path.clone(),
+            Self::Verb => log.request.verb.clone(),
+            Self::Status => log.status.to_string(),
+            Self::Host => log.remote_host.clone(),
+        }
+    }
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Self::Section => "Section",
+            Self::Path => "Path",
+            Self::Verb => "Verb",
+            Self::Status => "Status",
+            Self::Host => "Host",
+        }
+    }
+}
+
+impl FromStr for GroupBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "section" => Ok(Self::Section),
+            "path" => Ok(Self::Path),
+            "verb" => Ok(Self::Verb),
+            "status" => Ok(Self::Status),
+            "host" => Ok(Self::Host),
+            _ => Err(anyhow::anyhow!(
+                "Invalid group by: {}. Use one of: {}",
+                s,
+                Self::VALUES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Gini coefficient of the hits, from 0 when every key has the same hits to almost 1 when
+/// a single key has them all.
+fn gini(hits: impl Iterator<Item = u64>) -> f64 {
+    let mut hits = hits.collect::<Vec<_>>();
+    hits.sort_unstable();
+    let total = hits.iter().map(|hits| *hits as f64).sum::<f64>();
+    if total == 0.0 {
+        return 0.0;
+    }
+    let len = hits.len() as f64;
+    let weighted = hits
+        .iter()
+        .enumerate()
+        .map(|(i, hits)| (i + 1) as f64 * *hits as f64)
+        .sum::<f64>();
+    2.0 * weighted / (len * total) - (len + 1.0) / len
+}
+
+/// Key aggregating the logs over the maximum amount of keys.
+const OTHER_KEY: &str = "<other>";
+/// Maximum amount of distinct keys counted in [`OTHER_KEY`], so its memory stays bounded
+/// when it's never reset (e.g. cumulative stats of a long run).
+const MAX_OTHER_KEYS: usize = 10_000;
+
+/// Amount of periods shown in the sparklines.
+const SPARKLINE_LEN: usize = 8;
+/// Bars of the sparklines, from the lowest count to the highest one.
+const SPARKLINE_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Bar per count, scaled to the highest one.
+fn sparkline(counts: &VecDeque<u64>) -> String {
+    let max = counts.iter().copied().max().unwrap_or_default().max(1);
+    counts
+        .iter()
+        .map(|count| SPARKLINE_BARS[(count * (SPARKLINE_BARS.len() as u64 - 1) / max) as usize])
+        .collect()
+}
+
+/// How the stats are rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// A banner followed by a line per key.
+    Pretty,
+    /// A single line per key, without banner.
+    Compact,
+}
+
+/// Time unit of the rates of the stats, so the rates of low traffic aren't tiny fractions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RateUnit {
+    #[default]
+    Sec,
+    Min,
+    Hour,
+}
+
+impl RateUnit {
+    fn secs(self) -> f64 {
+        match self {
+            Self::Sec => 1.0,
+            Self::Min => 60.0,
+            Self::Hour => 3_600.0,
+        }
+    }
+
+    /// Unit in the labels of the rates, e.g. `Min` in `Avg Reqs/Min`.
+    fn label(self) -> &'static str {
+        match self {
+            Self::Sec => "Sec",
+            Self::Min => "Min",
+            Self::Hour => "Hour",
+        }
+    }
+}
+
+impl FromStr for RateUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sec" => Ok(Self::Sec),
+            "min" => Ok(Self::Min),
+            "hour" => Ok(Self::Hour),
+            _ => Err(anyhow::anyhow!(
+                "Invalid rate unit: {}. Use sec, min or hour",
+                s
+            )),
+        }
+    }
+}
+
+/// Aggregated values of the logs sharing the same key.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct KeyStats {
+    hits: u64,
+    // wide enough to add any amount of usize bytes
+    bytes: u128,
+    // reservoir sample of the request paths
+    examples: Vec<String>,
+    // only counted with bot patterns, the logs without user agent are neither
+    bot_hits: u64,
+    human_hits: u64,
+    // only counted with the status detail
+    statuses: BTreeMap<u16, u64>,
+}
+
+impl KeyStats {
+    /// Keeps `path` as an example with the same probability as any other seen path.
+    fn sample(&mut self, path: &str, max_examples: usize) {
+        if max_examples == 0 {
+            return;
+        }
+        if self.examples.len() < max_examples {
+            self.examples.push(path.to_string());
+        } else {
+            let i = rand::thread_rng().gen_range(0..self.hits);
+            if i < max_examples as u64 {
+                self.examples[i as usize] = path.to_string();
+            }
+        }
+    }
+}
+
+/// Current aggregates of a section (or of the key the stats are grouped by).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectionStat {
+    pub key: String,
+    pub hits: u64,
+    pub bytes: u128,
+    /// Sampled request paths, only with `max_examples`.
+    pub examples: Vec<String>,
+    /// Hits with the user agent of a bot and of a human, only with bot patterns.
+    pub bot_hits: u64,
+    pub human_hits: u64,
+    /// Hits per exact status code, only with the status detail.
+    pub statuses: BTreeMap<u16, u64>,
+}
+
+/// What's saved in a checkpoint to resume the stats where they were.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StatsState {
+    pending: VecDeque<String>,
+    trends: HashMap<String, VecDeque<u64>>,
+    buffer: HashMap<String, KeyStats>,
+    other_keys: HashSet<u64>,
+    protocols: BTreeMap<String, u64>,
+    is_initialized: bool,
+    last_time: usize,
+    last_seen_time: usize,
+    elapsed_secs: usize,
+    reported: bool,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Stats {
     period_in_secs: usize,
-    buffer: HashMap<String, Vec<HttpLog>>,
+    group_by: GroupBy,
+    cumulative: bool,
+    max_examples: usize,
+    format: StatsFormat,
+    rate_unit: RateUnit,
+    max_keys: Option<usize>,
+    protocol_breakdown: bool,
+    status_detail: bool,
+    gini: bool,
+    precision: Option<usize>,
+    // reporting on the wall-clock ticks instead of every period of log time
+    wall_clock: bool,
+    // maximum amount of lines written per group, the rest wait for the next groups
+    flush_lines: Option<usize>,
+    // rendered lines waiting to be written
+    pending: VecDeque<String>,
+    sparklines: bool,
+    bot_patterns: Option<BotPatterns>,
+    // hits of the last periods by key, to render the sparklines
+    trends: HashMap<String, VecDeque<u64>>,
+    // only aggregated values are kept so memory only grows with the amount of keys
+    buffer: HashMap<String, KeyStats>,
+    // hashes of the keys aggregated into OTHER_KEY, so they're cheap to keep. The count is
+    // approximate: keys with the same hash are counted once, and it stops at MAX_OTHER_KEYS
+    other_keys: HashSet<u64>,
+    // hits per protocol, ordered so they're always rendered the same way
+    protocols: BTreeMap<String, u64>,
+    // the first period starts with the first group seen
+    is_initialized: bool,
     last_time: usize,
+    last_seen_time: usize,
+    // seconds covered by the buffer
+    elapsed_secs: usize,
+    // whether a stats block has already been printed
+    reported: bool,
 }
 
 impl Stats {
     pub fn new(period_in_secs: usize) -> Self {
         Self {
             period_in_secs,
+            group_by: GroupBy::Section,
+            cumulative: false,
+            max_examples: 0,
+            format: StatsFormat::Pretty,
+            rate_unit: RateUnit::Sec,
+            max_keys: None,
+            protocol_breakdown: false,
+            status_detail: false,
+            gini: false,
+            precision: None,
+            wall_clock: false,
+            flush_lines: None,
+            pending: VecDeque::new(),
+            sparklines: false,
+            bot_patterns: None,
+            trends: HashMap::new(),
             buffer: HashMap::new(),
+            other_keys: HashSet::new(),
+            protocols: BTreeMap::new(),
+            is_initialized: false,
             last_time: 0,
+            last_seen_time: 0,
+            elapsed_secs: 0,
+            reported: false,
+        }
+    }
+
+    pub fn builder() -> StatsBuilder {
+        StatsBuilder::default()
+    }
+
+    /// Aggregates of every key in the current period (or the whole run when cumulative),
+    /// the most requested first. The buffer is kept as it is.
+    pub fn snapshot(&self) -> Vec<SectionStat> {
+        let mut snapshot = self
+            .buffer
+            .iter()
+            .map(|(key, key_stats)| SectionStat {
+                key: key.clone(),
+                hits: key_stats.hits,
+                bytes: key_stats.bytes,
+                examples: key_stats.examples.clone(),
+                bot_hits: key_stats.bot_hits,
+                human_hits: key_stats.human_hits,
+                statuses: key_stats.statuses.clone(),
+            })
+            .collect::<Vec<_>>();
+        snapshot.sort_unstable_by(|a, b| b.hits.cmp(&a.hits).then_with(|| a.key.cmp(&b.key)));
+        snapshot
+    }
+
+    /// Key under which the log is aggregated, taking into account the maximum amount of keys.
+    fn key(&mut self, log: &HttpLog) -> String {
+        let key = self.group_by.key(log);
+        let max_keys = match self.max_keys {
+            Some(max_keys) if !self.buffer.contains_key(&key) => max_keys,
+            _ => return key,
+        };
+        let tracked_keys = self.buffer.len() - usize::from(self.buffer.contains_key(OTHER_KEY));
+        if tracked_keys < max_keys {
+            return key;
+        }
+        if self.other_keys.len() < MAX_OTHER_KEYS {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            self.other_keys.insert(hasher.finish());
+        }
+        OTHER_KEY.to_string()
+    }
+
+    /// Adds the hits of the period to the trends, forgetting the keys without recent hits.
+    fn update_trends(&mut self) {
+        for key in self.buffer.keys() {
+            self.trends.entry(key.clone()).or_default();
+        }
+        let buffer = &self.buffer;
+        self.trends.retain(|key, counts| {
+            counts.push_back(buffer.get(key).map_or(0, |key_stats| key_stats.hits));
+            if counts.len() > SPARKLINE_LEN {
+                counts.pop_front();
+            }
+            counts.iter().any(|count| *count > 0)
+        });
+    }
+
+    fn reset(&mut self) {
+        self.buffer.clear();
+        self.other_keys.clear();
+        self.protocols.clear();
+        self.elapsed_secs = 0;
+    }
+
+    /// Writes the stats of every key in the buffer, or queues them when they're flushed
+    /// incrementally. The lines of the previous period are written first.
+    fn write_stats(&mut self, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        self.write_pending(None, writer)?;
+        if self.sparklines {
+            self.update_trends();
+        }
+        let lines = self.render_stats();
+        match self.flush_lines {
+            Some(_) => self.pending.extend(lines),
+            None => {
+                for line in lines {
+                    writer.write_all(line.as_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes up to `max_lines` of the queued lines, all of them without a maximum.
+    fn write_pending(
+        &mut self,
+        max_lines: Option<usize>,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        let len = max_lines.unwrap_or(usize::MAX).min(self.pending.len());
+        for line in self.pending.drain(..len) {
+            writer.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Lines with the stats of every key in the buffer.
+    fn render_stats(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let prefix = match (self.format, self.cumulative) {
+            (StatsFormat::Pretty, false) => stats_prefix(self.elapsed_secs),
+            (StatsFormat::Pretty, true) => cumulative_stats_prefix(self.elapsed_secs),
+            (StatsFormat::Compact, false) => compact_stats_prefix(self.elapsed_secs),
+            (StatsFormat::Compact, true) => compact_cumulative_stats_prefix(self.elapsed_secs),
+        };
+        if self.format == StatsFormat::Pretty {
+            lines.push(prefix.clone());
+        }
+        for key_stats in self.snapshot() {
+            let total_reqs = key_stats.hits;
+            let avg_time = self.elapsed_secs as f64 / total_reqs as f64;
+            let avg_bytes = key_stats.bytes / total_reqs as u128;
+            let avg_reqs = total_reqs as f64 / self.elapsed_secs as f64 * self.rate_unit.secs();
+            let mut msg = if self.format == StatsFormat::Compact {
+                prefix.clone()
+            } else {
+                String::new()
+            };
+            msg.push_str(&format!(
+                "{}: {}, Total Hits: {}, Avg Reqs/{}: {}, Avg Time: {}s, Avg Bytes: {}",
+                self.group_by.label(),
+                key_stats.key,
+                total_reqs,
+                self.rate_unit.label(),
+                format_float(avg_reqs, self.precision),
+                format_float(avg_time, self.precision),
+                avg_bytes
+            ));
+            if self.bot_patterns.is_some() {
+                msg.push_str(&format!(
+                    ", Bot Hits: {}, Human Hits: {}",
+                    key_stats.bot_hits, key_stats.human_hits
+                ));
+            }
+            if self.status_detail {
+                let statuses = key_stats
+                    .statuses
+                    .iter()
+                    .map(|(status, hits)| format!("{} = {}", status, hits))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                msg.push_str(&format!(", Statuses: {}", statuses));
+            }
+            if let Some(counts) = self.trends.get(&key_stats.key) {
+                msg.push_str(&format!(", Trend: {}", sparkline(counts)));
+            }
+            if !key_stats.examples.is_empty() {
+                msg.push_str(&format!(", Examples: {}", key_stats.examples.join(", ")));
+            }
+            msg.push('\n');
+            lines.push(msg);
+        }
+        if !self.other_keys.is_empty() {
+            let is_capped = self.other_keys.len() >= MAX_OTHER_KEYS;
+            lines.push(format!(
+                "{}s in {}: {}{}\n",
+                self.group_by.label(),
+                OTHER_KEY,
+                self.other_keys.len(),
+                if is_capped { "+" } else { "" }
+            ));
         }
+        if self.protocol_breakdown && !self.protocols.is_empty() {
+            let protocols = self
+                .protocols
+                .iter()
+                .map(|(protocol, hits)| format!("{} = {}", protocol, hits))
+                .collect::<Vec<_>>()
+                .join(", ");
+            lines.push(match self.format {
+                StatsFormat::Pretty => format!("Protocols: {}\n", protocols),
+                StatsFormat::Compact => format!("{}Protocols: {}\n", prefix, protocols),
+            });
+        }
+        // the keys aggregated into OTHER_KEY can't be told apart, so they're left out
+        let tracked_hits = self
+            .buffer
+            .iter()
+            .filter(|(key, _)| key.as_str() != OTHER_KEY)
+            .map(|(_, key_stats)| key_stats.hits)
+            .collect::<Vec<_>>();
+        if self.gini && !tracked_hits.is_empty() {
+            let gini = format_float(gini(tracked_hits.into_iter()), self.precision);
+            lines.push(match self.format {
+                StatsFormat::Pretty => format!("Gini: {}\n", gini),
+                StatsFormat::Compact => format!("{}Gini: {}\n", prefix, gini),
+            });
+        }
+        lines
+    }
+}
+
+/// Builds [`Stats`] with sensible defaults.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StatsBuilder {
+    period_in_secs: usize,
+    group_by: GroupBy,
+    cumulative: bool,
+    max_examples: usize,
+    format: StatsFormat,
+    rate_unit: RateUnit,
+    max_keys: Option<usize>,
+    protocol_breakdown: bool,
+    status_detail: bool,
+    gini: bool,
+    precision: Option<usize>,
+    wall_clock: bool,
+    flush_lines: Option<usize>,
+    sparklines: bool,
+    bot_patterns: Option<BotPatterns>,
+}
+
+impl Default for StatsBuilder {
+    fn default() -> Self {
+        Self {
+            period_in_secs: 10,
+            group_by: GroupBy::Section,
+            cumulative: false,
+            max_examples: 0,
+            format: StatsFormat::Pretty,
+            rate_unit: RateUnit::Sec,
+            max_keys: None,
+            protocol_breakdown: false,
+            status_detail: false,
+            gini: false,
+            precision: None,
+            wall_clock: false,
+            flush_lines: None,
+            sparklines: false,
+            bot_patterns: None,
+        }
+    }
+}
+
+impl StatsBuilder {
+    /// Amount of seconds between two stats reports.
+    pub fn period_in_secs(mut self, period_in_secs: usize) -> Self {
+        self.period_in_secs = period_in_secs;
+        self
+    }
+
+    /// Field of the logs used to group the stats.
+    pub fn group_by(mut self, group_by: GroupBy) -> Self {
+        self.group_by = group_by;
+        self
+    }
+
+    /// Reports running totals every period instead of resetting them.
+    pub fn cumulative(mut self, cumulative: bool) -> Self {
+        self.cumulative = cumulative;
+        self
+    }
+
+    /// Amount of example request paths shown for every key (none by default).
+    pub fn max_examples(mut self, max_examples: usize) -> Self {
+        self.max_examples = max_examples;
+        self
+    }
+
+    /// How the stats are rendered.
+    pub fn format(mut self, format: StatsFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Time unit of the average rates, seconds by default.
+    pub fn rate_unit(mut self, rate_unit: RateUnit) -> Self {
+        self.rate_unit = rate_unit;
+        self
+    }
+
+    /// Maximum amount of keys per period, the rest are aggregated into `<other>`.
+    pub fn max_keys(mut self, max_keys: Option<usize>) -> Self {
+        self.max_keys = max_keys;
+        self
+    }
+
+    /// Also reports the hits per request protocol, the unknown ones together.
+    pub fn protocol_breakdown(mut self, protocol_breakdown: bool) -> Self {
+        self.protocol_breakdown = protocol_breakdown;
+        self
+    }
+
+    /// Also reports the hits per exact status code of every key (e.g. 401 apart from 403).
+    pub fn status_detail(mut self, status_detail: bool) -> Self {
+        self.status_detail = status_detail;
+        self
+    }
+
+    /// Also reports the Gini coefficient of the hits of the keys, telling how skewed the
+    /// traffic is across them.
+    pub fn gini(mut self, gini: bool) -> Self {
+        self.gini = gini;
+        self
+    }
+
+    /// Amount of decimals of the averages (all of them by default).
+    pub fn precision(mut self, precision: Option<usize>) -> Self {
+        self.precision = precision;
+        self
+    }
+
+    /// Reports on every wall-clock tick, covering the logs processed since the previous one,
+    /// instead of every period of log time.
+    pub fn wall_clock(mut self, wall_clock: bool) -> Self {
+        self.wall_clock = wall_clock;
+        self
+    }
+
+    /// Writes at most this amount of lines per group of logs, spreading the stats of a period
+    /// over the following groups instead of writing them at once (all at once by default).
+    pub fn flush_lines(mut self, flush_lines: Option<usize>) -> Self {
+        self.flush_lines = flush_lines;
+        self
+    }
+
+    /// Shows a sparkline with the hits of the last periods of every key.
+    pub fn sparklines(mut self, sparklines: bool) -> Self {
+        self.sparklines = sparklines;
+        self
+    }
+
+    /// Splits the hits of every key between bots and humans by their user agent.
+    pub fn bot_patterns(mut self, bot_patterns: Option<BotPatterns>) -> Self {
+        self.bot_patterns = bot_patterns;
+        self
+    }
+
+    pub fn build(self) -> Stats {
+        let mut stats = Stats::new(self.period_in_secs);
+        stats.group_by = self.group_by;
+        stats.cumulative = self.cumulative;
+        stats.max_examples = self.max_examples;
+        stats.format = self.format;
+        stats.rate_unit = self.rate_unit;
+        stats.max_keys = self.max_keys;
+        stats.protocol_breakdown = self.protocol_breakdown;
+        stats.status_detail = self.status_detail;
+        stats.gini = self.gini;
+        stats.precision = self.precision;
+        stats.wall_clock = self.wall_clock;
+        stats.flush_lines = self.flush_lines;
+        stats.sparklines = self.sparklines;
+        stats.bot_patterns = self.bot_patterns;
+        stats
     }
 }
 
@@ -28,41 +647,133 @@ impl Processor for Stats {
         log_group: &GroupedHttpLogs,
         writer: &mut dyn std::io::Write,
     ) -> anyhow::Result<()> {
-        // get individual http logs and group them by section in our buffer
+        if !self.is_initialized {
+            // the second of the first group is part of the first period
+            self.last_time = log_group.time.saturating_sub(1);
+            self.is_initialized = true;
+        }
+        // get individual http logs and group them by key (section by default) in our buffer
         for log in &log_group.logs {
-            let section = log.request.section.clone();
-            let entry = self.buffer.entry(section).or_insert(Vec::new());
-            entry.push(log.clone());
+            let key = self.key(log);
+            let key_stats = self.buffer.entry(key).or_default();
+            key_stats.hits += 1;
+            key_stats.bytes += log.bytes as u128;
+            key_stats.sample(&log.request.path, self.max_examples);
+            if let (Some(bot_patterns), Some(user_agent)) = (&self.bot_patterns, &log.user_agent) {
+                match bot_patterns.is_bot(user_agent) {
+                    true => key_stats.bot_hits += 1,
+                    false => key_stats.human_hits += 1,
+                }
+            }
+            if self.status_detail {
+                *key_stats.statuses.entry(log.status).or_insert(0) += 1;
+            }
+            if self.protocol_breakdown {
+                *self
+                    .protocols
+                    .entry(log.request.protocol_label().to_string())
+                    .or_insert(0) += 1;
+            }
         }
+        // late groups are counted in the current period
+        self.last_seen_time = self.last_seen_time.max(log_group.time);
 
         // check if we're over our period in secs and if so, print the stats and clear the buffer
-        let diff_time = log_group.time - self.last_time;
+        let diff_time = log_group.time.saturating_sub(self.last_time);
 
-        if diff_time >= self.period_in_secs {
+        if diff_time >= self.period_in_secs && !self.wall_clock {
             tracing::info!("Printing stats");
             self.last_time = log_group.time;
-            writer.write_all(stats_prefix(diff_time).as_bytes())?;
-            // TODO: sort the stats by most requested sections
-            for (section, logs) in &self.buffer {
-                let mut total_reqs = 0;
-                let mut total_bytes = 0;
-                for log in logs {
-                    total_reqs += 1;
-                    total_bytes += log.bytes;
-                }
-                let avg_time = diff_time as f64 / total_reqs as f64;
-                let avg_bytes = total_bytes / total_reqs;
-                let avg_reqs_sec = total_reqs as f64 / (diff_time) as f64;
-                let msg = format!(
-                    "Section: {}, Total Hits: {}, Avg Reqs/Sec: {}, Avg Time: {}s, Avg Bytes: {}\n",
-                    section, total_reqs, avg_reqs_sec, avg_time, avg_bytes
-                );
-                writer.write_all(msg.as_bytes())?;
+            self.elapsed_secs += diff_time;
+            self.write_stats(writer)?;
+            self.reported = true;
+            if !self.cumulative {
+                self.reset();
             }
-            self.buffer.clear();
+        }
+        if let Some(flush_lines) = self.flush_lines {
+            self.write_pending(Some(flush_lines), writer)?;
+        }
+        Ok(())
+    }
+
+    fn tick(
+        &mut self,
+        elapsed: std::time::Duration,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        if !self.wall_clock {
+            return Ok(());
+        }
+        tracing::info!("Printing wall-clock stats");
+        self.elapsed_secs += elapsed.as_secs() as usize;
+        self.write_stats(writer)?;
+        self.reported = true;
+        if !self.cumulative {
+            self.reset();
         }
         Ok(())
     }
+
+    fn finalize(&mut self, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        self.write_pending(None, writer)?;
+        // the wall-clock stats only report full intervals
+        if self.buffer.is_empty() || self.wall_clock {
+            return Ok(());
+        }
+        if !self.reported {
+            // the period never elapsed, so the user would get no stats at all
+            tracing::warn!("The stats period never elapsed");
+            let msg = format!(
+                "{}No stats were printed: input span < stats period ({}s); lower --stats-period or check your data\n",
+                warning_prefix(),
+                self.period_in_secs
+            );
+            writer.write_all(msg.as_bytes())?;
+        } else if !self.cumulative {
+            // the windowed stats only report full periods
+            return Ok(());
+        }
+        tracing::info!("Printing final stats");
+        self.elapsed_secs += self.last_seen_time - self.last_time;
+        self.last_time = self.last_seen_time;
+        self.write_stats(writer)?;
+        // nothing comes after the final stats
+        self.write_pending(None, writer)?;
+        self.reported = true;
+        Ok(())
+    }
+
+    fn save_state(&self) -> anyhow::Result<Option<serde_json::Value>> {
+        let state = StatsState {
+            pending: self.pending.clone(),
+            trends: self.trends.clone(),
+            buffer: self.buffer.clone(),
+            other_keys: self.other_keys.clone(),
+            protocols: self.protocols.clone(),
+            is_initialized: self.is_initialized,
+            last_time: self.last_time,
+            last_seen_time: self.last_seen_time,
+            elapsed_secs: self.elapsed_secs,
+            reported: self.reported,
+        };
+        Ok(Some(serde_json::to_value(state)?))
+    }
+
+    fn restore_state(&mut self, state: serde_json::Value) -> anyhow::Result<()> {
+        let state: StatsState = serde_json::from_value(state)?;
+        self.pending = state.pending;
+        self.trends = state.trends;
+        self.buffer = state.buffer;
+        self.other_keys = state.other_keys;
+        self.protocols = state.protocols;
+        self.is_initialized = state.is_initialized;
+        self.last_time = state.last_time;
+        self.last_seen_time = state.last_seen_time;
+        self.elapsed_secs = state.elapsed_secs;
+        self.reported = state.reported;
+        Ok(())
+    }
 }
 
 fn stats_prefix(diff_time: usize) -> String {
@@ -72,11 +783,52 @@ fn stats_prefix(diff_time: usize) -> String {
         .to_string()
 }
 
+fn cumulative_stats_prefix(elapsed_secs: usize) -> String {
+    console::style(format!(
+        "\nCUMULATIVE STATS ({}s):\n********\n",
+        elapsed_secs
+    ))
+    .bold()
+    .blue()
+    .to_string()
+}
+
+fn compact_stats_prefix(diff_time: usize) -> String {
+    console::style(format!("STATS ({}s) ", diff_time))
+        .bold()
+        .blue()
+        .to_string()
+}
+
+fn compact_cumulative_stats_prefix(elapsed_secs: usize) -> String {
+    console::style(format!("CUMULATIVE STATS ({}s) ", elapsed_secs))
+        .bold()
+        .blue()
+        .to_string()
+}
+
+fn warning_prefix() -> String {
+    console::style("\n>>> WARNING\n")
+        .bold()
+        .yellow()
+        .to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::build_test_http_grouped_log;
-    use std::io::BufWriter;
+    use crate::test_utils::{build_test_http_grouped_log, build_test_http_log};
+    use std::{io::BufWriter, time::Duration};
+
+    fn build_grouped_log_with(
+        time: usize,
+        len: usize,
+        update: impl Fn(&mut HttpLog),
+    ) -> GroupedHttpLogs {
+        let mut group = build_test_http_grouped_log(time, len, None);
+        group.logs.iter_mut().for_each(update);
+        group
+    }
 
     #[tokio::test]
     async fn it_works() {
@@ -100,6 +852,31 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn rounds_the_averages_to_the_precision() {
+        let mut stats = Stats::builder()
+            .period_in_secs(3)
+            .precision(Some(2))
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_test_http_grouped_log(1, 3, Some("/api/users".to_string())),
+            build_test_http_grouped_log(2, 3, Some("/api/users".to_string())),
+            build_test_http_grouped_log(3, 2, Some("/api/friends".to_string())),
+        ];
+
+        for log in logs {
+            stats.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!("{}Section: /api, Total Hits: 8, Avg Reqs/Sec: 2.67, Avg Time: 0.38s, Avg Bytes: 100\n", stats_prefix(3))
+        );
+    }
+
     #[tokio::test]
     async fn shows_as_many_sections_as_needed() {
         let mut stats = Stats::new(3);
@@ -123,4 +900,631 @@ mod tests {
 
         assert!(expect.contains(&msg));
     }
+
+    #[tokio::test]
+    async fn builder_is_equivalent_to_new() {
+        assert_eq!(Stats::builder().build(), Stats::new(10));
+        assert_eq!(Stats::builder().period_in_secs(3).build(), Stats::new(3));
+    }
+
+    #[tokio::test]
+    async fn groups_by_verb() {
+        let mut stats = Stats::builder()
+            .period_in_secs(3)
+            .group_by(GroupBy::Verb)
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_grouped_log_with(1, 2, |log| log.request.verb = "GET".to_string()),
+            build_grouped_log_with(3, 1, |log| log.request.verb = "POST".to_string()),
+        ];
+
+        for log in logs {
+            stats.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(msg.starts_with(&stats_prefix(3)));
+        assert!(msg.contains("Verb: GET, Total Hits: 2, Avg Reqs/Sec: 0.6666666666666666, Avg Time: 1.5s, Avg Bytes: 100\n"));
+        assert!(msg.contains("Verb: POST, Total Hits: 1, Avg Reqs/Sec: 0.3333333333333333, Avg Time: 3s, Avg Bytes: 100\n"));
+    }
+
+    #[tokio::test]
+    async fn renders_the_trends_as_sparklines() {
+        let mut stats = Stats::builder().period_in_secs(1).sparklines(true).build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        for (time, len) in [(1, 1), (2, 2), (3, 4), (4, 8)] {
+            stats
+                .process(&build_test_http_grouped_log(time, len, None), &mut writer)
+                .unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        let trends = msg
+            .lines()
+            .filter_map(|line| line.split(", Trend: ").nth(1))
+            .collect::<Vec<_>>();
+        assert_eq!(trends, vec!["█", "▄█", "▂▄█", "▁▂▄█"]);
+    }
+
+    #[tokio::test]
+    async fn sparklines_keep_the_last_periods() {
+        let mut stats = Stats::builder().sparklines(true).build();
+
+        for hits in 1..12 {
+            stats.buffer.entry("/api".to_string()).or_default().hits = hits;
+            stats.update_trends();
+        }
+        stats.reset();
+        stats.buffer.entry("/report".to_string()).or_default().hits = 1;
+        stats.update_trends();
+
+        assert_eq!(
+            stats.trends["/api"],
+            (5..12).chain([0]).collect::<VecDeque<_>>()
+        );
+        assert_eq!(stats.trends["/report"], VecDeque::from([1]));
+        // idle keys are forgotten once they're out of the sparklines
+        stats.reset();
+        for _ in 0..SPARKLINE_LEN {
+            stats.update_trends();
+        }
+        assert!(stats.trends.is_empty());
+    }
+
+    #[tokio::test]
+    async fn splits_the_hits_between_bots_and_humans() {
+        let mut stats = Stats::builder()
+            .period_in_secs(3)
+            .bot_patterns(Some(BotPatterns::default()))
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_grouped_log_with(1, 2, |log| {
+                log.user_agent = Some("Mozilla/5.0 (compatible; Googlebot/2.1)".to_string())
+            }),
+            build_grouped_log_with(2, 3, |log| {
+                log.user_agent = Some("Mozilla/5.0 (X11; Linux x86_64) Firefox/120.0".to_string())
+            }),
+            // without user agent, neither a bot nor a human
+            build_grouped_log_with(3, 1, |_| ()),
+        ];
+        for log in logs {
+            stats.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(msg.contains("Total Hits: 6,"));
+        assert!(msg.contains(", Bot Hits: 2, Human Hits: 3"));
+    }
+
+    #[tokio::test]
+    async fn groups_by_path() {
+        let mut stats = Stats::builder()
+            .period_in_secs(3)
+            .group_by("path".parse().unwrap())
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_grouped_log_with(1, 2, |log| log.request.path = "/api/users".to_string()),
+            build_grouped_log_with(3, 1, |log| log.request.path = "/api/friends".to_string()),
+        ];
+
+        for log in logs {
+            stats.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        // both paths share the /api section
+        assert!(msg.contains("Path: /api/users, Total Hits: 2,"));
+        assert!(msg.contains("Path: /api/friends, Total Hits: 1,"));
+        assert!(!msg.contains("Path: /api,"));
+    }
+
+    #[tokio::test]
+    async fn groups_by_status() {
+        let mut stats = Stats::builder()
+            .period_in_secs(3)
+            .group_by("status".parse().unwrap())
+            .build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = vec![
+            build_grouped_log_with(1, 3, |log| log.status = 404),
+            build_grouped_log_with(3, 3, |log| log.status = 404),
+        ];
+
+        for log in logs {
+            stats.process(&log, &mut writer).unwrap();
+        }
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}Status: 404, Total Hits: 6, Avg Reqs/Sec: 2, Avg Time: 0.5s, Avg Bytes: 100\n",
+                stats_prefix(3)
+            )
+        );
+    }
+
+    fn run(stats: &mut Stats, logs: Vec<GroupedHttpLogs>) -> String {
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        for log in logs {
+            stats.process(&log, &mut writer).unwrap();
+        }
+        stats.finalize(&mut writer).unwrap();
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    fn build_cumulative_test_logs() -> Vec<GroupedHttpLogs> {
+        vec![
+            build_test_http_grouped_log(1, 1, None),
+            build_test_http_grouped_log(2, 1, None),
+            build_test_http_grouped_log(4, 3, None),
+            build_test_http_grouped_log(5, 1, None),
+        ]
+    }
+
+    #[tokio::test]
+    async fn windowed_stats_reset_every_period() {
+        let mut stats = Stats::builder().period_in_secs(2).build();
+
+        let msg = run(&mut stats, build_cumulative_test_logs());
+
+        assert_eq!(
+            msg,
+            format!(
+                "{}Section: /api, Total Hits: 2, Avg Reqs/Sec: 1, Avg Time: 1s, Avg Bytes: 100\n{}Section: /api, Total Hits: 3, Avg Reqs/Sec: 1.5, Avg Time: 0.6666666666666666s, Avg Bytes: 100\n",
+                stats_prefix(2),
+                stats_prefix(2)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn cumulative_stats_keep_running_totals() {
+        let mut stats = Stats::builder().period_in_secs(2).cumulative(true).build();
+
+        let msg = run(&mut stats, build_cumulative_test_logs());
+
+        assert_eq!(
+            msg,
+            format!(
+                "{}Section: /api, Total Hits: 2, Avg Reqs/Sec: 1, Avg Time: 1s, Avg Bytes: 100\n{}Section: /api, Total Hits: 5, Avg Reqs/Sec: 1.25, Avg Time: 0.8s, Avg Bytes: 100\n{}Section: /api, Total Hits: 6, Avg Reqs/Sec: 1.2, Avg Time: 0.8333333333333334s, Avg Bytes: 100\n",
+                cumulative_stats_prefix(2),
+                cumulative_stats_prefix(4),
+                cumulative_stats_prefix(5)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn wall_clock_stats_report_on_every_tick() {
+        let mut stats = Stats::builder().period_in_secs(2).wall_clock(true).build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        // the periods of log time don't report anything
+        for log in build_cumulative_test_logs() {
+            stats.process(&log, &mut writer).unwrap();
+        }
+        let before_tick = writer.buffer().len();
+        stats.tick(Duration::from_secs(3), &mut writer).unwrap();
+        stats
+            .process(&build_test_http_grouped_log(5, 3, None), &mut writer)
+            .unwrap();
+        stats.tick(Duration::from_secs(3), &mut writer).unwrap();
+        stats
+            .process(&build_test_http_grouped_log(6, 1, None), &mut writer)
+            .unwrap();
+        // the last interval isn't over
+        stats.finalize(&mut writer).unwrap();
+
+        assert_eq!(before_tick, 0);
+        assert_eq!(
+            String::from_utf8(writer.into_inner().unwrap()).unwrap(),
+            format!(
+                "{}Section: /api, Total Hits: 6, Avg Reqs/Sec: 2, Avg Time: 0.5s, Avg Bytes: 100\n{}Section: /api, Total Hits: 3, Avg Reqs/Sec: 1, Avg Time: 1s, Avg Bytes: 100\n",
+                stats_prefix(3),
+                stats_prefix(3)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn the_snapshot_has_the_current_aggregates_by_hits() {
+        let mut stats = Stats::new(10);
+        let mut writer = std::io::sink();
+
+        let logs = vec![
+            build_test_http_grouped_log(1, 1, Some("/report".to_string())),
+            build_test_http_grouped_log(2, 3, Some("/api/users".to_string())),
+            build_test_http_grouped_log(3, 1, Some("/help".to_string())),
+        ];
+        for log in logs {
+            stats.process(&log, &mut writer).unwrap();
+        }
+        let snapshot = stats.snapshot();
+
+        let expected = [("/api", 3, 300), ("/help", 1, 100), ("/report", 1, 100)]
+            .into_iter()
+            .map(|(key, hits, bytes)| SectionStat {
+                key: key.to_string(),
+                hits,
+                bytes,
+                examples: Vec::new(),
+                bot_hits: 0,
+                human_hits: 0,
+                statuses: BTreeMap::new(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(snapshot, expected);
+        // the buffer isn't cleared
+        assert_eq!(stats.snapshot(), snapshot);
+    }
+
+    #[tokio::test]
+    async fn incremental_flushing_spreads_the_same_stats() {
+        let groups = ["/a", "/b", "/c", "/d", "/e", "/f", "/g"]
+            .iter()
+            .zip([1, 1, 1, 1, 3, 4, 4])
+            .map(|(section, time)| build_test_http_grouped_log(time, 1, Some(section.to_string())))
+            .collect::<Vec<_>>();
+        let mut stats = Stats::builder()
+            .period_in_secs(2)
+            .flush_lines(Some(2))
+            .build();
+
+        // the banner and a section, then two sections per group
+        let mut sections_per_group = Vec::new();
+        let mut incremental = String::new();
+        for group in &groups {
+            let mut writer = Vec::<u8>::new();
+            stats.process(group, &mut writer).unwrap();
+            let msg = String::from_utf8(writer).unwrap();
+            sections_per_group.push(msg.matches("Section:").count());
+            incremental.push_str(&msg);
+        }
+        let all_at_once = run(&mut Stats::builder().period_in_secs(2).build(), groups);
+
+        assert_eq!(sections_per_group, vec![0, 0, 0, 0, 1, 2, 2]);
+        let mut incremental = incremental.lines().collect::<Vec<_>>();
+        let mut all_at_once = all_at_once.lines().collect::<Vec<_>>();
+        incremental.sort_unstable();
+        all_at_once.sort_unstable();
+        assert_eq!(incremental, all_at_once);
+    }
+
+    #[tokio::test]
+    async fn pending_stats_are_written_when_finalizing() {
+        let mut stats = Stats::builder()
+            .period_in_secs(2)
+            .flush_lines(Some(1))
+            .build();
+
+        let msg = run(&mut stats, build_cumulative_test_logs());
+
+        assert_eq!(msg.matches("Section:").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn periods_start_with_the_first_group_and_allow_late_groups() {
+        // a 1 second buffer may let late groups through, the period is still 10 seconds
+        let mut stats = Stats::new(10);
+
+        let msg = run(
+            &mut stats,
+            vec![
+                build_test_http_grouped_log(1549573860, 2, None),
+                build_test_http_grouped_log(1549573863, 2, None),
+                build_test_http_grouped_log(1549573861, 1, None),
+                build_test_http_grouped_log(1549573866, 3, None),
+                build_test_http_grouped_log(1549573869, 2, None),
+                build_test_http_grouped_log(1549573872, 1, None),
+            ],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{}Section: /api, Total Hits: 10, Avg Reqs/Sec: 1, Avg Time: 1s, Avg Bytes: 100\n",
+                stats_prefix(10)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn warns_and_flushes_when_the_period_never_elapses() {
+        let mut stats = Stats::new(10);
+
+        let msg = run(
+            &mut stats,
+            vec![
+                build_test_http_grouped_log(1, 2, None),
+                build_test_http_grouped_log(3, 1, None),
+            ],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{}No stats were printed: input span < stats period (10s); lower --stats-period or check your data\n{}Section: /api, Total Hits: 3, Avg Reqs/Sec: 1, Avg Time: 1s, Avg Bytes: 100\n",
+                warning_prefix(),
+                stats_prefix(3)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn examples_are_capped_and_observed() {
+        let paths = (0..20).map(|i| format!("/api/{}", i)).collect::<Vec<_>>();
+        let mut key_stats = KeyStats::default();
+
+        for path in &paths {
+            key_stats.hits += 1;
+            key_stats.sample(path, 5);
+        }
+
+        assert_eq!(key_stats.examples.len(), 5);
+        assert!(key_stats.examples.iter().all(|e| paths.contains(e)));
+    }
+
+    #[tokio::test]
+    async fn shows_examples_in_every_line() {
+        let mut stats = Stats::builder().period_in_secs(1).max_examples(5).build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        stats
+            .process(
+                &build_test_http_grouped_log(1, 2, Some("/api/users".to_string())),
+                &mut writer,
+            )
+            .unwrap();
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}Section: /api, Total Hits: 2, Avg Reqs/Sec: 2, Avg Time: 0.5s, Avg Bytes: 100, Examples: /api/users, /api/users\n",
+                stats_prefix(1)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn huge_bytes_do_not_overflow() {
+        let mut stats = Stats::new(1);
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = build_grouped_log_with(1, 3, |log| log.bytes = usize::MAX);
+        stats.process(&logs, &mut writer).unwrap();
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            msg,
+            format!(
+                "{}Section: /api, Total Hits: 3, Avg Reqs/Sec: 3, Avg Time: 0.3333333333333333s, Avg Bytes: {}\n",
+                stats_prefix(1),
+                usize::MAX
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn converts_the_rates_to_the_rate_unit() {
+        let rates = ["sec", "min", "hour"].map(|unit| {
+            let mut stats = Stats::builder()
+                .period_in_secs(4)
+                .rate_unit(unit.parse().unwrap())
+                .build();
+            // 2 requests over 4 seconds
+            let msg = run(
+                &mut stats,
+                vec![
+                    build_test_http_grouped_log(1, 1, None),
+                    build_test_http_grouped_log(4, 1, None),
+                ],
+            );
+            let (_, rate) = msg.split_once(", Avg Reqs/").unwrap();
+            rate.split(',').next().unwrap().to_string()
+        });
+
+        assert_eq!(rates, ["Sec: 0.5", "Min: 30", "Hour: 1800"]);
+        assert!("day".parse::<RateUnit>().is_err());
+    }
+
+    fn build_format_test_stats(format: StatsFormat) -> String {
+        let mut stats = Stats::builder().period_in_secs(2).format(format).build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        for time in 1..=2 {
+            stats
+                .process(&build_test_http_grouped_log(time, 1, None), &mut writer)
+                .unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn renders_pretty_stats() {
+        assert_eq!(
+            build_format_test_stats(StatsFormat::Pretty),
+            format!(
+                "{}Section: /api, Total Hits: 2, Avg Reqs/Sec: 1, Avg Time: 1s, Avg Bytes: 100\n",
+                stats_prefix(2)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn renders_compact_stats() {
+        assert_eq!(
+            build_format_test_stats(StatsFormat::Compact),
+            format!(
+                "{}Section: /api, Total Hits: 2, Avg Reqs/Sec: 1, Avg Time: 1s, Avg Bytes: 100\n",
+                compact_stats_prefix(2)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn aggregates_the_keys_over_the_max_into_other() {
+        let mut stats = Stats::builder().period_in_secs(1).max_keys(Some(2)).build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        for (i, section) in ["/a", "/b", "/c", "/d", "/c"].iter().enumerate() {
+            stats
+                .process(
+                    &build_test_http_grouped_log(0, i + 1, Some(format!("{}/x", section))),
+                    &mut writer,
+                )
+                .unwrap();
+        }
+        stats
+            .process(&build_test_http_grouped_log(1, 1, None), &mut writer)
+            .unwrap();
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(msg.contains("Section: /a, Total Hits: 1,"));
+        assert!(msg.contains("Section: /b, Total Hits: 2,"));
+        assert!(msg.contains("Section: <other>, Total Hits: 13,"));
+        assert!(msg.contains("Sections in <other>: 3\n"));
+        assert!(!msg.contains("Section: /api,"));
+    }
+
+    #[tokio::test]
+    async fn the_keys_counted_in_other_are_capped() {
+        let mut stats = Stats::builder().period_in_secs(1).max_keys(Some(1)).build();
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let logs = (0..MAX_OTHER_KEYS + 10)
+            .map(|i| build_test_http_log(0, Some(format!("/s{}/x", i))))
+            .collect();
+        stats
+            .process(&GroupedHttpLogs { time: 0, logs }, &mut writer)
+            .unwrap();
+        stats
+            .process(&build_test_http_grouped_log(1, 1, None), &mut writer)
+            .unwrap();
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(msg.contains(&format!("Sections in <other>: {}+\n", MAX_OTHER_KEYS)));
+    }
+
+    #[tokio::test]
+    async fn reports_the_protocol_breakdown() {
+        let mut stats = Stats::builder()
+            .period_in_secs(2)
+            .protocol_breakdown(true)
+            .build();
+
+        let msg = run(
+            &mut stats,
+            vec![
+                build_grouped_log_with(1, 2, |log| log.request.protocol = "HTTP/1.0".to_string()),
+                build_grouped_log_with(1, 3, |log| log.request.protocol = "HTTP/2".to_string()),
+                build_grouped_log_with(2, 1, |log| log.request.protocol = "garbage".to_string()),
+                build_grouped_log_with(4, 1, |log| log.request.protocol = "HTTP/2".to_string()),
+            ],
+        );
+
+        assert_eq!(
+            msg,
+            format!(
+                "{}Section: /api, Total Hits: 6, Avg Reqs/Sec: 3, Avg Time: 0.3333333333333333s, Avg Bytes: 100\nProtocols: HTTP/1.0 = 2, HTTP/2 = 3, unknown = 1\n{}Section: /api, Total Hits: 1, Avg Reqs/Sec: 0.5, Avg Time: 2s, Avg Bytes: 100\nProtocols: HTTP/2 = 1\n",
+                stats_prefix(2),
+                stats_prefix(2)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_the_hits_per_status_code() {
+        let mut stats = Stats::builder()
+            .period_in_secs(2)
+            .status_detail(true)
+            .build();
+
+        let msg = run(
+            &mut stats,
+            vec![
+                build_grouped_log_with(1, 2, |log| log.status = 401),
+                build_grouped_log_with(1, 1, |log| log.status = 403),
+                build_grouped_log_with(1, 1, |log| log.status = 200),
+                build_grouped_log_with(2, 3, |log| log.status = 404),
+                build_grouped_log_with(4, 2, |log| log.status = 403),
+            ],
+        );
+
+        let statuses = msg
+            .lines()
+            .filter_map(|line| line.split_once(", Statuses: "))
+            .map(|(_, statuses)| statuses)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses, ["200 = 1, 401 = 2, 403 = 1, 404 = 3", "403 = 2"]);
+    }
+
+    #[tokio::test]
+    async fn the_gini_coefficient_tells_how_skewed_the_hits_are() {
+        let even = gini([10, 10, 10, 10].into_iter());
+        let skewed = gini((0..19).map(|_| 1).chain([10_000]));
+
+        assert!(even.abs() < 1e-9, "even gini = {}", even);
+        assert!(skewed > 0.9, "skewed gini = {}", skewed);
+        assert_eq!(gini([7].into_iter()), 0.0);
+        assert_eq!(gini(std::iter::empty()), 0.0);
+    }
+
+    #[tokio::test]
+    async fn reports_the_gini_coefficient_of_every_period() {
+        let mut stats = Stats::builder()
+            .period_in_secs(2)
+            .gini(true)
+            .precision(Some(2))
+            .build();
+
+        let msg = run(
+            &mut stats,
+            vec![
+                // evenly spread
+                build_test_http_grouped_log(1, 3, Some("/api/user".to_string())),
+                build_test_http_grouped_log(2, 3, Some("/report".to_string())),
+                // hot-spotting on /report
+                build_test_http_grouped_log(3, 1, Some("/api/user".to_string())),
+                build_test_http_grouped_log(4, 9, Some("/report".to_string())),
+            ],
+        );
+
+        let ginis = msg
+            .lines()
+            .filter_map(|line| line.strip_prefix("Gini: "))
+            .collect::<Vec<_>>();
+        assert_eq!(ginis, ["0.00", "0.40"]);
+    }
+
+    #[tokio::test]
+    async fn the_gini_coefficient_leaves_other_out() {
+        let mut stats = Stats::builder()
+            .period_in_secs(2)
+            .max_keys(Some(2))
+            .gini(true)
+            .precision(Some(2))
+            .build();
+
+        let msg = run(
+            &mut stats,
+            vec![
+                build_test_http_grouped_log(1, 3, Some("/a/x".to_string())),
+                build_test_http_grouped_log(1, 3, Some("/b/x".to_string())),
+                build_test_http_grouped_log(1, 10, Some("/c/x".to_string())),
+                build_test_http_grouped_log(1, 1, Some("/d/x".to_string())),
+            ],
+        );
+
+        assert!(msg.contains("Section: <other>, Total Hits: 11,"));
+        assert!(msg.contains("\nGini: 0.00\n"));
+    }
 }
@@ -0,0 +1,88 @@
+use super::alerts::{AlertNotifier, AlertSeverity};
+use std::sync::Mutex;
+use syslog::{Facility, Formatter3164, Logger, LoggerBackend};
+
+/// Sends the alert messages to syslog.
+pub struct SyslogSink {
+    // the loggers need `&mut` to write
+    logger: Mutex<Logger<LoggerBackend, Formatter3164>>,
+}
+
+impl SyslogSink {
+    /// Connects to a syslog server over UDP or, without an address, to the local socket.
+    pub fn connect(addr: Option<&str>) -> anyhow::Result<Self> {
+        let formatter = Formatter3164 {
+            facility: Facility::LOG_USER,
+            hostname: None,
+            process: env!("CARGO_PKG_NAME").to_string(),
+            pid: std::process::id(),
+        };
+        let logger = match addr {
+            Some(addr) => syslog::udp(formatter, "0.0.0.0:0", addr),
+            None => syslog::unix(formatter),
+        }
+        .map_err(|e| anyhow::anyhow!("Can't connect to syslog: {}", e))?;
+        Ok(Self {
+            logger: Mutex::new(logger),
+        })
+    }
+}
+
+impl AlertNotifier for SyslogSink {
+    fn notify(&self, severity: AlertSeverity, msg: &str) -> anyhow::Result<()> {
+        let mut logger = self
+            .logger
+            .lock()
+            .map_err(|_| anyhow::anyhow!("The syslog logger is poisoned"))?;
+        match severity {
+            AlertSeverity::Warning => logger.warning(msg),
+            AlertSeverity::Notice => logger.notice(msg),
+        }
+        .map_err(|e| anyhow::anyhow!("Can't send the alert to syslog: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        processors::{Alerts, Processor},
+        test_utils::build_test_http_grouped_log,
+    };
+    use std::{net::UdpSocket, sync::Arc, time::Duration};
+
+    #[tokio::test]
+    async fn alerts_are_sent_to_syslog_with_their_severity() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        listener
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let sink = SyslogSink::connect(Some(&addr)).unwrap();
+        let mut alerts = Alerts::builder()
+            .avg_req_sec_threshold(1)
+            .window_size_in_secs(2)
+            .notifier(Some(Arc::new(sink)))
+            .build();
+
+        let mut writer = std::io::sink();
+        alerts
+            .process(&build_test_http_grouped_log(1, 3, None), &mut writer)
+            .unwrap();
+        alerts
+            .process(&build_test_http_grouped_log(4, 1, None), &mut writer)
+            .unwrap();
+
+        let mut buf = [0; 1024];
+        let len = listener.recv(&mut buf).unwrap();
+        let triggered = String::from_utf8_lossy(&buf[..len]).to_string();
+        let len = listener.recv(&mut buf).unwrap();
+        let recovered = String::from_utf8_lossy(&buf[..len]).to_string();
+
+        // user facility (1) * 8 + warning (4) and notice (5)
+        assert!(triggered.starts_with("<12>"), "{}", triggered);
+        assert!(triggered.ends_with("High traffic generated an alert - hits = 1.5, triggered at 1"));
+        assert!(recovered.starts_with("<13>"), "{}", recovered);
+        assert!(recovered.ends_with("Normal traffic recovered - hits = 0.5, recovered at 4"));
+    }
+}
@@ -0,0 +1,133 @@
+use super::GroupedHttpLogs;
+use super::Processor;
+use tracing::instrument;
+
+/// Alert written by one of the processors of an [`AlertTimeline`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TimelineEntry {
+    time: usize,
+    message: String,
+}
+
+/// Runs the alert processors forwarding their output, and prints a timeline of all
+/// their alerts once the logs end. Meant to tune the thresholds replaying historical logs.
+pub struct AlertTimeline {
+    processors: Vec<Box<dyn Processor>>,
+    speed: usize,
+    first_time: Option<usize>,
+    entries: Vec<TimelineEntry>,
+}
+
+impl AlertTimeline {
+    /// `speed` is the replay speed, used to show when the alerts were written.
+    pub fn new(processors: Vec<Box<dyn Processor>>, speed: usize) -> Self {
+        Self {
+            processors,
+            speed: speed.max(1),
+            first_time: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Keeps the alert messages written by the processors, without their banners.
+    fn record(&mut self, time: usize, output: &[u8]) {
+        let output = console::strip_ansi_codes(&String::from_utf8_lossy(output)).to_string();
+        self.entries.extend(
+            output
+                .lines()
+                .filter(|line| !line.is_empty() && !line.starts_with(">>>"))
+                .map(|line| TimelineEntry {
+                    time,
+                    message: line.to_string(),
+                }),
+        );
+    }
+
+    fn write_timeline(&self, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        writer.write_all(timeline_prefix(self.speed).as_bytes())?;
+        if self.entries.is_empty() {
+            writer.write_all(b"No alerts\n")?;
+        }
+        let first_time = self.first_time.unwrap_or_default();
+        for entry in &self.entries {
+            let replay_secs = (entry.time - first_time) as f64 / self.speed as f64;
+            let msg = format!("{} (+{}s) {}\n", entry.time, replay_secs, entry.message);
+            writer.write_all(msg.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl Processor for AlertTimeline {
+    #[instrument(skip(self, writer))]
+    fn process(
+        &mut self,
+        log_group: &GroupedHttpLogs,
+        writer: &mut dyn std::io::Write,
+    ) -> anyhow::Result<()> {
+        self.first_time.get_or_insert(log_group.time);
+        let mut output = Vec::new();
+        for processor in self.processors.iter_mut() {
+            processor.process(log_group, &mut output)?;
+        }
+        writer.write_all(&output)?;
+        self.record(log_group.time, &output);
+        Ok(())
+    }
+
+    fn finalize(&mut self, writer: &mut dyn std::io::Write) -> anyhow::Result<()> {
+        let mut output = Vec::new();
+        for processor in self.processors.iter_mut() {
+            processor.finalize(&mut output)?;
+        }
+        writer.write_all(&output)?;
+        self.write_timeline(writer)
+    }
+}
+
+impl std::fmt::Debug for AlertTimeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AlertTimeline")
+            .field("processors", &self.processors.len())
+            .field("speed", &self.speed)
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+fn timeline_prefix(speed: usize) -> String {
+    console::style(format!("\nALERT TIMELINE ({}x):\n********\n", speed))
+        .bold()
+        .magenta()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{processors::Alerts, test_utils::build_test_http_grouped_log};
+    use std::io::BufWriter;
+
+    #[tokio::test]
+    async fn prints_the_timeline_of_the_alerts() {
+        let mut timeline = AlertTimeline::new(vec![Box::new(Alerts::new(1, 2))], 2);
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let groups = vec![
+            build_test_http_grouped_log(10, 1, None),
+            build_test_http_grouped_log(11, 3, None),
+            build_test_http_grouped_log(12, 1, None),
+            build_test_http_grouped_log(16, 1, None),
+        ];
+        for group in groups {
+            timeline.process(&group, &mut writer).unwrap();
+        }
+        timeline.finalize(&mut writer).unwrap();
+
+        let msg = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(msg.ends_with(&format!(
+            "{}11 (+0.5s) High traffic generated an alert - hits = 2, triggered at 11\n16 (+3s) Normal traffic recovered - hits = 0.5, recovered at 16\n",
+            timeline_prefix(2)
+        )));
+    }
+}
@@ -0,0 +1,126 @@
+use futures::Stream;
+use pin_project::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+/// Time spent in a stage of the pipeline and amount of items it produced.
+#[derive(Debug, Default)]
+pub struct StageTimer {
+    nanos: AtomicU64,
+    items: AtomicUsize,
+}
+
+impl StageTimer {
+    pub fn add(&self, elapsed: Duration, items: usize) {
+        self.nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+        self.items.fetch_add(items, Ordering::Relaxed);
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.nanos.load(Ordering::Relaxed))
+    }
+
+    pub fn items(&self) -> usize {
+        self.items.load(Ordering::Relaxed)
+    }
+}
+
+/// Measures the time spent polling a stream, without a timer it only forwards the items.
+#[pin_project]
+#[must_use = "streams do nothing unless polled"]
+#[derive(Debug)]
+pub struct Timed<St> {
+    #[pin]
+    stream: St,
+    timer: Option<Arc<StageTimer>>,
+}
+
+impl<St> Timed<St> {
+    pub fn new(stream: St, timer: Option<Arc<StageTimer>>) -> Self {
+        Self { stream, timer }
+    }
+}
+
+impl<St: Stream> Stream for Timed<St> {
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let timer = match this.timer {
+            Some(timer) => timer,
+            None => return this.stream.poll_next(cx),
+        };
+        let start = Instant::now();
+        let poll = this.stream.poll_next(cx);
+        let items = matches!(poll, Poll::Ready(Some(_))) as usize;
+        timer.add(start.elapsed(), items);
+        poll
+    }
+}
+
+/// Time spent in every stage of the pipeline, printed with `--profile`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// Reading and deserializing the logs.
+    pub reading: Duration,
+    /// Buffering, filtering and grouping the logs, without the reading.
+    pub buffering: Duration,
+    /// Dispatching the groups to the processors, finalizing them included.
+    pub processing: Duration,
+    pub logs: usize,
+    pub groups: usize,
+}
+
+impl Profile {
+    fn total(&self) -> Duration {
+        self.reading + self.buffering + self.processing
+    }
+
+    fn percentage(&self, stage: Duration) -> f64 {
+        match self.total().as_secs_f64() {
+            total if total > 0.0 => stage.as_secs_f64() * 100.0 / total,
+            _ => 0.0,
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{}",
+            console::style(format!("\nPROFILE (total {:.3?}):\n********", self.total()))
+                .bold()
+                .cyan()
+        )?;
+        writeln!(
+            f,
+            "Reading: {:.3?} ({:.1}%), {} logs",
+            self.reading,
+            self.percentage(self.reading),
+            self.logs
+        )?;
+        writeln!(
+            f,
+            "Buffering: {:.3?} ({:.1}%), {} groups",
+            self.buffering,
+            self.percentage(self.buffering),
+            self.groups
+        )?;
+        writeln!(
+            f,
+            "Processing: {:.3?} ({:.1}%)",
+            self.processing,
+            self.percentage(self.processing)
+        )
+    }
+}
@@ -1,5 +1,25 @@
+use crate::{
+    anonymize::Anonymizer,
+    clf::read_clf_async,
+    json::read_json_array_async,
+    record_limit::RecordLimit,
+    section::{is_malformed_path, FirstSegment, SectionExtractor, SectionPolicy, UNKNOWN_SECTION},
+};
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZstdDecoder};
+use csv_async::StringRecord;
+use futures::{future, stream, StreamExt};
 use serde::{Deserialize, Deserializer, Serialize};
-use tokio_stream::StreamExt;
+use std::{
+    ops::Range,
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tracing::instrument;
 
 pub type AsyncReader = dyn tokio::io::AsyncRead + Send + Sync + Unpin;
@@ -11,15 +31,47 @@ pub struct LogRequest {
     pub verb: String,
     /// Path of the request.
     pub path: String,
-    /// First part of the path.
+    /// First part of the path, unless another [`SectionPolicy`] is used.
     pub section: String,
     /// Protocol of the request.
     pub protocol: String,
 }
 
+/// Protocols a request is expected to use.
+const KNOWN_PROTOCOLS: &[&str] = &[
+    "HTTP/0.9", "HTTP/1.0", "HTTP/1.1", "HTTP/2", "HTTP/2.0", "HTTP/3",
+];
+
+/// Label of the protocols out of [`KNOWN_PROTOCOLS`] (e.g. garbage).
+pub const UNKNOWN_PROTOCOL: &str = "unknown";
+
 impl LogRequest {
+    /// The protocol if it's a known one, [`UNKNOWN_PROTOCOL`] otherwise.
+    pub fn protocol_label(&self) -> &str {
+        KNOWN_PROTOCOLS
+            .iter()
+            .find(|protocol| protocol.eq_ignore_ascii_case(&self.protocol))
+            .copied()
+            .unwrap_or(UNKNOWN_PROTOCOL)
+    }
+
     pub fn from_str(line: &str) -> anyhow::Result<Self> {
-        let mut parts = line.split_whitespace();
+        Self::from_str_with_separator(line, &RequestSeparator::Whitespace)
+    }
+
+    /// Parses a request whose verb, path and protocol are split by the separator.
+    pub fn from_str_with_separator(
+        line: &str,
+        separator: &RequestSeparator,
+    ) -> anyhow::Result<Self> {
+        let mut parts: Box<dyn Iterator<Item = &str>> = match separator {
+            RequestSeparator::Whitespace => Box::new(line.split_whitespace()),
+            RequestSeparator::Custom(separator) => Box::new(
+                line.split(separator.as_str())
+                    .map(str::trim)
+                    .filter(|part| !part.is_empty()),
+            ),
+        };
 
         let verb = parts
             .next()
@@ -31,20 +83,69 @@ impl LogRequest {
             .next()
             .ok_or_else(|| anyhow::anyhow!("Invalid line, no protocol: {}", line))?;
 
-        let section = path
-            .chars()
-            .enumerate()
-            .take_while(|(i, c)| *i == 0 || *c != '/')
-            .map(|(_, c)| c)
-            .collect::<String>();
-
         Ok(Self {
             verb: verb.to_string(),
             path: path.to_string(),
-            section,
+            section: FirstSegment.section(path),
             protocol: protocol.to_string(),
         })
     }
+
+    /// Replaces the numeric and UUID segments of the path (and so of the section) with
+    /// [`ID_PLACEHOLDER`], so the requests to the same resource are aggregated together.
+    pub fn normalize_path(&mut self) {
+        if !self.path.split('/').any(is_id) {
+            return;
+        }
+        self.path = self
+            .path
+            .split('/')
+            .map(|segment| {
+                if is_id(segment) {
+                    ID_PLACEHOLDER
+                } else {
+                    segment
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        self.section = FirstSegment.section(&self.path);
+    }
+}
+
+/// What splits the verb, the path and the protocol of the request column.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum RequestSeparator {
+    /// Any amount of whitespace, e.g. `GET /api/user HTTP/1.0`.
+    #[default]
+    Whitespace,
+    /// A literal delimiter, e.g. `|` for `GET|/api/user|HTTP/1.0`.
+    Custom(String),
+}
+
+impl FromStr for RequestSeparator {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err(anyhow::anyhow!("Invalid request separator, it's empty")),
+            "whitespace" => Ok(Self::Whitespace),
+            s if s.trim().is_empty() => Ok(Self::Whitespace),
+            s => Ok(Self::Custom(s.to_string())),
+        }
+    }
+}
+
+/// Placeholder of the ID segments of the normalized paths.
+pub const ID_PLACEHOLDER: &str = "{id}";
+
+/// Whether a path segment is a number or a UUID.
+fn is_id(segment: &str) -> bool {
+    let is_number = !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit());
+    let is_uuid = segment.len() == 36
+        && segment.split('-').map(str::len).eq([8, 4, 4, 4, 12])
+        && segment.bytes().all(|b| b == b'-' || b.is_ascii_hexdigit());
+    is_number || is_uuid
 }
 
 fn deserialize_log_request<'de, D>(deserializer: D) -> Result<LogRequest, D::Error>
@@ -55,6 +156,61 @@ where
     LogRequest::from_str(&buf).map_err(serde::de::Error::custom)
 }
 
+/// Byte count written by the servers when a response has no body (e.g. a 304).
+const NO_BYTES: &str = "-";
+
+/// Parses a byte count, taking [`NO_BYTES`] and empty fields as 0.
+pub fn parse_byte_count(bytes: &str) -> Result<usize, std::num::ParseIntError> {
+    match bytes.trim() {
+        "" | NO_BYTES => Ok(0),
+        bytes => bytes.parse(),
+    }
+}
+
+/// Response time written by the servers when it's unknown.
+const NO_VALUE: &str = "-";
+
+/// Parses a response time in milliseconds, taking [`NO_VALUE`] and empty fields as unknown.
+fn parse_response_time(response_time: &str) -> anyhow::Result<Option<u64>> {
+    match response_time.trim() {
+        "" | NO_VALUE => Ok(None),
+        response_time => response_time
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid response time {}: {}", response_time, e)),
+    }
+}
+
+/// Reads the byte count as a number, taking [`NO_BYTES`] and empty fields as 0.
+pub fn deserialize_byte_count<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct ByteCountVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for ByteCountVisitor {
+        type Value = usize;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "an amount of bytes or {}", NO_BYTES)
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, bytes: u64) -> Result<usize, E> {
+            usize::try_from(bytes).map_err(E::custom)
+        }
+
+        fn visit_str<E: serde::de::Error>(self, bytes: &str) -> Result<usize, E> {
+            parse_byte_count(bytes).map_err(E::custom)
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> Result<usize, E> {
+            Ok(0)
+        }
+    }
+
+    deserializer.deserialize_any(ByteCountVisitor)
+}
+
 /// Represents an Http Log.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct HttpLog {
@@ -73,30 +229,646 @@ pub struct HttpLog {
     pub request: LogRequest,
     /// The status code.
     pub status: u16,
-    /// The amount of bytes.
+    /// The amount of bytes, 0 for `-` or empty.
+    #[serde(deserialize_with = "deserialize_byte_count")]
     pub bytes: usize,
+    /// Milliseconds taken to respond, only when the input has a response time column.
+    #[serde(skip)]
+    pub response_time_ms: Option<u64>,
+    /// Referrer of the request, only in the Combined Log Format.
+    #[serde(skip)]
+    pub referrer: Option<String>,
+    /// User agent of the client, only in the Combined Log Format.
+    #[serde(skip)]
+    pub user_agent: Option<String>,
+}
+
+/// Fields of a CSV record, with the request as it's written so it's only parsed once, with
+/// the configured [`RequestSeparator`].
+#[derive(Deserialize)]
+struct CsvLog<'a> {
+    remote_host: String,
+    rfc931: String,
+    auth_user: String,
+    time: usize,
+    request: &'a str,
+    status: u16,
+    #[serde(deserialize_with = "deserialize_byte_count")]
+    bytes: usize,
+}
+
+/// Name of the header holding the epoch time of the log.
+const TIME_COLUMN: &str = "date";
+/// Position of the time in the [`HttpLog`] fields.
+const TIME_INDEX: usize = 3;
+/// Name of the optional column holding the response time in milliseconds.
+const RESPONSE_TIME_COLUMN: &str = "responsetime";
+/// Byte order mark of UTF-8 files.
+pub const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Names of the columns in the order of the [`HttpLog`] fields.
+const COLUMNS: [&str; 7] = [
+    "remotehost",
+    "rfc931",
+    "authuser",
+    TIME_COLUMN,
+    "request",
+    "status",
+    "bytes",
+];
+
+/// Column names supplied for files without a header row, in any order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header(Vec<String>);
+
+impl Header {
+    fn position(&self, column: &str) -> Option<usize> {
+        self.0.iter().position(|c| c == column)
+    }
+
+    /// Sorts the fields of a record as the [`HttpLog`] fields.
+    fn reorder(&self, record: &StringRecord) -> StringRecord {
+        COLUMNS
+            .iter()
+            .filter_map(|column| self.position(column))
+            .map(|i| record.get(i).unwrap_or_default())
+            .collect()
+    }
+}
+
+impl FromStr for Header {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let header = Self(s.split(',').map(|c| c.trim().to_string()).collect());
+        // the time is the only one that can be filled with a fallback
+        if let Some(column) = COLUMNS
+            .iter()
+            .find(|c| **c != TIME_COLUMN && header.position(c).is_none())
+        {
+            anyhow::bail!("Invalid header, missing column: {}", column);
+        }
+        Ok(header)
+    }
+}
+
+/// What to do when a log has no time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeFallback {
+    /// Uses the ingestion wall-clock time.
+    Now,
+}
+
+impl FromStr for TimeFallback {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "now" => Ok(Self::Now),
+            _ => Err(anyhow::anyhow!("Invalid time fallback: {}", s)),
+        }
+    }
+}
+
+impl TimeFallback {
+    fn time(self) -> usize {
+        match self {
+            Self::Now => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as usize)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Format of the input.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// CSV with a header row, unless one is supplied.
+    #[default]
+    Csv,
+    /// A single JSON array of log objects with the same fields as the CSV columns.
+    JsonArray,
+    /// Common Log Format lines, with the referrer and the user agent of the Combined Log
+    /// Format when present.
+    Clf,
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json-array" => Ok(Self::JsonArray),
+            "clf" => Ok(Self::Clf),
+            _ => Err(anyhow::anyhow!(
+                "Invalid format: {}. Use csv, json-array or clf",
+                s
+            )),
+        }
+    }
+}
+
+/// Options tweaking how the logs are read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReaderOptions {
+    /// Time used for the logs with a missing or empty time column.
+    /// Without it, those logs are errors.
+    pub time_fallback: Option<TimeFallback>,
+    /// Lines longer than this amount of bytes are skipped and reported as errors.
+    pub max_record_bytes: Option<usize>,
+    /// Column names of a file without a header row.
+    pub header: Option<Header>,
+    /// Seconds added to the time of the logs (it can be negative).
+    /// It's not applied to the fallback time.
+    pub epoch_offset: i64,
+    /// Format of the input.
+    pub format: InputFormat,
+    /// Replaces the numeric and UUID segments of the paths with a placeholder.
+    pub normalize_paths: bool,
+    /// Replaces the personal data of the logs with salted hashes.
+    pub anonymizer: Option<Anonymizer>,
+    /// Decides the sections of the paths instead of their first part.
+    pub sections: Option<SectionPolicy>,
+    /// What splits the parts of the request column.
+    pub request_separator: RequestSeparator,
+    /// Name of the column holding the time of the logs, `date` by default.
+    pub time_column: Option<String>,
+    /// Section of the requests with a malformed path, [`UNKNOWN_SECTION`] by default.
+    pub unknown_section: Option<String>,
+}
+
+impl ReaderOptions {
+    /// Column names with the time column renamed to `date`, so the rest of the reading
+    /// doesn't depend on its name.
+    fn rename_time_column<'a>(&self, headers: impl Iterator<Item = &'a str>) -> StringRecord {
+        headers
+            .map(|h| match &self.time_column {
+                Some(time_column) if h == time_column => TIME_COLUMN,
+                _ => h,
+            })
+            .collect()
+    }
+
+    /// Deserializes a CSV record into an [`HttpLog`].
+    fn to_http_log(
+        &self,
+        record: StringRecord,
+        has_time_column: bool,
+        response_time: Option<&str>,
+    ) -> anyhow::Result<HttpLog> {
+        let (record, is_fallback_time) = match self.time_fallback {
+            Some(fallback) if !has_time_column => {
+                let time = fallback.time().to_string();
+                let record = record
+                    .iter()
+                    .take(TIME_INDEX)
+                    .chain(std::iter::once(time.as_str()))
+                    .chain(record.iter().skip(TIME_INDEX))
+                    .collect();
+                (record, true)
+            }
+            Some(fallback) if record.get(TIME_INDEX) == Some("") => {
+                let time = fallback.time().to_string();
+                let record = record
+                    .iter()
+                    .enumerate()
+                    .map(|(i, field)| {
+                        if i == TIME_INDEX {
+                            time.as_str()
+                        } else {
+                            field
+                        }
+                    })
+                    .collect();
+                (record, true)
+            }
+            _ => (record, false),
+        };
+        let csv_log = record.deserialize::<CsvLog>(None)?;
+        let mut log = HttpLog {
+            remote_host: csv_log.remote_host,
+            rfc931: csv_log.rfc931,
+            auth_user: csv_log.auth_user,
+            time: csv_log.time,
+            request: LogRequest::from_str_with_separator(csv_log.request, &self.request_separator)?,
+            status: csv_log.status,
+            bytes: csv_log.bytes,
+            response_time_ms: None,
+            referrer: None,
+            user_agent: None,
+        };
+        log.response_time_ms = response_time
+            .map(parse_response_time)
+            .transpose()?
+            .flatten();
+        if !is_fallback_time {
+            log.time = self.offset_time(log.time)?;
+        }
+        self.rewrite(&mut log);
+        Ok(log)
+    }
+
+    /// Rewrites the fields of a log read from any format, as asked by the options.
+    pub fn rewrite(&self, log: &mut HttpLog) {
+        if self.normalize_paths {
+            log.request.normalize_path();
+        }
+        if let Some(sections) = &self.sections {
+            log.request.section = sections.section(&log.request.path);
+        }
+        // whatever the policy, malformed paths are aggregated together
+        if is_malformed_path(&log.request.path) {
+            log.request.section = self
+                .unknown_section
+                .as_deref()
+                .unwrap_or(UNKNOWN_SECTION)
+                .to_string();
+        }
+        if let Some(anonymizer) = &self.anonymizer {
+            anonymizer.anonymize(log);
+        }
+    }
+
+    /// Time of a log from the time read, if any, applying the epoch offset or the fallback.
+    pub fn log_time(&self, time: Option<usize>) -> anyhow::Result<usize> {
+        match (time, self.time_fallback) {
+            (Some(time), _) => self.offset_time(time),
+            (None, Some(fallback)) => Ok(fallback.time()),
+            (None, None) => anyhow::bail!("Missing time"),
+        }
+    }
+
+    /// Applies the epoch offset to a time read from a log.
+    fn offset_time(&self, time: usize) -> anyhow::Result<usize> {
+        if self.epoch_offset == 0 {
+            return Ok(time);
+        }
+        (time as i64)
+            .checked_add(self.epoch_offset)
+            .and_then(|time| usize::try_from(time).ok())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The epoch offset {} moves the time {} out of range",
+                    self.epoch_offset,
+                    time
+                )
+            })
+    }
+
+    /// Extracts the time and the bytes of a record without deserializing it.
+    fn to_time_and_bytes(
+        &self,
+        record: &StringRecord,
+        columns: &TimeAndBytesColumns,
+    ) -> anyhow::Result<(usize, usize)> {
+        let field = |i: Option<usize>| i.and_then(|i| record.get(i)).map(unquote);
+        let time = match field(columns.time) {
+            Some(time) if !time.is_empty() => Some(time.parse()?),
+            _ => None,
+        };
+        let time = self.log_time(time)?;
+        let bytes = field(Some(columns.bytes)).ok_or_else(|| anyhow::anyhow!("Missing bytes"))?;
+        let bytes = parse_byte_count(bytes)?;
+        Ok((time, bytes))
+    }
+}
+
+/// Position of the columns read by [`read_time_and_bytes_async`].
+#[derive(Debug, Clone, Copy)]
+struct TimeAndBytesColumns {
+    time: Option<usize>,
+    bytes: usize,
+}
+
+impl TimeAndBytesColumns {
+    fn new(headers: &StringRecord) -> Self {
+        if headers.is_empty() {
+            return Self {
+                time: Some(TIME_INDEX),
+                bytes: COLUMNS.len() - 1,
+            };
+        }
+        let position = |column| headers.iter().position(|h| h == column);
+        Self {
+            time: position(TIME_COLUMN),
+            bytes: position("bytes").unwrap_or(COLUMNS.len() - 1),
+        }
+    }
+}
+
+/// Removes the quotes of a single field, see [`unquote_fields`].
+fn unquote(field: &str) -> &str {
+    field
+        .strip_prefix('"')
+        .and_then(|field| field.strip_suffix('"'))
+        .unwrap_or(field)
+}
+
+/// Removes the quotes the CSV parser keeps when there are spaces before the opening quote
+/// (e.g. ` "10.0.0.1"`), so those fields are read as if they were not preceded by spaces.
+/// The fields must already be trimmed.
+fn unquote_fields(record: &StringRecord) -> StringRecord {
+    record
+        .iter()
+        .map(|field| {
+            match field
+                .strip_prefix('"')
+                .and_then(|field| field.strip_suffix('"'))
+            {
+                Some(unquoted) => unquoted.replace("\"\"", "\""),
+                None => field.to_string(),
+            }
+        })
+        .collect()
+}
+
+/// Skips the UTF-8 BOM that some Windows tools add at the beginning of the files.
+pub async fn skip_bom(reader: &mut AsyncReader) -> BufReader<&mut AsyncReader> {
+    let mut reader = BufReader::new(reader);
+    match reader.fill_buf().await {
+        Ok(buf) if buf.starts_with(UTF8_BOM) => reader.consume(UTF8_BOM.len()),
+        Ok(_) => (),
+        Err(e) => tracing::error!("Error looking for a BOM: {}", e),
+    }
+    reader
+}
+
+/// Whether a file is Brotli-compressed, judging by its `.br` extension.
+pub fn is_brotli(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "br")
+}
+
+/// Whether a file is compressed, judging by its `.br`, `.gz` or `.zst` extension.
+pub fn is_compressed(path: &Path) -> bool {
+    is_brotli(path)
+        || path
+            .extension()
+            .is_some_and(|extension| extension == "gz" || extension == "zst")
+}
+
+/// Decompresses a Brotli-compressed input as it's read.
+pub fn brotli_decoder(reader: Box<AsyncReader>) -> Box<AsyncReader> {
+    Box::new(BrotliDecoder::new(BufReader::new(reader)))
+}
+
+/// Decompresses an input as it's read, judging by its extension like [`is_compressed`].
+/// Other inputs are left untouched.
+pub fn decompress(path: &Path, reader: Box<AsyncReader>) -> Box<AsyncReader> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("br") => brotli_decoder(reader),
+        Some("gz") => {
+            let mut decoder = GzipDecoder::new(BufReader::new(reader));
+            // rotated logs are often several gzip members appended
+            decoder.multiple_members(true);
+            Box::new(decoder)
+        }
+        Some("zst") => Box::new(ZstdDecoder::new(BufReader::new(reader))),
+        _ => reader,
+    }
+}
+
+/// Where a record comes from in its source. Neither the BOM nor the lines skipped
+/// for being longer than `max_record_bytes` are counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordMeta {
+    /// Line where the record starts (1-based).
+    pub line: u64,
+    /// Bytes of the record in the source.
+    pub bytes: Range<u64>,
 }
 
 /// Reads a CSV file asynchronously.
-#[instrument(skip(reader))]
 pub async fn read_csv_async(
     reader: &mut AsyncReader,
+    options: ReaderOptions,
 ) -> impl futures::Stream<Item = Result<HttpLog, anyhow::Error>> + '_ {
-    csv_async::AsyncReaderBuilder::new()
+    read_csv_with_meta_async(reader, options)
+        .await
+        .map(|record| record.map(|(log, _)| log))
+}
+
+/// Reads a CSV file asynchronously, pairing every log with where it comes from.
+#[instrument(skip(reader))]
+pub async fn read_csv_with_meta_async(
+    reader: &mut AsyncReader,
+    options: ReaderOptions,
+) -> impl futures::Stream<Item = Result<(HttpLog, RecordMeta), anyhow::Error>> + '_ {
+    let reader = RecordLimit::new(skip_bom(reader).await, options.max_record_bytes);
+    let skipped = reader.skipped();
+    let mut reported_skips = 0;
+    let mut csv_reader = csv_async::AsyncReaderBuilder::new()
         .flexible(true)
         .trim(csv_async::Trim::All)
-        .create_reader(reader)
-        .into_records()
-        .map(|record| {
-            record
-                .and_then(|r| r.deserialize::<HttpLog>(None).map(std::convert::Into::into))
-                .map_err(anyhow::Error::from)
+        .has_headers(options.header.is_none())
+        .create_reader(reader);
+    let headers = match &options.header {
+        Some(header) => header.0.iter().collect(),
+        None => match csv_reader.headers().await {
+            Ok(headers) => unquote_fields(headers),
+            Err(e) => {
+                tracing::error!("Error reading the headers: {}", e);
+                StringRecord::new()
+            }
+        },
+    };
+    // the duplicated headers are found by their original names
+    let renamed_headers = options.rename_time_column(headers.iter());
+    let header = options
+        .header
+        .is_some()
+        .then(|| Header(renamed_headers.iter().map(str::to_string).collect()));
+    let has_time_column =
+        renamed_headers.is_empty() || renamed_headers.iter().any(|h| h == TIME_COLUMN);
+    let response_time_index = headers.iter().position(|h| h == RESPONSE_TIME_COLUMN);
+    let max_record_bytes = options.max_record_bytes.unwrap_or_default();
+    // headers repeated mid-file (e.g. concatenated files) are skipped without being errors
+    let duplicated_headers = Arc::new(AtomicUsize::new(0));
+    let records = stream::unfold(csv_reader, |mut csv_reader| async move {
+        let mut record = StringRecord::new();
+        let record = match csv_reader.read_record(&mut record).await {
+            Ok(false) => return None,
+            Ok(true) => {
+                let (line, start) = record
+                    .position()
+                    .map_or((0, 0), |position| (position.line(), position.byte()));
+                let meta = RecordMeta {
+                    line,
+                    bytes: start..csv_reader.position().byte(),
+                };
+                Ok((record, meta))
+            }
+            Err(e) => Err(e),
+        };
+        Some((record, csv_reader))
+    })
+    .filter_map({
+        let duplicated_headers = duplicated_headers.clone();
+        move |record| {
+            let record = record.map(|(r, meta)| (unquote_fields(&r), meta));
+            if matches!(&record, Ok((r, _)) if !headers.is_empty() && r.iter().eq(headers.iter())) {
+                duplicated_headers.fetch_add(1, Ordering::SeqCst);
+                return future::ready(None);
+            }
+            // the response time is taken before the fields are sorted, as it's not one of them
+            let response_time = match (&record, response_time_index) {
+                (Ok((r, _)), Some(i)) => r.get(i).map(str::to_string),
+                _ => None,
+            };
+            let record = match &header {
+                Some(header) => record.map(|(r, meta)| (header.reorder(&r), meta)),
+                None => record,
+            };
+            future::ready(Some(record.map_err(anyhow::Error::from).and_then(
+                |(r, meta)| match options.to_http_log(r, has_time_column, response_time.as_deref())
+                {
+                    Ok(log) => Ok((log, meta)),
+                    Err(e) => Err(anyhow::anyhow!(
+                        "Invalid record at line {} (bytes {:?}): {}",
+                        meta.line,
+                        meta.bytes,
+                        e
+                    )),
+                },
+            )))
+        }
+    });
+    // the skipped lines are reported as errors along with the records read after them
+    records
+        .map(Some)
+        .chain(stream::once(future::ready(None)))
+        .flat_map(move |record| {
+            let skipped = skipped.load(Ordering::SeqCst);
+            let errors = (reported_skips..skipped)
+                .map(|_| {
+                    Err(anyhow::anyhow!(
+                        "Skipped a record longer than {} bytes",
+                        max_record_bytes
+                    ))
+                })
+                .collect::<Vec<_>>();
+            reported_skips = skipped;
+            if record.is_none() {
+                let duplicated_headers = duplicated_headers.load(Ordering::SeqCst);
+                if duplicated_headers > 0 {
+                    tracing::info!("Skipped {} duplicated header rows", duplicated_headers);
+                }
+            }
+            stream::iter(errors.into_iter().chain(record))
         })
 }
 
+/// Reads the time and the bytes of every record of a CSV file, skipping the rest of the
+/// fields. Much cheaper than [`read_csv_async`] when the logs are just counted.
+#[instrument(skip(reader))]
+pub async fn read_time_and_bytes_async(
+    reader: &mut AsyncReader,
+    options: ReaderOptions,
+) -> impl futures::Stream<Item = anyhow::Result<(usize, usize)>> + '_ {
+    let reader = RecordLimit::new(skip_bom(reader).await, options.max_record_bytes);
+    let skipped = reader.skipped();
+    let mut csv_reader = csv_async::AsyncReaderBuilder::new()
+        .flexible(true)
+        .trim(csv_async::Trim::All)
+        .has_headers(options.header.is_none())
+        .create_reader(reader);
+    let headers = match &options.header {
+        Some(header) => header.0.iter().collect(),
+        None => match csv_reader.headers().await {
+            Ok(headers) => unquote_fields(headers),
+            Err(e) => {
+                tracing::error!("Error reading the headers: {}", e);
+                StringRecord::new()
+            }
+        },
+    };
+    let columns = TimeAndBytesColumns::new(&options.rename_time_column(headers.iter()));
+    let max_record_bytes = options.max_record_bytes.unwrap_or_default();
+    let options = Arc::new(options);
+    // the record is reused to avoid allocating for every row
+    let records = stream::unfold(
+        (csv_reader, StringRecord::new()),
+        move |(mut csv_reader, mut record)| {
+            let options = options.clone();
+            async move {
+                loop {
+                    let result = match csv_reader.read_record(&mut record).await {
+                        Ok(false) => return None,
+                        // headers repeated mid-file (e.g. concatenated files)
+                        Ok(true)
+                            if columns.time.and_then(|i| record.get(i)).map(unquote)
+                                == Some(options.time_column.as_deref().unwrap_or(TIME_COLUMN)) =>
+                        {
+                            continue
+                        }
+                        Ok(true) => options
+                            .to_time_and_bytes(&record, &columns)
+                            .map_err(|e| anyhow::anyhow!("Invalid record: {}", e)),
+                        Err(e) => Err(e.into()),
+                    };
+                    return Some((result, (csv_reader, record)));
+                }
+            }
+        },
+    );
+    // the skipped lines are only known once the source has been read
+    records.chain(
+        stream::once(async move { skipped.load(Ordering::SeqCst) }).flat_map(move |skipped| {
+            stream::iter((0..skipped).map(move |_| {
+                Err(anyhow::anyhow!(
+                    "Skipped a record longer than {} bytes",
+                    max_record_bytes
+                ))
+            }))
+        }),
+    )
+}
+
+/// Reads several sources of the format in the options at the same time, merging their logs
+/// as they come. The merged logs are not ordered, that's up to the buffer.
+pub async fn read_many_async<'a>(
+    readers: Vec<&'a mut AsyncReader>,
+    options: ReaderOptions,
+) -> impl futures::Stream<Item = Result<HttpLog, anyhow::Error>> + 'a {
+    let log_streams = future::join_all(readers.into_iter().map(|reader| {
+        let options = options.clone();
+        async move {
+            match options.format {
+                InputFormat::Csv => read_csv_async(reader, options).await.left_stream(),
+                InputFormat::JsonArray => read_json_array_async(reader, options)
+                    .await
+                    .left_stream()
+                    .right_stream(),
+                InputFormat::Clf => read_clf_async(reader, options)
+                    .await
+                    .right_stream()
+                    .right_stream(),
+            }
+        }
+    }))
+    .await;
+    stream::select_all(log_streams.into_iter().map(Box::pin))
+}
+
+/// Reads the time and the bytes of several CSV sources at the same time.
+pub async fn read_many_time_and_bytes_async<'a>(
+    readers: Vec<&'a mut AsyncReader>,
+    options: ReaderOptions,
+) -> impl futures::Stream<Item = anyhow::Result<(usize, usize)>> + 'a {
+    let streams = future::join_all(
+        readers
+            .into_iter()
+            .map(|reader| read_time_and_bytes_async(reader, options.clone())),
+    )
+    .await;
+    stream::select_all(streams.into_iter().map(Box::pin))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{buffered_logs::BufferedLogs, processors::Stats, test_utils::run_processor};
     use futures::{FutureExt, TryStreamExt};
 
     const ERR: &'static str = "err";
@@ -115,6 +887,9 @@ mod tests {
             },
             status: 200,
             bytes: 1234,
+            response_time_ms: None,
+            referrer: None,
+            user_agent: None,
         }
     }
 
@@ -127,7 +902,7 @@ mod tests {
 "10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234"#
             .as_bytes();
 
-        let result = read_csv_async(&mut input)
+        let result = read_csv_async(&mut input, ReaderOptions::default())
             .map(|tx| tx.map_err(|_| ERR))
             .await
             .collect::<Vec<_>>()
@@ -150,7 +925,7 @@ mod tests {
   10.0.0.1  , -   ,apache, a  ,  GET /api/user HTTP/1.0 , 200 ,  1234 "#
             .as_bytes();
 
-        let result = read_csv_async(&mut input)
+        let result = read_csv_async(&mut input, ReaderOptions::default())
             .map(|tx| tx.map_err(|_| ERR))
             .await
             .collect::<Vec<_>>()
@@ -169,7 +944,7 @@ mod tests {
 "10.0.0.1","-","apache","a" ,"GET /api/user HTTP/1.0" ,200 ,1234 "#
             .as_bytes();
 
-        let result = read_csv_async(&mut input)
+        let result = read_csv_async(&mut input, ReaderOptions::default())
             .map(|tx| tx.map_err(|_| ERR))
             .await
             .collect::<Vec<_>>()
@@ -181,21 +956,22 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn reads_csv_async_does_not_work_with_untrimmed_content_with_quotes_and_front_spaces() {
+    async fn reads_csv_async_works_ok_with_untrimmed_content_with_quotes_and_front_spaces() {
         let mut input = r#"
   "remotehost" ,"rfc931"  ,"authuser"  ,"date"  ,  "request", "status",  "bytes"
   "10.0.0.1" ,  "-" ,  "apache"  ,   1549573860  , "GET /api/user HTTP/1.0"  , 200 , 1234
 "10.0.0.1","-","apache","a" ,"GET /api/user HTTP/1.0" ,200 ,1234 "#
             .as_bytes();
 
-        let result = read_csv_async(&mut input)
+        let result = read_csv_async(&mut input, ReaderOptions::default())
             .map(|tx| tx.map_err(|_| ERR))
             .await
             .collect::<Vec<_>>()
             .await;
 
         let expected = vec![Ok(build_test_http_log(1549573860)), Err(ERR)];
-        assert_ne!(result, expected)
+
+        assert_eq!(result, expected)
     }
 
     #[tokio::test]
@@ -207,7 +983,7 @@ mod tests {
 "10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234,"#
             .as_bytes();
 
-        let result = read_csv_async(&mut input)
+        let result = read_csv_async(&mut input, ReaderOptions::default())
             .map(|tx| tx.map_err(|_| ERR))
             .await
             .collect::<Vec<_>>()
@@ -221,4 +997,681 @@ mod tests {
 
         assert_eq!(result, expected);
     }
+
+    #[tokio::test]
+    async fn missing_time_is_an_error_without_fallback() {
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.1","-","apache",,"GET /api/user HTTP/1.0",200,1234"#
+            .as_bytes();
+
+        let result = read_csv_async(&mut input, ReaderOptions::default())
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result, vec![Err(ERR)]);
+    }
+
+    #[tokio::test]
+    async fn missing_time_falls_back_to_now() {
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.1","-","apache",,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234"#
+            .as_bytes();
+        let options = ReaderOptions {
+            time_fallback: Some(TimeFallback::Now),
+            ..ReaderOptions::default()
+        };
+
+        let before = TimeFallback::Now.time();
+        let result = read_csv_async(&mut input, options)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let after = TimeFallback::Now.time();
+
+        assert!(result[0].time >= before && result[0].time <= after);
+        assert_eq!(result[1], build_test_http_log(1549573860));
+    }
+
+    #[tokio::test]
+    async fn missing_time_column_falls_back_to_now() {
+        let input = r#"
+"remotehost","rfc931","authuser","request","status","bytes"
+"10.0.0.1","-","apache","GET /api/user HTTP/1.0",200,1234"#;
+        let options = ReaderOptions {
+            time_fallback: Some(TimeFallback::Now),
+            ..ReaderOptions::default()
+        };
+
+        let before = TimeFallback::Now.time();
+        let result = read_csv_async(&mut input.as_bytes(), options)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let after = TimeFallback::Now.time();
+
+        assert_eq!(result.len(), 1);
+        assert!(result[0].time >= before && result[0].time <= after);
+        assert_eq!(result[0].request, build_test_http_log(0).request);
+
+        let result = read_csv_async(&mut input.as_bytes(), ReaderOptions::default())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        assert!(result[0].is_err());
+    }
+
+    #[tokio::test]
+    async fn the_time_column_can_have_another_name() {
+        let input = r#""remotehost","rfc931","authuser","ts","request","status","bytes"
+"10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"remotehost","rfc931","authuser","ts","request","status","bytes"
+"10.0.0.1","-","apache",1549573861,"GET /api/user HTTP/1.0",200,1234"#;
+        // the fallback would be used if the time column wasn't found
+        let options = ReaderOptions {
+            time_column: Some("ts".to_string()),
+            time_fallback: Some(TimeFallback::Now),
+            ..ReaderOptions::default()
+        };
+
+        let logs = read_csv_async(&mut input.as_bytes(), options.clone())
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let times_and_bytes = read_time_and_bytes_async(&mut input.as_bytes(), options.clone())
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let headerless = input.lines().nth(1).unwrap();
+        let reordered = read_csv_async(
+            &mut headerless.as_bytes(),
+            ReaderOptions {
+                header: Some(
+                    "remotehost,rfc931,authuser,ts,request,status,bytes"
+                        .parse()
+                        .unwrap(),
+                ),
+                ..options
+            },
+        )
+        .await
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+
+        assert_eq!(
+            logs,
+            vec![
+                build_test_http_log(1549573860),
+                build_test_http_log(1549573861)
+            ]
+        );
+        assert_eq!(
+            times_and_bytes,
+            vec![(1549573860, 1234), (1549573861, 1234)]
+        );
+        assert_eq!(reordered, vec![build_test_http_log(1549573860)]);
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_skips_the_utf8_bom() {
+        let input = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache","a","GET /api/user HTTP/1.0",200,1234"#;
+        let mut bom_input = std::io::Cursor::new([UTF8_BOM, input.as_bytes()].concat());
+
+        let result = read_csv_async(&mut bom_input, ReaderOptions::default())
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        let expected = read_csv_async(&mut input.as_bytes(), ReaderOptions::default())
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result, expected);
+        assert_eq!(result, vec![Ok(build_test_http_log(1549573860)), Err(ERR)]);
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_skips_records_over_the_max_bytes() {
+        let input = format!(
+            r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573860,"GET /{} HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234"#,
+            "a".repeat(10_000)
+        );
+        let mut input = std::io::Cursor::new(input.into_bytes());
+        let options = ReaderOptions {
+            max_record_bytes: Some(200),
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let logs = result
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .cloned()
+            .collect::<Vec<_>>();
+        let errors = result.iter().filter(|r| r.is_err()).count();
+
+        assert_eq!(logs, vec![build_test_http_log(1549573860); 2]);
+        assert_eq!(errors, 1);
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_skips_duplicated_headers() {
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234"#
+            .as_bytes();
+
+        let result = read_csv_async(&mut input, ReaderOptions::default())
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let expected = vec![
+            Ok(build_test_http_log(1549573860)),
+            Ok(build_test_http_log(1549573860)),
+        ];
+
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_with_a_supplied_header() {
+        let mut input = r#"
+1549573860,"10.0.0.1","-","apache",200,1234,"GET /api/user HTTP/1.0"
+1549573861,"10.0.0.1","-","apache",200,1234,"GET /api/user HTTP/1.0""#
+            .as_bytes();
+        let options = ReaderOptions {
+            header: Some(
+                "date,remotehost,rfc931,authuser,status,bytes,request"
+                    .parse()
+                    .unwrap(),
+            ),
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async(&mut input, options)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result,
+            vec![
+                build_test_http_log(1549573860),
+                build_test_http_log(1549573861)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn supplied_header_needs_all_the_columns_but_the_time() {
+        assert!("remotehost,rfc931,authuser,request,status,bytes"
+            .parse::<Header>()
+            .is_ok());
+        assert!("remotehost,rfc931,authuser,date,request,status"
+            .parse::<Header>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_applies_the_epoch_offset() {
+        let input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.1","-","apache",1549577460,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",10,"GET /api/user HTTP/1.0",200,1234"#;
+        let options = ReaderOptions {
+            epoch_offset: -3600,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async(&mut input.as_bytes(), options.clone())
+            .await
+            .map(|tx| tx.map_err(|_| ERR))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result, vec![Ok(build_test_http_log(1549573860)), Err(ERR)]);
+
+        // the groups and the stats periods move with the offset too
+        let input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.1","-","apache",1549577460,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549577461,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.1","-","apache",1549577475,"GET /report HTTP/1.0",200,1234"#;
+        let mut input = input.as_bytes();
+        let logs = read_csv_async(&mut input, options).await;
+        let groups = BufferedLogs::new(logs, 2).collect::<Vec<_>>().await;
+        let msg = run_processor(&mut Stats::new(10), groups.clone());
+
+        let times = groups.iter().map(|group| group.time).collect::<Vec<_>>();
+        assert_eq!(times, [1549573860, 1549573861, 1549573875]);
+        // the period starts with the first offset log and elapses with the last one
+        assert_eq!(msg.matches("STATS (").count(), 1);
+        assert!(msg.contains("STATS (16s):"));
+        assert!(msg.contains("Section: /api, Total Hits: 2,"));
+        assert!(msg.contains("Section: /report, Total Hits: 1,"));
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_with_the_record_positions() {
+        let row = r#""10.0.0.1","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234"#;
+        let input = format!(
+            r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+{0}
+"10.0.0.1","-","apache","a","GET /api/user HTTP/1.0",200,1234
+{0}"#,
+            row
+        );
+        let mut reader = std::io::Cursor::new(input.clone().into_bytes());
+
+        let result = read_csv_with_meta_async(&mut reader, ReaderOptions::default())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let records = result
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .collect::<Vec<_>>();
+        let lines = records
+            .iter()
+            .map(|(_, meta)| meta.line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![3, 5]);
+        for (log, meta) in records {
+            assert_eq!(log, &build_test_http_log(1549573860));
+            let raw = &input[meta.bytes.start as usize..meta.bytes.end as usize];
+            assert_eq!(raw.trim_end(), row);
+        }
+
+        let error = result[1].as_ref().unwrap_err().to_string();
+        assert!(error.starts_with("Invalid record at line 4"));
+    }
+
+    #[tokio::test]
+    async fn validates_the_protocols() {
+        let protocol = |line: &str| {
+            LogRequest::from_str(line)
+                .unwrap()
+                .protocol_label()
+                .to_string()
+        };
+
+        assert_eq!(protocol("GET /api/user HTTP/1.0"), "HTTP/1.0");
+        assert_eq!(protocol("GET /api/user http/2"), "HTTP/2");
+        assert_eq!(protocol("GET /api/user HTPP/1.1"), UNKNOWN_PROTOCOL);
+        assert_eq!(protocol("GET /api/user \\x16\\x03"), UNKNOWN_PROTOCOL);
+    }
+
+    #[tokio::test]
+    async fn normalizes_the_id_segments_of_the_paths() {
+        let normalize = |line: &str| {
+            let mut request = LogRequest::from_str(line).unwrap();
+            request.normalize_path();
+            (request.path, request.section)
+        };
+
+        assert_eq!(
+            normalize("GET /api/user/12345 HTTP/1.0"),
+            ("/api/user/{id}".to_string(), "/api".to_string())
+        );
+        assert_eq!(
+            normalize("GET /orders/9f1c2a4e-7b3d-4e8a-9c6f-0123456789AB/items/7 HTTP/1.0"),
+            ("/orders/{id}/items/{id}".to_string(), "/orders".to_string())
+        );
+        // the section is taken from the normalized path
+        assert_eq!(
+            normalize("GET /42/report HTTP/1.0"),
+            ("/{id}/report".to_string(), "/{id}".to_string())
+        );
+        // segments which only look like IDs are kept
+        assert_eq!(
+            normalize("GET /api/v2/user42/1a2b/9f1c2a4e-7b3d HTTP/1.0"),
+            (
+                "/api/v2/user42/1a2b/9f1c2a4e-7b3d".to_string(),
+                "/api".to_string()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_normalizing_the_paths() {
+        let mut input = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user/1 HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573860,"GET /api/user/2 HTTP/1.0",200,1234"#
+            .as_bytes();
+        let options = ReaderOptions {
+            normalize_paths: true,
+            ..ReaderOptions::default()
+        };
+
+        let paths = read_csv_async(&mut input, options)
+            .await
+            .map_ok(|log| log.request.path)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(paths, vec!["/api/user/{id}", "/api/user/{id}"]);
+    }
+
+    #[tokio::test]
+    async fn the_section_policy_decides_the_sections() {
+        let input = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user/1 HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573860,"GET /report HTTP/1.0",200,1234"#;
+        let options = ReaderOptions {
+            normalize_paths: true,
+            sections: Some("depth:3".parse().unwrap()),
+            ..ReaderOptions::default()
+        };
+
+        let sections = read_csv_async(&mut std::io::Cursor::new(input), options)
+            .await
+            .map_ok(|log| log.request.section)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(sections, vec!["/api/user/{id}", "/report"]);
+    }
+
+    #[tokio::test]
+    async fn malformed_paths_land_in_the_unknown_section() {
+        let input = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573860,"GET / HTTP/1.0",200,1234
+"10.0.0.5","-","apache",1549573860,"GET /report HTTP/1.0",200,1234"#;
+
+        let sections = read_csv_async(&mut std::io::Cursor::new(input), ReaderOptions::default())
+            .await
+            .map_ok(|log| log.request.section)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut empty = crate::test_utils::build_test_http_log(1, None);
+        empty.request.path.clear();
+        let options = ReaderOptions {
+            unknown_section: Some("?".to_string()),
+            ..ReaderOptions::default()
+        };
+        options.rewrite(&mut empty);
+
+        assert_eq!(sections, vec![UNKNOWN_SECTION, "/", "/report"]);
+        assert_eq!(empty.request.section, "?");
+    }
+
+    #[tokio::test]
+    async fn missing_byte_counts_are_read_as_zero() {
+        let mut input = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",304,-
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",304,"-"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",204,
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",204,""
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,lots"#
+            .as_bytes();
+
+        let bytes = read_csv_async(&mut input, ReaderOptions::default())
+            .await
+            .map_ok(|log| log.bytes)
+            .collect::<Vec<_>>()
+            .await;
+
+        let (bytes, errors) = bytes.split_at(5);
+        assert_eq!(
+            bytes
+                .iter()
+                .map(|b| *b.as_ref().unwrap())
+                .collect::<Vec<_>>(),
+            vec![0, 0, 0, 0, 1234]
+        );
+        assert!(errors[0].is_err());
+    }
+
+    #[tokio::test]
+    async fn splits_the_request_by_the_separator() {
+        let pipe = "|".parse::<RequestSeparator>().unwrap();
+        let request = LogRequest::from_str_with_separator("GET|/api/user|HTTP/1.0", &pipe).unwrap();
+        let spaced = LogRequest::from_str_with_separator(
+            "GET  /api/user HTTP/1.0",
+            &RequestSeparator::default(),
+        )
+        .unwrap();
+
+        assert_eq!(request, spaced);
+        assert_eq!(request.section, "/api");
+        assert_eq!(
+            LogRequest::from_str_with_separator("GET|/my docs/a b|HTTP/1.0", &pipe)
+                .unwrap()
+                .path,
+            "/my docs/a b"
+        );
+        assert!(LogRequest::from_str_with_separator("GET /api/user HTTP/1.0", &pipe).is_err());
+        assert_eq!(
+            " ".parse::<RequestSeparator>().unwrap(),
+            RequestSeparator::Whitespace
+        );
+        assert!("".parse::<RequestSeparator>().is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_pipe_separated_requests() {
+        let mut input = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET|/api/user|HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573861,"POST|/report/daily|HTTP/1.1",500,1307"#
+            .as_bytes();
+        let mut expected = r#""remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",1549573861,"POST /report/daily HTTP/1.1",500,1307"#
+            .as_bytes();
+        let options = ReaderOptions {
+            request_separator: RequestSeparator::Custom("|".to_string()),
+            ..ReaderOptions::default()
+        };
+
+        let logs = read_csv_async(&mut input, options)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let expected = read_csv_async(&mut expected, ReaderOptions::default())
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(logs, expected);
+        assert_eq!(logs[1].request.section, "/report");
+    }
+
+    #[tokio::test]
+    async fn reads_the_optional_response_time_column() {
+        let mut input =
+            r#""remotehost","rfc931","authuser","date","request","status","bytes","responsetime"
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234,35
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234,-
+"10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234,slow"#
+                .as_bytes();
+        let mut without_header =
+            "1549573860,\"GET /api/user HTTP/1.0\",200,1234,\"10.0.0.2\",\"-\",\"apache\",120"
+                .as_bytes();
+        let options = ReaderOptions {
+            header: Some(
+                "date,request,status,bytes,remotehost,rfc931,authuser,responsetime"
+                    .parse()
+                    .unwrap(),
+            ),
+            ..ReaderOptions::default()
+        };
+
+        let logs = read_csv_async(&mut input, ReaderOptions::default())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        let reordered = read_csv_async(&mut without_header, options)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(logs[0].as_ref().unwrap().response_time_ms, Some(35));
+        assert_eq!(logs[1].as_ref().unwrap().response_time_ms, None);
+        assert!(logs[2].is_err());
+        assert_eq!(reordered[0].response_time_ms, Some(120));
+        assert_eq!(reordered[0].remote_host, "10.0.0.2");
+    }
+
+    #[tokio::test]
+    async fn crlf_and_mixed_line_endings_parse_like_lf() {
+        let rows = [
+            r#""remotehost","rfc931","authuser","date","request","status","bytes""#,
+            r#""10.0.0.2","-","apache",1549573860,"GET /api/user HTTP/1.0",200,1234"#,
+            r#"10.0.0.4,-,apache,1549573861,GET /report HTTP/1.0,500,1307"#,
+            r#""10.0.0.4","-","apache",1549573862,"GET /api/help HTTP/1.0",200,"1234""#,
+        ];
+        let longest_row = rows.iter().map(|row| row.len()).max().unwrap();
+        async fn read(
+            input: String,
+            max_record_bytes: Option<usize>,
+        ) -> Vec<Result<HttpLog, String>> {
+            let mut input = std::io::Cursor::new(input);
+            let options = ReaderOptions {
+                max_record_bytes,
+                ..ReaderOptions::default()
+            };
+            read_csv_async(&mut input, options)
+                .await
+                .map_err(|e| e.to_string())
+                .collect()
+                .await
+        }
+
+        for max_record_bytes in [None, Some(longest_row)] {
+            let expected = read(rows.join("\n"), max_record_bytes).await;
+            let crlf = read(rows.join("\r\n") + "\r\n", max_record_bytes).await;
+            let mixed = read(
+                format!("{}\r\n{}\n{}\r{}\r\n", rows[0], rows[1], rows[2], rows[3]),
+                max_record_bytes,
+            )
+            .await;
+
+            assert_eq!(expected.len(), 3);
+            assert!(expected.iter().all(Result::is_ok), "{:?}", expected);
+            assert_eq!(crlf, expected);
+            assert_eq!(mixed, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn brotli_input_gives_the_same_groups_as_the_plain_one() {
+        use crate::{
+            buffered_logs::GroupedHttpLogs,
+            process::{process_logs, ProcessOptions},
+        };
+        use async_compression::tokio::bufread::BrotliEncoder;
+        use std::sync::Mutex;
+        use tokio::io::AsyncReadExt;
+
+        async fn groups(mut reader: Box<AsyncReader>) -> Vec<GroupedHttpLogs> {
+            let groups = Arc::new(Mutex::new(Vec::new()));
+            let groups_clone = groups.clone();
+            let mut mock_processor = crate::processors::MockProcessor::new();
+            mock_processor
+                .expect_next_interesting_time()
+                .return_const(None);
+            mock_processor
+                .expect_process()
+                .returning(move |log_group, _| {
+                    groups_clone.lock().unwrap().push(log_group.clone());
+                    Ok(())
+                });
+            mock_processor.expect_finalize().returning(|_| Ok(()));
+            process_logs(
+                vec![reader.as_mut()],
+                vec![Box::new(mock_processor)],
+                Vec::new(),
+                &ProcessOptions::default(),
+            )
+            .await
+            .unwrap();
+            let groups = groups.lock().unwrap().clone();
+            groups
+        }
+        let sample = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/sample.csv")).unwrap();
+        let mut compressed = Vec::new();
+        BrotliEncoder::new(sample.as_slice())
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+
+        let plain = groups(Box::new(std::io::Cursor::new(sample))).await;
+        let brotli = groups(brotli_decoder(Box::new(std::io::Cursor::new(compressed)))).await;
+
+        assert!(!plain.is_empty());
+        assert_eq!(brotli, plain);
+        assert!(is_brotli(Path::new("logs/access.csv.br")));
+        assert!(!is_brotli(Path::new("logs/access.csv")));
+    }
+
+    #[tokio::test]
+    async fn detects_the_compression_by_extension() {
+        use async_compression::tokio::bufread::{GzipEncoder, ZstdEncoder};
+        use tokio::io::AsyncReadExt;
+
+        let sample = b"remotehost,date\n10.0.0.1,1549573860\n";
+        let mut gzip = Vec::new();
+        GzipEncoder::new(&sample[..])
+            .read_to_end(&mut gzip)
+            .await
+            .unwrap();
+        // a second member, as in concatenated rotated logs
+        gzip.extend_from_slice(&gzip.clone());
+        let mut zstd = Vec::new();
+        ZstdEncoder::new(&sample[..])
+            .read_to_end(&mut zstd)
+            .await
+            .unwrap();
+
+        for (path, input, expected) in [
+            ("access.csv.gz", gzip, sample.repeat(2)),
+            ("access.csv.zst", zstd, sample.to_vec()),
+            ("access.csv", sample.to_vec(), sample.to_vec()),
+        ] {
+            let mut decompressed = Vec::new();
+            decompress(Path::new(path), Box::new(std::io::Cursor::new(input)))
+                .read_to_end(&mut decompressed)
+                .await
+                .unwrap();
+            assert_eq!(decompressed, expected, "{}", path);
+        }
+        assert!(is_compressed(Path::new("logs/access.csv.zst")));
+        assert!(!is_compressed(Path::new("logs/access.csv")));
+    }
 }
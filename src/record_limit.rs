@@ -0,0 +1,128 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+/// Whether the byte ends a line. As in the CSV reader, lines end with `\n`, `\r` or `\r\n`
+/// (seen as a line followed by an empty one), so files with Windows or mixed line endings
+/// have the same limits.
+pub fn is_line_end(byte: &u8) -> bool {
+    matches!(byte, b'\n' | b'\r')
+}
+
+/// Skips the lines longer than a given amount of bytes without buffering them whole,
+/// so a corrupted file with a missing newline can't exhaust the memory.
+#[derive(Debug)]
+pub struct RecordLimit<R> {
+    inner: R,
+    max_bytes: Option<usize>,
+    // line being accumulated until its newline is found
+    line: Vec<u8>,
+    // amount of bytes of a complete line already handed out
+    pos: usize,
+    is_line_complete: bool,
+    is_discarding: bool,
+    skipped: Arc<AtomicUsize>,
+}
+
+impl<R> RecordLimit<R>
+where
+    R: AsyncBufRead + Unpin,
+{
+    /// Without `max_bytes`, the reader is left untouched.
+    pub fn new(inner: R, max_bytes: Option<usize>) -> Self {
+        Self {
+            inner,
+            max_bytes,
+            line: Vec::new(),
+            pos: 0,
+            is_line_complete: false,
+            is_discarding: false,
+            skipped: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Amount of lines skipped so far.
+    pub fn skipped(&self) -> Arc<AtomicUsize> {
+        self.skipped.clone()
+    }
+
+    fn skip_line(&mut self, max_bytes: usize) {
+        tracing::error!("Skipping a record longer than {} bytes", max_bytes);
+        self.line.clear();
+        self.skipped.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl<R> AsyncRead for RecordLimit<R>
+where
+    R: AsyncBufRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let max_bytes = match this.max_bytes {
+            Some(max_bytes) => max_bytes,
+            None => return Pin::new(&mut this.inner).poll_read(cx, buf),
+        };
+        loop {
+            if this.is_line_complete {
+                let len = buf.remaining().min(this.line.len() - this.pos);
+                buf.put_slice(&this.line[this.pos..this.pos + len]);
+                this.pos += len;
+                if this.pos == this.line.len() {
+                    this.line.clear();
+                    this.pos = 0;
+                    this.is_line_complete = false;
+                }
+                return Poll::Ready(Ok(()));
+            }
+
+            let available = match Pin::new(&mut this.inner).poll_fill_buf(cx) {
+                Poll::Ready(Ok(available)) => available,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if available.is_empty() {
+                // end of the input, handing out the last line if it has no newline
+                this.is_discarding = false;
+                if this.line.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+                this.is_line_complete = true;
+                continue;
+            }
+
+            let (chunk_len, has_newline) = match available.iter().position(is_line_end) {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            let content_len = if has_newline {
+                chunk_len - 1
+            } else {
+                chunk_len
+            };
+
+            if this.is_discarding {
+                this.is_discarding = !has_newline;
+            } else if this.line.len() + content_len > max_bytes {
+                this.skip_line(max_bytes);
+                this.is_discarding = !has_newline;
+            } else {
+                this.line.extend_from_slice(&available[..chunk_len]);
+                this.is_line_complete = has_newline;
+            }
+            Pin::new(&mut this.inner).consume(chunk_len);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use crate::buffered_logs::GroupedHttpLogs;
+use futures::{Stream, StreamExt};
+use std::time::Duration;
+
+/// Emits the groups paced by the time between them, `speed` times faster,
+/// so historical logs are processed as if they were live.
+pub fn replay<St>(stream: St, speed: usize) -> impl Stream<Item = GroupedHttpLogs>
+where
+    St: Stream<Item = GroupedHttpLogs>,
+{
+    let speed = speed.max(1) as f64;
+    let mut last_time = None;
+    stream.then(move |group| {
+        let elapsed_secs = last_time.map_or(0, |last_time| group.time.saturating_sub(last_time));
+        last_time = Some(group.time);
+        async move {
+            tokio::time::sleep(Duration::from_secs_f64(elapsed_secs as f64 / speed)).await;
+            group
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::build_test_http_grouped_log;
+    use futures::stream;
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn groups_are_paced_by_their_time() {
+        let groups = vec![
+            build_test_http_grouped_log(10, 1, None),
+            build_test_http_grouped_log(12, 1, None),
+            build_test_http_grouped_log(15, 1, None),
+        ];
+        let start = Instant::now();
+
+        let replayed = replay(stream::iter(groups.clone()), 100)
+            .collect::<Vec<_>>()
+            .await;
+
+        // 5 seconds of logs at 100x
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(replayed, groups);
+    }
+}
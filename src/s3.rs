@@ -0,0 +1,123 @@
+use crate::reader::AsyncReader;
+use futures::StreamExt;
+use object_store::{aws::AmazonS3Builder, path::Path, ObjectStore};
+use std::io;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::StreamReader;
+
+/// Chunks of the object downloaded ahead of the reader.
+const CHUNKS_IN_FLIGHT: usize = 16;
+
+/// Bucket and key of an `s3://bucket/key` URL.
+fn parse_url(url: &str) -> anyhow::Result<(&str, &str)> {
+    match url
+        .strip_prefix("s3://")
+        .and_then(|rest| rest.split_once('/'))
+    {
+        Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => Ok((bucket, key)),
+        _ => Err(anyhow::anyhow!(
+            "Invalid S3 URL: {}. Use s3://bucket/key",
+            url
+        )),
+    }
+}
+
+/// Streams an object of S3, e.g. `s3://bucket/logs/access.csv`. The credentials and the
+/// region are taken from the standard AWS environment variables, falling back to the web
+/// identity, container (ECS/EKS) or instance (IMDS) credentials. The profiles of
+/// `~/.aws/config` and `~/.aws/credentials` aren't read, so `AWS_PROFILE` is ignored.
+pub async fn open(url: &str) -> anyhow::Result<Box<AsyncReader>> {
+    let (bucket, key) = parse_url(url)?;
+    let has_keys = std::env::var_os("AWS_ACCESS_KEY_ID").is_some();
+    if std::env::var_os("AWS_PROFILE").is_some() && !has_keys {
+        tracing::warn!(
+            "AWS_PROFILE is ignored, export the credentials of the profile to read {} with them",
+            url
+        );
+    }
+    let store = AmazonS3Builder::from_env()
+        .with_bucket_name(bucket)
+        .build()
+        .map_err(|e| anyhow::anyhow!("Can't connect to S3 to read {}: {}", url, e))?;
+    open_object(&store, key).await
+}
+
+/// Streams the body of an object. The download goes on in another task, so the reader
+/// doesn't need to be `Sync`.
+async fn open_object(store: &dyn ObjectStore, key: &str) -> anyhow::Result<Box<AsyncReader>> {
+    let mut body = store
+        .get(&Path::from(key))
+        .await
+        .map_err(|e| anyhow::anyhow!("Can't read the object {}: {}", key, e))?
+        .into_stream();
+    let (tx, rx) = tokio::sync::mpsc::channel(CHUNKS_IN_FLIGHT);
+    tokio::spawn(async move {
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(io::Error::other);
+            let is_error = chunk.is_err();
+            // the reader is gone or the download failed
+            if tx.send(chunk).await.is_err() || is_error {
+                break;
+            }
+        }
+    });
+    Ok(Box::new(StreamReader::new(ReceiverStream::new(rx))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{decompress, read_csv_async, ReaderOptions};
+    use async_compression::tokio::bufread::GzipEncoder;
+    use futures::TryStreamExt;
+    use object_store::memory::InMemory;
+    use tokio::io::AsyncReadExt;
+
+    const CSV: &str =
+        "\"remotehost\",\"rfc931\",\"authuser\",\"date\",\"request\",\"status\",\"bytes\"
+\"10.0.0.2\",\"-\",\"apache\",1549573860,\"GET /api/user HTTP/1.0\",200,1234
+\"10.0.0.4\",\"-\",\"apache\",1549573861,\"GET /report HTTP/1.0\",200,1136";
+
+    #[tokio::test]
+    async fn streams_the_objects() {
+        let store = InMemory::new();
+        let mut compressed = Vec::new();
+        GzipEncoder::new(CSV.as_bytes())
+            .read_to_end(&mut compressed)
+            .await
+            .unwrap();
+        store
+            .put(&Path::from("logs/access.csv"), CSV.into())
+            .await
+            .unwrap();
+        store
+            .put(&Path::from("logs/access.csv.gz"), compressed.into())
+            .await
+            .unwrap();
+
+        for key in ["logs/access.csv", "logs/access.csv.gz"] {
+            let object = open_object(&store, key).await.unwrap();
+            let mut reader = decompress(std::path::Path::new(key), object);
+            let logs = read_csv_async(reader.as_mut(), ReaderOptions::default())
+                .await
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+
+            assert_eq!(logs.len(), 2);
+            assert_eq!(logs[1].request.section, "/report");
+        }
+        assert!(open_object(&store, "logs/missing.csv").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn parses_the_urls() {
+        assert_eq!(
+            parse_url("s3://bucket/logs/access.csv").unwrap(),
+            ("bucket", "logs/access.csv")
+        );
+        assert!(parse_url("s3://bucket").is_err());
+        assert!(parse_url("s3:///access.csv").is_err());
+        assert!(parse_url("https://bucket/access.csv").is_err());
+    }
+}
@@ -0,0 +1,66 @@
+use crate::buffered_logs::LogResult;
+use futures::{Stream, StreamExt};
+use std::io::Write;
+use tracing::instrument;
+
+/// Writes the first `len` records of a stream as they were parsed, so a column mapped to
+/// the wrong field shows up at a glance. The records which can't be parsed are written
+/// with their error.
+#[instrument(skip(logs, writer))]
+pub async fn dry_parse_sample(
+    logs: impl Stream<Item = LogResult>,
+    len: usize,
+    writer: &mut dyn Write,
+) -> anyhow::Result<()> {
+    let mut logs = Box::pin(logs.take(len).enumerate());
+    while let Some((i, log)) = logs.next().await {
+        match log {
+            Ok(log) => writeln!(writer, "Record {}:\n{:#?}", i + 1, log)?,
+            Err(e) => writeln!(writer, "Record {}: can't be parsed: {:#}", i + 1, e)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{read_csv_async, HttpLog, LogRequest, ReaderOptions};
+
+    #[tokio::test]
+    async fn writes_the_first_records_as_parsed() {
+        let csv =
+            "\"remotehost\",\"rfc931\",\"authuser\",\"date\",\"request\",\"status\",\"bytes\"\n\
+            \"10.0.0.2\",\"-\",\"apache\",1549573860,\"GET /api/user HTTP/1.0\",200,1234\n\
+            \"10.0.0.4\",\"-\",\"apache\",1549573861,\"POST /report HTTP/1.0\",oops,12\n\
+            \"10.0.0.5\",\"-\",\"apache\",1549573862,\"GET /api/help HTTP/1.0\",200,12\n";
+        let mut reader = csv.as_bytes();
+        let logs = read_csv_async(&mut reader, ReaderOptions::default()).await;
+        let mut output = Vec::new();
+
+        dry_parse_sample(logs, 2, &mut output).await.unwrap();
+
+        let expected = HttpLog {
+            remote_host: "10.0.0.2".to_string(),
+            rfc931: "-".to_string(),
+            auth_user: "apache".to_string(),
+            time: 1549573860,
+            request: LogRequest {
+                verb: "GET".to_string(),
+                path: "/api/user".to_string(),
+                section: "/api".to_string(),
+                protocol: "HTTP/1.0".to_string(),
+            },
+            status: 200,
+            bytes: 1234,
+            response_time_ms: None,
+            referrer: None,
+            user_agent: None,
+        };
+        let output = String::from_utf8(output).unwrap();
+        let (first, second) = output.split_once("\nRecord 2: ").unwrap();
+        assert_eq!(first, format!("Record 1:\n{:#?}", expected));
+        assert!(second.starts_with("can't be parsed: "));
+        assert!(!output.contains("10.0.0.5"));
+    }
+}
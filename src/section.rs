@@ -0,0 +1,141 @@
+use regex::Regex;
+use std::{fmt, str::FromStr, sync::Arc};
+
+/// Section of the requests with a malformed path, unless another one is configured.
+pub const UNKNOWN_SECTION: &str = "<unknown>";
+
+/// Whether the path can't yield a valid section: it's empty or doesn't start with a slash
+/// (e.g. `api/user` or `*`). Every valid path, even `/`, starts with one.
+pub fn is_malformed_path(path: &str) -> bool {
+    !path.starts_with('/')
+}
+
+/// Decides the section of a request from its path.
+pub trait SectionExtractor: fmt::Debug + Send + Sync {
+    fn section(&self, path: &str) -> String;
+}
+
+/// First part of the path (e.g. `/api` for `/api/user`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FirstSegment;
+
+impl SectionExtractor for FirstSegment {
+    fn section(&self, path: &str) -> String {
+        Depth(1).section(path)
+    }
+}
+
+/// First N parts of the path (e.g. `/api/user` for `/api/user/1` with a depth of 2).
+/// Shorter paths are sections by themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Depth(pub usize);
+
+impl SectionExtractor for Depth {
+    fn section(&self, path: &str) -> String {
+        let end = path
+            .char_indices()
+            .filter(|(i, c)| *i > 0 && *c == '/')
+            .map(|(i, _)| i)
+            .nth(self.0.saturating_sub(1))
+            .unwrap_or(path.len());
+        path[..end].to_string()
+    }
+}
+
+/// First capture group of a regex matching the path, or the whole match if it has no groups.
+/// The paths it doesn't match fall back to their [`FirstSegment`].
+#[derive(Debug, Clone)]
+pub struct RegexSection(pub Regex);
+
+impl SectionExtractor for RegexSection {
+    fn section(&self, path: &str) -> String {
+        match self.0.captures(path) {
+            Some(captures) => captures
+                .get(1)
+                .or_else(|| captures.get(0))
+                .map(|m| m.as_str().to_string())
+                .unwrap_or_default(),
+            None => FirstSegment.section(path),
+        }
+    }
+}
+
+/// Section extractor chosen at startup: `first`, `depth:N` or `regex:PATTERN`.
+#[derive(Debug, Clone)]
+pub struct SectionPolicy(Arc<dyn SectionExtractor>);
+
+impl SectionPolicy {
+    pub fn new(extractor: impl SectionExtractor + 'static) -> Self {
+        Self(Arc::new(extractor))
+    }
+
+    pub fn section(&self, path: &str) -> String {
+        self.0.section(path)
+    }
+}
+
+/// Policies are only equal to their clones.
+impl PartialEq for SectionPolicy {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SectionPolicy {}
+
+impl FromStr for SectionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, arg) = s.split_once(':').unwrap_or((s, ""));
+        match kind {
+            "first" if arg.is_empty() => Ok(Self::new(FirstSegment)),
+            "depth" => match arg.parse() {
+                Ok(depth) if depth > 0 => Ok(Self::new(Depth(depth))),
+                _ => anyhow::bail!("Invalid section depth: {}. Use a number above 0", arg),
+            },
+            "regex" => Regex::new(arg)
+                .map(|regex| Self::new(RegexSection(regex)))
+                .map_err(|e| anyhow::anyhow!("Invalid section regex {}: {}", arg, e)),
+            _ => anyhow::bail!(
+                "Invalid section policy: {}. Use first, depth:N or regex:PATTERN",
+                s
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATH: &str = "/api/user/42/orders";
+
+    fn section(policy: &str, path: &str) -> String {
+        policy.parse::<SectionPolicy>().unwrap().section(path)
+    }
+
+    #[tokio::test]
+    async fn every_extractor_gets_its_section_of_the_same_path() {
+        assert_eq!(FirstSegment.section(PATH), "/api");
+        assert_eq!(Depth(1).section(PATH), "/api");
+        assert_eq!(Depth(3).section(PATH), "/api/user/42");
+        assert_eq!(Depth(10).section(PATH), PATH);
+        let with_group = RegexSection(Regex::new(r"^(/api/\w+)/\d+").unwrap());
+        let without_group = RegexSection(Regex::new(r"^/\w+/\w+").unwrap());
+        assert_eq!(with_group.section(PATH), "/api/user");
+        assert_eq!(without_group.section(PATH), "/api/user");
+        // unmatched paths fall back to the first segment
+        assert_eq!(with_group.section("/report/1"), "/report");
+    }
+
+    #[tokio::test]
+    async fn parses_the_section_policies() {
+        assert_eq!(section("first", PATH), "/api");
+        assert_eq!(section("depth:2", PATH), "/api/user");
+        assert_eq!(section(r"regex:^/api/(\w+)", PATH), "user");
+        for invalid in ["first:2", "depth", "depth:0", "depth:a", "regex:(", "last"] {
+            assert!(invalid.parse::<SectionPolicy>().is_err(), "{}", invalid);
+        }
+    }
+}
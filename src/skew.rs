@@ -0,0 +1,111 @@
+use crate::buffered_logs::LogResult;
+use futures::{Stream, StreamExt};
+use std::fmt;
+use tracing::instrument;
+
+/// Out-of-orderness of the timestamps of a log stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SkewReport {
+    /// Amount of correctly parsed records.
+    pub records: usize,
+    /// Amount of records older than a previously seen one.
+    pub out_of_order: usize,
+    /// Max skew in seconds.
+    pub max: usize,
+    /// Median skew in seconds.
+    pub median: usize,
+    /// 99th percentile skew in seconds.
+    pub p99: usize,
+}
+
+impl SkewReport {
+    fn from_skews(mut skews: Vec<usize>) -> Self {
+        skews.sort_unstable();
+        Self {
+            records: skews.len(),
+            out_of_order: skews.iter().filter(|s| **s > 0).count(),
+            max: skews.last().copied().unwrap_or_default(),
+            median: percentile(&skews, 50),
+            p99: percentile(&skews, 99),
+        }
+    }
+}
+
+impl fmt::Display for SkewReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Skew analysis - records: {}, out of order: {}, max: {}s, median: {}s, p99: {}s",
+            self.records, self.out_of_order, self.max, self.median, self.p99
+        )
+    }
+}
+
+/// Nearest-rank percentile of an already sorted slice.
+fn percentile(sorted: &[usize], p: usize) -> usize {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p * sorted.len()).div_ceil(100);
+    sorted[rank.saturating_sub(1)]
+}
+
+/// Measures how far behind the running max time every record of the stream is.
+/// The max skew is the minimum `--buffer-seconds` needed to get the logs in order.
+#[instrument(skip(log_stream))]
+pub async fn analyze_skew(log_stream: impl Stream<Item = LogResult>) -> SkewReport {
+    let mut running_max = None;
+    let skews = log_stream
+        .filter_map(|log| async move {
+            log.map_err(|e| tracing::error!("Error analyzing skew: {}", e))
+                .ok()
+        })
+        .map(|log| {
+            let max = running_max.get_or_insert(log.time);
+            *max = log.time.max(*max);
+            *max - log.time
+        })
+        .collect::<Vec<_>>()
+        .await;
+    SkewReport::from_skews(skews)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{read_csv_async, ReaderOptions};
+
+    #[tokio::test]
+    async fn reports_the_skew_of_an_out_of_order_sequence() {
+        let mut input = r#"
+"remotehost","rfc931","authuser","date","request","status","bytes"
+"10.0.0.2","-","apache",10,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",12,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.4","-","apache",11,"GET /api/user HTTP/1.0",200,1234
+"10.0.0.2","-","apache",15,"GET /api/help HTTP/1.0",200,1234
+"10.0.0.5","-","apache","a","GET /api/help HTTP/1.0",200,1234
+"10.0.0.4","-","apache",9,"GET /api/help HTTP/1.0",200,1234
+"10.0.0.5","-","apache",16,"POST /report HTTP/1.0",500,1307"#
+            .as_bytes();
+
+        let report = analyze_skew(read_csv_async(&mut input, ReaderOptions::default()).await).await;
+
+        assert_eq!(
+            report,
+            SkewReport {
+                records: 6,
+                out_of_order: 2,
+                max: 6,
+                median: 0,
+                p99: 6,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn ordered_input_has_no_skew() {
+        let report = SkewReport::from_skews(vec![0; 10]);
+        assert_eq!(report.max, 0);
+        assert_eq!(report.out_of_order, 0);
+    }
+}
@@ -0,0 +1,100 @@
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StatusRule {
+    /// A whole class of status codes, e.g. `4xx`.
+    Class(u16),
+    /// A single status code, e.g. `404`.
+    Code(u16),
+}
+
+impl StatusRule {
+    fn matches(self, status: u16) -> bool {
+        match self {
+            Self::Class(class) => status / 100 == class,
+            Self::Code(code) => status == code,
+        }
+    }
+}
+
+/// Matches status codes against a comma separated list of classes and codes (e.g. `4xx,5xx,304`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusMatcher {
+    rules: Vec<StatusRule>,
+}
+
+impl StatusMatcher {
+    /// Client and server errors (`4xx,5xx`).
+    pub fn errors() -> Self {
+        Self {
+            rules: vec![StatusRule::Class(4), StatusRule::Class(5)],
+        }
+    }
+
+    /// Server errors (`5xx`).
+    pub fn server_errors() -> Self {
+        Self {
+            rules: vec![StatusRule::Class(5)],
+        }
+    }
+
+    pub fn matches(&self, status: u16) -> bool {
+        self.rules.iter().any(|rule| rule.matches(status))
+    }
+}
+
+impl FromStr for StatusMatcher {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rules = s
+            .split(',')
+            .map(str::trim)
+            .filter(|rule| !rule.is_empty())
+            .map(|rule| {
+                let lowercase = rule.to_lowercase();
+                let parsed = match lowercase.strip_suffix("xx") {
+                    Some(class) => class.parse().ok().map(StatusRule::Class),
+                    None => lowercase.parse().ok().map(StatusRule::Code),
+                };
+                parsed.ok_or_else(|| anyhow::anyhow!("Invalid status rule: {}", rule))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if rules.is_empty() {
+            anyhow::bail!("Invalid status spec, no rules found: {}", s);
+        }
+        Ok(Self { rules })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn matches_classes_and_codes() {
+        let matcher = "4xx, 5XX,304".parse::<StatusMatcher>().unwrap();
+        assert!(matcher.matches(404));
+        assert!(matcher.matches(503));
+        assert!(matcher.matches(304));
+        assert!(!matcher.matches(200));
+        assert!(!matcher.matches(301));
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_the_5xx() {
+        let matcher = StatusMatcher::server_errors();
+        assert_eq!(matcher, "5xx".parse().unwrap());
+        assert!(matcher.matches(500));
+        assert!(matcher.matches(599));
+        assert!(!matcher.matches(404));
+        assert!(!matcher.matches(600));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_specs() {
+        assert!("4xx,abc".parse::<StatusMatcher>().is_err());
+        assert!("".parse::<StatusMatcher>().is_err());
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{reader::AsyncReader, record_limit::is_line_end};
+use std::{collections::VecDeque, io::SeekFrom};
+use tokio::{
+    fs::File,
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, BufReader},
+};
+use tracing::instrument;
+
+/// Size of the blocks read backwards looking for the last lines of a file.
+const BLOCK_SIZE: u64 = 64 * 1024;
+
+/// Reads the next line into `line`, its line end included, returning the amount of bytes
+/// read (0 at the end of the input). Lines end like in the CSV reader (see [`is_line_end`]),
+/// but a `\r\n` ends a single line here. The lines longer than `max_bytes` are read but not
+/// kept, leaving `line` empty.
+async fn read_line(
+    reader: &mut (impl AsyncBufRead + Unpin),
+    line: &mut Vec<u8>,
+    max_bytes: Option<usize>,
+) -> std::io::Result<usize> {
+    let mut read = 0;
+    let mut is_skipped = false;
+    let mut is_after_cr = false;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            break;
+        }
+        if is_after_cr {
+            // the `\n` of a `\r\n` may come in the next chunk
+            if available[0] == b'\n' {
+                if !is_skipped {
+                    line.push(b'\n');
+                }
+                reader.consume(1);
+                read += 1;
+            }
+            break;
+        }
+        let (len, line_end) = match available.iter().position(is_line_end) {
+            Some(i) => (i + 1, Some(available[i])),
+            None => (available.len(), None),
+        };
+        let content_len = len - line_end.map_or(0, |_| 1);
+        if !is_skipped && max_bytes.is_some_and(|max| line.len() + content_len > max) {
+            tracing::error!(
+                "Skipping a record longer than {} bytes",
+                max_bytes.unwrap_or_default()
+            );
+            line.clear();
+            is_skipped = true;
+        }
+        if !is_skipped {
+            line.extend_from_slice(&available[..len]);
+        }
+        reader.consume(len);
+        read += len;
+        match line_end {
+            Some(b'\r') => is_after_cr = true,
+            Some(_) => break,
+            None => (),
+        }
+    }
+    Ok(read)
+}
+
+/// Reader of the header (if any) and the last `lines` lines of a file.
+/// The file is read backwards by blocks, so only its tail is read. These are physical
+/// lines, not CSV records: a quoted field spanning several lines counts as several.
+#[instrument(skip(file))]
+pub async fn tail_file(
+    file: File,
+    lines: usize,
+    has_header: bool,
+) -> anyhow::Result<Box<AsyncReader>> {
+    tail_file_by_blocks(file, lines, has_header, BLOCK_SIZE).await
+}
+
+async fn tail_file_by_blocks(
+    file: File,
+    lines: usize,
+    has_header: bool,
+    block_size: u64,
+) -> anyhow::Result<Box<AsyncReader>> {
+    let mut file = BufReader::new(file);
+    let mut header = Vec::new();
+    if has_header {
+        read_line(&mut file, &mut header, None).await?;
+    }
+    let mut file = file.into_inner();
+    let offset = tail_offset(&mut file, header.len() as u64, lines, block_size).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+    Ok(Box::new(std::io::Cursor::new(header).chain(file)))
+}
+
+/// Offset where the last `lines` lines after `start` begin, with the line ends of [`read_line`].
+async fn tail_offset(
+    file: &mut File,
+    start: u64,
+    lines: usize,
+    block_size: u64,
+) -> anyhow::Result<u64> {
+    let len = file.metadata().await?.len();
+    if lines == 0 {
+        return Ok(len);
+    }
+    let mut block = vec![0; block_size as usize];
+    let mut line_ends = 0;
+    let mut end = len;
+    // the byte after the one being checked, which may be in the previous block
+    let mut next = None;
+    while end > start {
+        let block_start = end.saturating_sub(block_size).max(start);
+        let block = &mut block[..(end - block_start) as usize];
+        file.seek(SeekFrom::Start(block_start)).await?;
+        file.read_exact(block).await?;
+        for (i, byte) in block.iter().enumerate().rev() {
+            let pos = block_start + i as u64;
+            let is_crlf = *byte == b'\r' && next == Some(b'\n');
+            next = Some(*byte);
+            // the line end ending the file doesn't start another line
+            if !is_line_end(byte) || is_crlf || pos == len - 1 {
+                continue;
+            }
+            line_ends += 1;
+            if line_ends == lines {
+                return Ok(pos + 1);
+            }
+        }
+        end = block_start;
+    }
+    Ok(start)
+}
+
+/// Reader of the header (if any) and the last `lines` lines of a stream, physical lines
+/// as in [`tail_file`]. Only those lines are kept while the stream is read, skipping the ones longer than
+/// `max_bytes` so a missing line end can't exhaust the memory.
+#[instrument(skip(reader))]
+pub async fn tail_stream(
+    reader: &mut AsyncReader,
+    lines: usize,
+    has_header: bool,
+    max_bytes: Option<usize>,
+) -> anyhow::Result<Box<AsyncReader>> {
+    let mut reader = BufReader::new(reader);
+    let mut header = Vec::new();
+    if has_header {
+        read_line(&mut reader, &mut header, None).await?;
+    }
+    let mut tail = VecDeque::new();
+    loop {
+        let mut line = Vec::new();
+        if read_line(&mut reader, &mut line, max_bytes).await? == 0 {
+            break;
+        }
+        if line.is_empty() {
+            continue;
+        }
+        tail.push_back(line);
+        if tail.len() > lines {
+            tail.pop_front();
+        }
+    }
+    header.extend(tail.into_iter().flatten());
+    Ok(Box::new(std::io::Cursor::new(header)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        process::{process_logs, ProcessOptions},
+        processors::MockProcessor,
+    };
+    use std::sync::{Arc, Mutex};
+
+    const HEADER: &str = r#""remotehost","rfc931","authuser","date","request","status","bytes""#;
+
+    fn build_input(records: usize) -> String {
+        let mut input = format!("{}\n", HEADER);
+        for time in 0..records {
+            input.push_str(&format!(
+                "\"10.0.0.2\",\"-\",\"apache\",{},\"GET /api/user HTTP/1.0\",200,1234\n",
+                1549573860 + time
+            ));
+        }
+        input
+    }
+
+    /// Times of the logs reaching the processors.
+    async fn processed_times(mut reader: Box<AsyncReader>) -> Vec<usize> {
+        let processed = Arc::new(Mutex::new(Vec::new()));
+        let processed_clone = processed.clone();
+        let mut mock_processor = MockProcessor::new();
+        mock_processor
+            .expect_next_interesting_time()
+            .return_const(None);
+        mock_processor
+            .expect_process()
+            .returning(move |log_group, _| {
+                let mut processed = processed_clone.lock().unwrap();
+                processed.extend(log_group.logs.iter().map(|log| log.time));
+                Ok(())
+            });
+        mock_processor.expect_finalize().returning(|_| Ok(()));
+
+        process_logs(
+            vec![reader.as_mut()],
+            vec![Box::new(mock_processor)],
+            Vec::new(),
+            &ProcessOptions::default(),
+        )
+        .await
+        .unwrap();
+        let processed = processed.lock().unwrap().clone();
+        processed
+    }
+
+    #[tokio::test]
+    async fn only_the_last_lines_of_a_file_reach_the_processors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.csv");
+        std::fs::write(&path, build_input(10)).unwrap();
+
+        // blocks smaller than a line, so several of them are read
+        let reader = tail_file_by_blocks(File::open(&path).await.unwrap(), 3, true, 16)
+            .await
+            .unwrap();
+        let all = tail_file(File::open(&path).await.unwrap(), 100, true)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            processed_times(reader).await,
+            vec![1549573867, 1549573868, 1549573869]
+        );
+        assert_eq!(processed_times(all).await.len(), 10);
+    }
+
+    #[tokio::test]
+    async fn only_the_last_lines_of_a_stream_reach_the_processors() {
+        let input = build_input(10);
+        let without_trailing_newline = input.trim_end().to_string();
+
+        let reader = tail_stream(&mut std::io::Cursor::new(input), 2, true, None)
+            .await
+            .unwrap();
+        let trimmed = tail_stream(
+            &mut std::io::Cursor::new(without_trailing_newline),
+            2,
+            true,
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(processed_times(reader).await, vec![1549573868, 1549573869]);
+        assert_eq!(processed_times(trimmed).await, vec![1549573868, 1549573869]);
+    }
+
+    #[tokio::test]
+    async fn windows_and_old_mac_line_ends_count_as_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let crlf = dir.path().join("crlf.csv");
+        let cr = dir.path().join("cr.csv");
+        std::fs::write(&crlf, build_input(10).replace('\n', "\r\n")).unwrap();
+        std::fs::write(&cr, build_input(10).replace('\n', "\r")).unwrap();
+
+        // blocks splitting the `\r\n` too
+        let crlf_file = tail_file_by_blocks(File::open(&crlf).await.unwrap(), 3, true, 7)
+            .await
+            .unwrap();
+        let cr_file = tail_file(File::open(&cr).await.unwrap(), 3, true)
+            .await
+            .unwrap();
+        let cr_stream = tail_stream(
+            &mut std::io::Cursor::new(std::fs::read(&cr).unwrap()),
+            3,
+            true,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let last = vec![1549573867, 1549573868, 1549573869];
+        assert_eq!(processed_times(crlf_file).await, last);
+        assert_eq!(processed_times(cr_file).await, last);
+        assert_eq!(processed_times(cr_stream).await, last);
+    }
+
+    #[tokio::test]
+    async fn the_lines_over_the_max_bytes_are_not_kept() {
+        let mut input = build_input(3);
+        input.push_str(&"x".repeat(1_000));
+        input.push('\n');
+
+        let reader = tail_stream(&mut std::io::Cursor::new(input), 2, true, Some(100))
+            .await
+            .unwrap();
+
+        assert_eq!(processed_times(reader).await, vec![1549573861, 1549573862]);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::reader::AsyncReader;
+use futures::ready;
+use std::{
+    fs::File,
+    future::Future,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    task::{JoinError, JoinHandle},
+};
+
+/// Copies the input to an archive file as it's read, leaving what's read untouched.
+pub fn tee(reader: Box<AsyncReader>, archive: &Path) -> anyhow::Result<Box<AsyncReader>> {
+    let file = File::create(archive)
+        .map_err(|e| anyhow::anyhow!("Can't create the tee archive {:?}: {}", archive, e))?;
+    Ok(Box::new(Tee::new(reader, archive, file)))
+}
+
+/// Writes every chunk read from the inner reader to a file, off the async threads. The
+/// archive is flushed at the end of the input, or when the reader is dropped before it.
+pub struct Tee<R> {
+    inner: R,
+    path: PathBuf,
+    // taken while it's written
+    archive: Option<BufWriter<File>>,
+    // the write of the last chunk, or the final flush
+    writing: Option<JoinHandle<(BufWriter<File>, io::Result<()>)>>,
+    is_at_end: bool,
+}
+
+impl<R> Tee<R>
+where
+    R: AsyncRead + Unpin,
+{
+    pub fn new(inner: R, path: &Path, archive: File) -> Self {
+        Self {
+            inner,
+            path: path.to_path_buf(),
+            archive: Some(BufWriter::new(archive)),
+            writing: None,
+            is_at_end: false,
+        }
+    }
+}
+
+impl<R> Tee<R> {
+    /// Writes to the archive in a blocking task.
+    fn spawn_write(
+        &mut self,
+        write: impl FnOnce(&mut BufWriter<File>) -> io::Result<()> + Send + 'static,
+    ) -> io::Result<()> {
+        let mut archive = self
+            .archive
+            .take()
+            .ok_or_else(|| self.archive_error(io::Error::other("a previous write failed")))?;
+        self.writing = Some(tokio::task::spawn_blocking(move || {
+            let written = write(&mut archive);
+            (archive, written)
+        }));
+        Ok(())
+    }
+
+    /// Result of the write in flight, if any, given the archive back.
+    fn written(
+        &mut self,
+        writing: Result<(BufWriter<File>, io::Result<()>), JoinError>,
+    ) -> io::Result<()> {
+        self.writing = None;
+        let (archive, written) = writing.map_err(|e| self.archive_error(io::Error::other(e)))?;
+        self.archive = Some(archive);
+        written.map_err(|e| self.archive_error(e))
+    }
+
+    fn archive_error(&self, error: io::Error) -> io::Error {
+        io::Error::new(
+            error.kind(),
+            format!("Can't write the tee archive {:?}: {}", self.path, error),
+        )
+    }
+}
+
+impl<R> AsyncRead for Tee<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            // a chunk is only read once the previous one is archived
+            if let Some(writing) = &mut this.writing {
+                let writing = ready!(Pin::new(writing).poll(cx));
+                this.written(writing)?;
+            }
+            if this.is_at_end {
+                return Poll::Ready(Ok(()));
+            }
+            let filled = buf.filled().len();
+            ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+            match &buf.filled()[filled..] {
+                [] => {
+                    this.is_at_end = true;
+                    this.spawn_write(|archive| archive.flush())?;
+                }
+                read => {
+                    let chunk = read.to_vec();
+                    this.spawn_write(move |archive| archive.write_all(&chunk))?;
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+}
+
+impl<R> Drop for Tee<R> {
+    fn drop(&mut self) {
+        // waits for the write in flight, if any, and flushes what's read up to now
+        let flushed = match self.writing.take() {
+            Some(writing) => self.written(futures::executor::block_on(writing)),
+            None => Ok(()),
+        }
+        .and_then(|_| match &mut self.archive {
+            Some(archive) => archive.flush().map_err(|e| self.archive_error(e)),
+            None => Ok(()),
+        });
+        if let Err(e) = flushed {
+            tracing::error!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{read_many_async, ReaderOptions};
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn the_archive_is_identical_to_the_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("archive.csv");
+        // a BOM, a malformed record and no trailing newline must all be kept
+        let input = "\u{feff}\"remotehost\",\"rfc931\",\"authuser\",\"date\",\"request\",\"status\",\"bytes\"\r\n\
+            \"10.0.0.2\",\"-\",\"apache\",1549573860,\"GET /api/user HTTP/1.0\",200,1234\r\n\
+            \"10.0.0.4\",\"-\",\"apache\",oops,\"GET /api/user HTTP/1.0\",200,1234\n\
+            \"10.0.0.5\",\"-\",\"apache\",1549573861,\"POST /report HTTP/1.0\",500,12";
+        let mut reader = tee(Box::new(input.as_bytes()), &archive).unwrap();
+
+        let logs = read_many_async(vec![reader.as_mut()], ReaderOptions::default())
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        // flushed at the end of the input, before the reader is dropped
+        assert_eq!(logs.len(), 3);
+        assert_eq!(logs.iter().filter(|log| log.is_ok()).count(), 2);
+        assert_eq!(std::fs::read(&archive).unwrap(), input.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn the_archive_is_flushed_when_the_reader_is_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("archive.csv");
+        let mut reader = tee(Box::new(&b"10.0.0.2,-,apache"[..]), &archive).unwrap();
+
+        let mut read = [0; 8];
+        tokio::io::AsyncReadExt::read_exact(&mut reader, &mut read)
+            .await
+            .unwrap();
+        drop(reader);
+
+        assert_eq!(std::fs::read(&archive).unwrap(), b"10.0.0.2");
+    }
+
+    #[tokio::test]
+    async fn fails_when_the_archive_cannot_be_written() {
+        let mut reader = tee(Box::new(&b"10.0.0.2"[..]), Path::new("/dev/full")).unwrap();
+
+        let mut read = Vec::new();
+        let error = tokio::io::AsyncReadExt::read_to_end(&mut reader, &mut read)
+            .await
+            .unwrap_err();
+
+        assert!(error
+            .to_string()
+            .starts_with("Can't write the tee archive \"/dev/full\""));
+    }
+}
@@ -1,7 +1,12 @@
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    io::Write,
+    sync::{Arc, Mutex, OnceLock},
+};
 
 use crate::{
     buffered_logs::GroupedHttpLogs,
+    processors::Processor,
     reader::{HttpLog, LogRequest},
 };
 
@@ -48,6 +53,9 @@ pub fn build_test_http_log(time: usize, path: Option<String>) -> HttpLog {
         .unwrap(),
         status: 200,
         bytes: 100,
+        response_time_ms: None,
+        referrer: None,
+        user_agent: None,
     }
 }
 
@@ -63,3 +71,61 @@ pub fn build_test_http_grouped_log(
             .collect(),
     }
 }
+
+/// Group of `len` test logs, every one of them changed by `f` (e.g. to set its status).
+pub fn build_test_http_grouped_log_with(
+    time: usize,
+    len: usize,
+    f: impl Fn(&mut HttpLog),
+) -> GroupedHttpLogs {
+    let mut group = build_test_http_grouped_log(time, len, None);
+    group.logs.iter_mut().for_each(f);
+    group
+}
+
+/// Output of the processor for the groups, finalizing it after them.
+pub fn run_processor(processor: &mut dyn Processor, groups: Vec<GroupedHttpLogs>) -> String {
+    let mut writer = Vec::<u8>::new();
+    for group in groups {
+        processor.process(&group, &mut writer).unwrap();
+    }
+    processor.finalize(&mut writer).unwrap();
+    String::from_utf8(writer).unwrap()
+}
+
+/// Writer appending to a shared buffer.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(pub Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).to_string()
+    }
+}
+
+/// Diagnostic logs of all the tests from the first call on. The tests run concurrently,
+/// so only assert on messages which are unique to a test.
+pub fn captured_logs() -> SharedBuffer {
+    static LOGS: OnceLock<SharedBuffer> = OnceLock::new();
+    LOGS.get_or_init(|| {
+        let logs = SharedBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::set_global_default(subscriber).unwrap();
+        logs
+    })
+    .clone()
+}
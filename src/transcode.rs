@@ -0,0 +1,167 @@
+use crate::reader::AsyncReader;
+use encoding_rs::{Decoder, Encoding, UTF_8};
+use std::{
+    io,
+    pin::Pin,
+    str::FromStr,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncBufRead, AsyncRead, BufReader, ReadBuf};
+
+/// Encoding of the input, e.g. `latin1` or `windows-1252`. Any WHATWG label is accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEncoding(&'static Encoding);
+
+impl Default for InputEncoding {
+    fn default() -> Self {
+        Self(UTF_8)
+    }
+}
+
+impl FromStr for InputEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match Encoding::for_label(s.trim().as_bytes()) {
+            // the lines and the CSV separators are found by their ASCII bytes
+            Some(encoding) if encoding.is_ascii_compatible() => Ok(Self(encoding)),
+            Some(encoding) => Err(anyhow::anyhow!(
+                "Unsupported input encoding: {}. Only ASCII-compatible encodings can be read",
+                encoding.name()
+            )),
+            None => Err(anyhow::anyhow!("Invalid input encoding: {}", s)),
+        }
+    }
+}
+
+impl InputEncoding {
+    /// Transcodes the input to UTF-8 as it's read. UTF-8 inputs are left untouched.
+    pub fn transcode(self, reader: Box<AsyncReader>) -> Box<AsyncReader> {
+        match self.0 == UTF_8 {
+            true => reader,
+            false => Box::new(Transcoder::new(BufReader::new(reader), self.0)),
+        }
+    }
+}
+
+/// Decodes an input in another encoding into UTF-8. The invalid sequences are replaced
+/// with U+FFFD instead of failing the whole input.
+pub struct Transcoder<R> {
+    inner: R,
+    decoder: Decoder,
+    // decoded bytes not handed out yet
+    output: Vec<u8>,
+    pos: usize,
+    is_done: bool,
+}
+
+impl<R> Transcoder<R>
+where
+    R: AsyncBufRead + Unpin,
+{
+    pub fn new(inner: R, encoding: &'static Encoding) -> Self {
+        Self {
+            inner,
+            decoder: encoding.new_decoder(),
+            output: Vec::new(),
+            pos: 0,
+            is_done: false,
+        }
+    }
+}
+
+impl<R> AsyncRead for Transcoder<R>
+where
+    R: AsyncBufRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.pos < this.output.len() {
+                let len = buf.remaining().min(this.output.len() - this.pos);
+                buf.put_slice(&this.output[this.pos..this.pos + len]);
+                this.pos += len;
+                return Poll::Ready(Ok(()));
+            }
+            if this.is_done {
+                return Poll::Ready(Ok(()));
+            }
+
+            let available = match Pin::new(&mut this.inner).poll_fill_buf(cx) {
+                Poll::Ready(Ok(available)) => available,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            // an empty chunk is the end of the input, flushing the decoder
+            let is_last = available.is_empty();
+            let max_len = this
+                .decoder
+                .max_utf8_buffer_length(available.len())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Chunk too long"))?;
+            this.output.resize(max_len, 0);
+            // the output is long enough to decode the whole chunk at once
+            let (_, read, written, _) =
+                this.decoder
+                    .decode_to_utf8(available, &mut this.output, is_last);
+            this.output.truncate(written);
+            this.pos = 0;
+            this.is_done = is_last;
+            Pin::new(&mut this.inner).consume(read);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{read_csv_async, ReaderOptions};
+    use futures::TryStreamExt;
+
+    #[tokio::test]
+    async fn latin1_input_is_decoded() {
+        // "Ñ" and "é" are single bytes in Latin-1
+        let input =
+            b"\"remotehost\",\"rfc931\",\"authuser\",\"date\",\"request\",\"status\",\"bytes\"
+\"10.0.0.2\",\"-\",\"Mu\xF1oz\",1549573860,\"GET /caf\xE9 HTTP/1.0\",200,1234"
+                .to_vec();
+        // without decoding, the invalid UTF-8 fails the record
+        let mut reader: Box<AsyncReader> = Box::new(std::io::Cursor::new(input.clone()));
+        let undecoded = read_csv_async(reader.as_mut(), ReaderOptions::default())
+            .await
+            .try_collect::<Vec<_>>()
+            .await;
+        let mut reader = "latin1"
+            .parse::<InputEncoding>()
+            .unwrap()
+            .transcode(Box::new(std::io::Cursor::new(input)));
+
+        let logs = read_csv_async(reader.as_mut(), ReaderOptions::default())
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert!(undecoded.is_err());
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].auth_user, "Muñoz");
+        assert_eq!(logs[0].request.path, "/café");
+    }
+
+    #[tokio::test]
+    async fn parses_the_encoding_labels() {
+        assert_eq!(
+            "latin1".parse::<InputEncoding>().unwrap(),
+            "windows-1252".parse().unwrap()
+        );
+        assert_eq!(
+            "utf-8".parse::<InputEncoding>().unwrap(),
+            InputEncoding::default()
+        );
+        assert!("utf-16le".parse::<InputEncoding>().is_err());
+        assert!("klingon".parse::<InputEncoding>().is_err());
+    }
+}
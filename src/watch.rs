@@ -0,0 +1,203 @@
+use crate::{
+    buffered_logs::LogResult,
+    reader::{read_csv_async, ReaderOptions},
+    record_limit::is_line_end,
+};
+use futures::{stream, Stream, StreamExt};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{Cursor, SeekFrom},
+    path::{Path, PathBuf},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncSeekExt},
+    sync::mpsc::{self, UnboundedReceiver},
+};
+
+/// Part of a watched file already read.
+#[derive(Debug, Default)]
+struct TailedFile {
+    offset: u64,
+    // header row prepended to the appended lines so they can be parsed on their own
+    header: Vec<u8>,
+}
+
+struct DirWatcher {
+    // dropping the watcher stops the events
+    _watcher: RecommendedWatcher,
+    events: UnboundedReceiver<PathBuf>,
+    pending: VecDeque<PathBuf>,
+    files: HashMap<PathBuf, TailedFile>,
+    options: ReaderOptions,
+}
+
+impl DirWatcher {
+    async fn next_path(&mut self) -> Option<PathBuf> {
+        match self.pending.pop_front() {
+            Some(path) => Some(path),
+            None => self.events.recv().await,
+        }
+    }
+
+    /// Reads the complete lines added to the file since the last time.
+    async fn read_new_logs(&mut self, path: PathBuf) -> Vec<LogResult> {
+        let file = self.files.entry(path.clone()).or_default();
+        let chunk = match read_from(&path, file).await {
+            Ok(chunk) => chunk,
+            Err(e) => return vec![Err(e)],
+        };
+        // the last line may still be being written
+        let len = match chunk.iter().rposition(is_line_end) {
+            Some(i) => i + 1,
+            None => return Vec::new(),
+        };
+        let mut input = if file.offset == 0 {
+            if self.options.header.is_none() {
+                let header_len = chunk.iter().position(is_line_end).unwrap_or(0) + 1;
+                file.header = chunk[..header_len].to_vec();
+            }
+            Vec::new()
+        } else {
+            file.header.clone()
+        };
+        input.extend_from_slice(&chunk[..len]);
+        file.offset += len as u64;
+        tracing::debug!("Read {} new bytes from {:?}", len, path);
+
+        let mut reader = Cursor::new(input);
+        read_csv_async(&mut reader, self.options.clone())
+            .await
+            .collect()
+            .await
+    }
+}
+
+async fn read_from(path: &Path, file: &mut TailedFile) -> anyhow::Result<Vec<u8>> {
+    let mut reader = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Can't open the watched file {:?}: {}", path, e))?;
+    if reader.metadata().await?.len() < file.offset {
+        tracing::warn!("{:?} was truncated, reading it from the beginning", path);
+        *file = TailedFile::default();
+    }
+    reader.seek(SeekFrom::Start(file.offset)).await?;
+    let mut chunk = Vec::new();
+    reader.read_to_end(&mut chunk).await?;
+    Ok(chunk)
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "csv")
+}
+
+/// Reads the `*.csv` files of a directory, the ones already there and the ones created
+/// afterwards, merging their logs as they come. Files are followed when they're appended to.
+/// The stream ends on Ctrl-C.
+pub fn watch_dir(
+    dir: &Path,
+    options: ReaderOptions,
+) -> anyhow::Result<impl Stream<Item = LogResult>> {
+    let (tx, events) = mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                for path in event.paths.into_iter().filter(|path| is_csv(path)) {
+                    // the receiver is only gone once the stream is dropped
+                    let _ = tx.send(path);
+                }
+            }
+            Ok(_) => (),
+            Err(e) => tracing::error!("Error watching the directory: {}", e),
+        })?;
+    // watching before listing the files so none of them is missed
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    let mut existing = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| is_csv(path))
+        .collect::<Vec<_>>();
+    existing.sort();
+
+    let dir_watcher = DirWatcher {
+        _watcher: watcher,
+        events,
+        pending: existing.into(),
+        files: HashMap::new(),
+        options,
+    };
+    let log_stream = stream::unfold(dir_watcher, |mut dir_watcher| async move {
+        let path = dir_watcher.next_path().await?;
+        let logs = dir_watcher.read_new_logs(path).await;
+        Some((stream::iter(logs), dir_watcher))
+    })
+    .flatten();
+    Ok(log_stream.take_until(async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Error listening for Ctrl-C: {}", e);
+            futures::future::pending::<()>().await;
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffered_logs::BufferedLogs;
+    use std::time::Duration;
+
+    const HEADER: &str = r#""remotehost","rfc931","authuser","date","request","status","bytes""#;
+
+    fn row(time: usize) -> String {
+        format!(
+            r#""10.0.0.1","-","apache",{},"GET /api/user HTTP/1.0",200,1234"#,
+            time
+        )
+    }
+
+    fn write_csv(path: &Path, times: &[usize]) {
+        let rows = times
+            .iter()
+            .map(|time| row(*time) + "\n")
+            .collect::<String>();
+        std::fs::write(path, format!("{}\n{}", HEADER, rows)).unwrap();
+    }
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> S::Item {
+        tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("timed out waiting for the watched files")
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn new_files_are_merged_into_the_same_buffer() {
+        let dir = tempfile::tempdir().unwrap();
+        write_csv(&dir.path().join("access-1.csv"), &[1549573860, 1549573860]);
+
+        let log_stream = watch_dir(dir.path(), ReaderOptions::default()).unwrap();
+        let mut groups = Box::pin(BufferedLogs::new(log_stream, 2));
+        write_csv(&dir.path().join("access-2.csv"), &[1549573860, 1549573864]);
+
+        // the logs of the same second are grouped even if they come from different files
+        let group = next(&mut groups).await;
+        assert_eq!((group.time, group.logs.len()), (1549573860, 3));
+    }
+
+    #[tokio::test]
+    async fn appended_lines_are_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("access.csv");
+        write_csv(&path, &[1549573860]);
+
+        let mut log_stream = Box::pin(watch_dir(dir.path(), ReaderOptions::default()).unwrap());
+        assert_eq!(next(&mut log_stream).await.unwrap().time, 1549573860);
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        std::io::Write::write_all(&mut file, (row(1549573861) + "\n").as_bytes()).unwrap();
+
+        assert_eq!(next(&mut log_stream).await.unwrap().time, 1549573861);
+    }
+}
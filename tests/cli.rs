@@ -0,0 +1,228 @@
+use assert_cmd::Command;
+use predicates::prelude::*;
+
+fn log_ingestor() -> Command {
+    Command::cargo_bin("log-ingestor").unwrap()
+}
+
+#[test]
+fn processes_the_sample_file() {
+    log_ingestor()
+        .arg("sample.csv")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("STATS (10s):"))
+        // the first period starts with the first log, not with the epoch
+        .stdout(predicate::str::contains("STATS (1549573859s):").not())
+        .stdout(predicate::str::contains("Section: /api, Total Hits: 52,"))
+        .stdout(predicate::str::contains(
+            "High traffic generated an alert - hits = 10.05, triggered at 1549573956",
+        ))
+        .stdout(predicate::str::contains("Normal traffic recovered"))
+        .stdout(predicate::str::contains("PEAK SECOND:"));
+}
+
+#[test]
+fn processes_the_standard_input() {
+    log_ingestor()
+        .pipe_stdin("sample.csv")
+        .unwrap()
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("STATS (10s):"))
+        .stdout(predicate::str::contains(
+            "High traffic generated an alert - hits = 10.05, triggered at 1549573956",
+        ));
+}
+
+#[test]
+fn processes_the_sample_file_with_a_custom_buffer() {
+    log_ingestor()
+        .args([
+            "sample.csv",
+            "--buffer-seconds",
+            "5",
+            "--stats-period",
+            "20",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("STATS (20s):"))
+        .stdout(predicate::str::contains("High traffic generated an alert"));
+}
+
+#[test]
+fn rejects_invalid_options() {
+    log_ingestor()
+        .args(["sample.csv", "--group-by", "nope"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid group by: nope"));
+}
+
+#[test]
+fn rejects_filtering_the_counted_logs() {
+    log_ingestor()
+        .args(["sample.csv", "--count-only", "--only-status", "5xx"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "--count-only can't filter the logs with --only-status",
+        ));
+}
+
+#[test]
+fn writes_the_expanded_requests() {
+    let dir = tempfile::tempdir().unwrap();
+    let csv_out = dir.path().join("out.csv");
+    log_ingestor()
+        .args(["sample.csv", "--expand-request", "--csv-out"])
+        .arg(&csv_out)
+        .assert()
+        .success();
+    let written = std::fs::read_to_string(csv_out).unwrap();
+    assert!(written.lines().next().unwrap().contains("section"));
+}
+
+#[test]
+fn reports_the_watched_logs_every_wall_interval() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::copy("sample.csv", dir.path().join("sample.csv")).unwrap();
+    log_ingestor()
+        .arg("--watch-dir")
+        .arg(dir.path())
+        .args(["--wall-interval", "1s", "--max-runtime", "3s"])
+        .timeout(std::time::Duration::from_secs(30))
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("STATS"));
+}
+
+#[test]
+fn prints_the_rps_histogram_with_custom_edges() {
+    log_ingestor()
+        .args([
+            "sample.csv",
+            "--rps-histogram",
+            "--rps-histogram-edges",
+            "5,20",
+        ])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("5-19"))
+        .stdout(predicate::str::contains("20+"));
+}
+
+#[test]
+fn rejects_counting_the_logs_with_other_modes() {
+    log_ingestor()
+        .args(["sample.csv", "--count-only", "--analyze-skew"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    log_ingestor()
+        .args(["--watch-dir", ".", "--count-only"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    log_ingestor()
+        .args(["sample.csv", "--dry-parse-sample", "3", "--count-only"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+    log_ingestor()
+        .args(["sample.csv", "sample.csv", "--compare", "--count-only"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn rejects_the_conflicting_options() {
+    let conflicts: &[&[&str]] = &[
+        &["--watch-dir", ".", "--analyze-skew"],
+        &["--watch-dir", ".", "--tail-lines", "5"],
+        &["--watch-dir", ".", "--brotli"],
+        &["--watch-dir", ".", "--input-encoding", "latin1"],
+        &["--watch-dir", ".", "--tee", "tee.csv"],
+        &["sample.csv", "sample.csv", "--compare", "--analyze-skew"],
+        &["--watch-dir", ".", "--compare"],
+        &["sample.csv", "--dry-parse-sample", "3", "--analyze-skew"],
+        &["--watch-dir", ".", "--dry-parse-sample", "3"],
+        &["--journald", "--watch-dir", "."],
+        &["--journald", "--tail-lines", "5"],
+        &["--journald", "--count-only"],
+        &["sample.csv", "--watch-dir", "."],
+        &["sample.csv", "--journald"],
+        &[
+            "sample.csv",
+            "--alert-tier",
+            "100:warn",
+            "--alert-threshold",
+            "5",
+        ],
+        &["sample.csv", "--compact", "--pretty"],
+    ];
+    for args in conflicts {
+        log_ingestor()
+            .args(*args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+}
+
+#[test]
+fn runs_the_options_with_their_requirements() {
+    log_ingestor()
+        .args([
+            "sample.csv",
+            "--anonymize",
+            "host",
+            "--anonymize-salt",
+            "pepper",
+        ])
+        .args(["--leaderboard", "--leaderboard-size", "2"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("LEADERBOARD (last 10s):"))
+        .stdout(predicate::str::contains("3a0141e0c5b5b152"));
+    log_ingestor()
+        .args(["sample.csv", "--replay", "--replay-speed", "1000"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ALERT TIMELINE (1000x):"));
+}
+
+#[cfg(feature = "syslog")]
+#[test]
+fn sends_the_alerts_to_a_syslog_server() {
+    log_ingestor()
+        .args(["sample.csv", "--syslog", "--syslog-addr", "127.0.0.1:514"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn rejects_the_options_without_their_requirements() {
+    let requirements: &[&[&str]] = &[
+        &["sample.csv", "--expand-request"],
+        &["sample.csv", "--wall-interval", "1s"],
+        &["sample.csv", "--rps-histogram-edges", "5,20"],
+        &["sample.csv", "--anonymize-salt", "pepper"],
+        &["sample.csv", "--syslog-addr", "127.0.0.1:514"],
+        &["sample.csv", "--replay-speed", "2"],
+        &["sample.csv", "--leaderboard-size", "2"],
+        &["--journald-unit", "nginx.service"],
+        &["--journald-since", "1 hour ago"],
+    ];
+    for args in requirements {
+        log_ingestor()
+            .args(*args)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "The following required arguments were not provided",
+            ));
+    }
+}